@@ -3,15 +3,27 @@
 // This crate performs pre-compilation asset retrieval and cargo metadata setting.
 // During runtime of a dependent `build.rs`, this crate will retrieve cargo metadata and export environment variables.
 //
-use std::{env::var, process::Command};
+use std::env::var;
 
-const ASSET_SCRIPT_PATH: &str = "scripts/asset.sh";
 const DEFAULT_CACHE_PATH: &str = "cache";
 const DEFAULT_DIRECTORY_PATH: &str = "assets/dependencies";
+const PARALLEL_VARIABLE: &str = "IGNITION_PARALLEL";
+/// Environment variables read by the build script itself (those read by retrievals are recorded as they are read)
+const BUILD_VARIABLES: [&str; 4] = [
+    "IGNITION_BUCKET_URL",
+    "IGNITION_CACHE_PATH",
+    "IGNITION_DIRECTORY_PATH",
+    PARALLEL_VARIABLE,
+];
 
 include!("src/lib.rs");
 
+/// Signature shared by all per-asset retrieval functions
+type AssetJob = fn(&str, &str, &str, &str, &str) -> IgnitionResult<()>;
+
 /// Entry point for all asset retrieval and environment variable setting
+///
+/// Assets are retrieved concurrently unless IGNITION_PARALLEL=0, and all failures are reported together.
 fn asset(
     var_bucket_url: &str,
     build_dir: &str,
@@ -19,27 +31,80 @@ fn asset(
     var_directory_path: &str,
     target: &str,
 ) {
-    asset_script();
-
-    #[cfg(feature = "download-opencv")]
-    #[allow(clippy::needless_borrow)]
-    asset_opencv(
-        &var_bucket_url,
-        &build_dir,
-        &var_cache_path,
-        &var_directory_path,
-        &target,
-    );
-
-    #[cfg(feature = "download-onnxruntime")]
-    #[allow(clippy::needless_borrow)]
-    asset_onnxruntime(
-        &var_bucket_url,
-        &build_dir,
-        &var_cache_path,
-        &var_directory_path,
-        &target,
-    );
+    let jobs: Vec<(&str, AssetJob)> = vec![
+        #[cfg(feature = "download-opencv")]
+        ("opencv", asset_opencv),
+        #[cfg(feature = "download-onnxruntime")]
+        ("onnxruntime", asset_onnxruntime),
+    ];
+
+    let run = |job: &AssetJob| {
+        job(
+            var_bucket_url,
+            build_dir,
+            var_cache_path,
+            var_directory_path,
+            target,
+        )
+    };
+    let results: Vec<(&str, IgnitionResult<()>)> = if parallel() {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = jobs
+                .iter()
+                .map(|(name, job)| (*name, scope.spawn(move || run(job))))
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    (
+                        name,
+                        handle.join().expect("asset retrieval thread panicked"),
+                    )
+                })
+                .collect()
+        })
+    } else {
+        jobs.iter().map(|(name, job)| (*name, run(job))).collect()
+    };
+
+    let errors: Vec<String> = results
+        .into_iter()
+        .filter_map(|(name, result)| result.err().map(|err| format!("{}: {}", name, err)))
+        .collect();
+    if !errors.is_empty() {
+        panic!("asset retrieval failed:\n  {}", errors.join("\n  "));
+    }
+}
+
+/// Determine the cache path: IGNITION_CACHE_PATH (relative to the build directory), otherwise the machine-global
+/// cache shared by all builds (see `retrieval::cache::global_path`), otherwise `cache` in the build directory, as
+/// in vendored mode (see `retrieval::vendor::dir`), the cache never being shared
+fn cache_path(bucket_url: &str) -> String {
+    var("IGNITION_CACHE_PATH").unwrap_or_else(|_| {
+        if retrieval::vendor::dir().is_some() {
+            return DEFAULT_CACHE_PATH.to_string();
+        }
+        retrieval::cache::global_path(bucket_url).map_or(DEFAULT_CACHE_PATH.to_string(), |path| {
+            path.to_string_lossy().into_owned()
+        })
+    })
+}
+
+/// Determine the path of the cache to collect: the machine-global cache as a whole if the cache path is within it
+/// (see `retrieval::cache::global_dir`), otherwise the cache path (relative to the build directory)
+fn gc_path(build_dir: &str, cache_path: &str) -> std::path::PathBuf {
+    let path = std::path::Path::new(build_dir).join(cache_path);
+    match retrieval::cache::global_dir() {
+        Some(global_dir) if path.starts_with(&global_dir) => global_dir,
+        _ => path,
+    }
+}
+
+/// Determine if assets are retrieved concurrently (IGNITION_PARALLEL, default enabled)
+fn parallel() -> bool {
+    retrieval::env_flag(PARALLEL_VARIABLE)
+        .expect("IGNITION_PARALLEL environment variable error")
+        .unwrap_or(true)
 }
 
 /// Retrieve OpenCV asset and set environment variables
@@ -50,19 +115,17 @@ fn asset_opencv(
     cache_path: &str,
     directory_path: &str,
     target: &str,
-) {
-    asset_retrieve(
+) -> IgnitionResult<()> {
+    let extract_path = asset_retrieve(
         var_bucket_url,
         "opencv",
         build_dir,
         cache_path,
         directory_path,
         target,
-    );
-    let _ = environment_variables(
-        "opencv",
-        Some(&std::path::Path::new(&build_dir).join(directory_path)),
-    );
+    )?;
+    environment_variables("opencv", extract_path.parent())?;
+    Ok(())
 }
 
 /// Retrieve Onnruuntime asset and set environment variable
@@ -73,22 +136,21 @@ fn asset_onnxruntime(
     cache_path: &str,
     directory_path: &str,
     target: &str,
-) {
-    asset_retrieve(
+) -> IgnitionResult<()> {
+    let extract_path = asset_retrieve(
         var_bucket_url,
         "onnxruntime",
         build_dir,
         cache_path,
         directory_path,
         target,
-    );
-    let _ = environment_variables(
-        "onnxruntime",
-        Some(&std::path::Path::new(&build_dir).join(directory_path)),
-    );
+    )?;
+    environment_variables("onnxruntime", extract_path.parent())?;
+    Ok(())
 }
 
-/// Retrieve an asset by name using the asset.sh script
+/// Retrieve an asset by name using the retrieval module, returning the directory it was extracted into (within the
+/// directory of the target, see `retrieval::AssetLocation`)
 fn asset_retrieve(
     var_bucket_url: &str,
     asset: &str,
@@ -96,40 +158,33 @@ fn asset_retrieve(
     cache_path: &str,
     directory_path: &str,
     target: &str,
-) {
-    let mut output = Command::new(ASSET_SCRIPT_PATH)
-        .args([
-            var_bucket_url,
-            asset,
-            build_dir,
-            cache_path,
-            directory_path,
-            target,
-        ])
-        .spawn()
-        .expect("asset.sh command failed to start");
-    let _ = output.wait().expect("asset.sh command failed to complete");
-}
-
-/// Prepare the asset.sh script by making it executable
-fn asset_script() {
-    let mut output = Command::new("chmod")
-        .arg("+x")
-        .arg(ASSET_SCRIPT_PATH)
-        .spawn()
-        .expect("'chmod +x <script-path>' failed");
-
-    let _ = output.wait().expect("'chmod +x <script-path>' failed");
+) -> IgnitionResult<std::path::PathBuf> {
+    retrieval::retrieve(
+        var_bucket_url,
+        asset,
+        std::path::Path::new(build_dir),
+        cache_path,
+        directory_path,
+        target,
+        None,
+    )
 }
 
 /// Main entry point
 fn main() {
-    // force re-run by pointing to a non-existent file
-    println!("cargo:rerun-if-changed=NULL");
+    // re-run when the manifests or settings change (see `retrieval::rerun::print_directives` for retrievals)
+    println!("cargo:rerun-if-changed=config/environment.json");
+    println!("cargo:rerun-if-changed=config/target.json");
+    for name in BUILD_VARIABLES {
+        println!("cargo:rerun-if-env-changed={}", name);
+    }
 
     // require definition and format: /../target/<target-triplet>/<build-type>/build/<ignition-build-id>/out
     let out_dir = var("OUT_DIR").unwrap();
-    let build_dir = out_dir.split(&"/build".to_string()).next().unwrap();
+    let build_dir = &retrieval::build_dir(std::path::Path::new(&out_dir))
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
 
     // parse target
     let target = std::env::var("TARGET").unwrap_or("".to_string());
@@ -137,12 +192,76 @@ fn main() {
     // retrieve assets and set environment variables (note: target exclusion)
     #[cfg(any(feature = "download-opencv", feature = "download-onnxruntime"))]
     if !(target.starts_with("aarch64-") && target.contains("linux")) {
+        let bucket_url =
+            var("IGNITION_BUCKET_URL").expect("IGNITION_BUCKET_URL environment variable error");
+        let cache_path = cache_path(&bucket_url);
+        let directory_path =
+            var("IGNITION_DIRECTORY_PATH").unwrap_or(DEFAULT_DIRECTORY_PATH.to_string());
         asset(
-            &var("IGNITION_BUCKET_URL").expect("IGNITION_BUCKET_URL environment variable error"),
+            &bucket_url,
             build_dir,
-            &var("IGNITION_CACHE_PATH").unwrap_or(DEFAULT_CACHE_PATH.to_string()),
-            &var("IGNITION_DIRECTORY_PATH").unwrap_or(DEFAULT_DIRECTORY_PATH.to_string()),
+            &cache_path,
+            &directory_path,
             &target,
         );
+
+        // remove extractions of other targets, versions or assets (if enabled)
+        match retrieval::prune::prune_configured(
+            std::path::Path::new(build_dir),
+            &directory_path,
+            &target,
+        ) {
+            Ok(Some(pruning)) => println!(
+                "--prune: {} removed, {} bytes freed",
+                pruning.removed.len(),
+                pruning.freed
+            ),
+            Ok(None) => {}
+            Err(err) => println!("cargo:warning=failed to prune extractions: {}", err),
+        }
+
+        // evict least recently used cache entries beyond the cache size limit (if enabled)
+        match retrieval::gc::collect_configured(&gc_path(build_dir, &cache_path)) {
+            Ok(Some(collection)) => println!(
+                "--cache gc: {} evicted, {} bytes freed, {} bytes cached",
+                collection.evicted.len(),
+                collection.freed,
+                collection.size
+            ),
+            Ok(None) => {}
+            Err(err) => println!("cargo:warning=failed to collect cache: {}", err),
+        }
+    }
+
+    // summarize the retrievals (cache hits, revalidations and downloads)
+    if let Err(err) = retrieval::metrics::print_summary() {
+        println!("cargo:warning=failed to summarize retrievals: {}", err);
+    }
+
+    // write the download metrics report (if enabled)
+    match retrieval::metrics::write_report(std::path::Path::new(&out_dir)) {
+        Ok(Some(path)) => println!("--metrics: {}", path.display()),
+        Ok(None) => {}
+        Err(err) => println!("cargo:warning=failed to write metrics report: {}", err),
     }
+
+    // write the SBOM fragment of retrieved assets (if enabled)
+    match retrieval::sbom::write(std::path::Path::new(&out_dir)) {
+        Ok(Some(path)) => println!("--sbom: {}", path.display()),
+        Ok(None) => {}
+        Err(err) => println!("cargo:warning=failed to write sbom: {}", err),
+    }
+
+    // harvest license files of retrieved assets, exported as cargo metadata for packaging
+    #[cfg(feature = "licenses")]
+    match retrieval::licenses::write(std::path::Path::new(&out_dir)) {
+        Ok(path) => {
+            println!("--licenses: {}", path.display());
+            println!("cargo::metadata=LICENSES_PATH={}", path.display());
+        }
+        Err(err) => println!("cargo:warning=failed to harvest licenses: {}", err),
+    }
+
+    // re-run when the inputs of retrievals change, or their extractions are removed
+    retrieval::rerun::print_directives();
 }
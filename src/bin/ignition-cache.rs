@@ -0,0 +1,357 @@
+// ignition-cache.rs
+//
+// This binary contains the command line interface to the asset cache, e.g. to populate the cache in a CI step
+// before `cargo build` (`ignition-cache prefetch --target <triplet> <asset>...`), to vendor assets into the
+// repository (`ignition-cache vendor --target <triplet>... [--directory <path>] <asset>...`), to carry the cache to
+// another machine as a single tarball (`ignition-cache export|import [--lockfile <path>] <bundle> [<directory>]`), to report its
+// disk usage or the provenance of its archives (`ignition-cache stats|manifest [--json] [<directory>]`), or to
+// verify the cache of a CI runner (`ignition-cache verify [--delete|--refetch] [<directory>]`).
+//
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use ignition::{
+    IgnitionResult,
+    retrieval::{
+        self, bundle,
+        cache::{self, CacheRepair},
+        gc,
+        lockfile::Lockfile,
+        vendor,
+    },
+};
+
+const USAGE: &str = "usage: ignition-cache prefetch --target <triplet> <asset>...
+       ignition-cache vendor --target <triplet>... [--directory <path>] <asset>...
+       ignition-cache export [--lockfile <path>] <bundle> [<directory>]
+       ignition-cache import [--lockfile <path>] <bundle> [<directory>]
+       ignition-cache manifest [--json] [<directory>]
+       ignition-cache stats [--json] [<directory>]
+       ignition-cache verify [--delete|--refetch] [<directory>]";
+
+/// Main entry point
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("prefetch") => prefetch(&args[1..]),
+        Some("vendor") => vendor(&args[1..]),
+        Some("export") => transfer(&args[1..], bundle::export),
+        Some("import") => transfer(&args[1..], |directory, lockfile, bundle_path| {
+            bundle::import(bundle_path, directory, lockfile)
+        }),
+        Some("manifest") => manifest(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Prefetch the assets of <args> for their target into the cache, see `retrieval::prefetch`.
+///
+/// Exit status is 1 if an asset fails to be retrieved, 2 on usage errors.
+fn prefetch(args: &[String]) -> ExitCode {
+    let mut target = None;
+    let mut assets = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" if target.is_none() => target = args.next(),
+            _ if !arg.starts_with('-') => assets.push(arg.as_str()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(target) = target.filter(|_| !assets.is_empty()) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    match retrieval::prefetch(&assets, target) {
+        Ok(archives) => {
+            println!("prefetched {} assets for {}", archives.len(), target);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Vendor the assets of <args> for their targets into their directory (`vendor/ignition` by default), see
+/// `vendor::vendor`.
+///
+/// Exit status is 1 if an asset fails to be retrieved, 2 on usage errors.
+fn vendor(args: &[String]) -> ExitCode {
+    let mut targets = Vec::new();
+    let mut directory = None;
+    let mut assets = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.clone().next()) {
+            ("--target", Some(target)) => targets.push(target.as_str()),
+            ("--directory", Some(path)) if directory.is_none() => {
+                directory = Some(PathBuf::from(path))
+            }
+            _ if !arg.starts_with('-') => {
+                assets.push(arg.as_str());
+                continue;
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+        args.next();
+    }
+    if targets.is_empty() || assets.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+    let directory = directory.unwrap_or_else(|| PathBuf::from(vendor::DEFAULT_VENDOR_PATH));
+    match vendor::vendor(&assets, &targets, &directory) {
+        Ok(archives) => {
+            println!(
+                "vendored {} archives into {}",
+                archives.len(),
+                directory.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Export or import (<transfer>) the bundle of <args> for the cache at its directory (the machine-global cache by
+/// default), see `bundle::export` and `bundle::import`. The lockfile defaults to that of builds (see
+/// `Lockfile::path`).
+///
+/// Exit status is 1 on errors, 2 on usage errors.
+fn transfer(
+    args: &[String],
+    transfer: impl FnOnce(&Path, &Path, &Path) -> IgnitionResult<bundle::BundleManifest>,
+) -> ExitCode {
+    let mut lockfile = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lockfile" if lockfile.is_none() => lockfile = args.next().map(PathBuf::from),
+            _ if !arg.starts_with('-') => paths.push(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let (bundle_path, directory) = match paths.as_slice() {
+        [bundle_path] => (bundle_path, cache::global_dir()),
+        [bundle_path, directory] => (bundle_path, Some(directory.clone())),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let Some(directory) = directory else {
+        eprintln!("ignition-cache: no cache directory (set IGNITION_CACHE_DIR)");
+        return ExitCode::from(2);
+    };
+    let result = match lockfile {
+        Some(lockfile) => Ok(lockfile),
+        None => Lockfile::path(),
+    }
+    .and_then(|lockfile| transfer(&directory, &lockfile, bundle_path));
+    match result {
+        Ok(manifest) => {
+            println!(
+                "{}: {} archives",
+                bundle_path.display(),
+                manifest.archives.len()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parse the arguments of a report (`[--json] [<directory>]`): whether to report as JSON, and the cache directory
+/// (the machine-global cache by default). Error is the exit status on usage errors.
+fn report_args(args: &[String]) -> Result<(bool, PathBuf), ExitCode> {
+    let mut json = false;
+    let mut directory = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if !arg.starts_with('-') && directory.is_none() => {
+                directory = Some(PathBuf::from(arg))
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return Err(ExitCode::from(2));
+            }
+        }
+    }
+    let Some(directory) = directory.or_else(cache::global_dir) else {
+        eprintln!("ignition-cache: no cache directory (set IGNITION_CACHE_DIR)");
+        return Err(ExitCode::from(2));
+    };
+    Ok((json, directory))
+}
+
+/// Print <report> as JSON. Exit status is 1 on errors.
+fn print_json(report: &impl serde::Serialize) -> ExitCode {
+    match serde_json::to_string_pretty(report) {
+        Ok(contents) => {
+            println!("{}", contents);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// List the recorded archives of the cache at the directory of <args> (the machine-global cache by default) with
+/// their provenance, as JSON with `--json`, see `cache::manifest`.
+///
+/// Exit status is 1 on errors, 2 on usage errors.
+fn manifest(args: &[String]) -> ExitCode {
+    let (json, directory) = match report_args(args) {
+        Ok(report_args) => report_args,
+        Err(code) => return code,
+    };
+    let manifest = match cache::manifest(&directory) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if json {
+        return print_json(&manifest);
+    }
+    for archive in manifest.archives.iter() {
+        let record = &archive.record;
+        println!(
+            "{}: {} sha256 {} from {} (retrieved {}, ignition {})",
+            archive.path.display(),
+            record.asset.as_deref().unwrap_or("(unknown asset)"),
+            record.sha256,
+            record.url,
+            record
+                .retrieved
+                .map_or("(unknown)".to_string(), |retrieved| retrieved.to_string()),
+            record.ignition_version.as_deref().unwrap_or("(unknown)")
+        );
+    }
+    println!(
+        "{}: {} recorded archives",
+        directory.display(),
+        manifest.archives.len()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Report the statistics of the cache at the directory of <args> (the machine-global cache by default), as JSON
+/// with `--json`, see `gc::stats`.
+///
+/// Exit status is 1 on errors, 2 on usage errors.
+fn stats(args: &[String]) -> ExitCode {
+    let (json, directory) = match report_args(args) {
+        Ok(report_args) => report_args,
+        Err(code) => return code,
+    };
+    let stats = match gc::stats(&directory) {
+        Ok(stats) => stats,
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if json {
+        return print_json(&stats);
+    }
+    let assets = stats
+        .assets
+        .iter()
+        .map(|(asset, usage)| (asset.as_str(), usage));
+    for (asset, usage) in assets.chain([("(other)", &stats.other)]) {
+        if usage.entries.is_empty() {
+            continue;
+        }
+        println!(
+            "{}: {} bytes, {} entries, last used {}",
+            asset,
+            usage.size,
+            usage.entries.len(),
+            usage.last_used
+        );
+    }
+    println!(
+        "{}: {} bytes, {} entries",
+        directory.display(),
+        stats.size,
+        stats.entries
+    );
+    ExitCode::SUCCESS
+}
+
+/// Verify the cache at the directory of <args> (the machine-global cache by default), see `cache::verify_cache`.
+///
+/// Exit status is 1 if corrupt entries remain (not repaired), 2 on errors.
+fn verify(args: &[String]) -> ExitCode {
+    let mut repair = CacheRepair::Report;
+    let mut directory = None;
+    for arg in args {
+        match arg.as_str() {
+            "--delete" => repair = CacheRepair::Delete,
+            "--refetch" => repair = CacheRepair::Refetch,
+            _ if !arg.starts_with('-') && directory.is_none() => {
+                directory = Some(PathBuf::from(arg))
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(directory) = directory.or_else(cache::global_dir) else {
+        eprintln!("ignition-cache: no cache directory (set IGNITION_CACHE_DIR)");
+        return ExitCode::from(2);
+    };
+    match cache::verify_cache(&directory, repair) {
+        Ok(verification) => {
+            println!(
+                "{}: {} verified, {} corrupt, {} repaired, {} skipped",
+                directory.display(),
+                verification.verified.len(),
+                verification.corrupt.len(),
+                verification.repaired.len(),
+                verification.skipped.len()
+            );
+            match verification.corrupt.len() > verification.repaired.len() {
+                true => ExitCode::FAILURE,
+                false => ExitCode::SUCCESS,
+            }
+        }
+        Err(err) => {
+            eprintln!("ignition-cache: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
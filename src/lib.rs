@@ -4,15 +4,25 @@
 // This is shared during execution of `ignition/build.rs` and dependent `build.rs` scripts.
 //
 
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+pub mod retrieval;
 
 use serde::Deserialize;
-use serde_json::from_str;
 use thiserror::Error;
 
 // `config/environment.json` not available at runtime, so need to include contents as a string
 const ENVIRONMENT_CONFIG: &str = include_str!("../config/environment.json");
 const METADATA_KEY_PREFIX: &str = "DEP_IGNITION_SYS_";
+const DIGEST_KEY_SUFFIX: &str = "_SHA256";
+const ASSET_KEY_PREFIX: &str = "IGNITION_";
+const VERSION_KEY_SUFFIX: &str = "_VERSION";
+const VARIANT_KEY_SUFFIX: &str = "_VARIANT";
+const FEATURE_KEY_PREFIX: &str = "CARGO_FEATURE_";
 
 /// Error type for Ignition functions.
 #[derive(Error, Clone, Debug)]
@@ -24,8 +34,110 @@ pub enum IgnitionError {
     #[error("environment variable error: {0}")]
     EnvironmentVariableError(#[from] std::env::VarError),
     /// Any error arising from usage of serde_json for deserializing configuration string.
-    #[error("failed to deserialize configuration string: {0}")]
+    #[error("failed to deserialize configuration string: {}", retrieval::redact::redact(.0))]
     ConfigurationDeserializationError(String),
+    /// Target triplet has no matching build in `config/target.json`.
+    #[error("unsupported target: {0}")]
+    UnsupportedTargetError(String),
+    /// Any error arising from an asset download request.
+    #[error("failed to download asset: {}", retrieval::redact::redact(.0))]
+    DownloadError(String),
+    /// Unexpected HTTP status code in response to an asset download request.
+    #[error("failed to download asset: http status {0}")]
+    HttpStatusError(u16),
+    /// Any error arising from extraction of an asset archive.
+    #[error("failed to extract asset archive: {}", retrieval::redact::redact(.0))]
+    ExtractionError(String),
+    /// Any filesystem error arising during asset retrieval, with its kind.
+    #[error("io error: {}", retrieval::redact::redact(.1))]
+    IoError(std::io::ErrorKind, String),
+    /// Missing or unusable credentials for an authenticated asset source.
+    #[error("credential error: {}", retrieval::redact::redact(.0))]
+    CredentialError(String),
+    /// Invalid configuration value in the manifest or an environment variable.
+    #[error("invalid configuration: {}", retrieval::redact::redact(.0))]
+    ConfigurationError(String),
+    /// Checksum of a downloaded file doesn't match the expected checksum.
+    #[error("checksum mismatch: {path}: expected {expected}, actual {actual}")]
+    ChecksumMismatch {
+        /// Path of the downloaded file.
+        path: String,
+        /// Expected checksum.
+        expected: String,
+        /// Actual checksum of the file.
+        actual: String,
+    },
+    /// Decryption of an encrypted asset archive failed.
+    #[error("decryption failed: {}", retrieval::redact::redact(.0))]
+    DecryptionError(String),
+    /// Expected content of an asset is missing after extraction.
+    #[error("missing asset content: {0}")]
+    MissingContent(String),
+    /// Archive entry would be written outside of the extraction directory (e.g. zip-slip).
+    #[error("unsafe archive entry in {archive}: {entry}: {reason}")]
+    UnsafeArchiveEntry {
+        /// Path of the archive.
+        archive: String,
+        /// Path of the entry within the archive.
+        entry: String,
+        /// Why the entry is unsafe.
+        reason: String,
+    },
+    /// Download or extracted size of an asset exceeds its limit.
+    #[error("size limit exceeded: {}", retrieval::redact::redact(.0))]
+    SizeLimitExceeded(String),
+    /// Asset archive rejected by the scan hook or command.
+    #[error("scan rejected asset: {}", retrieval::redact::redact(.0))]
+    ScanRejected(String),
+    /// Post-extract hook of an asset failed, or left the asset directory.
+    #[error("post-extract hook failed: {}", retrieval::redact::redact(.0))]
+    HookFailed(String),
+    /// Patch of an asset missing, invalid or not applying to the extracted asset.
+    #[error("failed to apply patch: {}", retrieval::redact::redact(.0))]
+    PatchFailed(String),
+    /// Missing or invalid signature of a downloaded file.
+    #[error("signature verification failed: {}", retrieval::redact::redact(.0))]
+    SignatureError(String),
+    /// Missing or invalid provenance attestation of a downloaded file.
+    #[error("provenance verification failed: {}", retrieval::redact::redact(.0))]
+    ProvenanceError(String),
+    /// Asset url or connection to a host outside of the allowlist.
+    #[error("host not allowed: {}", retrieval::redact::redact(.0))]
+    HostNotAllowed(String),
+    /// Retrieved asset doesn't match the lockfile (`IGNITION_LOCKED=true`).
+    #[error("lockfile mismatch: {}", retrieval::redact::redact(.0))]
+    LockfileMismatch(String),
+    /// Lock of an asset held by another build for longer than the lock timeout.
+    #[error("timed out waiting for lock: {}", retrieval::redact::redact(.0))]
+    LockTimeout(String),
+    /// Manifest invalid against the schema of assets, with the location of each issue (see `retrieval::schema`).
+    #[error("invalid manifest: {}", retrieval::redact::redact(.0))]
+    InvalidManifest(String),
+    /// Network access refused in offline mode (`IGNITION_OFFLINE`), e.g. to retrieve an asset missing in the cache.
+    #[error("offline: {}", retrieval::redact::redact(.0))]
+    Offline(String),
+}
+
+impl IgnitionError {
+    /// Determine if the error is (likely) transient, so the failed operation is worth retrying.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            IgnitionError::HttpStatusError(status) => {
+                *status >= 500 || *status == 408 || *status == 429
+            }
+            IgnitionError::DownloadError(_) => true,
+            // network failures only, not e.g. missing files or denied permissions
+            IgnitionError::IoError(kind, _) => matches!(
+                kind,
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
 }
 
 /// Convert serde_json::Error to IgnitionError.
@@ -35,8 +147,28 @@ impl From<serde_json::Error> for IgnitionError {
     }
 }
 
+/// Convert std::io::Error to IgnitionError.
+impl From<std::io::Error> for IgnitionError {
+    fn from(err: std::io::Error) -> Self {
+        IgnitionError::IoError(err.kind(), err.to_string())
+    }
+}
+
+/// Convert ureq::Error to IgnitionError.
+impl From<ureq::Error> for IgnitionError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::StatusCode(status) => IgnitionError::HttpStatusError(status),
+            ureq::Error::Other(err) if err.is::<retrieval::allowlist::HostNotAllowed>() => {
+                IgnitionError::HostNotAllowed(err.to_string())
+            }
+            err => IgnitionError::DownloadError(err.to_string()),
+        }
+    }
+}
+
 /// Environment configuration for a particular asset.
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct AssetEnvironment {
     /// List of contents expected on extaction of asset archive.
     #[serde(default)]
@@ -44,11 +176,478 @@ pub struct AssetEnvironment {
     /// Mapping of contents to their corresponding environment variables.
     #[serde(default)]
     pub environment: HashMap<String, String>,
+    /// Overrides of `contents` and `environment` per target, keyed by target triplet (e.g.
+    /// `x86_64-pc-windows-msvc`) or operating system (e.g. `windows`), see `AssetEnvironment::for_target`.
+    #[serde(default)]
+    pub targets: HashMap<String, AssetTargetEnvironment>,
+    /// Additional files (e.g. models) as mapping of content paths to source urls (any supported scheme, e.g.
+    /// `hf://<org>/<repo>@<revision>/<path>`), installed next to the extracted asset archive.
+    #[serde(default)]
+    pub files: HashMap<String, String>,
+    /// Asset version, substituted for `{version}` in the url template (part of the default bucket layout of
+    /// versioned assets) and namespacing cache entries and extractions (see `retrieval::AssetLocation`), overridden
+    /// by `IGNITION_<ASSET>_VERSION` (see `version_key`) and checked against the lockfile (see
+    /// `retrieval::lockfile`).
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Asset variant (e.g. `cuda`), one of `variants`: the default variant, overridden by the variant of an enabled
+    /// cargo feature, then by `IGNITION_<ASSET>_VARIANT` (see `AssetEnvironment::select_variant`). The selected
+    /// variant is substituted for `{asset_variant}` in the url template (part of the default bucket layout of
+    /// assets with variants), namespaces cache entries and extractions (see `retrieval::AssetLocation`) and is
+    /// checked against the lockfile (see `retrieval::lockfile`).
+    #[serde(default)]
+    pub variant: Option<String>,
+    /// Named variants of the asset (e.g. `cpu` and `cuda` builds), with their overrides of the asset's configuration,
+    /// see `AssetVariant`.
+    #[serde(default)]
+    pub variants: HashMap<String, AssetVariant>,
+    /// Url template of the asset archive (default `{bucket}/{directory}/{asset}/{platform}/{file}`, with
+    /// `/{version}` then `/{asset_variant}` after `{asset}` for versioned assets and assets with a variant), see
+    /// `retrieval::AssetLocation`.
+    #[serde(default)]
+    pub url_template: Option<String>,
+    /// SHA-256 checksums of the asset archive per target, keyed by bucket path
+    /// (`<os>/<arch>[/<environment>][/<variant>]`), verified before extraction.
+    #[serde(default)]
+    pub sha256: HashMap<String, String>,
+    /// Expected SHA-512 checksums of the archive, keyed as `sha256`.
+    #[serde(default)]
+    pub sha512: HashMap<String, String>,
+    /// Expected BLAKE3 checksums of the archive, keyed as `sha256` (preferred for multi-GB archives).
+    #[serde(default)]
+    pub blake3: HashMap<String, String>,
+    /// Format of the archive (`tar.gz`, `tar.zst`, `tar.xz`, `zip` or `7z`), determining its file extension
+    /// (default `tar.gz`), or `raw` for a single file (e.g. an `.onnx` model) downloaded and installed as is.
+    /// Archives are extracted according to their magic bytes, whatever their format or url.
+    #[serde(default)]
+    pub format: retrieval::extract::ArchiveFormat,
+    /// Path of the file of a `raw` asset within its directory (default the asset name), e.g. `model.onnx`, its
+    /// file name naming it in the bucket (`{file}`). `contents` name it as `<asset>/<path>`.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Number of parts of an archive published split (`<file>.part01` to `<file>.partNN` next to the url of the
+    /// archive), e.g. for hosts capping object sizes, joined once downloaded, see `retrieval::split`. 0 (default)
+    /// for archives published whole.
+    #[serde(default)]
+    pub parts: usize,
+    /// Number of leading path components stripped from archive entries on extraction (e.g. 1 for archives nesting
+    /// everything under `<name>-<version>/`).
+    #[serde(default)]
+    pub strip_components: usize,
+    /// Glob patterns of the archive entries extracted (after `strip_components`), e.g. `opencv/include` or
+    /// `opencv/lib/*.so*`, all entries if empty.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Glob patterns of the archive entries not extracted (e.g. `opencv/share/opencv4/samples`).
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// How symbolic links of the archive are extracted: `preserve`, `dereference` (copied) or `skip` (default
+    /// `dereference` on Windows, otherwise `preserve`).
+    #[serde(default)]
+    pub symlinks: Option<retrieval::extract::SymlinkPolicy>,
+    /// How the asset is installed into the asset directory: `extract` (default), or `copy` (for isolation),
+    /// `symlink` (for speed), `hardlink` (for deduplication) / `reflink` from an extraction in the cache shared by
+    /// builds, see `retrieval::install`.
+    #[serde(default)]
+    pub install: Option<retrieval::install::InstallMode>,
+    /// Glob patterns of the installed files marked executable (relative to the asset directory, as `include`),
+    /// e.g. `bin/opencv_version` for tools of zip archives, which don't preserve unix permissions.
+    #[serde(default)]
+    pub executable: Vec<String>,
+    /// Commands fixing up the extracted asset (e.g. `install_name_tool -id @rpath/libfoo.dylib lib/libfoo.dylib`),
+    /// run in order within the asset directory before `contents` are verified, see `retrieval::hooks::run`. With
+    /// `hardlink` installs, hooks must replace files (as `install_name_tool` does) rather than modify them in place.
+    #[serde(default)]
+    pub post_extract: Vec<String>,
+    /// Unified diffs (e.g. `patches/opencv-cmake.patch`, relative to the package of the build script) applied in
+    /// order to the extracted asset before its `post_extract` hooks, see `retrieval::patch::apply`.
+    #[serde(default)]
+    pub patches: Vec<String>,
+    /// Encryption of the archive at rest in the bucket (`age` or `aes-256-gcm`), decrypted before extraction.
+    #[serde(default)]
+    pub encryption: Option<retrieval::encryption::Encryption>,
+    /// SHA-256 checksums of files of the asset, keyed by path (as `contents`), verified after extraction
+    /// (`sha512:<hex digest>` or `blake3:<hex digest>` for other algorithms).
+    #[serde(default)]
+    pub content_sha256: HashMap<String, String>,
+    /// Hosts allowed to download from (`*.<domain>` for subdomains), unrestricted if empty, see
+    /// `retrieval::allowlist`.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Fallback bucket urls, tried in order after `IGNITION_BUCKET_URL` fails.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// SPKI pins (`sha256/<base64>`) per host, applied to all TLS connections to the host.
+    #[serde(default)]
+    pub pins: HashMap<String, Vec<String>>,
+    /// Refuse plain HTTP urls and redirects for the asset (as `IGNITION_REQUIRE_TLS=true` for all assets).
+    #[serde(default)]
+    pub require_tls: bool,
+    /// Trusted OpenPGP public keys (ASCII-armored, or key file paths), optionally with a validity window: if any
+    /// (or `IGNITION_TRUSTED_KEYS` is set), the archive must have a valid detached signature (`<url>.asc`) by one
+    /// of them, see `retrieval::signature::TrustedKey`. A validity window is checked against the signing time
+    /// recorded in the signature, which the signer controls.
+    #[serde(default)]
+    pub trusted_keys: Vec<retrieval::signature::TrustedKey>,
+    /// Minisign public key (as in `minisign.pub`): if set, the archive must have a valid minisign signature
+    /// (`<url>.minisig`) by it (or one of `minisign_keys`).
+    #[serde(default)]
+    pub minisign_key: Option<String>,
+    /// Additional minisign public keys, optionally with a validity window (e.g. current and previous keys while a
+    /// publisher rotates keys), see `retrieval::signature::TrustedKey`.
+    #[serde(default)]
+    pub minisign_keys: Vec<retrieval::signature::TrustedKey>,
+    /// Sigstore verification policy: if set, the archive (or OCI artifact) must have a valid keyless cosign
+    /// signature by a matching identity.
+    #[serde(default)]
+    pub sigstore: Option<retrieval::signature::SigstorePolicy>,
+    /// SLSA provenance policy: if set (required with `IGNITION_REQUIRE_PROVENANCE=true`), the archive must have a
+    /// provenance attestation by the expected builder from the expected source repository.
+    #[serde(default)]
+    pub provenance: Option<retrieval::provenance::ProvenancePolicy>,
+    /// Download the archive over BitTorrent first (`<url>.torrent`, with the bucket urls as web seeds), e.g. for
+    /// multi-GB assets.
+    #[serde(default)]
+    pub torrent: bool,
+    /// Revalidate the cached archive on every retrieval (for mutable urls, e.g. `latest/`).
+    #[serde(default)]
+    pub revalidate: bool,
+    /// Time to live of the cached archive (e.g. `"7d"`, for mutable urls republished periodically), revalidated (or
+    /// fetched again) once cached for longer, overridden by `IGNITION_CACHE_TTL`, see `retrieval::expiry`.
+    #[serde(default)]
+    pub ttl: Option<String>,
+    /// Url of the organization-wide remote cache of the asset, resolved after the local cache and before upstream
+    /// sources, overridden by `IGNITION_REMOTE_CACHE_URL`, see `retrieval::remote`.
+    #[serde(default)]
+    pub remote_cache: Option<String>,
+    /// Push the archive and files retrieved from upstream sources to the `remote_cache` once verified, overridden by
+    /// `IGNITION_REMOTE_CACHE_PUSH`.
+    #[serde(default)]
+    pub remote_cache_push: bool,
+    /// Keep the archive encrypted at rest in the cache (as `IGNITION_CACHE_ENCRYPTION=true` for all assets), e.g.
+    /// for licensed assets on shared machines, decrypting it into the build directory only, with the key
+    /// `IGNITION_CACHE_KEY` or printed by `IGNITION_CACHE_KEY_COMMAND`, see `retrieval::encryption::CacheSeal`.
+    #[serde(default)]
+    pub encrypt_cache: bool,
+    /// Mark the extracted asset read-only after installation, verifying it before extracting it again (and on reuse
+    /// with `full` integrity checks), as `IGNITION_READ_ONLY=true` for all assets, see `retrieval::readonly`.
+    #[serde(default)]
+    pub read_only: bool,
+    /// How a complete extraction is checked before it is reused: `stamp`, `spot` (default, sizes and samples of
+    /// the files) or `full`, overridden by `IGNITION_INTEGRITY`, see `retrieval::integrity`.
+    #[serde(default)]
+    pub integrity: Option<retrieval::integrity::IntegrityCheck>,
+    /// Maximum download size of the asset archive (and each additional file), e.g. `"2G"`, overridden by
+    /// `IGNITION_MAX_DOWNLOAD_SIZE`.
+    #[serde(default)]
+    pub max_download_size: Option<String>,
+    /// Maximum total size of the extracted asset archive, e.g. `"8G"`, overridden by
+    /// `IGNITION_MAX_EXTRACTED_SIZE`.
+    #[serde(default)]
+    pub max_extracted_size: Option<String>,
+    /// Command scanning the asset archive before extraction (e.g. a malware scanner), overridden by
+    /// `IGNITION_SCAN_COMMAND`.
+    #[serde(default)]
+    pub scan_command: Option<String>,
+    /// SPDX license expression of the asset (e.g. `Apache-2.0`), for the SBOM.
+    #[serde(default)]
+    pub license: Option<String>,
+    /// Retry policy for retrieval of the asset, overridden by `IGNITION_RETRY_*` environment variables.
+    #[serde(default)]
+    pub retry: Option<retrieval::retry::RetryPolicy>,
+}
+
+/// Overrides of the environment configuration of an asset for a particular target (e.g. the libraries of a Windows
+/// build), see `AssetEnvironment::for_target`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AssetTargetEnvironment {
+    /// List of contents expected on extraction for the target, replacing the asset's `contents`.
+    #[serde(default)]
+    pub contents: Option<Vec<String>>,
+    /// Mapping of contents to their corresponding environment variables for the target, added to (or replacing
+    /// entries of) the asset's `environment`.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+}
+
+/// Variant of an asset (e.g. its `cuda` build), overriding the asset's configuration once selected, see
+/// `AssetEnvironment::select_variant`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AssetVariant {
+    /// Cargo feature selecting the variant, of the crate of the build script (e.g. `cuda`, enabled if
+    /// `CARGO_FEATURE_CUDA` is set).
+    #[serde(default)]
+    pub feature: Option<String>,
+    /// Url template of the archive of the variant, replacing the asset's `url_template`.
+    #[serde(default)]
+    pub url_template: Option<String>,
+    /// List of contents expected on extraction of the variant, replacing the asset's `contents`.
+    #[serde(default)]
+    pub contents: Option<Vec<String>>,
+    /// Mapping of contents to their corresponding environment variables for the variant, added to (or replacing
+    /// entries of) the asset's `environment`.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Overrides of the variant per target, replacing the asset's sections of the same targets.
+    #[serde(default)]
+    pub targets: HashMap<String, AssetTargetEnvironment>,
+    /// SHA-256 checksums of the archive of the variant per target, added to (or replacing entries of) the asset's
+    /// `sha256`.
+    #[serde(default)]
+    pub sha256: HashMap<String, String>,
+    /// SHA-512 checksums of the archive of the variant, as `sha256`.
+    #[serde(default)]
+    pub sha512: HashMap<String, String>,
+    /// BLAKE3 checksums of the archive of the variant, as `sha256`.
+    #[serde(default)]
+    pub blake3: HashMap<String, String>,
+}
+
+impl AssetEnvironment {
+    /// Select the variant of <asset> and apply its overrides (see `AssetVariant`) over the asset's configuration:
+    /// `IGNITION_<ASSET>_VARIANT` if set (see `variant_key`), otherwise the variant of the enabled cargo feature
+    /// (e.g. `cuda` for `--features cuda`), otherwise the default `variant`, otherwise none. Variants of several
+    /// enabled features (e.g. unified by Cargo across dependent crates) are an error, resolved by the variable.
+    pub fn select_variant(&mut self, asset: &str) -> IgnitionResult<()> {
+        let key = variant_key(asset);
+        let mut featured: Vec<&String> = self
+            .variants
+            .iter()
+            .filter(|(_, variant)| variant.feature.as_deref().is_some_and(is_feature_enabled))
+            .map(|(name, _)| name)
+            .collect();
+        featured.sort();
+        let selected = match retrieval::env_value::<String>(&key)?.filter(|name| !name.is_empty()) {
+            Some(name) => Some(name),
+            None => match featured.as_slice() {
+                [] => self.variant.take(),
+                [name] => Some(name.to_string()),
+                names => {
+                    return Err(IgnitionError::ConfigurationError(format!(
+                        "{}: variants {} selected by cargo features (set {})",
+                        asset,
+                        names
+                            .iter()
+                            .map(|name| name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        key
+                    )));
+                }
+            },
+        };
+        let mut variants = std::mem::take(&mut self.variants);
+        let Some(name) = selected else {
+            self.variant = None;
+            return Ok(());
+        };
+        let Some(variant) = variants.remove(&name).filter(|_| is_path_component(&name)) else {
+            let mut names: Vec<String> = variants.into_keys().collect();
+            names.sort();
+            return Err(IgnitionError::ConfigurationError(format!(
+                "{}: unknown variant {:?} (variants: {}, see {})",
+                asset,
+                name,
+                match names.is_empty() {
+                    true => "none".to_string(),
+                    false => names.join(", "),
+                },
+                key
+            )));
+        };
+        if variant.url_template.is_some() {
+            self.url_template = variant.url_template;
+        }
+        if let Some(contents) = variant.contents {
+            self.contents = contents;
+        }
+        self.environment.extend(variant.environment);
+        self.targets.extend(variant.targets);
+        self.sha256.extend(variant.sha256);
+        self.sha512.extend(variant.sha512);
+        self.blake3.extend(variant.blake3);
+        self.variant = Some(name);
+        Ok(())
+    }
+
+    /// Resolve the per-target overrides (`targets`) of the asset for the target <triplet>: the section of its
+    /// operating system (e.g. `windows`), then the more specific section of the triplet itself, are applied over
+    /// the asset's `contents` and `environment`.
+    pub fn for_target(mut self, triplet: &str) -> Self {
+        let os = retrieval::target::TargetTriplet::parse(triplet)
+            .ok()
+            .map(|target_triplet| target_triplet.operating_system().to_string());
+        for key in os.iter().map(String::as_str).chain([triplet]) {
+            let Some(section) = self.targets.remove(key) else {
+                continue;
+            };
+            if let Some(contents) = section.contents {
+                self.contents = contents;
+            }
+            self.environment.extend(section.environment);
+        }
+        self
+    }
 }
 
 /// Result type for Ignition functions.
 pub type IgnitionResult<T, E = IgnitionError> = std::result::Result<T, E>;
 
+/// Manifest parsed by `parsed_manifest`, reset when assets are declared (see `retrieval::declared::declare`).
+static MANIFEST: Mutex<Option<ParsedManifest>> = Mutex::new(None);
+
+/// Manifest merged and validated once per build script (see `manifest_value`): the entries of the assets as JSON,
+/// and their environment configuration.
+#[derive(Clone)]
+struct ParsedManifest {
+    entries: Arc<HashMap<String, serde_json::Value>>,
+    assets: Arc<HashMap<String, AssetEnvironment>>,
+}
+
+/// Retrieve the environment configuration of all assets: the embedded `config/environment.json`, merged with the
+/// project, local and override manifests (if any), see `manifest_value`. Asset versions are those of
+/// `IGNITION_<ASSET>_VERSION` if set (see `version_key`), so consumers select (or pin) versions without editing the
+/// manifest, and the variant of each asset is selected (see `AssetEnvironment::select_variant`). Versions and
+/// variants are path components of cache entries and extractions, so they are limited to ASCII alphanumerics, `.`,
+/// `_`, `+` and `-`.
+///
+/// The manifest is only read, merged and validated on the first call (and again once assets are declared).
+pub fn manifest() -> IgnitionResult<Arc<HashMap<String, AssetEnvironment>>> {
+    Ok(parsed_manifest()?.assets)
+}
+
+/// Retrieve the parsed manifest, parsing it if it wasn't yet (or assets were declared since), see `manifest`.
+fn parsed_manifest() -> IgnitionResult<ParsedManifest> {
+    // held while parsing, so declarations meanwhile reset the manifest once parsed (see `reset_manifest`)
+    let mut parsed = MANIFEST.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(parsed) = parsed.as_ref() {
+        return Ok(parsed.clone());
+    }
+    let value = manifest_value()?;
+    let entries: HashMap<String, serde_json::Value> = serde_json::from_value(value.clone())?;
+    let mut manifest: HashMap<String, AssetEnvironment> = serde_json::from_value(value)?;
+    for (asset, asset_cfg) in manifest.iter_mut() {
+        let key = version_key(asset);
+        if let Some(version) =
+            retrieval::env_value::<String>(&key)?.filter(|version| !version.is_empty())
+        {
+            asset_cfg.version = Some(version);
+        }
+        if let Some(version) = asset_cfg.version.as_deref()
+            && !is_path_component(version)
+        {
+            return Err(IgnitionError::ConfigurationError(format!(
+                "{}: invalid version {:?} (see {})",
+                asset, version, key
+            )));
+        }
+        asset_cfg.select_variant(asset)?;
+    }
+    Ok(parsed
+        .insert(ParsedManifest {
+            entries: Arc::new(entries),
+            assets: Arc::new(manifest),
+        })
+        .clone())
+}
+
+/// Reset the parsed manifest, so the next call to `manifest` parses it again (e.g. with newly declared assets).
+fn reset_manifest() {
+    *MANIFEST.lock().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Determine if <value> is usable as a path component of cache entries and extractions (e.g. a version).
+fn is_path_component(value: &str) -> bool {
+    !matches!(value, "" | "." | "..")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-'))
+}
+
+/// Determine if the cargo <feature> of the crate of the build script is enabled (`CARGO_FEATURE_<FEATURE>` set).
+fn is_feature_enabled(feature: &str) -> bool {
+    std::env::var_os(
+        FEATURE_KEY_PREFIX.to_string() + &feature.to_ascii_uppercase().replace('-', "_"),
+    )
+    .is_some()
+}
+
+/// Retrieve the manifest as JSON: the embedded `config/environment.json`, with the assets declared by the build script
+/// (see `retrieval::declared`), then the layers of the manifest merged over it in order (see `retrieval::layers`):
+/// the project manifest (`ignition.toml`), the developer-local manifest (`ignition.local.toml`) and the override
+/// manifest at `IGNITION_ENVIRONMENT_CONFIG` (JSON, TOML or YAML by extension, see `retrieval::format`), so
+/// downstream users can add or modify assets without forking. Objects are merged recursively (e.g. an asset's
+/// `sha256` for one more platform, or its `environment`), other values (e.g. `contents`) replaced, and `null` values
+/// remove their entry (e.g. an asset as a whole, in JSON or YAML: TOML has no null). The merged manifest is validated
+/// against the schema of assets, issues being reported with their location within the manifests (see
+/// `retrieval::schema::validate`).
+fn manifest_value() -> IgnitionResult<serde_json::Value> {
+    let mut manifest = retrieval::format::ManifestFormat::Json
+        .parse(ENVIRONMENT_CONFIG, "config/environment.json")?;
+    let mut sources = vec![retrieval::schema::ManifestSource {
+        origin: "config/environment.json".to_string(),
+        contents: Some(ENVIRONMENT_CONFIG.to_string()),
+    }];
+    // assets declared by the build script of a dependent crate, see `retrieval::declared`
+    for (declared, source) in retrieval::declared::manifests() {
+        merge(&mut manifest, declared);
+        sources.push(source);
+    }
+    for layer in retrieval::layers::layers()? {
+        let contents = layer.read()?;
+        let origin = layer.path.display().to_string();
+        let overrides =
+            retrieval::format::ManifestFormat::from_path(&layer.path).parse(&contents, &origin)?;
+        merge(&mut manifest, overrides);
+        sources.push(retrieval::schema::ManifestSource {
+            origin,
+            contents: Some(contents),
+        });
+    }
+    retrieval::schema::validate(&manifest, &sources)?;
+    Ok(manifest)
+}
+
+/// Merge <overrides> over <value>: objects are merged recursively, `null` values removing their entry, and other
+/// values replaced.
+fn merge(value: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (value, overrides) {
+        (serde_json::Value::Object(entries), serde_json::Value::Object(overrides)) => {
+            for (key, entry) in overrides {
+                match entry {
+                    serde_json::Value::Null => {
+                        entries.remove(&key);
+                    }
+                    entry => match entries.get_mut(&key) {
+                        Some(value) => merge(value, entry),
+                        None => {
+                            entries.insert(key, entry);
+                        }
+                    },
+                }
+            }
+        }
+        (value, overrides) => *value = overrides,
+    }
+}
+
+/// Retrieve the environment configuration for a particular asset.
+pub fn asset_config(asset: &str) -> IgnitionResult<AssetEnvironment> {
+    manifest()?
+        .get(asset)
+        .cloned()
+        .ok_or(IgnitionError::BadHashMapKeyError(asset.to_string()))
+}
+
+/// Retrieve the manifest entry of a particular asset as JSON (keys sorted), e.g. to fingerprint it.
+pub(crate) fn asset_entry(asset: &str) -> IgnitionResult<serde_json::Value> {
+    parsed_manifest()?
+        .entries
+        .get(asset)
+        .cloned()
+        .ok_or(IgnitionError::BadHashMapKeyError(asset.to_string()))
+}
+
 /// Determine environment variables for a particular asset.
 ///
 /// Assuming environment.json formatted as:
@@ -56,13 +655,58 @@ pub type IgnitionResult<T, E = IgnitionError> = std::result::Result<T, E>;
 /// {
 ///     ...
 ///     "asset": {
+///         "mirrors": [
+///             "https://mirror.example.com/bucket"
+///         ],
+///         "pins": {
+///             "mirror.example.com": ["sha256/<base64>"]
+///         },
 ///         "contents": [
 ///             "path/to/content1",
 ///             "path/to/content2"
 ///         ],
+///         "sha256": {
+///             "linux/x86_64": "<hex digest>"
+///         },
+///         "blake3": {
+///             "linux/aarch64": "<hex digest>"
+///         },
+///         "content_sha256": {
+///             "path/to/content1/lib.so": "<hex digest>"
+///         },
+///         "trusted_keys": [
+///             "path/to/publisher.asc",
+///             { "key": "path/to/previous.asc", "not_after": "2025-12-31" }
+///         ],
+///         "minisign_key": "<base64 public key>",
+///         "license": "Apache-2.0",
+///         "max_download_size": "2G",
+///         "max_extracted_size": "8G",
+///         "files": {
+///             "path/to/content2": "hf://org/repo@revision/model.onnx"
+///         },
 ///         "environment": {
 ///             "path/to/content1": "ENV_VAR1",
 ///             "path/to/content2": "ENV_VAR2"
+///         },
+///         "targets": {
+///             "windows": {
+///                 "contents": ["path/to/content3", "path/to/content2"],
+///                 "environment": {
+///                     "path/to/content3": "ENV_VAR1"
+///                 }
+///             }
+///         },
+///         "variant": "cpu",
+///         "variants": {
+///             "cpu": {},
+///             "cuda": {
+///                 "feature": "cuda",
+///                 "contents": ["path/to/content1", "path/to/content2", "path/to/content4"],
+///                 "environment": {
+///                     "path/to/content4": "ENV_VAR3"
+///                 }
+///             }
 ///         }
 ///     }
 ///     ...
@@ -80,14 +724,28 @@ pub type IgnitionResult<T, E = IgnitionError> = std::result::Result<T, E>;
 ///
 /// The optional <directory_path> parameter determines if these environment variables are set OR retrieved.
 /// In either case, the operation is blind -- set/get not validated, so possible to overwrite or return empty strings.
+/// Contents and their variables are those of the selected variant of the asset (see
+/// `AssetEnvironment::select_variant`) for the target of the build script (`TARGET`), i.e. with its `targets`
+/// overrides (see `AssetEnvironment::for_target`).
+/// Variables are only set for a complete extraction of the asset (see `retrieval::is_extracted`), never for the
+/// partial extraction of a cancelled build.
 pub fn environment_variables(
     asset: &str,
     directory_path: Option<&Path>,
 ) -> IgnitionResult<HashMap<String, String>> {
-    let env_cfg: HashMap<String, AssetEnvironment> = from_str(ENVIRONMENT_CONFIG)?;
-    let asset_cfg = env_cfg
-        .get(asset)
-        .ok_or(IgnitionError::BadHashMapKeyError(asset.to_string()))?;
+    // build scripts resolve the overrides of the target they build for
+    let asset_cfg = match std::env::var("TARGET") {
+        Ok(target) => asset_config(asset)?.for_target(&target),
+        Err(_) => asset_config(asset)?,
+    };
+    if let Some(directory_path) = directory_path
+        && !retrieval::is_extracted(&directory_path.join(asset))
+    {
+        return Err(IgnitionError::ExtractionError(format!(
+            "{}: incomplete extraction",
+            directory_path.join(asset).display()
+        )));
+    }
     let mut env_vars = HashMap::new();
     for cont in asset_cfg.contents.iter() {
         let env_var = asset_cfg
@@ -113,3 +771,120 @@ pub fn environment_variables(
     }
     Ok(env_vars)
 }
+
+/// Environment variable overriding the version of an asset (`IGNITION_<ASSET>_VERSION`, e.g.
+/// `IGNITION_OPENCV_VERSION`).
+pub fn version_key(asset: &str) -> String {
+    format!(
+        "{}{}{}",
+        ASSET_KEY_PREFIX,
+        asset.to_ascii_uppercase().replace('-', "_"),
+        VERSION_KEY_SUFFIX
+    )
+}
+
+/// Environment variable selecting the variant of an asset (`IGNITION_<ASSET>_VARIANT`, e.g.
+/// `IGNITION_ONNXRUNTIME_VARIANT`).
+pub fn variant_key(asset: &str) -> String {
+    format!(
+        "{}{}{}",
+        ASSET_KEY_PREFIX,
+        asset.to_ascii_uppercase().replace('-', "_"),
+        VARIANT_KEY_SUFFIX
+    )
+}
+
+/// Cargo metadata key of the SHA-256 digest of an asset's archive (`<ASSET>_SHA256`).
+pub fn digest_key(asset: &str) -> String {
+    asset.to_ascii_uppercase().replace('-', "_") + DIGEST_KEY_SUFFIX
+}
+
+/// Retrieve the SHA-256 digest of the archive of a particular asset in a dependent crate's build script, as
+/// exported in cargo metadata on retrieval (`DEP_IGNITION_SYS_<ASSET>_SHA256`).
+///
+/// Dependent crates can assert they link against the exact expected binaries, or pass the digest on to runtime
+/// code (e.g. with `cargo::rustc-env`).
+pub fn asset_digest(asset: &str) -> IgnitionResult<String> {
+    Ok(std::env::var(
+        METADATA_KEY_PREFIX.to_string() + &digest_key(asset),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AssetEnvironment, merge};
+
+    #[test]
+    fn merge_layers_recursively() {
+        let mut manifest = json!({
+            "opencv": {
+                "version": "4.10.0",
+                "contents": ["opencv/include", "opencv/lib"],
+                "sha256": { "linux/x86_64": "a" }
+            },
+            "onnxruntime": { "version": "1.20.0" }
+        });
+        merge(
+            &mut manifest,
+            json!({
+                "opencv": {
+                    "contents": ["opencv/lib"],
+                    "sha256": { "linux/aarch64": "b" }
+                },
+                "mylib": { "version": "1.2.0" }
+            }),
+        );
+        assert_eq!(
+            manifest,
+            json!({
+                "opencv": {
+                    "version": "4.10.0",
+                    "contents": ["opencv/lib"],
+                    "sha256": { "linux/x86_64": "a", "linux/aarch64": "b" }
+                },
+                "onnxruntime": { "version": "1.20.0" },
+                "mylib": { "version": "1.2.0" }
+            })
+        );
+    }
+
+    #[test]
+    fn merge_removes_null_entries() {
+        let mut manifest = json!({
+            "opencv": { "version": "4.10.0", "license": "Apache-2.0" },
+            "onnxruntime": { "version": "1.20.0" }
+        });
+        merge(
+            &mut manifest,
+            json!({ "opencv": { "license": null }, "onnxruntime": null, "missing": null }),
+        );
+        assert_eq!(manifest, json!({ "opencv": { "version": "4.10.0" } }));
+    }
+
+    #[test]
+    fn target_sections_apply_over_the_asset() {
+        let asset_cfg: AssetEnvironment = serde_json::from_value(json!({
+            "contents": ["lib", "include"],
+            "environment": { "lib": "LIB_DIR", "include": "INCLUDE_DIR" },
+            "targets": {
+                "windows": {
+                    "contents": ["bin", "include"],
+                    "environment": { "bin": "LIB_DIR" }
+                },
+                "x86_64-pc-windows-msvc": {
+                    "environment": { "include": "MSVC_INCLUDE_DIR" }
+                }
+            }
+        }))
+        .unwrap();
+        let linux = asset_cfg.clone().for_target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.contents, ["lib", "include"]);
+        assert_eq!(linux.environment["lib"], "LIB_DIR");
+        let windows = asset_cfg.for_target("x86_64-pc-windows-msvc");
+        assert_eq!(windows.contents, ["bin", "include"]);
+        assert_eq!(windows.environment["bin"], "LIB_DIR");
+        assert_eq!(windows.environment["include"], "MSVC_INCLUDE_DIR");
+    }
+}
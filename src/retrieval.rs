@@ -0,0 +1,1504 @@
+// retrieval.rs
+//
+// This module contains asset retrieval logic: target resolution, download, caching and extraction.
+// This replaces `scripts/asset.sh` and is usable from `ignition/build.rs` and dependent `build.rs` scripts.
+//
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AssetEnvironment, IgnitionError, IgnitionResult, asset_config, asset_entry, digest_key,
+};
+
+pub mod allowlist;
+pub mod audit;
+pub mod bundle;
+pub mod cache;
+pub mod checksum;
+pub mod declared;
+pub mod download;
+pub mod encryption;
+pub mod expiry;
+pub mod extract;
+pub mod format;
+pub mod gc;
+pub mod hooks;
+pub mod http;
+pub mod install;
+pub mod integrity;
+pub mod layers;
+pub mod licenses;
+pub mod lock;
+pub mod lockfile;
+pub mod metrics;
+pub mod offline;
+pub mod patch;
+pub mod progress;
+pub mod provenance;
+pub mod prune;
+pub mod readonly;
+pub mod redact;
+pub mod remote;
+pub mod rerun;
+pub mod retry;
+pub mod sbom;
+pub mod scan;
+pub mod schema;
+pub mod signature;
+pub mod source;
+pub mod split;
+pub mod target;
+pub mod template;
+pub mod throttle;
+pub mod tls;
+pub mod vendor;
+
+const REVALIDATE_VARIABLE: &str = "IGNITION_REVALIDATE";
+const TORRENT_VARIABLE: &str = "IGNITION_TORRENT";
+const MAX_DOWNLOAD_SIZE_VARIABLE: &str = "IGNITION_MAX_DOWNLOAD_SIZE";
+const MAX_EXTRACTED_SIZE_VARIABLE: &str = "IGNITION_MAX_EXTRACTED_SIZE";
+const REQUIRE_PROVENANCE_VARIABLE: &str = "IGNITION_REQUIRE_PROVENANCE";
+const REQUIRE_CHECKSUM_VARIABLE: &str = "IGNITION_REQUIRE_CHECKSUM";
+const READ_ONLY_VARIABLE: &str = "IGNITION_READ_ONLY";
+const STREAMING_EXTRACTION_VARIABLE: &str = "IGNITION_STREAMING_EXTRACTION";
+const DRY_RUN_VARIABLE: &str = "IGNITION_DRY_RUN";
+const BUCKET_URL_VARIABLE: &str = "IGNITION_BUCKET_URL";
+const CACHE_PATH_VARIABLE: &str = "IGNITION_CACHE_PATH";
+const DIRECTORY_PATH_VARIABLE: &str = "IGNITION_DIRECTORY_PATH";
+/// Cache path (relative to the current directory) without a machine-global cache, as the build script's.
+const DEFAULT_CACHE_PATH: &str = "cache";
+/// Directory path of assets, as the build script's.
+const DEFAULT_DIRECTORY_PATH: &str = "assets/dependencies";
+
+/// File extension of (tar.gz) asset archive files.
+pub const FILE_EXTENSION: &str = ".tar.gz";
+/// File extension of the stamp of a complete extraction, next to it (see `is_extracted`).
+pub const STAMP_EXTENSION: &str = ".stamp";
+
+/// Source urls and destination paths of a particular asset for a resolved target.
+pub struct AssetLocation {
+    /// Remote urls of the asset archive file, one per bucket url (primary first, then mirrors).
+    pub urls: Vec<String>,
+    /// Local path of the cached asset archive file.
+    pub archive_path: PathBuf,
+    /// Local directory the asset archive is extracted into.
+    pub extract_path: PathBuf,
+    /// Local directory of the extraction directories of the target (and version), which `contents` paths are
+    /// relative to.
+    pub target_path: PathBuf,
+}
+
+impl AssetLocation {
+    /// Determine the location of an asset for a resolved target.
+    ///
+    /// Layout matches the bucket layout (for each of <bucket_urls>), unless the asset defines a `url_template`:
+    /// ```text
+    /// url:         <bucket_url>/<directory>/<asset>[/<version>][/<asset_variant>]/<os>/<arch>[/<environment>][/<variant>]/<asset>.tar.gz
+    /// archive:     <root>/<cache>/<directory>/<asset>[/<version>][/<asset_variant>]/<os>/<arch>[/<environment>][/<variant>]/<asset>.tar.gz
+    /// extraction:  <root>/<directory>/<triplet>[/<variant>][/<version>][/<asset_variant>]/<asset>
+    /// ```
+    /// Cache entries and extractions are namespaced by target, asset `version` and asset `variant` (e.g. `cuda`, not
+    /// to be confused with the `<variant>` of the target, e.g. a Jetpack release), so builds for several targets (or
+    /// of several versions or variants) sharing the root and cache coexist rather than overwrite each other.
+    /// The cache path may be absolute, e.g. the machine-global cache shared by builds (see `cache::global_path`).
+    /// Archives of another `format` carry its extension (e.g. `<asset>.zip`), encrypted archives (`encryption`)
+    /// the extension of their encryption (e.g. `<asset>.tar.gz.age`). Files of `raw` assets are named by their
+    /// `path` (e.g. `model.onnx`), and installed at it within the extraction directory.
+    ///
+    /// Url template placeholders:
+    /// ```text
+    /// {bucket}       bucket url (without trailing '/')
+    /// {directory}    directory path
+    /// {asset}        asset name
+    /// {version}      asset version
+    /// {asset_variant}
+    ///                asset variant (e.g. cuda)
+    /// {target}       target triplet (e.g. x86_64-unknown-linux-gnu)
+    /// {platform}     <os>/<arch>[/<environment>][/<variant>]
+    /// {os}, {arch}, {environment}, {variant}
+    ///                bucket path components of the target (environment and variant possibly empty)
+    /// {file}         <asset>.tar.gz|.tar.zst|.tar.xz|.zip|.7z[.age|.enc], or the file name of a raw asset
+    /// ```
+    pub fn new(
+        bucket_urls: &[&str],
+        asset: &str,
+        asset_cfg: &AssetEnvironment,
+        root: &Path,
+        cache_path: &str,
+        directory_path: &str,
+        target: &target::ResolvedTarget,
+    ) -> IgnitionResult<Self> {
+        let raw_path = raw_path(asset, asset_cfg);
+        let file_name = format!(
+            "{}{}{}",
+            match asset_cfg.format {
+                extract::ArchiveFormat::Raw => raw_path
+                    .file_name()
+                    .map_or(asset.into(), |file_name| file_name.to_string_lossy()),
+                _ => asset.into(),
+            },
+            asset_cfg.format.extension(),
+            asset_cfg
+                .encryption
+                .map_or("", |encryption| encryption.extension())
+        );
+        let components = target.path_components();
+        let url_template = asset_cfg.url_template.as_deref().unwrap_or(
+            match (&asset_cfg.version, &asset_cfg.variant) {
+                (Some(_), Some(_)) => template::VERSIONED_VARIANT_URL_TEMPLATE,
+                (Some(_), None) => template::VERSIONED_URL_TEMPLATE,
+                (None, Some(_)) => template::VARIANT_URL_TEMPLATE,
+                (None, None) => template::DEFAULT_URL_TEMPLATE,
+            },
+        );
+        let urls = bucket_urls
+            .iter()
+            .map(|bucket_url| {
+                template::expand(url_template, |name| {
+                    Some(match name {
+                        "bucket" => bucket_url.trim_end_matches('/').to_string(),
+                        "directory" => directory_path.trim_matches('/').to_string(),
+                        "asset" => asset.to_string(),
+                        "version" => asset_cfg.version.clone()?,
+                        "asset_variant" => asset_cfg.variant.clone()?,
+                        "target" => target.triplet.clone(),
+                        "platform" => components.join("/"),
+                        "os" => target.os.clone(),
+                        "arch" => target.build.architecture_alias.clone(),
+                        "environment" => target.build.environment_alias.clone(),
+                        "variant" => target.build.variant_alias.clone(),
+                        "file" => file_name.clone(),
+                        _ => return None,
+                    })
+                })
+            })
+            .collect::<IgnitionResult<_>>()?;
+        let version = asset_cfg.version.as_deref();
+        let asset_variant = asset_cfg.variant.as_deref();
+        let mut archive_path = root.join(cache_path).join(directory_path).join(asset);
+        archive_path.extend(version);
+        archive_path.extend(asset_variant);
+        for component in components.iter() {
+            archive_path.push(component);
+        }
+        archive_path.push(&file_name);
+        let mut target_path = root.join(directory_path).join(&target.triplet);
+        target_path.extend(
+            Some(target.build.variant_alias.as_str()).filter(|variant| !variant.is_empty()),
+        );
+        target_path.extend(version);
+        target_path.extend(asset_variant);
+        let extract_path = target_path.join(asset);
+        Ok(AssetLocation {
+            urls,
+            archive_path,
+            extract_path,
+            target_path,
+        })
+    }
+}
+
+/// Retrieve an asset by name: resolve target, download archive into cache (if absent) and extract.
+///
+/// The retrieval (see `retrieve_resolved`) goes through the steps:
+/// - locate the archive: its urls (<bucket_url>, then the asset's `mirrors`) and cache entry (see `AssetLocation`);
+/// - fetch it into the cache unless cached: from the lower or remote cache (see `cache`, `remote`), otherwise
+///   downloaded (see `download_mirrored`), cached archives being revalidated or fetched again once expired (see
+///   `expiry`);
+/// - verify it before extraction: checksum, signatures, provenance and scan (see `checksum`, `signature`,
+///   `provenance`, `scan`), then the lockfile (see `lockfile::check`);
+/// - extract it into a staging directory, installed as a whole (see `extract`, `install`), then fix it up and verify
+///   its contents (`executable`, `patches`, `post_extract`, `contents`), and stamp it (see `is_extracted`);
+/// - record it: cargo metadata (see `asset_digest`), metrics, audit log, SBOM and licenses.
+///
+/// The optional <variant> parameter forces retrieval of a variant-specific build (e.g. "35" for Jetpack 5).
+/// Return is the directory the asset archive was extracted into.
+pub fn retrieve(
+    bucket_url: &str,
+    asset: &str,
+    root: &Path,
+    cache_path: &str,
+    directory_path: &str,
+    target: &str,
+    variant: Option<&str>,
+) -> IgnitionResult<PathBuf> {
+    retrieve_recorded(
+        bucket_url,
+        asset,
+        root,
+        cache_path,
+        directory_path,
+        target,
+        variant,
+        false,
+    )
+}
+
+/// Prefetch <assets> for <target> into the cache, without extracting them, e.g. in a CI step populating the cache
+/// before `cargo build`, so download failures are attributed to that step (and the cache to its own layer).
+///
+/// Assets are retrieved as by the build script (see `retrieve`), from `IGNITION_BUCKET_URL` into
+/// `IGNITION_CACHE_PATH` (relative to the current directory), otherwise the machine-global cache (see
+/// `cache::global_path`): archives and their additional `files` are downloaded (if absent) and verified, but
+/// neither extracted nor installed. Builds use the prefetched cache if they share it, i.e. by default, or with an
+/// absolute `IGNITION_CACHE_PATH`. From a standalone tool (see `ignition-cache prefetch`):
+/// ```text
+/// let archives = ignition::retrieval::prefetch(&["opencv", "onnxruntime"], "x86_64-unknown-linux-gnu")?;
+/// ```
+/// Return is the paths of the cached archives, in the order of <assets>.
+pub fn prefetch(assets: &[&str], target: &str) -> IgnitionResult<Vec<PathBuf>> {
+    let bucket_url = std::env::var(BUCKET_URL_VARIABLE).map_err(|err| {
+        IgnitionError::ConfigurationError(format!("{}: {}", BUCKET_URL_VARIABLE, err))
+    })?;
+    let cache_path = match env_value::<String>(CACHE_PATH_VARIABLE)? {
+        Some(cache_path) => cache_path,
+        None => cache::global_path(&bucket_url).map_or(DEFAULT_CACHE_PATH.to_string(), |path| {
+            path.to_string_lossy().into_owned()
+        }),
+    };
+    let directory_path = env_value::<String>(DIRECTORY_PATH_VARIABLE)?
+        .unwrap_or_else(|| DEFAULT_DIRECTORY_PATH.to_string());
+    let root = std::env::current_dir()?;
+    assets
+        .iter()
+        .map(|asset| {
+            println!("--prefetch: {} ({})", asset, target);
+            retrieve_recorded(
+                &bucket_url,
+                asset,
+                &root,
+                &cache_path,
+                &directory_path,
+                target,
+                None,
+                true,
+            )
+        })
+        .collect()
+}
+
+/// Retrieve an asset by name as `retrieve` (only into the cache with <prefetch>), recording metrics and auditing
+/// the retrieval.
+#[allow(clippy::too_many_arguments)]
+fn retrieve_recorded(
+    bucket_url: &str,
+    asset: &str,
+    root: &Path,
+    cache_path: &str,
+    directory_path: &str,
+    target: &str,
+    variant: Option<&str>,
+    prefetch: bool,
+) -> IgnitionResult<PathBuf> {
+    let mut asset_metrics = metrics::AssetMetrics::start(asset, target);
+    let result = target::resolve(target, variant).and_then(|resolved| {
+        retrieve_resolved(
+            bucket_url,
+            asset,
+            root,
+            cache_path,
+            directory_path,
+            &resolved,
+            prefetch,
+            &mut asset_metrics,
+        )
+    });
+    let audited = audit::record(&asset_metrics, &result);
+    let result = result.and_then(|path| audited.map(|_| path));
+    asset_metrics.record(&result);
+    result
+}
+
+/// Retrieve an asset by name for a <resolved> target, see `retrieve` (and `prefetch` with <prefetch>, returning
+/// the archive path), in stages (see `Retrieval`): the retrieval is resolved, then its archive fetched into the
+/// cache, verified and recorded in the cache, extracted, and the extraction post-processed.
+///
+/// Concurrent retrievals of the asset wait for each other (see `lock::PathLock`). A cached archive is revalidated
+/// with a conditional request with `revalidate` (or `IGNITION_REVALIDATE=true`), keeping the cached copy if
+/// unchanged or if the request fails, as once expired (see `expiry::ttl`), being fetched again instead if it can't
+/// be revalidated. Archives split into `parts` are never downloaded over BitTorrent nor revalidated.
+///
+/// Without a checksum for the target, the archive fails with `IGNITION_REQUIRE_CHECKSUM=true`, as without a
+/// `provenance` policy with `IGNITION_REQUIRE_PROVENANCE=true`. A mismatching cached archive is downloaded again,
+/// an archive failing verification is deleted. Downloads larger than `max_download_size` and archives extracting
+/// to more than `max_extracted_size` are an error. With `IGNITION_DRY_RUN=true`, the entries the verified archive
+/// would extract are listed instead (see `extract::list`), nothing being extracted.
+#[allow(clippy::too_many_arguments)]
+fn retrieve_resolved(
+    bucket_url: &str,
+    asset: &str,
+    root: &Path,
+    cache_path: &str,
+    directory_path: &str,
+    resolved: &target::ResolvedTarget,
+    prefetch: bool,
+    asset_metrics: &mut metrics::AssetMetrics,
+) -> IgnitionResult<PathBuf> {
+    let retrieval = Retrieval::resolve(
+        bucket_url,
+        asset,
+        root,
+        cache_path,
+        directory_path,
+        resolved,
+        prefetch,
+        asset_metrics,
+    )?;
+    let download_start = Instant::now();
+    let fetched = retrieval.fetch(asset_metrics)?;
+    let (plain_archive, sha256) = retrieval.verify(fetched, asset_metrics)?;
+    asset_metrics.download_secs = download_start.elapsed().as_secs_f64();
+    retrieval.record(&sha256, asset_metrics)?;
+    println!("--archive: {}", retrieval.location.archive_path.display());
+    if prefetch {
+        return retrieval.prefetch_files(asset_metrics);
+    }
+    if retrieval.dry_run {
+        // paths as `contents` entries, relative to the directory path
+        let entries = extract::list(&plain_archive, &retrieval.extract_options)?;
+        for entry in entries.iter() {
+            println!("--entry: {}/{}", asset, entry);
+        }
+        println!(
+            "--dry run: {} entries, {} bytes (not extracted)",
+            entries.len(),
+            entries.iter().map(|entry| entry.size).sum::<u64>()
+        );
+        return Ok(retrieval.location.extract_path);
+    }
+    if let Some(extraction) = retrieval.extract(plain_archive, &sha256, asset_metrics)? {
+        retrieval.post_process(extraction, asset_metrics)?;
+    }
+    // export the archive digest as cargo metadata for dependent crates (see `asset_digest`)
+    println!("cargo::metadata={}={}", digest_key(asset), sha256);
+    Ok(retrieval.location.extract_path)
+}
+
+/// Retrieval of an asset for a resolved target (see `retrieve_resolved`): its configuration and location, the
+/// settings of its stages, and the locks and guards held until it completes.
+struct Retrieval<'a> {
+    asset: &'a str,
+    resolved: &'a target::ResolvedTarget,
+    /// Retrieval only into the cache (see `prefetch`).
+    prefetch: bool,
+    asset_cfg: AssetEnvironment,
+    location: AssetLocation,
+    cache_root: PathBuf,
+    /// Cache directory of the additional `files` of the asset (see `retrieve_files`).
+    files_cache_dir: PathBuf,
+    /// Seal of the archive if encrypted in the cache, the archive being retrieved decrypted into the build directory.
+    seal: Option<encryption::CacheSeal>,
+    remote_cache: Option<remote::RemoteCache>,
+    retry_policy: retry::RetryPolicy,
+    max_download_size: Option<u64>,
+    extract_options: extract::ExtractOptions,
+    install_mode: install::InstallMode,
+    /// Tree of the archive extracted next to it, if installed from the cache (see `install::tree_path`), and its
+    /// stamp.
+    tree_path: PathBuf,
+    tree_stamp_path: PathBuf,
+    /// Staging directories of the extraction and of the tree.
+    staging_path: PathBuf,
+    tree_staging_path: PathBuf,
+    /// Only list the entries the archive would extract (`IGNITION_DRY_RUN`).
+    dry_run: bool,
+    _allowed_hosts: allowlist::AllowedHostsGuard,
+    _require_tls: tls::RequireTlsGuard,
+    // locks released in this order
+    _sealed_lock: Option<lock::PathLock>,
+    _archive_lock: lock::PathLock,
+    _extract_lock: Option<lock::PathLock>,
+}
+
+/// Archive fetched into the cache by `Retrieval::fetch`.
+struct FetchedArchive {
+    /// Download the archive over BitTorrent if downloaded again.
+    torrent: bool,
+    /// SHA-256 digest of the archive hashed while it was streamed, so it isn't read again.
+    streamed_sha256: Option<String>,
+}
+
+/// Extraction of an archive by `Retrieval::extract`, to post-process.
+struct Extraction {
+    /// Stamp of the extraction, written once post-processed (see `extraction_stamp`).
+    stamp: String,
+    /// Protect the extraction from modifications (see `readonly`).
+    read_only: bool,
+}
+
+impl<'a> Retrieval<'a> {
+    /// Resolve the retrieval of <asset> for the <resolved> target: its configuration and location (see
+    /// `AssetLocation`), with the cache layout checked and its settings resolved from the manifest and the
+    /// environment, locking the extraction (unless <prefetch>) and the cache entry of the archive, and verifying
+    /// its urls are allowed (`require_tls`, `allowed_hosts`), which requests are restricted to until it completes.
+    /// Staging directories left by an interrupted or rejected retrieval are removed.
+    #[allow(clippy::too_many_arguments)]
+    fn resolve(
+        bucket_url: &str,
+        asset: &'a str,
+        root: &Path,
+        cache_path: &str,
+        directory_path: &str,
+        resolved: &'a target::ResolvedTarget,
+        prefetch: bool,
+        asset_metrics: &mut metrics::AssetMetrics,
+    ) -> IgnitionResult<Self> {
+        let asset_cfg = asset_config(asset)?.for_target(&resolved.triplet);
+        let retry_policy = retry::RetryPolicy::resolve(asset_cfg.retry.as_ref())?;
+        let bucket_urls: Vec<&str> = [bucket_url]
+            .into_iter()
+            .chain(asset_cfg.mirrors.iter().map(String::as_str))
+            .collect();
+        let mut location = AssetLocation::new(
+            &bucket_urls,
+            asset,
+            &asset_cfg,
+            root,
+            cache_path,
+            directory_path,
+            resolved,
+        )?;
+        // caches of other layouts are migrated (or set aside) before anything is cached in them
+        let cache_root = root.join(cache_path);
+        cache::check_layout(&cache_root)?;
+        // archives sealed in the cache are retrieved decrypted into the build directory
+        let seal = encryption::CacheSeal::resolve(
+            asset,
+            asset_cfg.encrypt_cache,
+            asset_cfg.encryption,
+            &location.archive_path,
+            &location.target_path,
+        )?;
+        if let Some(seal) = seal.as_ref() {
+            location.archive_path = seal.archive_path.clone();
+        }
+        asset_metrics.archive_path = Some(location.archive_path.clone());
+        // concurrent builds retrieve the asset in turn, always locking its directory before its cache entry
+        let extract_lock = (!prefetch)
+            .then(|| lock::PathLock::acquire(&location.extract_path))
+            .transpose()?;
+        let archive_lock = lock::PathLock::acquire(&location.archive_path)?;
+        let sealed_lock = seal
+            .as_ref()
+            .map(|seal| lock::PathLock::acquire(&seal.sealed_path))
+            .transpose()?;
+        let require_tls = tls::require_tls(asset_cfg.require_tls)?;
+        if require_tls {
+            for url in location.urls.iter().chain(asset_cfg.files.values()) {
+                if !tls::is_secure_url(url)? {
+                    return Err(IgnitionError::ConfigurationError(format!(
+                        "{}: insecure url {} (TLS required)",
+                        asset, url
+                    )));
+                }
+            }
+        }
+        let require_tls = tls::RequireTlsGuard::new(require_tls);
+        let allowed_hosts = allowlist::allowed_hosts(&asset_cfg.allowed_hosts)?;
+        if let Some(allowed_hosts) = allowed_hosts.as_deref() {
+            for url in location.urls.iter().chain(asset_cfg.files.values()) {
+                allowlist::check_url(url, allowed_hosts)?;
+            }
+        }
+        let allowed_hosts = allowlist::AllowedHostsGuard::new(allowed_hosts);
+        let max_download_size = size_limit(
+            MAX_DOWNLOAD_SIZE_VARIABLE,
+            asset_cfg.max_download_size.as_deref(),
+        )?;
+        let remote_cache = remote::RemoteCache::resolve(
+            asset_cfg.remote_cache.as_deref(),
+            asset_cfg.remote_cache_push,
+        )?;
+        let max_extracted_size = size_limit(
+            MAX_EXTRACTED_SIZE_VARIABLE,
+            asset_cfg.max_extracted_size.as_deref(),
+        )?;
+        let extract_options = extract::ExtractOptions {
+            max_size: max_extracted_size,
+            strip_components: asset_cfg.strip_components,
+            include: asset_cfg.include.clone(),
+            exclude: asset_cfg.exclude.clone(),
+            symlinks: extract::SymlinkPolicy::resolve(asset_cfg.symlinks)?,
+            threads: extract::parallel::extract_threads()?,
+            raw_path: (asset_cfg.format == extract::ArchiveFormat::Raw)
+                .then(|| raw_path(asset, &asset_cfg)),
+        };
+        let install_mode = install::InstallMode::resolve(asset_cfg.install)?;
+        // archives installed from the cache are extracted into a tree next to them
+        let tree_path = install::tree_path(&location.archive_path);
+        // a staging directory left by an interrupted or rejected retrieval is stale
+        let staging_path = download::partial_path(&location.extract_path);
+        let tree_staging_path = download::partial_path(&tree_path);
+        for path in [&staging_path, &tree_staging_path] {
+            if path.exists() {
+                fs::remove_dir_all(path)?;
+            }
+        }
+        let dry_run = env_flag(DRY_RUN_VARIABLE)?.unwrap_or(false);
+        Ok(Retrieval {
+            asset,
+            resolved,
+            prefetch,
+            files_cache_dir: root
+                .join(cache_path)
+                .join(directory_path)
+                .join(asset)
+                .join("files"),
+            tree_stamp_path: download::sidecar_path(&tree_path, STAMP_EXTENSION),
+            asset_cfg,
+            location,
+            cache_root,
+            seal,
+            remote_cache,
+            retry_policy,
+            max_download_size,
+            extract_options,
+            install_mode,
+            tree_path,
+            staging_path,
+            tree_staging_path,
+            dry_run,
+            _allowed_hosts: allowed_hosts,
+            _require_tls: require_tls,
+            _sealed_lock: sealed_lock,
+            _archive_lock: archive_lock,
+            _extract_lock: extract_lock,
+        })
+    }
+
+    /// Url the archive was retrieved from: the url it was downloaded from, otherwise its primary url.
+    fn url<'b>(&'b self, asset_metrics: &'b metrics::AssetMetrics) -> &'b str {
+        asset_metrics
+            .url
+            .as_deref()
+            .unwrap_or(&self.location.urls[0])
+    }
+
+    /// Fetch the archive into the cache: copied from the read-only lower cache or pulled from the remote cache if
+    /// missing (see `cache::copy_from_lower`, `remote::pull`), otherwise downloaded (see `download_mirrored`), while
+    /// extracting it if it can be streamed (see `download_extracting`). Cached archives are revalidated, or
+    /// downloaded again once expired or if cached from a url the manifest no longer lists.
+    fn fetch(&self, asset_metrics: &mut metrics::AssetMetrics) -> IgnitionResult<FetchedArchive> {
+        let Retrieval {
+            asset,
+            asset_cfg,
+            location,
+            cache_root,
+            seal,
+            retry_policy,
+            max_download_size,
+            ..
+        } = self;
+        let max_download_size = *max_download_size;
+        // archives missing in the cache are copied from the read-only lower cache, otherwise pulled from the remote
+        // cache (if any), and verified as cached archives
+        if let Some(seal) = seal.as_ref() {
+            // only sealed archives are copied or pulled
+            seal.unseal(cache_root, self.remote_cache.as_ref(), max_download_size)?;
+        } else if !location.archive_path.exists()
+            && !cache::copy_from_lower(&location.archive_path, cache_root)?
+        {
+            remote::pull(
+                self.remote_cache.as_ref(),
+                &location.archive_path,
+                cache_root,
+                max_download_size,
+            )?;
+        }
+
+        // only re-download if the asset doesn't already exist, or changed remotely when revalidating
+        // split archives are only published as parts
+        let split = asset_cfg.parts > 0;
+        let offline = offline::is_offline()?;
+        // archives cached for longer than their ttl are revalidated, or fetched again without validators
+        let expired = !offline
+            && location.archive_path.exists()
+            && expiry::ttl(asset_cfg.ttl.as_deref())?
+                .is_some_and(|ttl| expiry::is_expired(&location.archive_path, ttl));
+        // archives cached from a url the manifest no longer lists (e.g. after an edit of its url template) are stale
+        let url_changed = !offline
+            && location.archive_path.exists()
+            && cache::CacheRecord::load(&location.archive_path)
+                .is_some_and(|record| !location.urls.contains(&record.url));
+        let refetch = url_changed
+            || expired
+                && (split || !expiry::can_revalidate(&location.urls[0], &location.archive_path));
+        let revalidate = (env_flag(REVALIDATE_VARIABLE)?.unwrap_or(asset_cfg.revalidate)
+            || expired)
+            && !split
+            && !offline;
+        let torrent = env_flag(TORRENT_VARIABLE)?.unwrap_or(asset_cfg.torrent)
+            && !split
+            && !offline
+            && !refetch;
+        let mut fetched = FetchedArchive {
+            torrent,
+            streamed_sha256: None,
+        };
+        if refetch {
+            println!(
+                "--{}: {} (fetching again)",
+                match url_changed {
+                    true => "url changed",
+                    false => "expired",
+                },
+                location.archive_path.display()
+            );
+            match download_mirrored(
+                asset,
+                location,
+                retry_policy,
+                torrent,
+                max_download_size,
+                asset_cfg.parts,
+                None,
+            ) {
+                Ok((url, _)) => {
+                    expiry::record(&location.archive_path)?;
+                    asset_metrics.cache_hit = false;
+                    asset_metrics.url = Some(url);
+                }
+                Err(err) => println!("--refetch failed: {} (using cached archive)", err),
+            }
+        } else if !location.archive_path.exists() {
+            if let Some(vendor_dir) = vendor::dir() {
+                let cached_path = seal
+                    .as_ref()
+                    .map_or(&location.archive_path, |seal| &seal.sealed_path);
+                return Err(IgnitionError::Offline(format!(
+                    "{}: {} is not vendored in {} (vendor it with ignition-cache vendor)",
+                    asset,
+                    cache::relative_path(cached_path, cache_root)
+                        .unwrap_or_else(|| cached_path.clone())
+                        .display(),
+                    vendor_dir.display()
+                )));
+            }
+            if offline && !location.urls.iter().any(|url| offline::is_local(url)) {
+                // an actionable error rather than the failure of the first url
+                return Err(IgnitionError::Offline(format!(
+                    "{}: {} is not cached (build online once, or set a file:// bucket url or mirror)",
+                    asset,
+                    location.archive_path.display()
+                )));
+            }
+            // archives verified or scanned before extraction are not streamed
+            let verified = !signature::trusted_keys(asset_cfg).is_empty()
+                || !signature::minisign_keys(asset_cfg).is_empty()
+                || asset_cfg.sigstore.is_some()
+                || asset_cfg.provenance.is_some()
+                || env_flag(REQUIRE_PROVENANCE_VARIABLE)?.unwrap_or(false)
+                || scan::is_configured(asset_cfg.scan_command.as_deref())?;
+            let streaming = (env_flag(STREAMING_EXTRACTION_VARIABLE)?.unwrap_or(true)
+                && !self.dry_run
+                && !self.prefetch
+                && !split
+                && !verified
+                && asset_cfg.encryption.is_none()
+                && asset_cfg.format.is_streamable())
+            .then_some(StreamingExtraction {
+                staging_path: match self.install_mode {
+                    install::InstallMode::Extract => &self.staging_path,
+                    _ => &self.tree_staging_path,
+                },
+                options: &self.extract_options,
+            });
+            let (url, sha256) = download_mirrored(
+                asset,
+                location,
+                retry_policy,
+                torrent,
+                max_download_size,
+                asset_cfg.parts,
+                streaming.as_ref(),
+            )?;
+            expiry::record(&location.archive_path)?;
+            asset_metrics.cache_hit = false;
+            asset_metrics.url = Some(url);
+            fetched.streamed_sha256 = sha256;
+        } else if revalidate {
+            if expired {
+                println!(
+                    "--expired: {} (revalidating)",
+                    location.archive_path.display()
+                );
+            }
+            match retry_policy.run(asset, || {
+                download::download_if_modified(
+                    &location.urls[0],
+                    &location.archive_path,
+                    max_download_size,
+                )
+            }) {
+                Ok(true) => {
+                    println!("--modified: {}", location.archive_path.display());
+                    // delta-transfer sources are only checked once synchronized
+                    download::enforce_size_limit(
+                        &location.urls[0],
+                        &location.archive_path,
+                        max_download_size,
+                    )?;
+                    expiry::record(&location.archive_path)?;
+                    asset_metrics.cache_hit = false;
+                    asset_metrics.revalidated = true;
+                    asset_metrics.url = Some(location.urls[0].to_string());
+                }
+                Ok(false) => {
+                    expiry::record(&location.archive_path)?;
+                    asset_metrics.revalidated = true;
+                    println!("--unchanged: {}", location.archive_path.display())
+                }
+                Err(err) => println!("--revalidation failed: {} (using cached archive)", err),
+            }
+        }
+        Ok(fetched)
+    }
+
+    /// Verify the <fetched> archive before extraction: its checksum (downloading a corrupt cached archive again),
+    /// signatures, provenance and scan (see `checksum`, `signature`, `provenance`, `scan`), then the lockfile (see
+    /// `lockfile::check`), recording it in the SBOM (see `sbom`). An archive failing verification is deleted.
+    /// Return is the archive decrypted (see `encryption::decrypt`) and its SHA-256 digest.
+    fn verify(
+        &self,
+        fetched: FetchedArchive,
+        asset_metrics: &mut metrics::AssetMetrics,
+    ) -> IgnitionResult<(encryption::PlainArchive, String)> {
+        let Retrieval {
+            asset,
+            asset_cfg,
+            location,
+            ..
+        } = self;
+        let platform = self.resolved.path_components().join("/");
+        let checksum = checksum::expected(
+            asset_cfg,
+            &platform,
+            self.url(asset_metrics),
+            &location.archive_path,
+            !asset_metrics.cache_hit,
+        )?;
+        if checksum.is_none() && env_flag(REQUIRE_CHECKSUM_VARIABLE)?.unwrap_or(false) {
+            return Err(IgnitionError::ConfigurationError(format!(
+                "{}: no checksum for {} in the manifest or {} ({} is set)",
+                asset,
+                platform,
+                checksum::SUMS_FILE_NAME,
+                REQUIRE_CHECKSUM_VARIABLE
+            )));
+        }
+        if let Some(expected) = checksum.as_ref()
+            && let Err(err) = match fetched.streamed_sha256.as_deref() {
+                Some(sha256) if expected.algorithm == checksum::HashAlgorithm::Sha256 => {
+                    checksum::verify_digest(&location.archive_path, expected, sha256)
+                }
+                _ => checksum::verify(&location.archive_path, expected),
+            }
+        {
+            fs::remove_file(&location.archive_path)?;
+            if !asset_metrics.cache_hit {
+                return Err(err);
+            }
+            println!("--cached archive corrupt: {} (downloading again)", err);
+            let (url, _) = download_mirrored(
+                asset,
+                location,
+                &self.retry_policy,
+                fetched.torrent,
+                self.max_download_size,
+                asset_cfg.parts,
+                None,
+            )?;
+            asset_metrics.cache_hit = false;
+            asset_metrics.url = Some(url);
+            if let Err(err) = checksum::verify(&location.archive_path, expected) {
+                fs::remove_file(&location.archive_path)?;
+                return Err(err);
+            }
+        }
+        if checksum.is_some() {
+            println!("--checksum verified: {}", location.archive_path.display());
+        }
+        let url = self.url(asset_metrics);
+        let fetch = !asset_metrics.cache_hit;
+        let trusted_keys = signature::trusted_keys(asset_cfg);
+        if !trusted_keys.is_empty()
+            && let Err(err) =
+                signature::verify_gpg(url, &location.archive_path, &trusted_keys, fetch)
+        {
+            fs::remove_file(&location.archive_path)?;
+            return Err(err);
+        }
+        let minisign_keys = signature::minisign_keys(asset_cfg);
+        if !minisign_keys.is_empty()
+            && let Err(err) =
+                signature::verify_minisign(url, &location.archive_path, &minisign_keys, fetch)
+        {
+            fs::remove_file(&location.archive_path)?;
+            return Err(err);
+        }
+        if let Some(policy) = asset_cfg.sigstore.as_ref()
+            && let Err(err) = signature::verify_sigstore(url, &location.archive_path, policy, fetch)
+        {
+            fs::remove_file(&location.archive_path)?;
+            return Err(err);
+        }
+        match asset_cfg.provenance.as_ref() {
+            Some(policy) => {
+                if let Err(err) = provenance::verify(url, &location.archive_path, policy, fetch) {
+                    fs::remove_file(&location.archive_path)?;
+                    return Err(err);
+                }
+            }
+            None if env_flag(REQUIRE_PROVENANCE_VARIABLE)?.unwrap_or(false) => {
+                return Err(IgnitionError::ConfigurationError(format!(
+                    "{}: no provenance policy in the manifest ({} is set)",
+                    asset, REQUIRE_PROVENANCE_VARIABLE
+                )));
+            }
+            None => {}
+        }
+        let plain_archive =
+            encryption::decrypt(asset, &location.archive_path, asset_cfg.encryption)?;
+        if let Err(err) = scan::scan(
+            asset,
+            url,
+            &plain_archive,
+            asset_cfg.scan_command.as_deref(),
+        ) {
+            fs::remove_file(&location.archive_path)?;
+            return Err(err);
+        }
+        lockfile::check(
+            asset,
+            &platform,
+            &location.urls[0],
+            asset_cfg.version.as_deref(),
+            asset_cfg.variant.as_deref(),
+            &location.archive_path,
+        )?;
+        sbom::record(
+            asset,
+            asset_cfg.version.as_deref(),
+            &platform,
+            url,
+            asset_cfg.license.as_deref(),
+            &location.archive_path,
+        )?;
+        if !asset_metrics.cache_hit {
+            asset_metrics.bytes_downloaded += fs::metadata(&location.archive_path)?.len();
+        }
+        let sha256 = match checksum {
+            Some(expected) if expected.algorithm == checksum::HashAlgorithm::Sha256 => {
+                expected.digest
+            }
+            _ => match fetched.streamed_sha256 {
+                Some(sha256) => sha256,
+                None => checksum::sha256_file(&location.archive_path)?,
+            },
+        };
+        Ok((plain_archive, sha256))
+    }
+
+    /// Record the verified archive of SHA-256 digest <sha256> in the cache (see `cache::CacheRecord`, `gc::touch`),
+    /// sealing it if encrypted in the cache, and sharing archives retrieved from upstream sources with other
+    /// machines through the remote cache (if enabled).
+    fn record(&self, sha256: &str, asset_metrics: &metrics::AssetMetrics) -> IgnitionResult<()> {
+        let location = &self.location;
+        // recently used archives are evicted last, see `gc::collect`
+        gc::touch(&location.archive_path).ok();
+        // the verified archive is recorded for `cache::verify_cache`
+        cache::CacheRecord {
+            url: self.url(asset_metrics).to_string(),
+            sha256: sha256.to_string(),
+            asset: Some(self.asset.to_string()),
+            retrieved: Some(source::unix_time()),
+            ignition_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+        .save(&location.archive_path)?;
+        // archives retrieved from upstream sources are shared with other machines (if enabled), sealed if
+        // encrypted in the cache
+        if let Some(seal) = self.seal.as_ref() {
+            seal.seal(
+                !asset_metrics.cache_hit,
+                &self.cache_root,
+                self.remote_cache.as_ref(),
+            )?;
+        } else if !asset_metrics.cache_hit
+            && let Err(err) = remote::push(
+                self.remote_cache.as_ref(),
+                &location.archive_path,
+                &self.cache_root,
+            )
+        {
+            println!(
+                "cargo:warning=--remote cache push failed: {}: {}",
+                self.asset, err
+            );
+        }
+        Ok(())
+    }
+
+    /// Prefetch the additional `files` of the asset into the cache, see `prefetch`. Return is the path of the
+    /// cached archive (sealed if encrypted in the cache, the decrypted archive being removed).
+    fn prefetch_files(self, asset_metrics: &mut metrics::AssetMetrics) -> IgnitionResult<PathBuf> {
+        let files_start = Instant::now();
+        asset_metrics.bytes_downloaded += retrieve_files(
+            &self.asset_cfg.files,
+            &self.cache_root,
+            self.remote_cache.as_ref(),
+            &self.files_cache_dir,
+            None,
+            &self.retry_policy,
+            self.max_download_size,
+        )?;
+        asset_metrics.download_secs += files_start.elapsed().as_secs_f64();
+        let archive_path = &self.location.archive_path;
+        if let Some(seal) = self.seal.as_ref() {
+            // prefetched archives are only kept sealed in the cache
+            fs::remove_dir_all(archive_path.parent().unwrap_or(archive_path))?;
+            println!("--prefetched: {}", seal.sealed_path.display());
+            return Ok(seal.sealed_path.clone());
+        }
+        println!("--prefetched: {}", archive_path.display());
+        Ok(self.location.archive_path)
+    }
+
+    /// Extract the <plain_archive> of SHA-256 digest <sha256> into a staging directory, installed as a whole as the
+    /// extraction directory (see `extract`, `install`), its executables marked (`executable`), unless a complete
+    /// extraction of the archive with the same settings is intact (see `is_extracted`, `integrity`).
+    /// Return is the extraction to post-process, None if the existing one is kept.
+    fn extract(
+        &self,
+        plain_archive: encryption::PlainArchive,
+        sha256: &str,
+        asset_metrics: &mut metrics::AssetMetrics,
+    ) -> IgnitionResult<Option<Extraction>> {
+        let Retrieval {
+            asset,
+            asset_cfg,
+            location,
+            extract_options,
+            install_mode,
+            tree_path,
+            tree_stamp_path,
+            staging_path,
+            tree_staging_path,
+            ..
+        } = self;
+        let read_only = env_flag(READ_ONLY_VARIABLE)?.unwrap_or(asset_cfg.read_only);
+        let stamp_path = download::sidecar_path(&location.extract_path, STAMP_EXTENSION);
+        // an extraction removed (e.g. pruned) is retrieved again by the next build, see `rerun::print_directives`
+        rerun::record_path(&stamp_path);
+        // a missing lockfile would run the build script on every build
+        let lockfile_path = lockfile::Lockfile::path()?;
+        if lockfile_path.exists() {
+            rerun::record_path(&lockfile_path);
+        }
+        let stamp = extraction_stamp(
+            asset,
+            sha256,
+            extract_options,
+            *install_mode,
+            read_only,
+            asset_cfg,
+        )?;
+        // a complete extraction of the archive with the same settings is kept as long as it is intact
+        if is_extracted(&location.extract_path) {
+            let integrity = integrity::IntegrityCheck::resolve(asset_cfg.integrity)?;
+            // files are only hashed again as a whole by full checks, spot checks sampling them
+            let full = integrity == integrity::IntegrityCheck::Full;
+            let no_checksums = HashMap::new();
+            let (contents, content_sha256) = match full {
+                true => (asset_cfg.contents.clone(), &asset_cfg.content_sha256),
+                false => (
+                    asset_cfg
+                        .contents
+                        .iter()
+                        .chain(asset_cfg.content_sha256.keys())
+                        .cloned()
+                        .collect(),
+                    &no_checksums,
+                ),
+            };
+            let stale = match fs::read_to_string(&stamp_path)? == stamp {
+                false => Some("archive or settings changed".to_string()),
+                true if full && !readonly::verify(&location.extract_path)?.is_empty() => {
+                    Some("read-only asset modified".to_string())
+                }
+                true => match checksum::verify_contents(
+                    &location.target_path,
+                    &contents,
+                    content_sha256,
+                ) {
+                    Err(err) => Some(err.to_string()),
+                    Ok(()) => integrity::check(&location.extract_path, integrity)?
+                        .map(|reason| format!("corrupt: {}", reason)),
+                },
+            };
+            match stale {
+                None => {
+                    for path in [staging_path, tree_staging_path] {
+                        if path.exists() {
+                            fs::remove_dir_all(path)?;
+                        }
+                    }
+                    println!("--extracted: {}", location.extract_path.display());
+                    licenses::record(asset, &location.extract_path);
+                    return Ok(None);
+                }
+                Some(reason) => println!(
+                    "--extraction stale: {} ({}, extracting again)",
+                    location.extract_path.display(),
+                    reason
+                ),
+            }
+        }
+        println!("--extract: {}", location.extract_path.display());
+        let extract_start = Instant::now();
+        let modified = readonly::unprotect(&location.extract_path)?;
+        if !modified.is_empty() {
+            println!(
+                "cargo:warning=--read-only asset modified: {}: {} (extracting again)",
+                asset,
+                modified.join(", ")
+            );
+            fs::remove_dir_all(&location.extract_path)?;
+            // hard links share the modifications with the cached tree
+            if tree_stamp_path.exists() {
+                fs::remove_file(tree_stamp_path)?;
+            }
+        }
+        if *install_mode == install::InstallMode::Extract {
+            // tarballs extracted while downloading are only installed
+            if !staging_path.exists() {
+                extract::extract(&plain_archive, staging_path, extract_options)?;
+            }
+        } else {
+            let tree_key = install::tree_key(sha256, extract_options);
+            if install::is_current(tree_path, &tree_key) {
+                if tree_staging_path.exists() {
+                    fs::remove_dir_all(tree_staging_path)?;
+                }
+            } else {
+                if !tree_staging_path.exists() {
+                    extract::extract(&plain_archive, tree_staging_path, extract_options)?;
+                }
+                // installed as the extraction, see below
+                if tree_stamp_path.exists() {
+                    fs::remove_file(tree_stamp_path)?;
+                }
+                if tree_path.exists() {
+                    fs::remove_dir_all(tree_path)?;
+                }
+                fs::rename(tree_staging_path, tree_path)?;
+                cache::record_tree(tree_path)?;
+                fs::write(tree_stamp_path, tree_key)?;
+            }
+            install::install(tree_path, staging_path, *install_mode)?;
+        }
+        // the stamp goes first, so an interrupted installation is never taken for a complete extraction
+        if stamp_path.exists() {
+            fs::remove_file(&stamp_path)?;
+        }
+        patch::clear(&location.extract_path)?;
+        if location.extract_path.exists() {
+            fs::remove_dir_all(&location.extract_path)?;
+        }
+        fs::rename(staging_path, &location.extract_path)?;
+        drop(plain_archive);
+        extract::mark_executable(&location.extract_path, &asset_cfg.executable)?;
+        asset_metrics.extract_secs = extract_start.elapsed().as_secs_f64();
+        asset_metrics.extracted = true;
+        Ok(Some(Extraction { stamp, read_only }))
+    }
+
+    /// Post-process the <extraction>: install the additional `files` of the asset (see `retrieve_files`), apply its
+    /// `patches` and run its `post_extract` hooks (see `patch`, `hooks`), verify its `contents` (an archive
+    /// extracting to a corrupt extraction being deleted), then record its integrity, protect it if read-only, and
+    /// stamp it (see `is_extracted`).
+    fn post_process(
+        &self,
+        extraction: Extraction,
+        asset_metrics: &mut metrics::AssetMetrics,
+    ) -> IgnitionResult<()> {
+        let Retrieval {
+            asset,
+            asset_cfg,
+            location,
+            tree_stamp_path,
+            ..
+        } = self;
+        let files_start = Instant::now();
+        asset_metrics.bytes_downloaded += retrieve_files(
+            &asset_cfg.files,
+            &self.cache_root,
+            self.remote_cache.as_ref(),
+            &self.files_cache_dir,
+            Some(&location.target_path),
+            &self.retry_policy,
+            self.max_download_size,
+        )?;
+        asset_metrics.download_secs += files_start.elapsed().as_secs_f64();
+        patch::apply(asset, &location.extract_path, &asset_cfg.patches)?;
+        if let Err(err) = hooks::run(
+            asset,
+            &location.extract_path,
+            self.resolved,
+            &asset_cfg.post_extract,
+        ) {
+            // hooks modifying hard-linked files in place modify the cached tree
+            if tree_stamp_path.exists() {
+                fs::remove_file(tree_stamp_path)?;
+            }
+            return Err(err);
+        }
+        if let Err(err) = checksum::verify_contents(
+            &location.target_path,
+            &asset_cfg.contents,
+            &asset_cfg.content_sha256,
+        ) {
+            // a corrupted extraction is only repaired by downloading the archive again
+            fs::remove_file(&location.archive_path)?;
+            if tree_stamp_path.exists() {
+                fs::remove_file(tree_stamp_path)?;
+            }
+            return Err(err);
+        }
+        integrity::record(&location.extract_path)?;
+        if extraction.read_only {
+            readonly::protect(&location.extract_path)?;
+        }
+        fs::write(
+            download::sidecar_path(&location.extract_path, STAMP_EXTENSION),
+            extraction.stamp,
+        )?;
+        licenses::record(asset, &location.extract_path);
+        Ok(())
+    }
+}
+
+/// Download additional <files> (content path to url) into <cache_dir> (if absent), keyed by url so a changed url
+/// (e.g. new revision) is downloaded again, and install them at their content path within <directory> (if any).
+/// Files in the read-only lower cache of the cache at <cache_root> are copied instead (see
+/// `cache::copy_from_lower`), otherwise pulled from the <remote> cache, files downloaded being pushed to it (see
+/// `remote`). Files larger than <max_size> bytes are an error.
+/// Return is the number of bytes downloaded.
+fn retrieve_files(
+    files: &HashMap<String, String>,
+    cache_root: &Path,
+    remote: Option<&remote::RemoteCache>,
+    cache_dir: &Path,
+    directory: Option<&Path>,
+    retry_policy: &retry::RetryPolicy,
+    max_size: Option<u64>,
+) -> IgnitionResult<u64> {
+    let mut bytes_downloaded = 0;
+    for (content, url) in files.iter() {
+        let file_name = content.rsplit('/').next().unwrap_or(content);
+        let url_hash = source::hex(&Sha256::digest(url.as_bytes()));
+        let cache_path = cache_dir.join(&url_hash[..16]).join(file_name);
+        if !cache_path.exists()
+            && !cache::copy_from_lower(&cache_path, cache_root)?
+            && !remote::pull(remote, &cache_path, cache_root, max_size)?
+        {
+            println!("--file url: {}", redact::redact(url));
+            retry_policy.run(content, || {
+                download::download_limited(url, &cache_path, max_size)
+            })?;
+            bytes_downloaded += fs::metadata(&cache_path)?.len();
+            if let Err(err) = remote::push(remote, &cache_path, cache_root) {
+                println!(
+                    "cargo:warning=--remote cache push failed: {}: {}",
+                    content, err
+                );
+            }
+        } else {
+            gc::touch(&cache_path).ok();
+        }
+        let Some(directory) = directory else {
+            continue;
+        };
+        let path = directory.join(content);
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        println!("--file: {}", path.display());
+        source::local::copy(&cache_path, &path)?;
+    }
+    Ok(bytes_downloaded)
+}
+
+/// Download the asset archive from the first of its urls that succeeds, recording the successful mirror.
+///
+/// With <torrent>, a BitTorrent download (web-seeded by the urls) is attempted first. Archives split into
+/// <parts> parts are downloaded part by part (see `split::download`).
+/// Return is the url the archive was downloaded from, and its SHA-256 digest if computed while it was streamed (see
+/// `download_extracting`).
+fn download_mirrored(
+    asset: &str,
+    location: &AssetLocation,
+    retry_policy: &retry::RetryPolicy,
+    torrent: bool,
+    max_size: Option<u64>,
+    parts: usize,
+    streaming: Option<&StreamingExtraction>,
+) -> IgnitionResult<(String, Option<String>)> {
+    if torrent {
+        match source::torrent::download(&location.urls, &location.archive_path).and_then(|_| {
+            download::enforce_size_limit(&location.urls[0], &location.archive_path, max_size)
+        }) {
+            Ok(()) => {
+                println!("--torrent: {}", location.archive_path.display());
+                return Ok((location.urls[0].to_string(), None));
+            }
+            Err(err) => println!("--torrent failed: {} (falling back to urls)", err),
+        }
+    }
+    let mut errors = Vec::new();
+    for url in location.urls.iter() {
+        println!("--asset url: {}", redact::redact(url));
+        match retry_policy.run(asset, || match streaming {
+            _ if parts > 0 => {
+                split::download(url, &location.archive_path, parts, max_size).map(|_| None)
+            }
+            Some(streaming) => {
+                download_extracting(url, &location.archive_path, max_size, streaming)
+            }
+            None => download::download_limited(url, &location.archive_path, max_size).map(|_| None),
+        }) {
+            Ok(sha256) => {
+                println!("--mirror: {}", redact::redact(url));
+                return Ok((url.to_string(), sha256));
+            }
+            Err(err) if location.urls.len() == 1 => return Err(err),
+            Err(err) => {
+                println!("--mirror failed: {} ({})", redact::redact(url), err);
+                errors.push(format!("{}: {}", url, err));
+            }
+        }
+    }
+    Err(IgnitionError::DownloadError(format!(
+        "all mirrors failed: {}",
+        errors.join("; ")
+    )))
+}
+
+/// Relative path of the file of a raw <asset> within its extraction directory: the `path` of <asset_cfg>,
+/// otherwise the asset name.
+fn raw_path(asset: &str, asset_cfg: &AssetEnvironment) -> PathBuf {
+    PathBuf::from(asset_cfg.path.as_deref().unwrap_or(asset))
+}
+
+/// Build directory (`target/[<triplet>/]<profile>`, or that of a custom target or build directory) of the build
+/// script of <out_dir> (its `OUT_DIR`, `<build directory>/build/<crate>-<hash>/out`): the parent of the nearest
+/// `build` directory of <out_dir>. None if <out_dir> isn't within one.
+pub fn build_dir(out_dir: &Path) -> Option<&Path> {
+    out_dir
+        .ancestors()
+        .find(|ancestor| ancestor.file_name().is_some_and(|name| name == "build"))?
+        .parent()
+}
+
+/// Determine if the asset extracted into <extract_path> is complete: its extraction is stamped (`<asset>.stamp`,
+/// holding the SHA-256 digest of the archive and the settings of the extraction, see `extraction_stamp`) once
+/// installed and verified, see `retrieve`.
+pub fn is_extracted(extract_path: &Path) -> bool {
+    extract_path.is_dir() && download::sidecar_path(extract_path, STAMP_EXTENSION).is_file()
+}
+
+/// Stamp of the extraction of the archive of SHA-256 digest <sha256> with <options>, installed with <install_mode>
+/// (<read_only> or not) for <asset_cfg>: the digest first, then everything else the extracted asset depends on
+/// (including the digests of its `patches`, and of the manifest entry of <asset> as a whole), so an extraction of
+/// another archive or with other settings (or after any edit of the manifest entry) is stale.
+fn extraction_stamp(
+    asset: &str,
+    sha256: &str,
+    options: &extract::ExtractOptions,
+    install_mode: install::InstallMode,
+    read_only: bool,
+    asset_cfg: &AssetEnvironment,
+) -> IgnitionResult<String> {
+    let mut files: Vec<_> = asset_cfg.files.iter().collect();
+    files.sort();
+    Ok(format!(
+        "{}install={:?} read_only={} executable={:?} patches={:?} post_extract={:?} files={:?} manifest={}\n",
+        install::tree_key(sha256, options),
+        install_mode,
+        read_only,
+        asset_cfg.executable,
+        patch::digests(&asset_cfg.patches)?,
+        asset_cfg.post_extract,
+        files,
+        source::hex(&Sha256::digest(asset_entry(asset)?.to_string().as_bytes()))
+    ))
+}
+
+/// Extraction of an asset archive while it is downloaded, see `download_extracting`.
+struct StreamingExtraction<'a> {
+    /// Staging directory the archive is extracted into, installed once the archive is verified.
+    staging_path: &'a Path,
+    options: &'a extract::ExtractOptions,
+}
+
+/// Download the archive at <url> into <archive_path> as `download::download_limited`, extracting it into the
+/// staging directory of <streaming> while it is downloaded. A failed extraction (e.g. of an archive failing
+/// verification) only removes the staging directory, the archive being extracted after verification instead.
+///
+/// Only tarballs are streamed (unless `IGNITION_STREAMING_EXTRACTION=false`), and only if nothing must verify
+/// the archive before extraction (no signature, provenance or scan), nor decrypt it: the staging directory is
+/// installed once the archive is verified (see `retrieve_resolved`). Streamed archives are downloaded over a single
+/// request, not segmented: disable streaming for segmented downloads of large archives (see
+/// `download::SegmentConfig`).
+/// Return is the SHA-256 digest of the archive, hashed while it is downloaded, None if it wasn't streamed.
+fn download_extracting(
+    url: &str,
+    archive_path: &Path,
+    max_size: Option<u64>,
+    streaming: &StreamingExtraction,
+) -> IgnitionResult<Option<String>> {
+    if streaming.staging_path.exists() {
+        fs::remove_dir_all(streaming.staging_path)?;
+    }
+    let extracted = download::download_streaming(url, archive_path, max_size, |reader| {
+        extract::extract_stream(
+            reader,
+            archive_path,
+            streaming.staging_path,
+            streaming.options,
+        )
+    })?;
+    match extracted {
+        Some((Ok(()), sha256)) => {
+            println!("--streamed: {}", streaming.staging_path.display());
+            Ok(Some(sha256))
+        }
+        Some((Err(err), sha256)) => {
+            println!(
+                "--streaming extraction failed: {} (extracting after download)",
+                err
+            );
+            fs::remove_dir_all(streaming.staging_path).ok();
+            Ok(Some(sha256))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parse an optional environment variable, erroring on values that fail to parse.
+pub(crate) fn env_value<T: std::str::FromStr>(name: &str) -> IgnitionResult<Option<T>> {
+    rerun::record_variable(name);
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| IgnitionError::ConfigurationError(format!("{}={}", name, value))),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Parse an optional environment variable holding a flag: `1`/`true`/`yes`/`on` or `0`/`false`/`no`/`off`.
+pub(crate) fn env_flag(name: &str) -> IgnitionResult<Option<bool>> {
+    let Some(value) = env_value::<String>(name)? else {
+        return Ok(None);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => Err(IgnitionError::ConfigurationError(format!(
+            "{}={}",
+            name, value
+        ))),
+    }
+}
+
+/// Parse an optional environment variable holding a byte size, see `parse_size`.
+pub(crate) fn env_size(name: &str) -> IgnitionResult<Option<u64>> {
+    let Some(value) = env_value::<String>(name)? else {
+        return Ok(None);
+    };
+    parse_size(&value).map(Some).ok_or_else(|| {
+        IgnitionError::ConfigurationError(format!(
+            "{}={}: not a byte size (a non-negative whole number of bytes, K, M or G)",
+            name, value
+        ))
+    })
+}
+
+/// Parse a byte size, optionally suffixed with K, M or G (binary multiples), e.g. "512K", "10M" or "1.5G". None if
+/// negative, not finite, or not a whole number of bytes once multiplied.
+pub(crate) fn parse_size(value: &str) -> Option<u64> {
+    let value_upper = value.trim().to_ascii_uppercase();
+    let digits = value_upper.trim_end_matches(['B', '/', 'S']);
+    let (number, multiplier) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        _ => (digits, 1),
+    };
+    let size = number.trim().parse::<f64>().ok()? * multiplier as f64;
+    (size.is_finite() && size >= 0.0 && size.fract() == 0.0 && size < u64::MAX as f64)
+        .then_some(size as u64)
+}
+
+/// Resolve a size limit from the environment <variable>, otherwise the <manifest> value, None if unlimited.
+fn size_limit(variable: &str, manifest: Option<&str>) -> IgnitionResult<Option<u64>> {
+    if let Some(size) = env_size(variable)? {
+        return Ok(Some(size));
+    }
+    manifest
+        .map(|value| {
+            parse_size(value).ok_or_else(|| {
+                IgnitionError::ConfigurationError(format!("invalid size limit: {}", value))
+            })
+        })
+        .transpose()
+}
+
+/// Empty scratch directory <name> of the tests of this process, in the temporary directory.
+#[cfg(test)]
+pub(crate) fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("ignition-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn parse_size_of_units() {
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size("1024"), Some(1024));
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size("10m"), Some(10 << 20));
+        assert_eq!(parse_size(" 2G "), Some(2 << 30));
+        assert_eq!(parse_size("1.5G"), Some(3 << 29));
+        assert_eq!(parse_size("100MB"), Some(100 << 20));
+        assert_eq!(parse_size("1M/s"), Some(1 << 20));
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_sizes() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size("ten"), None);
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size("-1M"), None);
+        assert_eq!(parse_size("0.5"), None);
+        assert_eq!(parse_size("1.0000001K"), None);
+        assert_eq!(parse_size("inf"), None);
+        assert_eq!(parse_size("NaN"), None);
+        assert_eq!(parse_size("1e30G"), None);
+    }
+}
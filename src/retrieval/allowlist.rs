@@ -0,0 +1,147 @@
+// allowlist.rs
+//
+// This module contains the download host allowlist (`allowed_hosts` in the manifest or `IGNITION_ALLOWED_HOSTS`):
+// asset urls and connections (including redirects) to any other host abort the retrieval, so an injected url
+// can't pull arbitrary binaries into the build.
+//
+
+use std::{cell::RefCell, error::Error, fmt};
+
+use ureq::unversioned::transport::{ConnectionDetails, Connector, Transport};
+
+use super::{env_value, source};
+use crate::{IgnitionError, IgnitionResult};
+
+const ALLOWED_HOSTS_VARIABLE: &str = "IGNITION_ALLOWED_HOSTS";
+/// Schemes of urls naming a network host (rather than a bucket, account or local path).
+const HOST_SCHEMES: [&str; 7] = [
+    "http",
+    "https",
+    "zsync+http",
+    "zsync+https",
+    "rsync",
+    "sftp",
+    "oci",
+];
+
+thread_local! {
+    /// Allowed hosts for requests on this thread, see `AllowedHostsGuard`.
+    static ALLOWED_HOSTS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Determine the allowed hosts: `IGNITION_ALLOWED_HOSTS` (comma or whitespace separated), otherwise the manifest
+/// <allowed_hosts> of the asset (or of a retrieval in progress on this thread). Return is None if unrestricted.
+///
+/// Entries are host names, or `*.<domain>` for any subdomain of the domain.
+pub fn allowed_hosts(allowed_hosts: &[String]) -> IgnitionResult<Option<Vec<String>>> {
+    if let Some(value) = env_value::<String>(ALLOWED_HOSTS_VARIABLE)? {
+        return Ok(Some(
+            value
+                .split([',', ' ', '\n', '\t'])
+                .filter(|host| !host.is_empty())
+                .map(str::to_ascii_lowercase)
+                .collect(),
+        ));
+    }
+    if !allowed_hosts.is_empty() {
+        return Ok(Some(
+            allowed_hosts
+                .iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+        ));
+    }
+    Ok(ALLOWED_HOSTS.with_borrow(Clone::clone))
+}
+
+/// Guard restricting all requests on this thread (i.e. of an asset retrieval) to allowed hosts while alive.
+pub struct AllowedHostsGuard(Option<Vec<String>>);
+
+impl AllowedHostsGuard {
+    /// Restrict requests on this thread to <allowed_hosts> (None if unrestricted), until the guard is dropped.
+    pub fn new(allowed_hosts: Option<Vec<String>>) -> Self {
+        AllowedHostsGuard(ALLOWED_HOSTS.replace(allowed_hosts))
+    }
+}
+
+impl Drop for AllowedHostsGuard {
+    fn drop(&mut self) {
+        ALLOWED_HOSTS.set(self.0.take());
+    }
+}
+
+/// Determine if <host> is one of <allowed_hosts>.
+pub fn is_allowed(host: &str, allowed_hosts: &[String]) -> bool {
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    allowed_hosts
+        .iter()
+        .any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            None => *allowed == host,
+        })
+}
+
+/// Verify the host of <url> is one of <allowed_hosts>.
+///
+/// Only urls naming a network host are checked: cloud storage and Hugging Face urls name a bucket, account or
+/// repository instead, so their endpoints are checked on connection (see `HostAllowlistConnector`).
+pub fn check_url(url: &str, allowed_hosts: &[String]) -> IgnitionResult<()> {
+    if !HOST_SCHEMES.contains(&source::scheme(url)) {
+        return Ok(());
+    }
+    let (authority, _) = source::split_url(url);
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or(ipv6),
+        None => authority.split(':').next().unwrap_or(authority),
+    };
+    match is_allowed(host, allowed_hosts) {
+        true => Ok(()),
+        false => Err(IgnitionError::HostNotAllowed(format!("{} ({})", host, url))),
+    }
+}
+
+/// Connection to a host outside of the allowlist, as transport error.
+#[derive(Debug)]
+pub struct HostNotAllowed(pub String);
+
+impl fmt::Display for HostNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for HostNotAllowed {}
+
+/// Connector refusing connections (of requests and their redirects) to hosts outside of the allowlist, before
+/// connecting.
+#[derive(Debug)]
+pub struct HostAllowlistConnector(pub Option<Vec<String>>);
+
+impl<In: Transport> Connector<In> for HostAllowlistConnector {
+    type Out = In;
+
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, ureq::Error> {
+        if let Some(allowed_hosts) = self.0.as_ref() {
+            let host = details.uri.host().unwrap_or_default();
+            if !is_allowed(host, allowed_hosts) {
+                return Err(ureq::Error::Other(Box::new(HostNotAllowed(format!(
+                    "{} ({})",
+                    host, details.uri
+                )))));
+            }
+        }
+        Ok(chained)
+    }
+}
@@ -0,0 +1,116 @@
+// audit.rs
+//
+// This module contains the append-only audit log of asset retrievals (`IGNITION_AUDIT_LOG`): what was retrieved,
+// from where and by which build, as one JSON object per line.
+//
+
+use std::{
+    env::var,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use serde::Serialize;
+
+use super::{
+    checksum, env_value,
+    metrics::AssetMetrics,
+    source::{unix_time, utc_datetime},
+};
+use crate::IgnitionResult;
+
+const AUDIT_LOG_VARIABLE: &str = "IGNITION_AUDIT_LOG";
+
+/// Audit log entry of an asset retrieval.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    /// UTC time of the retrieval (RFC 3339).
+    timestamp: String,
+    asset: &'a str,
+    target: &'a str,
+    /// Url the archive was downloaded from, None on cache hits.
+    url: Option<&'a str>,
+    cache_hit: bool,
+    /// Local path of the archive.
+    archive: Option<String>,
+    /// Lowercase hex SHA-256 digest of the archive, None if it doesn't exist (e.g. failed retrieval).
+    sha256: Option<String>,
+    /// Error of a failed retrieval.
+    error: Option<String>,
+    build: BuildInfo,
+}
+
+/// Build performing the retrieval, from the cargo build script environment.
+#[derive(Serialize)]
+struct BuildInfo {
+    package: Option<String>,
+    package_version: Option<String>,
+    profile: Option<String>,
+    host: Option<String>,
+    hostname: Option<String>,
+    user: Option<String>,
+    process_id: u32,
+}
+
+impl BuildInfo {
+    fn current() -> Self {
+        BuildInfo {
+            package: var("CARGO_PKG_NAME").ok(),
+            package_version: var("CARGO_PKG_VERSION").ok(),
+            profile: var("PROFILE").ok(),
+            host: var("HOST").ok(),
+            hostname: var("HOSTNAME")
+                .or_else(|_| var("COMPUTERNAME"))
+                .ok()
+                .or_else(|| {
+                    fs::read_to_string("/etc/hostname")
+                        .ok()
+                        .map(|hostname| hostname.trim().to_string())
+                }),
+            user: var("USER").or_else(|_| var("USERNAME")).ok(),
+            process_id: std::process::id(),
+        }
+    }
+}
+
+/// Append the retrieval described by <asset_metrics> with <result> to the audit log, if enabled
+/// (`IGNITION_AUDIT_LOG=<path>`).
+///
+/// Each entry is appended with a single write to a file opened in append mode, so concurrent builds sharing the
+/// log don't interleave entries.
+pub fn record<T>(asset_metrics: &AssetMetrics, result: &IgnitionResult<T>) -> IgnitionResult<()> {
+    let Some(path) = env_value::<PathBuf>(AUDIT_LOG_VARIABLE)? else {
+        return Ok(());
+    };
+    let archive = asset_metrics
+        .archive_path
+        .as_deref()
+        .filter(|archive| archive.exists());
+    let (year, month, day, hour, minute, second) = utc_datetime(unix_time());
+    let entry = AuditEntry {
+        timestamp: format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, hour, minute, second
+        ),
+        asset: &asset_metrics.asset,
+        target: &asset_metrics.target,
+        url: asset_metrics.url.as_deref(),
+        cache_hit: asset_metrics.cache_hit,
+        archive: archive.map(|archive| archive.display().to_string()),
+        sha256: archive.map(checksum::sha256_file).transpose()?,
+        error: result.as_ref().err().map(|err| err.to_string()),
+        build: BuildInfo::current(),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(&entry)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,328 @@
+// bundle.rs
+//
+// This module contains cache bundles: the cached archives locked by a lockfile exported into a single tarball, and
+// imported (verified) into the cache of another machine, e.g. for hermetic CI builds without network access.
+//
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Builder, EntryType, Header};
+
+use super::{
+    cache, checksum,
+    download::{self, PARTIAL_EXTENSION},
+    install::TREE_EXTENSION,
+    lock::{LOCK_EXTENSION, PathLock},
+    lockfile::Lockfile,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+/// Path of the manifest within a bundle, its first entry.
+const MANIFEST_NAME: &str = "ignition-bundle.json";
+const MANIFEST_VERSION: u32 = 1;
+
+/// Manifest of a bundle: the archives it holds, with their side files.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleManifest {
+    /// Manifest format version.
+    pub version: u32,
+    /// Archives of the bundle.
+    pub archives: Vec<BundledArchive>,
+}
+
+/// Archive of a bundle.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundledArchive {
+    /// Asset name, as locked.
+    pub asset: String,
+    /// Target of the asset (bucket path `<os>/<arch>[/<environment>][/<variant>]`), as locked.
+    pub platform: String,
+    /// Path of the archive relative to the cache directory (with `/` separators).
+    pub path: String,
+    /// Lowercase hex SHA-256 digest of the archive.
+    pub sha256: String,
+    /// File names of the side files of the archive (e.g. its record and signatures), next to it.
+    pub side_files: Vec<String>,
+}
+
+/// Export the archives of the cache at <directory> locked by the lockfile at <lockfile> (see
+/// `lockfile::Lockfile`) into the gzipped tarball at <bundle_path>.
+///
+/// Archives are found by their records (see `cache::manifest`, written on every retrieval), and exported with their
+/// side files (records, published checksums, signatures, ...), except for lock files, partial downloads and
+/// extracted trees. The tarball starts with its manifest (`ignition-bundle.json`, see `BundleManifest`). Locked
+/// archives missing in the cache are an error, so a bundle always holds everything the lockfile locks.
+/// Return is the manifest of the bundle.
+pub fn export(
+    directory: &Path,
+    lockfile: &Path,
+    bundle_path: &Path,
+) -> IgnitionResult<BundleManifest> {
+    if !lockfile.is_file() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{}: no lockfile",
+            lockfile.display()
+        )));
+    }
+    let mut locked: HashMap<String, (String, String)> = HashMap::new();
+    for (asset, platforms) in Lockfile::load(lockfile)?.assets {
+        for (platform, locked_asset) in platforms {
+            locked
+                .entry(locked_asset.sha256)
+                .or_insert_with(|| (asset.clone(), platform));
+        }
+    }
+    let cached: BTreeMap<String, PathBuf> = cache::manifest(directory)?
+        .archives
+        .into_iter()
+        .map(|archive| (archive.record.sha256, archive.path))
+        .collect();
+    let mut archives = Vec::new();
+    let mut missing = Vec::new();
+    for (sha256, (asset, platform)) in locked {
+        let Some(archive_path) = cached.get(&sha256) else {
+            missing.push(format!("{} {}", asset, platform));
+            continue;
+        };
+        archives.push(BundledArchive {
+            asset,
+            platform,
+            path: relative_path(directory, archive_path),
+            side_files: side_files(archive_path)?,
+            sha256,
+        });
+    }
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{}: locked assets not in the cache {}: {}",
+            lockfile.display(),
+            directory.display(),
+            missing.join(", ")
+        )));
+    }
+    archives.sort_by(|a, b| a.path.cmp(&b.path));
+    let manifest = BundleManifest {
+        version: MANIFEST_VERSION,
+        archives,
+    };
+
+    let partial_path = download::partial_path(bundle_path);
+    let mut builder = Builder::new(GzEncoder::new(
+        File::create(&partial_path)?,
+        Compression::default(),
+    ));
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_entry_type(EntryType::Regular);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+    for archive in manifest.archives.iter() {
+        let archive_path = directory.join(&archive.path);
+        // a cache entry is never exported while it is being replaced, nor if corrupt
+        let _lock = PathLock::acquire(&archive_path)?;
+        checksum::verify(
+            &archive_path,
+            &checksum::Checksum::parse(&archive.sha256, checksum::HashAlgorithm::Sha256)?,
+        )?;
+        builder.append_path_with_name(&archive_path, &archive.path)?;
+        for side_file in archive.side_files.iter() {
+            builder.append_path_with_name(
+                archive_path.with_file_name(side_file),
+                side_path(&archive.path, side_file),
+            )?;
+        }
+        println!("--exported: {}", archive_path.display());
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&partial_path, bundle_path)?;
+    Ok(manifest)
+}
+
+/// Import the bundle at <bundle_path> (see `export`) into the cache at <directory>, verifying it against the
+/// lockfile at <lockfile> first.
+///
+/// The bundle is unpacked into a staging directory next to the cache (`<directory>.part`): entries must be the
+/// regular files listed in its manifest (at relative paths within the cache), every archive must match its
+/// manifest digest, and every digest must be locked by the lockfile, otherwise nothing is imported. Verified
+/// archives are then moved into the cache with their side files, replacing cached copies.
+/// Return is the manifest of the bundle.
+pub fn import(
+    bundle_path: &Path,
+    directory: &Path,
+    lockfile: &Path,
+) -> IgnitionResult<BundleManifest> {
+    if !lockfile.is_file() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{}: no lockfile",
+            lockfile.display()
+        )));
+    }
+    // concurrent imports into the cache take turns on its staging directory
+    let _lock = PathLock::acquire(directory)?;
+    let staging_path = download::partial_path(directory);
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)?;
+    }
+    fs::create_dir_all(&staging_path)?;
+    let result = unpack(bundle_path, &staging_path, lockfile).and_then(|manifest| {
+        for archive in manifest.archives.iter() {
+            let archive_path = directory.join(&archive.path);
+            if let Some(parent) = archive_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let _lock = PathLock::acquire(&archive_path)?;
+            // side files of the cached copy (e.g. of another archive) are replaced as a whole
+            if archive_path.exists() {
+                for side_file in side_files(&archive_path)? {
+                    fs::remove_file(archive_path.with_file_name(side_file))?;
+                }
+            }
+            fs::rename(staging_path.join(&archive.path), &archive_path)?;
+            for side_file in archive.side_files.iter() {
+                fs::rename(
+                    staging_path.join(side_path(&archive.path, side_file)),
+                    archive_path.with_file_name(side_file),
+                )?;
+            }
+            println!("--imported: {}", archive_path.display());
+        }
+        Ok(manifest)
+    });
+    fs::remove_dir_all(&staging_path)?;
+    result
+}
+
+/// Unpack the bundle at <bundle_path> into <staging_path>, verifying it against the lockfile at <lockfile> (see
+/// `import`). Return is the manifest of the bundle.
+fn unpack(
+    bundle_path: &Path,
+    staging_path: &Path,
+    lockfile: &Path,
+) -> IgnitionResult<BundleManifest> {
+    let invalid = |reason: String| {
+        IgnitionError::ConfigurationError(format!("{}: {}", bundle_path.display(), reason))
+    };
+    let mut tarball = Archive::new(GzDecoder::new(File::open(bundle_path)?));
+    let mut entries = tarball.entries()?;
+    let manifest: BundleManifest = match entries.next().transpose()? {
+        Some(mut entry) if entry.path()?.as_ref() == Path::new(MANIFEST_NAME) => {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            serde_json::from_str(&contents)?
+        }
+        _ => return Err(invalid(format!("no {} manifest", MANIFEST_NAME))),
+    };
+    if manifest.version != MANIFEST_VERSION {
+        return Err(invalid(format!(
+            "unsupported bundle version {}",
+            manifest.version
+        )));
+    }
+    let mut expected: HashMap<String, Option<&str>> = HashMap::new();
+    for archive in manifest.archives.iter() {
+        expected.insert(archive.path.clone(), Some(&archive.sha256));
+        for side_file in archive.side_files.iter() {
+            expected.insert(side_path(&archive.path, side_file), None);
+        }
+    }
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let name = relative_path(Path::new(""), &path);
+        if entry.header().entry_type() != EntryType::Regular
+            || !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            || expected.remove(&name).is_none()
+        {
+            return Err(invalid(format!("unexpected entry {}", path.display())));
+        }
+        let destination = staging_path.join(&path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&destination)?)?;
+    }
+    if !expected.is_empty() {
+        let mut missing: Vec<String> = expected.into_keys().collect();
+        missing.sort();
+        return Err(invalid(format!("missing entries {}", missing.join(", "))));
+    }
+    let lockfile_contents = Lockfile::load(lockfile)?;
+    let locked: Vec<&str> = lockfile_contents
+        .assets
+        .values()
+        .flat_map(|platforms| platforms.values())
+        .map(|locked_asset| locked_asset.sha256.as_str())
+        .collect();
+    for archive in manifest.archives.iter() {
+        if !locked.contains(&archive.sha256.as_str()) {
+            return Err(invalid(format!(
+                "{} {} not locked by {}",
+                archive.asset,
+                archive.platform,
+                lockfile.display()
+            )));
+        }
+        checksum::verify(
+            &staging_path.join(&archive.path),
+            &checksum::Checksum::parse(&archive.sha256, checksum::HashAlgorithm::Sha256)?,
+        )?;
+    }
+    Ok(manifest)
+}
+
+/// File names of the side files of the cached archive at <archive_path> (e.g. `<archive>.cache.json`), except
+/// for lock files, partial downloads and extracted trees (with their stamps), sorted.
+fn side_files(archive_path: &Path) -> IgnitionResult<Vec<String>> {
+    let file_name = archive_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let prefix = format!("{}.", file_name);
+    // the stamp and digests of the extracted tree go with the tree
+    let tree_prefix = format!("{}{}", file_name, TREE_EXTENSION);
+    let mut side_files = Vec::new();
+    for entry in fs::read_dir(archive_path.parent().unwrap_or(Path::new(".")))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file()
+            && name.starts_with(&prefix)
+            && !name.starts_with(&tree_prefix)
+            && !name.ends_with(LOCK_EXTENSION)
+            && !name.ends_with(PARTIAL_EXTENSION)
+        {
+            side_files.push(name);
+        }
+    }
+    side_files.sort();
+    Ok(side_files)
+}
+
+/// Path of the side file <side_file> of the archive at the bundle path <archive_path>.
+fn side_path(archive_path: &str, side_file: &str) -> String {
+    match archive_path.rsplit_once('/') {
+        Some((parent, _)) => format!("{}/{}", parent, side_file),
+        None => side_file.to_string(),
+    }
+}
+
+/// Path of <path> relative to <directory>, with `/` separators.
+fn relative_path(directory: &Path, path: &Path) -> String {
+    path.strip_prefix(directory)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
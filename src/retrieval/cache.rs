@@ -0,0 +1,511 @@
+// cache.rs
+//
+// This module contains the location of the machine-global asset cache: archives are cached outside of the build
+// directory by default, so workspaces, targets and clean builds share downloads rather than retrieving every asset
+// again. Cached archives and extracted trees are recorded with their digests (and archives with their provenance),
+// so the cache can be verified (and repaired) and audited as a whole. A read-only cache (e.g. shared over NFS by
+// CI runners) may be layered under the cache, cached files missing being copied from it rather than retrieved
+// again. The layout of every cache is versioned, so caches of earlier layouts are migrated (or set aside) rather
+// than mixed with entries of the current layout.
+//
+
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    STAMP_EXTENSION, checksum,
+    download::{self, sidecar_path},
+    install::TREE_EXTENSION,
+    lock::PathLock,
+    readonly, rerun, source, vendor,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+/// Migration of the cache at a cache root to the next layout version.
+type Migration = fn(&Path) -> IgnitionResult<()>;
+
+/// Version of the layout of the cache (paths of cached archives, files and their side files), recorded at the root
+/// of every cache (see `check_layout`). Bumped on every incompatible change of the layout, with a migration from
+/// the previous version in `MIGRATIONS` where possible.
+pub const LAYOUT_VERSION: u32 = 1;
+/// File name of the layout version of a cache, at its root.
+pub(crate) const LAYOUT_FILE_NAME: &str = "ignition-cache-layout";
+/// Migrations of the cache layout to the next version, by version migrated from.
+const MIGRATIONS: &[(u32, Migration)] = &[(0, adopt_unversioned)];
+const CACHE_DIR_VARIABLE: &str = "IGNITION_CACHE_DIR";
+const LOWER_CACHE_DIR_VARIABLE: &str = "IGNITION_CACHE_LOWER_DIR";
+const XDG_CACHE_HOME_VARIABLE: &str = "XDG_CACHE_HOME";
+const CARGO_HOME_VARIABLE: &str = "CARGO_HOME";
+const HOME_VARIABLE: &str = "HOME";
+const LOCAL_APP_DATA_VARIABLE: &str = "LOCALAPPDATA";
+/// Name of the directory of the cache within the cache directories of the user and Cargo.
+const CACHE_NAME: &str = "ignition";
+/// File extension of the record of a cached archive, next to it.
+const RECORD_EXTENSION: &str = ".cache.json";
+/// File extension of the digests of the files of an extracted tree (`SHA256SUMS` format), next to it.
+const TREE_SUMS_EXTENSION: &str = ".sha256";
+
+/// Directory of the machine-global cache: `IGNITION_CACHE_DIR`, otherwise `$XDG_CACHE_HOME/ignition`, otherwise
+/// `$CARGO_HOME/ignition`, otherwise the cache directory of the user (`~/.cache/ignition`,
+/// `~/Library/Caches/ignition` on macOS or `%LOCALAPPDATA%\ignition` on Windows). Return is None if neither is
+/// known (e.g. without a home directory).
+pub fn global_dir() -> Option<PathBuf> {
+    let variable = |name| {
+        rerun::record_variable(name);
+        env::var_os(name).filter(|value| !value.is_empty())
+    };
+    if let Some(directory) = variable(CACHE_DIR_VARIABLE) {
+        return Some(PathBuf::from(directory));
+    }
+    if let Some(directory) =
+        variable(XDG_CACHE_HOME_VARIABLE).or_else(|| variable(CARGO_HOME_VARIABLE))
+    {
+        return Some(PathBuf::from(directory).join(CACHE_NAME));
+    }
+    let directory = match cfg!(windows) {
+        true => PathBuf::from(variable(LOCAL_APP_DATA_VARIABLE)?),
+        false if cfg!(target_os = "macos") => PathBuf::from(variable(HOME_VARIABLE)?)
+            .join("Library")
+            .join("Caches"),
+        false => PathBuf::from(variable(HOME_VARIABLE)?).join(".cache"),
+    };
+    Some(directory.join(CACHE_NAME))
+}
+
+/// Cache path of the assets of <bucket_url> in the machine-global cache (see `global_dir`), for `retrieve`:
+/// `<global>/<digest>`, keyed by the bucket url, so projects retrieving assets of the same name from different
+/// buckets never share (or overwrite) each other's archives. Return is None without a global cache directory.
+pub fn global_path(bucket_url: &str) -> Option<PathBuf> {
+    let digest = source::hex(&Sha256::digest(bucket_url.trim_end_matches('/').as_bytes()));
+    Some(global_dir()?.join(&digest[..16]))
+}
+
+/// Directory of the read-only lower cache: the vendored directory in vendored mode (see `vendor::dir`), otherwise
+/// `IGNITION_CACHE_LOWER_DIR`, None if unset.
+pub fn lower_dir() -> Option<PathBuf> {
+    vendor::dir().or_else(|| {
+        rerun::record_variable(LOWER_CACHE_DIR_VARIABLE);
+        env::var_os(LOWER_CACHE_DIR_VARIABLE)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+/// Path of the cached file at <path> relative to the cache at <cache_root> (the cache path), as shared with other
+/// machines (see `copy_from_lower` and `remote`): relative to the machine-global cache directory as a whole if
+/// <cache_root> is within it (see `global_dir`), otherwise to <cache_root>. Return is None outside of the cache.
+pub fn relative_path(path: &Path, cache_root: &Path) -> Option<PathBuf> {
+    let base = match global_dir() {
+        Some(global_dir) if cache_root.starts_with(&global_dir) => global_dir,
+        _ => cache_root.to_path_buf(),
+    };
+    path.strip_prefix(&base).ok().map(Path::to_path_buf)
+}
+
+/// Copy the cached file at <path> (and its validators and published digests) from the read-only lower cache (see
+/// `lower_dir`), if there. The lower cache has the layout of the cache at <cache_root> (see `relative_path`), e.g.
+/// a copy of the cache of another machine. Nothing is ever written to (nor locked within) the lower cache, and
+/// files are copied rather than linked, as the cache modifies their metadata (see `gc::touch`).
+/// Return is true if the file was copied.
+pub fn copy_from_lower(path: &Path, cache_root: &Path) -> IgnitionResult<bool> {
+    let Some(lower_dir) = lower_dir() else {
+        return Ok(false);
+    };
+    let Some(relative_path) = relative_path(path, cache_root) else {
+        return Ok(false);
+    };
+    let lower_path = lower_dir.join(relative_path);
+    if !lower_path.is_file() {
+        return Ok(false);
+    }
+    println!("--cache lower: {}", lower_path.display());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let sums_extension = format!(".{}", checksum::SUMS_FILE_NAME);
+    // side files go first, so a complete copy of the file is never without them
+    for extension in [download::VALIDATORS_EXTENSION, sums_extension.as_str()] {
+        let lower_side_path = sidecar_path(&lower_path, extension);
+        if lower_side_path.is_file() {
+            copy_file(&lower_side_path, &sidecar_path(path, extension))?;
+        }
+    }
+    // copied into a partial file first, so an existing file at <path> is always complete
+    let partial_path = download::partial_path(path);
+    copy_file(&lower_path, &partial_path)?;
+    fs::rename(&partial_path, path)?;
+    Ok(true)
+}
+
+/// Copy the contents of the file at <source> into a file at <path> created writable, whatever the permissions of
+/// <source> (e.g. read-only files of the lower cache).
+fn copy_file(source: &Path, path: &Path) -> IgnitionResult<()> {
+    io::copy(&mut File::open(source)?, &mut File::create(path)?)?;
+    Ok(())
+}
+
+/// Check the layout of the cache at <cache_root> (the cache path), before anything is cached or locked in it.
+///
+/// New caches are recorded at the current layout (`ignition-cache-layout`, see `LAYOUT_VERSION`), caches without a
+/// recorded layout being of the unversioned layout (version 0). Caches of earlier layouts are migrated version by
+/// version (see `MIGRATIONS`), or moved aside if they can't be (`<cache_root>.v<version>`, evicted as any cache
+/// entry if within the machine-global cache, see `gc::collect`), the cache starting afresh. Caches of later layouts
+/// (written by later versions of ignition) are an error, rather than being mixed with entries of this layout.
+pub fn check_layout(cache_root: &Path) -> IgnitionResult<()> {
+    if layout_version(cache_root)? == Some(LAYOUT_VERSION) {
+        return Ok(());
+    }
+    // concurrent builds check the layout in turn
+    let _lock = PathLock::acquire(cache_root)?;
+    let mut version = match layout_version(cache_root)? {
+        Some(version) => version,
+        None if !cache_root.exists() || fs::read_dir(cache_root)?.next().is_none() => {
+            LAYOUT_VERSION
+        }
+        None => 0,
+    };
+    if version > LAYOUT_VERSION {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{}: cache layout version {} is later than supported version {} (upgrade ignition, or set a separate \
+             IGNITION_CACHE_PATH)",
+            cache_root.display(),
+            version,
+            LAYOUT_VERSION
+        )));
+    }
+    while version < LAYOUT_VERSION {
+        let Some((_, migrate)) = MIGRATIONS.iter().find(|(from, _)| *from == version) else {
+            let aside_path = PathBuf::from(format!("{}.v{}", cache_root.display(), version));
+            if aside_path.exists() {
+                fs::remove_dir_all(&aside_path)?;
+            }
+            fs::rename(cache_root, &aside_path)?;
+            println!(
+                "--cache layout: {} set aside (version {}, not migrated to {})",
+                aside_path.display(),
+                version,
+                LAYOUT_VERSION
+            );
+            version = LAYOUT_VERSION;
+            break;
+        };
+        migrate(cache_root)?;
+        println!(
+            "--cache layout: {} migrated from version {} to {}",
+            cache_root.display(),
+            version,
+            version + 1
+        );
+        version += 1;
+    }
+    fs::create_dir_all(cache_root)?;
+    fs::write(cache_root.join(LAYOUT_FILE_NAME), version.to_string())?;
+    Ok(())
+}
+
+/// Layout version of the cache at <cache_root> (see `check_layout`), None if not recorded.
+pub fn layout_version(cache_root: &Path) -> IgnitionResult<Option<u32>> {
+    let contents = match fs::read_to_string(cache_root.join(LAYOUT_FILE_NAME)) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    contents.trim().parse().map(Some).map_err(|_| {
+        IgnitionError::ConfigurationError(format!(
+            "{}: invalid cache layout version {}",
+            cache_root.join(LAYOUT_FILE_NAME).display(),
+            contents.trim()
+        ))
+    })
+}
+
+/// Migrate the cache at <cache_root> from the unversioned layout (version 0) to version 1: paths are unchanged, and
+/// records missing fields of later versions are read with defaults (see `CacheRecord`), so entries are kept as is.
+fn adopt_unversioned(_cache_root: &Path) -> IgnitionResult<()> {
+    Ok(())
+}
+
+/// Record of an archive in the cache (`<archive>.cache.json`), written once it is verified.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheRecord {
+    /// Url the archive was retrieved from.
+    pub url: String,
+    /// Lowercase hex SHA-256 digest of the archive.
+    pub sha256: String,
+    /// Name of the asset of the archive (absent from earlier records).
+    #[serde(default)]
+    pub asset: Option<String>,
+    /// Time the archive was recorded, in seconds since the Unix epoch (absent from earlier records).
+    #[serde(default)]
+    pub retrieved: Option<u64>,
+    /// Version of ignition recording the archive (absent from earlier records).
+    #[serde(default)]
+    pub ignition_version: Option<String>,
+}
+
+impl CacheRecord {
+    /// Load the record of the cached archive at <archive_path>, None if absent or unreadable.
+    pub fn load(archive_path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(sidecar_path(archive_path, RECORD_EXTENSION)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Record the cached archive at <archive_path>, unless already recorded with its digest, asset and URL (e.g.
+    /// from a mirror).
+    pub fn save(&self, archive_path: &Path) -> IgnitionResult<()> {
+        if CacheRecord::load(archive_path).is_some_and(|record| {
+            record.sha256 == self.sha256 && record.asset == self.asset && record.url == self.url
+        }) {
+            return Ok(());
+        }
+        fs::write(
+            sidecar_path(archive_path, RECORD_EXTENSION),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Recorded archive of the cache, see `manifest`.
+#[derive(Debug, Serialize)]
+pub struct RecordedArchive {
+    /// Path of the cached archive.
+    pub path: PathBuf,
+    /// Record of the archive.
+    #[serde(flatten)]
+    pub record: CacheRecord,
+}
+
+/// Manifest of the cache: the records of its archives, see `manifest`.
+#[derive(Debug, Default, Serialize)]
+pub struct CacheManifest {
+    /// Recorded archives, by path.
+    pub archives: Vec<RecordedArchive>,
+}
+
+/// Collect the manifest of the cache at <directory> (recursively) from the records of its archives (see
+/// `CacheRecord`), e.g. to identify archives retrieved from untrusted urls, long ago or by an earlier version.
+/// Archives without records (e.g. partial downloads, or cached before records) aren't listed.
+pub fn manifest(directory: &Path) -> IgnitionResult<CacheManifest> {
+    let mut manifest = CacheManifest::default();
+    if directory.is_dir() {
+        recorded_archives(directory, &mut manifest.archives)?;
+    }
+    manifest.archives.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(manifest)
+}
+
+/// Collect the recorded archives within the cache <directory> (recursively) into <archives>.
+fn recorded_archives(directory: &Path, archives: &mut Vec<RecordedArchive>) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir()
+            && !entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(TREE_EXTENSION)
+        {
+            recorded_archives(&entry.path(), archives)?;
+        } else if file_type.is_file()
+            && let Some(record) = CacheRecord::load(&entry.path())
+        {
+            archives.push(RecordedArchive {
+                path: entry.path(),
+                record,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Record the digests of the files of the extracted tree at <tree_path> (`<archive>.extracted.sha256`).
+pub fn record_tree(tree_path: &Path) -> IgnitionResult<()> {
+    let mut sums = String::new();
+    for (relative_path, digest) in readonly::digests(tree_path)? {
+        sums.push_str(&format!("{}  {}\n", digest, relative_path));
+    }
+    fs::write(sidecar_path(tree_path, TREE_SUMS_EXTENSION), sums)?;
+    Ok(())
+}
+
+/// Repair of the corrupt entries found by `verify_cache`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CacheRepair {
+    /// Corrupt entries are only reported.
+    #[default]
+    Report,
+    /// Corrupt archives and trees are deleted, to be retrieved (or extracted) again by the next build.
+    Delete,
+    /// Corrupt archives are fetched again from their recorded url (and verified), corrupt trees deleted.
+    Refetch,
+}
+
+/// Result of the verification of the cache.
+#[derive(Debug, Default)]
+pub struct CacheVerification {
+    /// Paths of the archives and extracted trees verified intact.
+    pub verified: Vec<PathBuf>,
+    /// Paths of the corrupt archives and extracted trees, with the reason.
+    pub corrupt: Vec<(PathBuf, String)>,
+    /// Paths of the corrupt entries repaired (see `CacheRepair`).
+    pub repaired: Vec<PathBuf>,
+    /// Paths of the entries not verified, locked by a retrieval in progress.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Verify the cache at <directory> (recursively), hashing every recorded archive and extracted tree again.
+///
+/// Archives are verified against their record (see `CacheRecord`), otherwise the `SHA256SUMS` file kept next to
+/// them (see `checksum::published`), and complete extracted trees (`<archive>.extracted`, see
+/// `install::tree_path`) against the digests of their files (see `record_tree`), files added or removed being
+/// corruption too. Files without digests (e.g. cached before records) aren't verified. Corrupt entries are
+/// repaired according to <repair>, and entries locked by a retrieval in progress (see `lock::PathLock`) skipped.
+pub fn verify_cache(directory: &Path, repair: CacheRepair) -> IgnitionResult<CacheVerification> {
+    let mut verification = CacheVerification::default();
+    if directory.is_dir() {
+        verify_directory(directory, repair, &mut verification)?;
+    }
+    Ok(verification)
+}
+
+/// Verify the cache <directory> into <verification>, see `verify_cache`.
+fn verify_directory(
+    directory: &Path,
+    repair: CacheRepair,
+    verification: &mut CacheVerification,
+) -> IgnitionResult<()> {
+    let mut paths = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for path in paths {
+        // side files of repaired entries are deleted with them
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if metadata.is_dir() {
+            match name.strip_suffix(TREE_EXTENSION) {
+                Some(archive_name) => {
+                    verify_tree(&directory.join(archive_name), repair, verification)?
+                }
+                None => verify_directory(&path, repair, verification)?,
+            }
+        } else if metadata.is_file() {
+            verify_archive(&path, repair, verification)?;
+        }
+    }
+    Ok(())
+}
+
+/// Verify the cached archive at <archive_path> into <verification>, if it has recorded or published digests.
+fn verify_archive(
+    archive_path: &Path,
+    repair: CacheRepair,
+    verification: &mut CacheVerification,
+) -> IgnitionResult<()> {
+    let record = CacheRecord::load(archive_path);
+    let expected = match record.as_ref() {
+        Some(record) => Some(record.sha256.clone()),
+        None => fs::read_to_string(sidecar_path(
+            archive_path,
+            &format!(".{}", checksum::SUMS_FILE_NAME),
+        ))
+        .ok()
+        .and_then(|sums| {
+            let file_name = archive_path.file_name()?.to_string_lossy();
+            checksum::parse_sums(&sums).remove(file_name.as_ref())
+        }),
+    };
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let Some(_lock) = PathLock::try_acquire(archive_path)? else {
+        println!("--skipped: {} (in use)", archive_path.display());
+        verification.skipped.push(archive_path.to_path_buf());
+        return Ok(());
+    };
+    let actual = checksum::sha256_file(archive_path)?;
+    if actual == expected {
+        println!("--verified: {}", archive_path.display());
+        verification.verified.push(archive_path.to_path_buf());
+        return Ok(());
+    }
+    let reason = format!("sha256 {}, expected {}", actual, expected);
+    println!("--corrupt: {} ({})", archive_path.display(), reason);
+    verification
+        .corrupt
+        .push((archive_path.to_path_buf(), reason));
+    if repair == CacheRepair::Report {
+        return Ok(());
+    }
+    fs::remove_file(archive_path)?;
+    fs::remove_file(sidecar_path(archive_path, download::VALIDATORS_EXTENSION)).ok();
+    if let (CacheRepair::Refetch, Some(record)) = (repair, record) {
+        let refetched = download::download(&record.url, archive_path).and_then(|_| {
+            checksum::verify(
+                archive_path,
+                &checksum::Checksum::parse(&record.sha256, checksum::HashAlgorithm::Sha256)?,
+            )
+        });
+        if let Err(err) = refetched {
+            println!("--repair failed: {} ({})", archive_path.display(), err);
+            fs::remove_file(archive_path).ok();
+            return Ok(());
+        }
+        println!("--repaired: {} (fetched again)", archive_path.display());
+    } else {
+        println!("--repaired: {} (deleted)", archive_path.display());
+    }
+    verification.repaired.push(archive_path.to_path_buf());
+    Ok(())
+}
+
+/// Verify the extracted tree of the cached archive at <archive_path> into <verification>, if it is complete and
+/// has recorded digests.
+fn verify_tree(
+    archive_path: &Path,
+    repair: CacheRepair,
+    verification: &mut CacheVerification,
+) -> IgnitionResult<()> {
+    let tree_path = sidecar_path(archive_path, TREE_EXTENSION);
+    let stamp_path = sidecar_path(&tree_path, STAMP_EXTENSION);
+    let Ok(sums) = fs::read_to_string(sidecar_path(&tree_path, TREE_SUMS_EXTENSION)) else {
+        return Ok(());
+    };
+    if !stamp_path.exists() {
+        return Ok(());
+    }
+    let Some(_lock) = PathLock::try_acquire(archive_path)? else {
+        println!("--skipped: {} (in use)", tree_path.display());
+        verification.skipped.push(tree_path);
+        return Ok(());
+    };
+    let modified = readonly::modified(&tree_path, &sums)?;
+    if modified.is_empty() {
+        println!("--verified: {}", tree_path.display());
+        verification.verified.push(tree_path);
+        return Ok(());
+    }
+    let reason = format!("modified {}", modified.join(", "));
+    println!("--corrupt: {} ({})", tree_path.display(), reason);
+    verification.corrupt.push((tree_path.clone(), reason));
+    if repair == CacheRepair::Report {
+        return Ok(());
+    }
+    // the stamp goes first, so a partially deleted tree is never taken for a complete one
+    fs::remove_file(&stamp_path)?;
+    fs::remove_dir_all(&tree_path)?;
+    fs::remove_file(sidecar_path(&tree_path, TREE_SUMS_EXTENSION))?;
+    println!("--repaired: {} (deleted)", tree_path.display());
+    verification.repaired.push(tree_path);
+    Ok(())
+}
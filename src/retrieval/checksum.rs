@@ -0,0 +1,314 @@
+// checksum.rs
+//
+// This module contains checksum verification of downloaded asset archives, before extraction, with SHA-256,
+// SHA-512 or BLAKE3 digests (BLAKE3 being the fastest for multi-GB archives).
+//
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256, Sha512};
+
+use super::{
+    REQUIRE_CHECKSUM_VARIABLE,
+    download::{self, sidecar_path},
+    env_flag, redact, source,
+    source::hex,
+};
+use crate::{AssetEnvironment, IgnitionError, IgnitionResult};
+
+/// File name of the checksums published next to asset archives.
+pub const SUMS_FILE_NAME: &str = "SHA256SUMS";
+
+const SUMS_VARIABLE: &str = "IGNITION_SHA256SUMS";
+/// Read buffer size when hashing files, large enough for BLAKE3 to hash many chunks in parallel (SIMD).
+const HASH_BUFFER_SIZE: usize = 1 << 20;
+
+/// Hash algorithm of a checksum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Algorithms, in order of preference.
+    pub const ALL: [HashAlgorithm; 3] = [
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha256,
+    ];
+
+    /// Name of the algorithm, as manifest field and checksum prefix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Length of the hex digest.
+    fn hex_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Sha512 => 128,
+        }
+    }
+
+    /// Lowercase hex digest of the file at <path>.
+    pub fn digest_file(&self, path: &Path) -> IgnitionResult<String> {
+        let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, File::open(path)?);
+        Ok(match self {
+            HashAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut reader, &mut hasher)?;
+                hex(&hasher.finalize())
+            }
+            HashAlgorithm::Sha512 => {
+                let mut hasher = Sha512::new();
+                io::copy(&mut reader, &mut hasher)?;
+                hex(&hasher.finalize())
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(&mut reader, &mut hasher)?;
+                hasher.finalize().to_hex().to_string()
+            }
+        })
+    }
+}
+
+/// Expected checksum of a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    /// Lowercase hex digest.
+    pub digest: String,
+}
+
+impl Checksum {
+    /// Parse a hex digest, optionally prefixed with its algorithm (`<algorithm>:<hex digest>`), otherwise of
+    /// <default_algorithm>.
+    pub fn parse(value: &str, default_algorithm: HashAlgorithm) -> IgnitionResult<Self> {
+        let value = value.trim();
+        let (algorithm, digest) = match value.split_once(':') {
+            Some((name, digest)) => (
+                HashAlgorithm::ALL
+                    .into_iter()
+                    .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+                    .ok_or_else(|| {
+                        IgnitionError::ConfigurationError(format!(
+                            "unsupported checksum algorithm: {}",
+                            value
+                        ))
+                    })?,
+                digest,
+            ),
+            None => (default_algorithm, value),
+        };
+        if digest.len() != algorithm.hex_len() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(IgnitionError::ConfigurationError(format!(
+                "invalid {} checksum: {}",
+                algorithm.name(),
+                value
+            )));
+        }
+        Ok(Checksum {
+            algorithm,
+            digest: digest.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.digest)
+    }
+}
+
+/// Lowercase hex SHA-256 digest of the file at <path>.
+pub fn sha256_file(path: &Path) -> IgnitionResult<String> {
+    HashAlgorithm::Sha256.digest_file(path)
+}
+
+/// Verify the file at <path> against the <expected> checksum.
+pub fn verify(path: &Path, expected: &Checksum) -> IgnitionResult<()> {
+    verify_digest(path, expected, &expected.algorithm.digest_file(path)?)
+}
+
+/// Verify the hex digest <actual> of the file at <path> (computed with the algorithm of <expected>, e.g. while it
+/// was downloaded) against <expected>.
+pub fn verify_digest(path: &Path, expected: &Checksum, actual: &str) -> IgnitionResult<()> {
+    if actual != expected.digest {
+        return Err(IgnitionError::ChecksumMismatch {
+            path: path.display().to_string(),
+            expected: expected.to_string(),
+            actual: format!("{}:{}", expected.algorithm.name(), actual),
+        });
+    }
+    Ok(())
+}
+
+/// Verify the contents of an asset installed in <directory>: every <contents> path must exist, and every file
+/// in <checksums> (keyed by path, as `contents`) must match its hex SHA-256 digest (or `<algorithm>:<hex digest>`).
+pub fn verify_contents(
+    directory: &Path,
+    contents: &[String],
+    checksums: &HashMap<String, String>,
+) -> IgnitionResult<()> {
+    for content in contents.iter().chain(checksums.keys()) {
+        if !directory.join(content).exists() {
+            return Err(IgnitionError::MissingContent(
+                directory.join(content).display().to_string(),
+            ));
+        }
+    }
+    for (content, expected) in checksums.iter() {
+        verify(
+            &directory.join(content),
+            &Checksum::parse(expected, HashAlgorithm::Sha256)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Expected checksum of the archive at <archive_path> downloaded from <url>: the manifest entry of <asset_cfg>
+/// for <platform> (`blake3`, `sha512` or `sha256`, in order of preference), otherwise the entry of the archive's
+/// file name in the `SHA256SUMS` file published next to it (disabled by `IGNITION_SHA256SUMS=false`).
+///
+/// The `SHA256SUMS` file is kept next to the cached archive, and only fetched again with <fetch> (i.e. a fresh
+/// download) or if absent. Return is None if neither lists the archive.
+pub fn expected(
+    asset_cfg: &AssetEnvironment,
+    platform: &str,
+    url: &str,
+    archive_path: &Path,
+    fetch: bool,
+) -> IgnitionResult<Option<Checksum>> {
+    for algorithm in HashAlgorithm::ALL {
+        let checksums = match algorithm {
+            HashAlgorithm::Sha256 => &asset_cfg.sha256,
+            HashAlgorithm::Sha512 => &asset_cfg.sha512,
+            HashAlgorithm::Blake3 => &asset_cfg.blake3,
+        };
+        if let Some(checksum) = checksums.get(platform) {
+            return Checksum::parse(checksum, algorithm).map(Some);
+        }
+    }
+    published(url, archive_path, fetch)
+}
+
+/// Checksum of the file downloaded from <url> listed in the `SHA256SUMS` file published next to it (disabled by
+/// `IGNITION_SHA256SUMS=false`), kept next to the cached archive at <archive_path> (see `expected`, e.g. for
+/// parts of a split archive). Return is None if the file isn't listed, or no `SHA256SUMS` file is published.
+///
+/// Failing to fetch the `SHA256SUMS` file (other than it not being published) is an error with
+/// `IGNITION_REQUIRE_CHECKSUM=true`, and a warning otherwise.
+pub fn published(url: &str, archive_path: &Path, fetch: bool) -> IgnitionResult<Option<Checksum>> {
+    if !env_flag(SUMS_VARIABLE)?.unwrap_or(true) {
+        return Ok(None);
+    }
+    let Some((file_name, sums_url)) = source::sibling_url(url, SUMS_FILE_NAME) else {
+        return Ok(None);
+    };
+    let sums_path = sidecar_path(archive_path, &format!(".{}", SUMS_FILE_NAME));
+    if fetch || !sums_path.exists() {
+        let published = source::local::path(&sums_url).is_none_or(|path| path.is_file());
+        match published
+            .then(|| download::download(&sums_url, &sums_path))
+            .transpose()
+        {
+            Ok(Some(())) => {}
+            // not published, so remember to avoid requesting it on every cache hit
+            Ok(None) | Err(IgnitionError::HttpStatusError(404)) => fs::write(&sums_path, "")?,
+            Err(err) => {
+                fs::remove_file(&sums_path).ok();
+                if env_flag(REQUIRE_CHECKSUM_VARIABLE)?.unwrap_or(false) {
+                    return Err(err);
+                }
+                println!(
+                    "cargo:warning=--{} unavailable: {} ({})",
+                    SUMS_FILE_NAME,
+                    redact::redact(&sums_url),
+                    err
+                );
+                return Ok(None);
+            }
+        }
+    }
+    parse_sums(&fs::read_to_string(&sums_path)?)
+        .remove(file_name)
+        .map(|digest| Checksum::parse(&digest, HashAlgorithm::Sha256))
+        .transpose()
+}
+
+/// Parse the contents of a `SHA256SUMS` file (`<hex digest>  [*]<file name>` lines, as output by `sha256sum`)
+/// into checksums by file name.
+pub fn parse_sums(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (digest, file_name) = line.trim().split_once(char::is_whitespace)?;
+            let file_name = file_name.trim_start();
+            let file_name = file_name.strip_prefix('*').unwrap_or(file_name);
+            let file_name = file_name.strip_prefix("./").unwrap_or(file_name);
+            Some((file_name.to_string(), digest.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checksum, HashAlgorithm, parse_sums};
+
+    const SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn parse_sums_of_sha256sum_output() {
+        let sums = parse_sums(&format!(
+            "{}  asset.tar.gz\n{}  *binary.zip\n\n{}  ./nested.tar.zst\n",
+            SHA256,
+            SHA256.to_ascii_uppercase(),
+            SHA256
+        ));
+        assert_eq!(sums.len(), 3);
+        assert_eq!(sums["asset.tar.gz"], SHA256);
+        assert_eq!(sums["binary.zip"], SHA256);
+        assert_eq!(sums["nested.tar.zst"], SHA256);
+    }
+
+    #[test]
+    fn parse_checksum_with_algorithm() {
+        let checksum = Checksum::parse(SHA256, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(checksum.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(checksum.digest, SHA256);
+        let checksum = Checksum::parse(
+            &format!("BLAKE3:{}", SHA256.to_ascii_uppercase()),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        assert_eq!(checksum.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(checksum.digest, SHA256);
+        assert_eq!(
+            Checksum::parse(&format!("sha256:{}", SHA256), HashAlgorithm::Blake3)
+                .unwrap()
+                .to_string(),
+            format!("sha256:{}", SHA256)
+        );
+    }
+
+    #[test]
+    fn parse_checksum_rejects_invalid_digests() {
+        assert!(Checksum::parse(&SHA256[1..], HashAlgorithm::Sha256).is_err());
+        assert!(Checksum::parse(SHA256, HashAlgorithm::Sha512).is_err());
+        assert!(Checksum::parse(&format!("md5:{}", SHA256), HashAlgorithm::Sha256).is_err());
+        assert!(Checksum::parse(&SHA256.replace('e', "g"), HashAlgorithm::Sha256).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+// declared.rs
+//
+// This module contains assets declared by dependent crates (`[package.metadata.ignition.assets]` in their
+// `Cargo.toml`, or `declare` in their build script): arbitrary assets retrieved by the build script of the crate
+// declaring them, with the url template, contents and environment variables it declares, so crates need neither a
+// fork of the manifest nor a feature of this crate per asset.
+//
+// `retrieve_declared` must be called from the build script of the declaring crate itself: its `Cargo.toml`, its
+// `OUT_DIR` and the `cargo:rustc-env` directives it prints are those of the crate whose build script runs.
+//
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{
+    BUCKET_URL_VARIABLE, CACHE_PATH_VARIABLE, DEFAULT_CACHE_PATH, DEFAULT_DIRECTORY_PATH,
+    DIRECTORY_PATH_VARIABLE, cache, env_value, format::ManifestFormat, rerun,
+    schema::ManifestSource, vendor,
+};
+use crate::{IgnitionError, IgnitionResult, asset_config, environment_variables};
+
+/// Path of the table of declared assets within the `Cargo.toml` of the declaring crate.
+const METADATA_POINTER: &str = "/package/metadata/ignition/assets";
+
+/// Manifests of the assets declared by this build script, with their sources, in order of declaration.
+static DECLARED: Mutex<Vec<(serde_json::Value, ManifestSource)>> = Mutex::new(Vec::new());
+
+/// Declare the assets of <manifest> (an object of assets, as `config/environment.json`), merged over the embedded
+/// manifest (before the override manifest, see `crate::manifest`) for the rest of the build script, e.g.:
+/// ```text
+/// ignition::retrieval::declared::declare(serde_json::json!({
+///     "mylib": {
+///         "version": "1.2.0",
+///         "url_template": "https://example.com/mylib/{version}/mylib-{target}.tar.gz",
+///         "contents": ["mylib/lib"],
+///         "environment": { "mylib/lib": "MYLIB_LIB_DIR" }
+///     }
+/// }))?;
+/// ```
+pub fn declare(manifest: serde_json::Value) -> IgnitionResult<()> {
+    declare_from(
+        manifest,
+        ManifestSource {
+            origin: "declared assets".to_string(),
+            contents: None,
+        },
+    )
+}
+
+/// Declare the assets of <manifest> as `declare`, from <source> (e.g. `Cargo.toml`), locating its issues.
+fn declare_from(manifest: serde_json::Value, source: ManifestSource) -> IgnitionResult<()> {
+    if !manifest.is_object() {
+        return Err(IgnitionError::ConfigurationDeserializationError(
+            "declared assets: not an object of assets".to_string(),
+        ));
+    }
+    DECLARED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push((manifest, source));
+    // not while declarations are locked: the manifest is parsed with its lock held
+    crate::reset_manifest();
+    Ok(())
+}
+
+/// Manifests of the declared assets with their sources, in order of declaration, see `declare`.
+pub(crate) fn manifests() -> Vec<(serde_json::Value, ManifestSource)> {
+    DECLARED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// Retrieve the assets declared by the crate of the build script: those of the `[package.metadata.ignition.assets]`
+/// table of its `Cargo.toml` (see `declare`), and those declared before, e.g. in the build script of a crate:
+/// ```toml
+/// [package.metadata.ignition.assets.mylib]
+/// version = "1.2.0"
+/// url_template = "https://example.com/mylib/{version}/mylib-{target}.tar.gz"
+/// contents = ["mylib/lib"]
+/// environment = { "mylib/lib" = "MYLIB_LIB_DIR" }
+/// ```
+/// ```text
+/// fn main() {
+///     ignition::retrieval::declared::retrieve_declared().unwrap();
+/// }
+/// ```
+///
+/// Assets are retrieved for the target of the build (`TARGET`) as `retrieval::retrieve`, from `IGNITION_BUCKET_URL`
+/// (if set: assets of url templates without `{bucket}` don't need one) into the cache of the build (see
+/// `cache::global_path`, or `IGNITION_CACHE_PATH` relative to the build directory), shared with the assets of this
+/// crate. They are extracted into `OUT_DIR` (`<OUT_DIR>/<directory>/<triplet>/<asset>`), so extractions of the
+/// assets of each crate are its own. Their environment variables (see `environment_variables`) are exported to the
+/// crate (`cargo:rustc-env`, e.g. for `env!("MYLIB_LIB_DIR")`), and Cargo directives for the inputs of the
+/// retrievals printed (see `rerun::print_directives`).
+/// Return is the environment variables of the declared assets.
+pub fn retrieve_declared() -> IgnitionResult<HashMap<String, String>> {
+    let manifest_path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR")?).join("Cargo.toml");
+    rerun::record_path(&manifest_path);
+    let contents = std::fs::read_to_string(&manifest_path)?;
+    let origin = manifest_path.display().to_string();
+    let cargo_manifest = ManifestFormat::Toml.parse(&contents, &origin)?;
+    if let Some(assets) = cargo_manifest.pointer(METADATA_POINTER) {
+        declare_from(
+            assets.clone(),
+            ManifestSource {
+                origin,
+                contents: Some(contents),
+            },
+        )?;
+    }
+    let mut assets: Vec<String> = manifests()
+        .iter()
+        .filter_map(|(manifest, _)| manifest.as_object())
+        .flat_map(|assets| assets.keys().cloned())
+        .collect();
+    assets.sort();
+    assets.dedup();
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    let target = std::env::var("TARGET")?;
+    let bucket_url = env_value::<String>(BUCKET_URL_VARIABLE)?;
+    let cache_path = cache_path(&out_dir, bucket_url.as_deref().unwrap_or_default())?;
+    let directory_path = env_value::<String>(DIRECTORY_PATH_VARIABLE)?
+        .unwrap_or_else(|| DEFAULT_DIRECTORY_PATH.to_string());
+    let mut env_vars = HashMap::new();
+    for asset in assets {
+        // assets of the default bucket layout (or a url template using it) need a bucket
+        let bucket_url = match bucket_url.as_deref() {
+            Some(bucket_url) => bucket_url,
+            None => match asset_config(&asset)?.for_target(&target).url_template {
+                Some(url_template) if !url_template.contains("{bucket}") => "",
+                _ => {
+                    return Err(IgnitionError::ConfigurationError(format!(
+                        "{}: the url template of the asset uses {{bucket}}, but {} is not set",
+                        asset, BUCKET_URL_VARIABLE
+                    )));
+                }
+            },
+        };
+        let extract_path = super::retrieve(
+            bucket_url,
+            &asset,
+            &out_dir,
+            &cache_path.to_string_lossy(),
+            &directory_path,
+            &target,
+            None,
+        )?;
+        for (env_var, value) in environment_variables(&asset, extract_path.parent())? {
+            println!("cargo:rustc-env={}={}", env_var, value);
+            env_vars.insert(env_var, value);
+        }
+    }
+    rerun::print_directives();
+    Ok(env_vars)
+}
+
+/// Cache path of the assets declared by the crate of <out_dir> (absolute): `IGNITION_CACHE_PATH` relative to the
+/// build directory (`target/<triplet>/<profile>`, as for the assets of this crate), otherwise the machine-global
+/// cache of <bucket_url>, otherwise `cache` in the build directory, as in vendored mode (see `vendor::dir`).
+fn cache_path(out_dir: &Path, bucket_url: &str) -> IgnitionResult<PathBuf> {
+    let build_dir = super::build_dir(out_dir).ok_or_else(|| {
+        IgnitionError::ConfigurationError(format!(
+            "OUT_DIR={}: not in a build directory",
+            out_dir.display()
+        ))
+    })?;
+    Ok(match env_value::<String>(CACHE_PATH_VARIABLE)? {
+        Some(cache_path) => build_dir.join(cache_path),
+        None if vendor::dir().is_some() => build_dir.join(DEFAULT_CACHE_PATH),
+        None => {
+            cache::global_path(bucket_url).unwrap_or_else(|| build_dir.join(DEFAULT_CACHE_PATH))
+        }
+    })
+}
@@ -0,0 +1,736 @@
+// download.rs
+//
+// This module contains the HTTP(S) download logic for asset archive files.
+//
+
+use std::{
+    ffi::OsString,
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ureq::{Agent, Body, http::Response};
+
+use super::{env_size, env_value, http, offline, progress::Progress, source, throttle::Throttled};
+use crate::{IgnitionError, IgnitionResult};
+
+/// File extension appended to partially downloaded files.
+pub const PARTIAL_EXTENSION: &str = ".part";
+/// File extension appended to downloaded files for their HTTP cache validators.
+pub const VALIDATORS_EXTENSION: &str = ".validators.json";
+/// File extension appended to partially downloaded files for the record of their segments.
+pub const SEGMENTS_EXTENSION: &str = ".segments.json";
+
+const SEGMENT_SIZE_VARIABLE: &str = "IGNITION_SEGMENT_SIZE";
+const SEGMENT_CONCURRENCY_VARIABLE: &str = "IGNITION_SEGMENT_CONCURRENCY";
+const DEFAULT_SEGMENT_SIZE: u64 = 16 << 20;
+const DEFAULT_SEGMENT_CONCURRENCY: usize = 4;
+
+/// Download the contents of <url> (any scheme supported by `source`) into the file at <path>, creating parent
+/// directories as required.
+///
+/// The download is written to `<path>.part` and renamed on completion, so an existing file at <path> is always
+/// a complete download. A `<path>.part` file left by an interrupted download is resumed with a Range request;
+/// servers that ignore the Range header cause a restart from zero. Segmented downloads resume their missing segments
+/// (see `SegmentRecord`). Local sources are hard-linked or copied,
+/// sftp sources are retrieved with the OpenSSH client and delta-transfer sources with rsync/zsync. The `ETag`
+/// and `Last-Modified` of HTTP(S) downloads are recorded next to the file for `download_if_modified`.
+pub fn download(url: &str, path: &Path) -> IgnitionResult<()> {
+    download_limited(url, path, None)
+}
+
+/// Download the contents of <url> into the file at <path> as `download`, erroring (and deleting the download)
+/// if it is larger than <max_size> bytes.
+///
+/// HTTP(S) downloads are aborted as soon as the size is known to exceed the limit (from the response headers or
+/// while streaming the body), other sources are checked on completion.
+pub fn download_limited(url: &str, path: &Path, max_size: Option<u64>) -> IgnitionResult<()> {
+    // validators of a previous download no longer apply
+    fs::remove_file(sidecar_path(path, VALIDATORS_EXTENSION)).ok();
+    if let Some(source_path) = source::local::path(url) {
+        source::local::copy(&source_path, path)?;
+    } else if source::scheme(url) == "sftp" {
+        offline::check_url(url)?;
+        source::sftp::download(url, path)?;
+    } else if source::delta::is_delta(url) {
+        offline::check_url(url)?;
+        source::delta::sync(url, path)?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial_path = partial_path(path);
+        let validators = download_resumable(url, &partial_path, max_size)?;
+        fs::rename(&partial_path, path)?;
+        validators.save(path)?;
+    }
+    enforce_size_limit(url, path, max_size)
+}
+
+/// Download the contents of <url> into the file at <path> as `download_limited`, passing the response body to
+/// <consume> as it is written (e.g. to extract an archive while it is downloaded). The body is downloaded entirely,
+/// even if <consume> stops reading early or fails.
+///
+/// The body is downloaded in order over a single request, i.e. never segmented (see `SegmentConfig`), whatever its
+/// size: the extraction of a large archive overlaps its download instead.
+///
+/// Return is the output of <consume> and the hex SHA-256 digest of the file (computed while it is written), None if
+/// the download isn't streamed (local, sftp and delta-transfer sources, or a partial download to resume),
+/// <consume> not being called then.
+pub fn download_streaming<T>(
+    url: &str,
+    path: &Path,
+    max_size: Option<u64>,
+    consume: impl FnOnce(&mut (dyn Read + Send)) -> T,
+) -> IgnitionResult<Option<(T, String)>> {
+    let partial_path = partial_path(path);
+    if source::local::path(url).is_some()
+        || source::scheme(url) == "sftp"
+        || source::delta::is_delta(url)
+        || partial_path.exists()
+        || sidecar_path(&partial_path, SEGMENTS_EXTENSION).exists()
+    {
+        download_limited(url, path, max_size)?;
+        return Ok(None);
+    }
+    // validators of a previous download no longer apply
+    fs::remove_file(sidecar_path(path, VALIDATORS_EXTENSION)).ok();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let source_request = source::http_request(url)?;
+    let agent = http::agent(&source_request.url)?;
+    let mut response = get(&agent, &source_request, None)?;
+    let validators = Validators::from_response(url, &response);
+    let length = content_length(&response);
+    if let (Some(max_size), Some(length)) = (max_size, length)
+        && length > max_size
+    {
+        return Err(size_limit_error(url, length, max_size));
+    }
+    let progress = Progress::new(file_name(url), 0, length)?;
+    // one byte more than the limit allows, to detect bodies exceeding it
+    let limit = max_size.map_or(u64::MAX, |max_size| max_size + 1);
+    let mut tee = TeeReader {
+        inner: progress.reader(Throttled::new(response.body_mut().as_reader())?.take(limit)),
+        file: File::create(&partial_path)?,
+        hasher: Sha256::new(),
+        written: 0,
+        error: None,
+    };
+    let output = consume(&mut tee);
+    // errors reading the body or writing the file fail the download, not only <consume>
+    if let Some(err) = tee.error.take() {
+        return Err(err.into());
+    }
+    io::copy(&mut tee, &mut io::sink())?;
+    tee.file.sync_all()?;
+    progress.finish();
+    if let Some(max_size) = max_size
+        && tee.written > max_size
+    {
+        fs::remove_file(&partial_path)?;
+        return Err(size_limit_error(url, tee.written, max_size));
+    }
+    fs::rename(&partial_path, path)?;
+    validators.save(path)?;
+    Ok(Some((output, source::hex(&tee.hasher.finalize()))))
+}
+
+/// Reader writing everything read from <inner> into <file> (and <hasher>), recording the first error.
+struct TeeReader<R> {
+    inner: R,
+    file: File,
+    hasher: Sha256,
+    /// Number of bytes written.
+    written: u64,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Read for TeeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf).and_then(|read| {
+            self.file.write_all(&buf[..read])?;
+            self.hasher.update(&buf[..read]);
+            Ok(read)
+        });
+        match &result {
+            Ok(read) => self.written += *read as u64,
+            Err(err) if self.error.is_none() => {
+                self.error = Some(io::Error::new(err.kind(), err.to_string()))
+            }
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+/// Error (deleting the file at <path> downloaded from <url>) if it is larger than <max_size> bytes.
+pub fn enforce_size_limit(url: &str, path: &Path, max_size: Option<u64>) -> IgnitionResult<()> {
+    let Some(max_size) = max_size else {
+        return Ok(());
+    };
+    let size = fs::metadata(path)?.len();
+    if size > max_size {
+        fs::remove_file(path)?;
+        fs::remove_file(sidecar_path(path, VALIDATORS_EXTENSION)).ok();
+        return Err(size_limit_error(url, size, max_size));
+    }
+    Ok(())
+}
+
+/// Re-download the file at <path> from <url> if its remote copy was modified: delta-transfer sources are
+/// synchronized against the cached copy, other sources are requested with a conditional GET request using the
+/// validators recorded on download. Modified copies larger than <max_size> bytes are aborted as by
+/// `download_limited`, keeping the file at <path>.
+///
+/// Return is true if the file was re-downloaded, false if it is unchanged or has no recorded validators
+/// (e.g. local or sftp sources, or servers sending neither `ETag` nor `Last-Modified`).
+pub fn download_if_modified(url: &str, path: &Path, max_size: Option<u64>) -> IgnitionResult<bool> {
+    if source::delta::is_delta(url) {
+        offline::check_url(url)?;
+        return source::delta::sync(url, path);
+    }
+    let Some(validators) = Validators::load(path) else {
+        return Ok(false);
+    };
+    let mut source_request = source::http_request(&validators.url)?;
+    if let Some(etag) = validators.etag.as_ref() {
+        source_request
+            .headers
+            .push(("If-None-Match".to_string(), etag.to_string()));
+    }
+    if let Some(last_modified) = validators.last_modified.as_ref() {
+        source_request
+            .headers
+            .push(("If-Modified-Since".to_string(), last_modified.to_string()));
+    }
+    let agent = http::agent(&source_request.url)?;
+    let mut response = get(&agent, &source_request, None)?;
+    if response.status().as_u16() == 304 {
+        return Ok(false);
+    }
+    let validators = Validators::from_response(&validators.url, &response);
+    let length = content_length(&response);
+    if let (Some(max_size), Some(length)) = (max_size, length)
+        && length > max_size
+    {
+        return Err(size_limit_error(url, length, max_size));
+    }
+    let progress = Progress::new(file_name(&validators.url), 0, length)?;
+    let partial_path = partial_path(path);
+    let mut file = File::create(&partial_path)?;
+    // one byte more than the limit allows, to detect bodies exceeding it
+    let limit = max_size.map_or(u64::MAX, |max_size| max_size + 1);
+    let written = io::copy(
+        &mut progress.reader(Throttled::new(response.body_mut().as_reader())?.take(limit)),
+        &mut file,
+    )?;
+    file.sync_all()?;
+    progress.finish();
+    if let Some(max_size) = max_size
+        && written > max_size
+    {
+        fs::remove_file(&partial_path)?;
+        return Err(size_limit_error(url, written, max_size));
+    }
+    fs::rename(&partial_path, path)?;
+    validators.save(path)?;
+    Ok(true)
+}
+
+/// Path of the partially downloaded file for <path>.
+pub fn partial_path(path: &Path) -> PathBuf {
+    sidecar_path(path, PARTIAL_EXTENSION)
+}
+
+/// Path of <path> with <extension> appended.
+pub fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    let mut sidecar = OsString::from(path.as_os_str());
+    sidecar.push(extension);
+    PathBuf::from(sidecar)
+}
+
+/// HTTP cache validators of a downloaded file, stored in `<path>.validators.json`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Validators {
+    /// Url the file was downloaded from.
+    pub url: String,
+    /// `ETag` response header.
+    pub etag: Option<String>,
+    /// `Last-Modified` response header.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Validators of the <response> to a request for <url>.
+    fn from_response(url: &str, response: &Response<Body>) -> Self {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Validators {
+            url: url.to_string(),
+            etag: header("ETag"),
+            last_modified: header("Last-Modified"),
+        }
+    }
+
+    /// Load the validators recorded for the file at <path>, None if absent or unreadable.
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(sidecar_path(path, VALIDATORS_EXTENSION)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Record these validators for the file at <path>, if there are any.
+    fn save(&self, path: &Path) -> IgnitionResult<()> {
+        let validators_path = sidecar_path(path, VALIDATORS_EXTENSION);
+        if self.etag.is_none() && self.last_modified.is_none() {
+            fs::remove_file(validators_path).ok();
+            return Ok(());
+        }
+        fs::write(validators_path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Stream the response body of a GET request to <url> into <partial_path>, continuing from its current length if
+/// its validators (`ETag` or `Last-Modified`, recorded next to it) are unchanged remotely (see `If-Range`).
+///
+/// A fresh download requests the first segment only: if the server reports a larger file, the remaining
+/// segments are downloaded concurrently (see `SegmentConfig`), and an interrupted segmented download is resumed
+/// segment by segment (see `SegmentRecord`). Responses larger than <max_size> bytes are aborted, deleting the
+/// partial file. Return is the validators of the response.
+fn download_resumable(
+    url: &str,
+    partial_path: &Path,
+    max_size: Option<u64>,
+) -> IgnitionResult<Validators> {
+    let source_request = source::http_request(url)?;
+    let agent = http::agent(&source_request.url)?;
+    let segment_config = SegmentConfig::resolve()?;
+    if let Some(record) = SegmentRecord::load(partial_path) {
+        if record.validators.url == url && max_size.is_none_or(|max_size| record.total <= max_size)
+        {
+            let concurrency = segment_config
+                .as_ref()
+                .map_or(1, |config| config.concurrency);
+            if download_segmented(
+                &agent,
+                &source_request,
+                None,
+                partial_path,
+                &record,
+                concurrency,
+            )? {
+                return Ok(record.validators);
+            }
+            println!("--segments: {} changed remotely (restarting)", url);
+        }
+        record.discard(partial_path)?;
+        return download_resumable(url, partial_path, max_size);
+    }
+    // a partial file is only resumed with the validators of its response, so a file changed remotely since is
+    // downloaded afresh (the server answering `If-Range` with the whole file) rather than appended to old bytes
+    let partial_validator = Validators::load(partial_path)
+        .filter(|validators| validators.url == url)
+        .and_then(|validators| validators.etag.or(validators.last_modified));
+    let offset = match partial_validator {
+        Some(_) => fs::metadata(partial_path).map_or(0, |metadata| metadata.len()),
+        None => 0,
+    };
+    let mut request = source_request.clone();
+    let range = if offset > 0 {
+        println!("--resume: {} bytes", offset);
+        request
+            .headers
+            .extend(partial_validator.map(|validator| ("If-Range".to_string(), validator)));
+        Some(format!("bytes={}-", offset))
+    } else {
+        segment_config
+            .as_ref()
+            .map(|config| format!("bytes=0-{}", config.size - 1))
+    };
+    let mut response = match get(&agent, &request, range.as_deref()) {
+        Ok(response) => response,
+        // stale partial file (e.g. remote file replaced by a smaller one), so restart from zero
+        Err(ureq::Error::StatusCode(416)) if offset > 0 => {
+            fs::remove_file(partial_path)?;
+            fs::remove_file(sidecar_path(partial_path, VALIDATORS_EXTENSION)).ok();
+            return download_resumable(url, partial_path, max_size);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let validators = Validators::from_response(url, &response);
+    let content_range = (response.status().as_u16() == 206)
+        .then(|| content_range(response.headers()))
+        .flatten();
+    let resumed = offset > 0 && content_range.is_some_and(|(start, _, _)| start == offset);
+    if offset > 0 && !resumed {
+        println!("--resume: {} changed remotely (restarting)", url);
+    }
+    let start = if resumed { offset } else { 0 };
+    if let Some(max_size) = max_size {
+        let total = match content_range {
+            Some((_, _, total)) => Some(total),
+            None => content_length(&response).map(|length| start + length),
+        };
+        if let Some(total) = total.filter(|total| *total > max_size) {
+            fs::remove_file(partial_path).ok();
+            return Err(size_limit_error(url, total, max_size));
+        }
+    }
+    if let (0, Some(config), Some((0, _, total))) = (offset, segment_config, content_range)
+        && total > config.size
+    {
+        let record = SegmentRecord {
+            validators: validators.clone(),
+            total,
+            size: config.size,
+        };
+        record.save(partial_path)?;
+        println!(
+            "--segments: {} x {} bytes ({} concurrent)",
+            record.count(),
+            config.size,
+            config.concurrency
+        );
+        download_segmented(
+            &agent,
+            &source_request,
+            Some(response),
+            partial_path,
+            &record,
+            config.concurrency,
+        )?;
+        return Ok(validators);
+    }
+    let progress = match content_range {
+        Some((start, _, total)) => Progress::new(file_name(url), start, Some(total))?,
+        None => Progress::new(file_name(url), 0, content_length(&response))?,
+    };
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(partial_path)?
+    } else {
+        File::create(partial_path)?
+    };
+    validators.save(partial_path)?;
+    // one byte more than the limit allows, to detect bodies exceeding it
+    let limit = max_size.map_or(u64::MAX, |max_size| (max_size + 1).saturating_sub(start));
+    let written = io::copy(
+        &mut progress.reader(Throttled::new(response.body_mut().as_reader())?.take(limit)),
+        &mut file,
+    )?;
+    file.sync_all()?;
+    progress.finish();
+    fs::remove_file(sidecar_path(partial_path, VALIDATORS_EXTENSION)).ok();
+    if let Some(max_size) = max_size
+        && start + written > max_size
+    {
+        fs::remove_file(partial_path)?;
+        return Err(size_limit_error(url, start + written, max_size));
+    }
+    Ok(validators)
+}
+
+/// Configuration of segmented (parallel byte-range) downloads of large asset archives.
+///
+/// Set by `IGNITION_SEGMENT_SIZE` (default 16M, 0 disables) and `IGNITION_SEGMENT_CONCURRENCY` (default 4,
+/// 1 disables). Only archives larger than one segment are segmented, and never those extracted while they are
+/// downloaded (see `download_streaming`).
+#[derive(Clone, Debug)]
+pub struct SegmentConfig {
+    /// Size of each segment in bytes.
+    pub size: u64,
+    /// Number of segments downloaded concurrently.
+    pub concurrency: usize,
+}
+
+impl SegmentConfig {
+    /// Resolve the segment configuration from environment variables, None if disabled.
+    pub fn resolve() -> IgnitionResult<Option<Self>> {
+        let size = env_size(SEGMENT_SIZE_VARIABLE)?.unwrap_or(DEFAULT_SEGMENT_SIZE);
+        let concurrency =
+            env_value(SEGMENT_CONCURRENCY_VARIABLE)?.unwrap_or(DEFAULT_SEGMENT_CONCURRENCY);
+        Ok((size > 0 && concurrency > 1).then_some(SegmentConfig { size, concurrency }))
+    }
+}
+
+/// Record of a segmented download, stored in `<path>.part.segments.json` next to its segments: each segment is
+/// downloaded into its own part file (`<path>.part.<index>`), whose length is the progress of the segment, so an
+/// interrupted download resumes the missing segments only. The segments are joined into `<path>.part` once all are
+/// complete.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SegmentRecord {
+    /// Validators of the response to the first segment (and url of the download).
+    pub validators: Validators,
+    /// Size of the file in bytes.
+    pub total: u64,
+    /// Size of each segment in bytes.
+    pub size: u64,
+}
+
+impl SegmentRecord {
+    /// Load the record of the segmented download into <partial_path>, None if absent or unreadable.
+    pub fn load(partial_path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(sidecar_path(partial_path, SEGMENTS_EXTENSION)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Record the segmented download into <partial_path>.
+    fn save(&self, partial_path: &Path) -> IgnitionResult<()> {
+        fs::write(
+            sidecar_path(partial_path, SEGMENTS_EXTENSION),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Number of segments.
+    fn count(&self) -> u64 {
+        self.total.div_ceil(self.size)
+    }
+
+    /// First and last byte of the segment <index>.
+    fn bounds(&self, index: u64) -> (u64, u64) {
+        let start = index * self.size;
+        (start, (start + self.size).min(self.total) - 1)
+    }
+
+    /// Remove the segments and the record of the segmented download into <partial_path>, e.g. to restart it.
+    fn discard(&self, partial_path: &Path) -> IgnitionResult<()> {
+        for index in 0..self.count() {
+            fs::remove_file(segment_path(partial_path, index)).ok();
+        }
+        fs::remove_file(sidecar_path(partial_path, SEGMENTS_EXTENSION))?;
+        Ok(())
+    }
+}
+
+/// Path of the part file of the segment <index> of the segmented download into <partial_path>.
+fn segment_path(partial_path: &Path, index: u64) -> PathBuf {
+    sidecar_path(partial_path, &format!(".{:05}", index))
+}
+
+/// Download the missing segments of the segmented download of <record> into <partial_path> (at most <concurrency>
+/// at once), the first segment from <first_response> if any (a fresh download), then join them into
+/// <partial_path>. Segments are requested with `If-Range`, so a file changed remotely since the first segment
+/// (another size, or a full response) is detected rather than mixed with the segments downloaded before.
+///
+/// Return is false if the file changed remotely, the segments being kept for `SegmentRecord::discard`.
+fn download_segmented(
+    agent: &Agent,
+    source_request: &source::HttpRequest,
+    first_response: Option<Response<Body>>,
+    partial_path: &Path,
+    record: &SegmentRecord,
+    concurrency: usize,
+) -> IgnitionResult<bool> {
+    let received = |index: u64| {
+        fs::metadata(segment_path(partial_path, index)).map_or(0, |metadata| metadata.len())
+    };
+    let length = |index: u64| {
+        let (start, end) = record.bounds(index);
+        end - start + 1
+    };
+    let pending: Vec<u64> = (u64::from(first_response.is_some())..record.count())
+        .filter(|index| received(*index) != length(*index))
+        .collect();
+    let done = record.total
+        - pending
+            .iter()
+            .map(|index| length(*index) - received(*index).min(length(*index)))
+            .sum::<u64>()
+        - first_response.as_ref().map_or(0, |_| length(0));
+    if first_response.is_none() {
+        println!(
+            "--resume: {} of {} segments",
+            record.count() - pending.len() as u64,
+            record.count()
+        );
+    }
+    let progress = Progress::new(file_name(&record.validators.url), done, Some(record.total))?;
+    let mut segment_request = source_request.clone();
+    if let Some(validator) = record
+        .validators
+        .etag
+        .as_ref()
+        .or(record.validators.last_modified.as_ref())
+    {
+        segment_request
+            .headers
+            .push(("If-Range".to_string(), validator.clone()));
+    }
+    let next = AtomicUsize::new(0);
+    let changed = AtomicBool::new(false);
+    let download_next = || -> IgnitionResult<()> {
+        while let Some(&index) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+            if changed.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let (start, end) = record.bounds(index);
+            // segments longer than expected are corrupt, so downloaded again
+            if received(index) > length(index) {
+                File::create(segment_path(partial_path, index))?;
+            }
+            let offset = start + received(index);
+            let response = get(
+                agent,
+                &segment_request,
+                Some(&format!("bytes={}-{}", offset, end)),
+            )?;
+            match (
+                response.status().as_u16(),
+                content_range(response.headers()),
+            ) {
+                (206, Some((range_start, _, total)))
+                    if range_start == offset && total == record.total => {}
+                (200, _) | (206, Some(_)) => {
+                    changed.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+                _ => {
+                    return Err(IgnitionError::DownloadError(format!(
+                        "unexpected response to range request: bytes {}-{}",
+                        offset, end
+                    )));
+                }
+            }
+            write_segment(
+                &segment_path(partial_path, index),
+                offset,
+                end,
+                response,
+                &progress,
+            )?;
+        }
+        Ok(())
+    };
+    thread::scope(|scope| {
+        let handles: Vec<_> = (1..concurrency.min(pending.len().max(1)))
+            .map(|_| scope.spawn(download_next))
+            .collect();
+        let first = match first_response {
+            Some(response) => write_segment(
+                &segment_path(partial_path, 0),
+                0,
+                record.bounds(0).1,
+                response,
+                &progress,
+            ),
+            None => Ok(()),
+        }
+        .and_then(|_| download_next());
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("segment download thread panicked"))
+            .chain([first])
+            .collect::<IgnitionResult<Vec<()>>>()
+    })?;
+    if changed.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+    // the segments are only removed once joined, so an interrupted join is joined again
+    let mut file = File::create(partial_path)?;
+    for index in 0..record.count() {
+        io::copy(
+            &mut File::open(segment_path(partial_path, index))?,
+            &mut file,
+        )?;
+    }
+    file.sync_all()?;
+    record.discard(partial_path)?;
+    progress.finish();
+    Ok(true)
+}
+
+/// Append the body of <response> (bytes <start>-<end> of the file) to the part file of a segment at
+/// <segment_path>.
+fn write_segment(
+    segment_path: &Path,
+    start: u64,
+    end: u64,
+    mut response: Response<Body>,
+    progress: &Progress,
+) -> IgnitionResult<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path)?;
+    let written = io::copy(
+        &mut progress.reader(Throttled::new(response.body_mut().as_reader())?),
+        &mut file,
+    )?;
+    file.sync_all()?;
+    if written != end - start + 1 {
+        return Err(IgnitionError::DownloadError(format!(
+            "truncated segment: bytes {}-{}, received {}",
+            start, end, written
+        )));
+    }
+    Ok(())
+}
+
+/// Error of a download from <url> of <size> bytes (at least) exceeding the <max_size> limit.
+fn size_limit_error(url: &str, size: u64, max_size: u64) -> IgnitionError {
+    IgnitionError::SizeLimitExceeded(format!("{}: {} bytes, limit {} bytes", url, size, max_size))
+}
+
+/// Send a GET request (with optional Range header) for <source_request>.
+fn get(
+    agent: &Agent,
+    source_request: &source::HttpRequest,
+    range: Option<&str>,
+) -> Result<Response<Body>, ureq::Error> {
+    let mut request = agent.get(&source_request.url);
+    for (name, value) in source_request.headers.iter() {
+        request = request.header(name, value);
+    }
+    if let Some(range) = range {
+        request = request.header("Range", range);
+    }
+    request.call()
+}
+
+/// Value of the `Content-Length` header of <response>, if present.
+fn content_length(response: &Response<Body>) -> Option<u64> {
+    response
+        .headers()
+        .get("Content-Length")?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// File name of <url>, for progress reports.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Parts of a `Content-Range: bytes <start>-<end>/<size>` response header.
+fn content_range(headers: &ureq::http::HeaderMap) -> Option<(u64, u64, u64)> {
+    let value = headers
+        .get("Content-Range")?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?;
+    let (range, size) = value.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some((
+        start.trim().parse().ok()?,
+        end.trim().parse().ok()?,
+        size.trim().parse().ok()?,
+    ))
+}
@@ -0,0 +1,359 @@
+// encryption.rs
+//
+// This module contains decryption of asset archives stored encrypted at rest in the bucket (`encryption` in the
+// manifest): age encrypted archives (`<asset>.tar.gz.age`, decrypted with the `age` client) and AES-256-GCM
+// encrypted archives (`<asset>.tar.gz.enc`, with the key from the environment or a key command, e.g. a KMS
+// client), and encryption of archives at rest in the cache (`encrypt_cache` in the manifest, see `CacheSeal`).
+//
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::Deserialize;
+
+use super::{
+    cache,
+    download::{self, sidecar_path},
+    env_flag, env_value, gc,
+    remote::{self, RemoteCache},
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const AGE_IDENTITY_VARIABLE: &str = "IGNITION_AGE_IDENTITY";
+const ASSET_KEY_VARIABLE: &str = "IGNITION_ASSET_KEY";
+const ASSET_KEY_COMMAND_VARIABLE: &str = "IGNITION_ASSET_KEY_COMMAND";
+const KEY_ASSET_VARIABLE: &str = "IGNITION_KEY_ASSET";
+const CACHE_ENCRYPTION_VARIABLE: &str = "IGNITION_CACHE_ENCRYPTION";
+const CACHE_KEY_VARIABLE: &str = "IGNITION_CACHE_KEY";
+const CACHE_KEY_COMMAND_VARIABLE: &str = "IGNITION_CACHE_KEY_COMMAND";
+const DECRYPTED_EXTENSION: &str = ".decrypted";
+/// File extension of an archive sealed in the cache, next to the path of the plaintext archive.
+const SEALED_EXTENSION: &str = ".sealed";
+/// File extension of the directory of the plaintext archive of a sealed asset, next to its extraction.
+const UNSEALED_EXTENSION: &str = ".archive";
+
+/// Encryption of an asset archive in the bucket.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum Encryption {
+    /// age encryption, decrypted with the identity file `IGNITION_AGE_IDENTITY`.
+    #[serde(rename = "age")]
+    Age,
+    /// AES-256-GCM encryption of the archive, prefixed with the 12 byte nonce (`nonce || ciphertext || tag`),
+    /// decrypted with the key `IGNITION_ASSET_KEY` or printed by `IGNITION_ASSET_KEY_COMMAND` (hex or base64).
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+}
+
+impl Encryption {
+    /// File extension appended to the archive file name of encrypted archives.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encryption::Age => ".age",
+            Encryption::Aes256Gcm => ".enc",
+        }
+    }
+}
+
+/// Plaintext asset archive: the archive itself, or its decrypted copy next to it, which is deleted on drop so
+/// the plaintext doesn't persist in the cache.
+pub struct PlainArchive {
+    path: PathBuf,
+    decrypted: bool,
+}
+
+impl Deref for PlainArchive {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PlainArchive {
+    fn drop(&mut self) {
+        if self.decrypted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Determine the plaintext of the archive of <asset> at <archive_path>, decrypting it next to the archive with
+/// <encryption> (None if not encrypted).
+pub fn decrypt(
+    asset: &str,
+    archive_path: &Path,
+    encryption: Option<Encryption>,
+) -> IgnitionResult<PlainArchive> {
+    let Some(encryption) = encryption else {
+        return Ok(PlainArchive {
+            path: archive_path.to_path_buf(),
+            decrypted: false,
+        });
+    };
+    let plain_archive = PlainArchive {
+        path: sidecar_path(archive_path, DECRYPTED_EXTENSION),
+        decrypted: true,
+    };
+    match encryption {
+        Encryption::Age => decrypt_age(archive_path, &plain_archive)?,
+        Encryption::Aes256Gcm => decrypt_aes_gcm(archive_path, &plain_archive, &asset_key(asset)?)?,
+    }
+    println!("--decrypted: {}", archive_path.display());
+    Ok(plain_archive)
+}
+
+/// Archive of an asset sealed in the cache: kept encrypted at rest in the cache (`<archive>.sealed`, AES-256-GCM
+/// encrypted as `Encryption::Aes256Gcm`), e.g. licensed assets on shared machines, and decrypted into the build
+/// directory only (`<asset>.archive/<file>`, next to the extraction), where it is retrieved as a cached archive.
+pub struct CacheSeal {
+    asset: String,
+    /// Path of the sealed archive in the cache.
+    pub sealed_path: PathBuf,
+    /// Path of the plaintext archive in the build directory.
+    pub archive_path: PathBuf,
+}
+
+impl CacheSeal {
+    /// Resolve the seal of the archive of <asset> cached at <cached_path> and extracted into <directory>:
+    /// `IGNITION_CACHE_ENCRYPTION`, otherwise <manifest> (`encrypt_cache` of the asset). Archives with an
+    /// <encryption> in the bucket are cached as published, i.e. encrypted, and never sealed.
+    /// Return is None if the archive isn't sealed.
+    pub fn resolve(
+        asset: &str,
+        manifest: bool,
+        encryption: Option<Encryption>,
+        cached_path: &Path,
+        directory: &Path,
+    ) -> IgnitionResult<Option<Self>> {
+        if !env_flag(CACHE_ENCRYPTION_VARIABLE)?.unwrap_or(manifest) || encryption.is_some() {
+            return Ok(None);
+        }
+        let mut archive_path = directory.join(format!("{}{}", asset, UNSEALED_EXTENSION));
+        archive_path.extend(cached_path.file_name());
+        Ok(Some(CacheSeal {
+            asset: asset.to_string(),
+            sealed_path: sidecar_path(cached_path, SEALED_EXTENSION),
+            archive_path,
+        }))
+    }
+
+    /// Decrypt the sealed archive into the plaintext archive if missing, the sealed archive being copied from the
+    /// read-only lower cache of the cache at <cache_root> or pulled from the <remote> cache if missing too (only
+    /// sealed archives leave the build directory). Sealed archives larger than <max_size> bytes are an error.
+    /// Return is true if the archive was decrypted.
+    pub fn unseal(
+        &self,
+        cache_root: &Path,
+        remote: Option<&RemoteCache>,
+        max_size: Option<u64>,
+    ) -> IgnitionResult<bool> {
+        if self.archive_path.exists() {
+            return Ok(false);
+        }
+        if !self.sealed_path.exists()
+            && !cache::copy_from_lower(&self.sealed_path, cache_root)?
+            && !remote::pull(remote, &self.sealed_path, cache_root, max_size)?
+        {
+            return Ok(false);
+        }
+        if let Some(parent) = self.archive_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // decrypted into a partial file first, so an existing archive is always complete
+        let partial_path = download::partial_path(&self.archive_path);
+        decrypt_aes_gcm(&self.sealed_path, &partial_path, &cache_key(&self.asset)?)?;
+        fs::rename(&partial_path, &self.archive_path)?;
+        // recently used archives are evicted last, see `gc::collect`
+        gc::touch(&self.sealed_path).ok();
+        println!("--unsealed: {}", self.sealed_path.display());
+        Ok(true)
+    }
+
+    /// Encrypt the (verified) plaintext archive into the sealed archive if missing, or if <modified> (e.g.
+    /// downloaded again), pushing it to the <remote> cache of the cache at <cache_root> (if enabled).
+    /// Return is true if the archive was sealed.
+    pub fn seal(
+        &self,
+        modified: bool,
+        cache_root: &Path,
+        remote: Option<&RemoteCache>,
+    ) -> IgnitionResult<bool> {
+        if self.sealed_path.exists() && !modified {
+            gc::touch(&self.sealed_path).ok();
+            return Ok(false);
+        }
+        if let Some(parent) = self.sealed_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial_path = download::partial_path(&self.sealed_path);
+        encrypt_aes_gcm(&self.archive_path, &partial_path, &cache_key(&self.asset)?)?;
+        fs::rename(&partial_path, &self.sealed_path)?;
+        println!("--sealed: {}", self.sealed_path.display());
+        if let Err(err) = remote::push(remote, &self.sealed_path, cache_root) {
+            println!(
+                "cargo:warning=--remote cache push failed: {}: {}",
+                self.asset, err
+            );
+        }
+        Ok(true)
+    }
+}
+
+/// Decrypt the age encrypted <archive_path> into <output_path> with the `age` client.
+fn decrypt_age(archive_path: &Path, output_path: &Path) -> IgnitionResult<()> {
+    let identity = env_value::<PathBuf>(AGE_IDENTITY_VARIABLE)?.ok_or_else(|| {
+        IgnitionError::ConfigurationError(format!(
+            "{}: {} required for age encrypted archives",
+            archive_path.display(),
+            AGE_IDENTITY_VARIABLE
+        ))
+    })?;
+    let output = Command::new("age")
+        .arg("--decrypt")
+        .arg("--identity")
+        .arg(&identity)
+        .arg("--output")
+        .arg(output_path)
+        .arg(archive_path)
+        .output()
+        .map_err(|err| IgnitionError::DecryptionError(format!("command 'age' failed: {}", err)))?;
+    if !output.status.success() {
+        return Err(IgnitionError::DecryptionError(format!(
+            "{}: {}",
+            archive_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Decrypt the AES-256-GCM encrypted <archive_path> into <output_path> with <key>.
+fn decrypt_aes_gcm(archive_path: &Path, output_path: &Path, key: &[u8]) -> IgnitionResult<()> {
+    let error = |reason: &str| {
+        IgnitionError::DecryptionError(format!("{}: {}", archive_path.display(), reason))
+    };
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| error("key must be 32 bytes"))?,
+    );
+    let mut data = fs::read(archive_path)?;
+    if data.len() < NONCE_LEN {
+        return Err(error("truncated ciphertext"));
+    }
+    let mut ciphertext = data.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&data).map_err(|_| error("invalid nonce"))?;
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| error("wrong key or corrupted ciphertext"))?;
+    fs::write(output_path, plaintext)?;
+    Ok(())
+}
+
+/// Encrypt <archive_path> into <output_path> with AES-256-GCM and <key>, with a random nonce (`nonce ||
+/// ciphertext || tag`, see `decrypt_aes_gcm`).
+fn encrypt_aes_gcm(archive_path: &Path, output_path: &Path, key: &[u8]) -> IgnitionResult<()> {
+    let error = |reason: &str| {
+        IgnitionError::DecryptionError(format!("{}: {}", archive_path.display(), reason))
+    };
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| error("key must be 32 bytes"))?,
+    );
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| error("no random nonce"))?;
+    let mut data = fs::read(archive_path)?;
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| error("encryption failed"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.append(&mut data);
+    fs::write(output_path, sealed)?;
+    Ok(())
+}
+
+/// Determine the AES-256 key of <asset>: `IGNITION_ASSET_KEY`, otherwise the output of
+/// `IGNITION_ASSET_KEY_COMMAND` (see `key`).
+fn asset_key(asset: &str) -> IgnitionResult<Vec<u8>> {
+    key(
+        asset,
+        ASSET_KEY_VARIABLE,
+        ASSET_KEY_COMMAND_VARIABLE,
+        "AES-256-GCM encrypted archives",
+    )
+}
+
+/// Determine the AES-256 key sealing the cached archive of <asset>: `IGNITION_CACHE_KEY`, otherwise the output of
+/// `IGNITION_CACHE_KEY_COMMAND` (see `key`), e.g. reading the OS keychain (`security find-generic-password -s
+/// ignition -w` on macOS, `secret-tool lookup service ignition` on Linux).
+fn cache_key(asset: &str) -> IgnitionResult<Vec<u8>> {
+    key(
+        asset,
+        CACHE_KEY_VARIABLE,
+        CACHE_KEY_COMMAND_VARIABLE,
+        "archives encrypted in the cache",
+    )
+}
+
+/// Determine the AES-256 key of <asset> for <purpose>: <key_variable>, otherwise the output of <command_variable>
+/// (split on whitespace, run with the asset name in `IGNITION_KEY_ASSET`), as hex or base64.
+fn key(
+    asset: &str,
+    key_variable: &str,
+    command_variable: &str,
+    purpose: &str,
+) -> IgnitionResult<Vec<u8>> {
+    let encoded = match env_value::<String>(key_variable)? {
+        Some(key) => key,
+        None => {
+            let command_line = env_value::<String>(command_variable)?.ok_or_else(|| {
+                IgnitionError::ConfigurationError(format!(
+                    "{}: {} or {} required for {}",
+                    asset, key_variable, command_variable, purpose
+                ))
+            })?;
+            let mut words = command_line.split_whitespace();
+            let program = words.next().unwrap_or_default();
+            let output = Command::new(program)
+                .args(words)
+                .env(KEY_ASSET_VARIABLE, asset)
+                .output()
+                .map_err(|err| {
+                    IgnitionError::DecryptionError(format!("command '{}' failed: {}", program, err))
+                })?;
+            if !output.status.success() {
+                return Err(IgnitionError::DecryptionError(format!(
+                    "{}: '{}' exited with {}: {}",
+                    asset,
+                    command_line,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+    };
+    decode_hex(&encoded)
+        .or_else(|| STANDARD.decode(&encoded).ok())
+        .ok_or_else(|| {
+            IgnitionError::DecryptionError(format!("{}: key is neither hex nor base64", asset))
+        })
+}
+
+/// Decode the lower or uppercase hex string <value>, None if invalid.
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,81 @@
+// expiry.rs
+//
+// This module contains the expiry of cached archives of mutable urls (e.g. a `latest/` prefix republished weekly):
+// archives cached for longer than the `ttl` of their asset are revalidated, or fetched again if they can't be.
+//
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    download::{self, sidecar_path},
+    env_value, source,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const TTL_VARIABLE: &str = "IGNITION_CACHE_TTL";
+/// File extension of the time a cached archive was last fetched or revalidated, next to it.
+pub(crate) const FETCHED_EXTENSION: &str = ".fetched";
+
+/// Resolve the time to live of cached archives: `IGNITION_CACHE_TTL`, otherwise the <manifest> value (`ttl` of
+/// the asset), None if cached archives never expire. Values are seconds, or suffixed with `s`, `m`, `h` or `d`
+/// (e.g. `12h` or `7d`).
+pub fn ttl(manifest: Option<&str>) -> IgnitionResult<Option<Duration>> {
+    let Some(value) = env_value::<String>(TTL_VARIABLE)?.or(manifest.map(String::from)) else {
+        return Ok(None);
+    };
+    parse_ttl(&value)
+        .map(Some)
+        .ok_or_else(|| IgnitionError::ConfigurationError(format!("invalid ttl: {}", value)))
+}
+
+/// Determine if the cached archive at <archive_path> was fetched or revalidated longer than <ttl> ago (see
+/// `record`). Archives without a recorded time (e.g. cached before their asset set a `ttl`) are expired.
+pub fn is_expired(archive_path: &Path, ttl: Duration) -> bool {
+    let fetched = fs::read_to_string(sidecar_path(archive_path, FETCHED_EXTENSION))
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    match fetched {
+        Some(fetched) => SystemTime::now()
+            .duration_since(fetched)
+            .is_ok_and(|age| age > ttl),
+        None => true,
+    }
+}
+
+/// Determine if the cached archive at <archive_path> of <url> can be revalidated (see
+/// `download::download_if_modified`): delta-transfer sources, or downloads with recorded validators.
+pub fn can_revalidate(url: &str, archive_path: &Path) -> bool {
+    source::delta::is_delta(url) || download::Validators::load(archive_path).is_some()
+}
+
+/// Record the cached archive at <archive_path> as fetched or revalidated now (`<archive>.fetched`, in seconds since
+/// the Unix epoch, as modification times record the use of the archive, see `gc::touch`).
+pub fn record(archive_path: &Path) -> IgnitionResult<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    fs::write(
+        sidecar_path(archive_path, FETCHED_EXTENSION),
+        now.to_string(),
+    )?;
+    Ok(())
+}
+
+/// Parse a time to live in seconds, optionally suffixed with s, m, h or d, e.g. "3600" or "7d".
+fn parse_ttl(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_lowercase();
+    let (number, multiplier) = match value.chars().last()? {
+        's' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 60 * 60),
+        'd' => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value.as_str(), 1),
+    };
+    let secs: u64 = number.trim().parse().ok()?;
+    Some(Duration::from_secs(secs.checked_mul(multiplier)?))
+}
@@ -0,0 +1,1823 @@
+// extract.rs
+//
+// This module contains the extraction logic for asset archive files (tar.gz, tar.zst, tar.xz, zip or 7z): archives
+// are decompressed and unpacked in a single streaming pass, without external tools, decompression and writes being
+// spread over threads (see `parallel`).
+//
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use flate2::read::MultiGzDecoder;
+use lzma_rust2::{XzReader, XzReaderMt};
+use serde::Deserialize;
+use sevenz_rust2::{ArchiveEntry, ArchiveReader, Password};
+use tar::{Archive, EntryType};
+use zip::{ZipArchive, read::ZipFile};
+
+use super::{FILE_EXTENSION, env_value};
+use crate::{IgnitionError, IgnitionResult};
+
+pub mod parallel;
+
+const SYMLINKS_VARIABLE: &str = "IGNITION_SYMLINKS";
+/// Read buffer size of archive files.
+const READ_BUFFER_SIZE: usize = 1 << 20;
+/// Maximum zstd window size (log2), for archives compressed with long distance matching (`zstd --long`).
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+/// Attribute flag of 7z entries carrying a unix file mode.
+const SEVEN_ZIP_UNIX_EXTENSION: u32 = 0x8000;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+/// Windows file attributes of archive entries applied to extracted files (others, e.g. directory, describe the entry
+/// rather than the file).
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+#[cfg(windows)]
+const FILE_ATTRIBUTES_APPLIED: u32 = 0x2 | 0x4 | 0x20; // hidden, system, archive
+/// Names of DOS devices, reserved on Windows (with or without an extension).
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// Characters invalid in Windows file names, besides control characters.
+const INVALID_CHARACTERS: &str = "<>:\"|?*";
+/// Maximum number of symbolic links followed resolving a dereferenced link (as `MAXSYMLINKS` on Linux).
+const MAX_LINK_HOPS: usize = 40;
+/// Maximum size of tar entries written by the writer pool (larger files being written by the unpacking thread).
+const MAX_POOLED_SIZE: u64 = 8 << 20;
+/// Maximum number of threads of the writer pool, writes being bound by the disk rather than the cores.
+const MAX_WRITER_THREADS: usize = 8;
+/// Number of magic bytes read to detect the format of an archive (see `ArchiveFormat::magic`).
+const MAGIC_LENGTH: u64 = 6;
+
+/// Format of an asset archive.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum ArchiveFormat {
+    /// Gzip compressed tarball (default).
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    /// Zstandard compressed tarball, much faster to decompress than gzip.
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    /// XZ (LZMA2) compressed tarball.
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    /// Zip archive (including zip64 archives over 4 GiB), e.g. for Windows packages.
+    #[serde(rename = "zip")]
+    Zip,
+    /// 7z archive, e.g. for Windows packages.
+    #[serde(rename = "7z")]
+    SevenZip,
+    /// Single file rather than an archive (e.g. an `.onnx` model or a license), installed as is (see
+    /// `ExtractOptions::raw_path`).
+    #[serde(rename = "raw")]
+    Raw,
+}
+
+impl ArchiveFormat {
+    /// Archive formats, as detected by `ArchiveFormat::detect`.
+    const ALL: [ArchiveFormat; 5] = [
+        ArchiveFormat::TarGz,
+        ArchiveFormat::TarZst,
+        ArchiveFormat::TarXz,
+        ArchiveFormat::Zip,
+        ArchiveFormat::SevenZip,
+    ];
+
+    /// File extension of archives of the format.
+    pub fn extension(&self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// File extensions of archives of the format, including abbreviations.
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ArchiveFormat::TarGz => &[FILE_EXTENSION, ".tgz"],
+            ArchiveFormat::TarZst => &[".tar.zst", ".tzst"],
+            ArchiveFormat::TarXz => &[".tar.xz", ".txz"],
+            ArchiveFormat::Zip => &[".zip"],
+            ArchiveFormat::SevenZip => &[".7z"],
+            // raw files are named by their path
+            ArchiveFormat::Raw => &[""],
+        }
+    }
+
+    /// Determine if archives of the format are extracted in a single sequential pass (i.e. tarballs), so can be
+    /// extracted from a stream (see `extract_stream`).
+    pub fn is_streamable(&self) -> bool {
+        !matches!(
+            self,
+            ArchiveFormat::Zip | ArchiveFormat::SevenZip | ArchiveFormat::Raw
+        )
+    }
+
+    /// Magic bytes at the start of archives of the format (for zip, a local file header or the end of central
+    /// directory record of an empty archive).
+    fn magic(&self) -> &'static [&'static [u8]] {
+        match self {
+            ArchiveFormat::TarGz => &[&[0x1f, 0x8b]],
+            ArchiveFormat::TarZst => &[&[0x28, 0xb5, 0x2f, 0xfd]],
+            ArchiveFormat::TarXz => &[&[0xfd, b'7', b'z', b'X', b'Z', 0x00]],
+            ArchiveFormat::Zip => &[b"PK\x03\x04", b"PK\x05\x06"],
+            ArchiveFormat::SevenZip => &[&[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c]],
+            ArchiveFormat::Raw => &[],
+        }
+    }
+
+    /// Determine the format of the archive at <archive_path>: by its magic bytes, so archives served under wrong or
+    /// missing extensions (e.g. behind signed urls) are extracted as what they are, otherwise (e.g. for zip
+    /// archives prefixed with an executable stub) by file extension.
+    pub fn detect(archive_path: &Path) -> IgnitionResult<Self> {
+        let mut magic = Vec::new();
+        File::open(archive_path)?
+            .take(MAGIC_LENGTH)
+            .read_to_end(&mut magic)?;
+        if let Some(format) = ArchiveFormat::from_magic(&magic) {
+            return Ok(format);
+        }
+        let file_name = archive_path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        ArchiveFormat::ALL
+            .into_iter()
+            .find(|format| {
+                format
+                    .extensions()
+                    .iter()
+                    .any(|extension| file_name.ends_with(extension))
+            })
+            .ok_or_else(|| {
+                IgnitionError::ExtractionError(format!(
+                    "{}: unknown archive format",
+                    archive_path.display()
+                ))
+            })
+    }
+
+    /// Format of the archive starting with the <magic> bytes, if any.
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        ArchiveFormat::ALL.into_iter().find(|format| {
+            format
+                .magic()
+                .iter()
+                .any(|format_magic| magic.starts_with(format_magic))
+        })
+    }
+}
+
+/// How symbolic link entries of archives are extracted (`symlinks` in the manifest or `IGNITION_SYMLINKS`).
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Symbolic links are created as such (default, except on Windows).
+    Preserve,
+    /// Symbolic links are replaced by copies of their targets, for filesystems that can't represent them
+    /// (default on Windows).
+    Dereference,
+    /// Symbolic links are not extracted.
+    Skip,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        match cfg!(windows) {
+            true => SymlinkPolicy::Dereference,
+            false => SymlinkPolicy::Preserve,
+        }
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = IgnitionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "preserve" => Ok(SymlinkPolicy::Preserve),
+            "dereference" | "copy" => Ok(SymlinkPolicy::Dereference),
+            "skip" => Ok(SymlinkPolicy::Skip),
+            _ => Err(IgnitionError::ConfigurationError(format!(
+                "{}={}",
+                SYMLINKS_VARIABLE, value
+            ))),
+        }
+    }
+}
+
+impl SymlinkPolicy {
+    /// Resolve the policy: `IGNITION_SYMLINKS`, otherwise the manifest <symlinks> of the asset, otherwise the
+    /// platform default.
+    pub fn resolve(symlinks: Option<SymlinkPolicy>) -> IgnitionResult<Self> {
+        Ok(env_value(SYMLINKS_VARIABLE)?
+            .or(symlinks)
+            .unwrap_or_default())
+    }
+}
+
+/// Options of an extraction.
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+    /// Maximum total size of the extracted entries in bytes.
+    pub max_size: Option<u64>,
+    /// Number of leading path components stripped from entries (as `tar --strip-components`), entries without
+    /// further components being skipped.
+    pub strip_components: usize,
+    /// Glob patterns of the (stripped) entries to extract, all if empty (see `ExtractOptions::is_selected`).
+    pub include: Vec<String>,
+    /// Glob patterns of the (stripped) entries not to extract, even if included.
+    pub exclude: Vec<String>,
+    /// How symbolic links are extracted.
+    pub symlinks: SymlinkPolicy,
+    /// Number of threads decompressing and writing entries (see `parallel::extract_threads`), 0 or 1 extracting on
+    /// the calling thread only.
+    pub threads: usize,
+    /// Relative path of the file of a raw asset (see `ArchiveFormat::Raw`), the file being installed at it rather
+    /// than extracted.
+    pub raw_path: Option<PathBuf>,
+}
+
+impl ExtractOptions {
+    /// Determine if the entry at the (stripped) relative <path> is extracted: it must match an `include` pattern
+    /// (if any) and no `exclude` pattern.
+    ///
+    /// Patterns are `/` separated paths where `*` matches any characters within a component, `?` a single
+    /// character and `**` any number of components. A pattern matching a directory matches everything within it,
+    /// e.g. `opencv/include` or `opencv/lib/*.so*`.
+    pub fn is_selected(&self, path: &Path) -> bool {
+        let matches = |pattern: &String| pattern_matches(pattern, path);
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// Relative path the entry at <path> is extracted at (see `local_path`), None if it is skipped (stripped
+    /// entirely or not selected).
+    fn entry_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = strip_path(path, self.strip_components).filter(|path| self.is_selected(path))?;
+        let local = local_path(&path);
+        if local != path {
+            println!(
+                "cargo:warning=--renamed: {} -> {} (invalid on Windows)",
+                path.display(),
+                local.display()
+            );
+        }
+        Some(local)
+    }
+}
+
+/// Extract the archive at <archive_path> (see `ArchiveFormat::detect`) into <directory>, creating it as required.
+///
+/// Every entry is validated before it is written: absolute paths, `..` components and links pointing outside of
+/// <directory> are an `UnsafeArchiveEntry` error, and extracting more than `max_size` bytes in total (e.g. a
+/// decompression bomb) is a `SizeLimitExceeded` error. Corrupted or truncated archives are an `ExtractionError`.
+/// Entries are extracted at their path stripped of `strip_components` leading components, and only if selected by
+/// the `include` and `exclude` patterns (see <options>), skipped entries not counting towards `max_size`.
+/// Symbolic links are extracted according to the `symlinks` policy, dereferenced links being copied once all
+/// entries are extracted (links to targets not extracted being skipped with a warning).
+///
+/// On Windows, entries are extracted through an extended-length path (`\\?\`), so deep trees aren't limited to
+/// `MAX_PATH`, names invalid on Windows (e.g. reserved device names as `aux.h`) are renamed with a warning (see
+/// `local_path`), and the read-only, hidden and system attributes of entries are applied.
+///
+/// With several `threads`, tarballs are decompressed on background threads (zstd frames and xz blocks of archives
+/// compressed in parallel, e.g. by `pzstd` or `xz -T`, concurrently) while small files are written by a pool of
+/// threads, and zip entries are extracted concurrently. 7z archives are extracted on the calling thread.
+///
+/// With a `raw_path`, the file at <archive_path> isn't an archive, and is copied into <directory> at that path.
+pub fn extract(
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    let directory = &extended_path(directory)?;
+    fs::create_dir_all(directory)?;
+    if let Some(path) = options.raw_path.as_deref() {
+        return install_raw(archive_path, directory, path, options);
+    }
+    let format = ArchiveFormat::detect(archive_path)?;
+    match format {
+        ArchiveFormat::Zip => return unpack_zip(archive_path, directory, options),
+        ArchiveFormat::SevenZip => return unpack_7z(archive_path, directory, options),
+        // blocks of xz files are located through their index, which requires seeking
+        ArchiveFormat::TarXz if options.threads > 1 => {
+            let file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+            let reader = XzReaderMt::new(file, true, options.threads as u32)
+                .map_err(|err| extraction_error(archive_path, &err))?;
+            return unpack_tar(reader, archive_path, directory, options);
+        }
+        _ => {}
+    }
+    extract_stream(File::open(archive_path)?, archive_path, directory, options)
+}
+
+/// Extract the tarball read from <reader> into <directory> as `extract`, e.g. while it is downloaded, its format
+/// detected by its magic bytes. Archives of formats which aren't streamable (see `ArchiveFormat::is_streamable`)
+/// are an error. <archive_path> only names the archive in errors.
+pub fn extract_stream(
+    reader: impl Read + Send,
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    let directory = &extended_path(directory)?;
+    fs::create_dir_all(directory)?;
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, reader);
+    let mut magic = Vec::new();
+    (&mut reader).take(MAGIC_LENGTH).read_to_end(&mut magic)?;
+    let format = ArchiveFormat::from_magic(&magic).ok_or_else(|| {
+        IgnitionError::ExtractionError(format!(
+            "{}: unknown archive format",
+            archive_path.display()
+        ))
+    })?;
+    // the magic bytes are read again by the decompressor
+    let reader = Cursor::new(magic).chain(reader);
+    if options.threads <= 1 {
+        let decoder = decoder(format, archive_path, reader)?;
+        return unpack_tar(decoder, archive_path, directory, options);
+    }
+    thread::scope(|scope| {
+        let decoder = match format {
+            ArchiveFormat::TarZst => {
+                parallel::decode_zstd(scope, reader, options.threads, ZSTD_WINDOW_LOG_MAX)
+            }
+            _ => parallel::decode_in_background(scope, decoder(format, archive_path, reader)?),
+        };
+        unpack_tar(decoder, archive_path, directory, options)
+    })
+}
+
+/// Decompressor of the tarball of streamable <format> read from <reader>.
+fn decoder<'a>(
+    format: ArchiveFormat,
+    archive_path: &Path,
+    reader: impl BufRead + Send + 'a,
+) -> IgnitionResult<Box<dyn Read + Send + 'a>> {
+    // multi-member (gzip), multi-frame (zstd) and multi-stream (xz) archives, e.g. compressed in parallel or
+    // concatenated, are decompressed entirely
+    Ok(match format {
+        ArchiveFormat::TarGz => Box::new(MultiGzDecoder::new(reader)),
+        ArchiveFormat::TarZst => {
+            let mut decoder = zstd::Decoder::with_buffer(reader)
+                .map_err(|err| extraction_error(archive_path, &err))?;
+            decoder
+                .window_log_max(ZSTD_WINDOW_LOG_MAX)
+                .map_err(|err| extraction_error(archive_path, &err))?;
+            Box::new(decoder)
+        }
+        ArchiveFormat::TarXz => Box::new(XzReader::new(reader, true)),
+        ArchiveFormat::Zip | ArchiveFormat::SevenZip | ArchiveFormat::Raw => {
+            return Err(IgnitionError::ExtractionError(format!(
+                "{}: {} archives can't be extracted from a stream",
+                archive_path.display(),
+                format.extension()
+            )));
+        }
+    })
+}
+
+/// Mark the files within <directory> (recursively, not following symbolic links) matching any of the glob
+/// <patterns> (relative to <directory>, see `ExtractOptions::is_selected`) executable, e.g. tools of zip archives,
+/// which carry no unix permissions. Executable permission is added where read permission is set. Patterns
+/// matching no file are reported with a warning. Nothing is changed on Windows, which has no executable
+/// permission.
+pub fn mark_executable(directory: &Path, patterns: &[String]) -> IgnitionResult<()> {
+    if patterns.is_empty() || cfg!(windows) {
+        return Ok(());
+    }
+    let mut matched = vec![false; patterns.len()];
+    mark_executable_within(directory, Path::new(""), patterns, &mut matched)?;
+    for (pattern, matched) in patterns.iter().zip(matched) {
+        if !matched {
+            println!("cargo:warning=--executable: no file matches {}", pattern);
+        }
+    }
+    Ok(())
+}
+
+/// Mark the files within <directory> at the relative <path> executable as `mark_executable`, recording the
+/// <matched> patterns.
+fn mark_executable_within(
+    directory: &Path,
+    path: &Path,
+    patterns: &[String],
+    matched: &mut [bool],
+) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory.join(path))? {
+        let entry = entry?;
+        let path = path.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            mark_executable_within(directory, &path, patterns, matched)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let mut selected = false;
+        for (pattern, matched) in patterns.iter().zip(matched.iter_mut()) {
+            if pattern_matches(pattern, &path) {
+                *matched = true;
+                selected = true;
+            }
+        }
+        #[cfg(unix)]
+        if selected {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = entry.metadata()?.permissions().mode();
+            let executable = mode | ((mode & 0o444) >> 2);
+            if executable != mode {
+                fs::set_permissions(entry.path(), fs::Permissions::from_mode(executable))?;
+                println!("--executable: {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Kind of an archive entry, see `ListedEntry`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    SymbolicLink,
+    HardLink,
+}
+
+/// Archive entry as it would be extracted, see `list`. Displayed as `<path>/` for directories, `<path> -> <target>`
+/// for symbolic links, `<path> => <target>` for hard links and `<path> (<size> bytes)` for files.
+#[derive(Clone, Debug)]
+pub struct ListedEntry {
+    /// Relative path the entry would be extracted at.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// Size of the entry in bytes.
+    pub size: u64,
+    /// Target of a link entry (relative to the link for symbolic links, to the directory for hard links).
+    pub link_target: Option<PathBuf>,
+}
+
+impl fmt::Display for ListedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = self.link_target.as_deref().unwrap_or(Path::new(""));
+        match self.kind {
+            EntryKind::File => write!(f, "{} ({} bytes)", self.path.display(), self.size),
+            EntryKind::Directory => write!(f, "{}/", self.path.display()),
+            EntryKind::SymbolicLink => {
+                write!(f, "{} -> {}", self.path.display(), target.display())
+            }
+            EntryKind::HardLink => write!(f, "{} => {}", self.path.display(), target.display()),
+        }
+    }
+}
+
+/// List the entries the archive at <archive_path> would extract with <options> (see `extract`) without writing
+/// anything, e.g. to author the `contents` of an asset: paths are stripped, renamed and selected as extracted,
+/// symbolic links skipped by the `symlinks` policy are left out, and unsafe entries are an error as well. Tarballs
+/// and 7z archives are decompressed entirely to list them, zip archives only read their central directory (and the
+/// targets of symbolic links).
+pub fn list(archive_path: &Path, options: &ExtractOptions) -> IgnitionResult<Vec<ListedEntry>> {
+    if let Some(path) = options.raw_path.as_deref() {
+        check_path(archive_path, path)?;
+        return Ok(vec![ListedEntry {
+            path: local_path(path),
+            kind: EntryKind::File,
+            size: fs::metadata(archive_path)?.len(),
+            link_target: None,
+        }]);
+    }
+    match ArchiveFormat::detect(archive_path)? {
+        ArchiveFormat::Zip => list_zip(archive_path, options),
+        ArchiveFormat::SevenZip => list_7z(archive_path, options),
+        format => {
+            let reader = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+            list_tar(
+                decoder(format, archive_path, reader)?,
+                archive_path,
+                options,
+            )
+        }
+    }
+}
+
+/// Install the raw asset file at <file_path> into <directory> at the relative <path>, validated as an archive entry
+/// (see `extract`).
+fn install_raw(
+    file_path: &Path,
+    directory: &Path,
+    path: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    check_path(file_path, path)?;
+    let path = local_path(path);
+    add_size(
+        file_path,
+        &mut 0,
+        fs::metadata(file_path)?.len(),
+        options.max_size,
+    )?;
+    let destination = directory.join(path);
+    create_parent(directory, &destination)?;
+    fs::copy(file_path, &destination)?;
+    Ok(())
+}
+
+/// Unpack the (decompressed) tar stream <reader> of the archive at <archive_path> into <directory>, see `extract`.
+fn unpack_tar(
+    reader: impl Read,
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    if options.threads <= 1 {
+        return unpack_tar_entries(reader, archive_path, directory, options, None);
+    }
+    thread::scope(|scope| {
+        let pool =
+            parallel::WriterPool::new(scope, directory, options.threads.min(MAX_WRITER_THREADS));
+        unpack_tar_entries(reader, archive_path, directory, options, Some(&pool))
+    })
+}
+
+/// Unpack the entries of the tar stream <reader> as `unpack_tar`, small regular files being written by the
+/// writer <pool> if any.
+fn unpack_tar_entries(
+    reader: impl Read,
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+    pool: Option<&parallel::WriterPool>,
+) -> IgnitionResult<()> {
+    let error = |err: io::Error| extraction_error(archive_path, &err);
+
+    // directories are unpacked last, so restrictive permissions don't prevent unpacking their contents
+    let mut directories = Vec::new();
+    let mut links = Vec::new();
+    let mut extracted_size = 0u64;
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries().map_err(error)? {
+        let mut entry = entry.map_err(error)?;
+        let original_path = entry.path().map_err(error)?.into_owned();
+        check_path(archive_path, &original_path)?;
+        let Some(path) = options.entry_path(&original_path) else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        let mut hard_link_target = None;
+        if let Some(target) = entry.link_name().map_err(error)? {
+            // symbolic links resolve relative to the link, hard links relative to the archive root (stripped too)
+            let symbolic = entry_type == EntryType::Symlink;
+            let target = match symbolic {
+                true => target.into_owned(),
+                false => strip_path(&target, options.strip_components).ok_or_else(|| {
+                    unsafe_entry(
+                        archive_path,
+                        &original_path,
+                        format!("link target {} stripped", target.display()),
+                    )
+                })?,
+            };
+            check_link(archive_path, &path, &target, symbolic)?;
+            match options.symlinks {
+                SymlinkPolicy::Preserve => {}
+                _ if !symbolic => {}
+                SymlinkPolicy::Dereference => {
+                    links.push((path, target));
+                    continue;
+                }
+                SymlinkPolicy::Skip => continue,
+            }
+            if !symbolic {
+                // the contents of hard links are stored with their target only
+                if !options.is_selected(&target) {
+                    return Err(IgnitionError::ExtractionError(format!(
+                        "{}: hard link {} to {}, which is not extracted (include it too)",
+                        archive_path.display(),
+                        path.display(),
+                        target.display()
+                    )));
+                }
+                hard_link_target = Some(local_path(&target));
+            }
+        }
+        add_size(
+            archive_path,
+            &mut extracted_size,
+            entry.header().size().map_err(error)?,
+            options.max_size,
+        )?;
+        if entry_type == EntryType::Directory {
+            directories.push((entry, path));
+        } else if let Some(pool) = pool
+            && entry_type == EntryType::Regular
+            && hard_link_target.is_none()
+            && entry.size() <= MAX_POOLED_SIZE
+        {
+            let mut contents = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut contents).map_err(error)?;
+            let header = entry.header();
+            pool.write(parallel::PooledFile {
+                destination: directory.join(&path),
+                contents,
+                mode: header.mode().ok(),
+                // as the tar crate, avoiding 0 modification times some tools don't handle
+                mtime: header.mtime().ok().map(|mtime| mtime.max(1)),
+            })
+            .map_err(error)?;
+        } else {
+            // earlier writes (e.g. of the target of a hard link, or of the same path) complete first
+            if let Some(pool) = pool {
+                pool.flush().map_err(error)?;
+            }
+            if let Some(target) = hard_link_target.as_deref() {
+                check_hard_link(archive_path, directory, &path, target)?;
+            }
+            unpack_tar_entry(&mut entry, directory, &path, hard_link_target.as_deref())
+                .map_err(error)?;
+        }
+    }
+    if let Some(pool) = pool {
+        pool.flush().map_err(error)?;
+    }
+    dereference_links(
+        archive_path,
+        directory,
+        &links,
+        &mut extracted_size,
+        options.max_size,
+    )?;
+    // before directory permissions are applied, which would follow links leading out of the directory
+    if options.symlinks == SymlinkPolicy::Preserve {
+        check_links(archive_path, directory)?;
+    }
+    for (mut entry, path) in directories {
+        unpack_tar_entry(&mut entry, directory, &path, None).map_err(error)?;
+    }
+    Ok(())
+}
+
+/// Unpack the tar <entry> at the relative <path> within <directory> (as a hard link to the relative
+/// <hard_link_target> if any).
+fn unpack_tar_entry<R: Read>(
+    entry: &mut tar::Entry<R>,
+    directory: &Path,
+    path: &Path,
+    hard_link_target: Option<&Path>,
+) -> io::Result<()> {
+    let destination = directory.join(path);
+    create_parent(directory, &destination)?;
+    // replace existing files (and links) rather than writing through them
+    if fs::symlink_metadata(&destination).is_ok_and(|metadata| !metadata.is_dir()) {
+        remove_file(&destination)?;
+    }
+    match hard_link_target {
+        Some(target) => fs::hard_link(directory.join(target), &destination).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("failed to link `{}`: {}", destination.display(), err),
+            )
+        }),
+        // unpack errors name the entry and destination, with the cause as source (see `extraction_error`)
+        None => entry.unpack(&destination).map(|_| ()),
+    }
+}
+
+/// Unpack the zip archive at <archive_path> into <directory>, see `extract`.
+fn unpack_zip(
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    let open = || -> IgnitionResult<_> {
+        let file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+        ZipArchive::new(file).map_err(|err| extraction_error(archive_path, &err))
+    };
+    let mut archive = open()?;
+    let links = Mutex::new(Vec::new());
+    let extracted_size = Mutex::new(0u64);
+    // entries are claimed in order by threads reading the archive through their own file
+    let next = AtomicUsize::new(0);
+    let unpack_entries = |archive: &mut ZipArchive<_>| {
+        unpack_zip_entries(
+            archive_path,
+            directory,
+            archive,
+            &next,
+            &links,
+            &extracted_size,
+            options,
+        )
+    };
+    let threads = options.threads.clamp(1, archive.len().max(1));
+    thread::scope(|scope| {
+        let workers: Vec<_> = (1..threads)
+            .map(|_| scope.spawn(|| unpack_entries(&mut open()?)))
+            .collect();
+        let result = unpack_entries(&mut archive);
+        workers.into_iter().fold(result, |result, worker| {
+            result.and(worker.join().unwrap_or_else(|_| {
+                Err(IgnitionError::ExtractionError(format!(
+                    "{}: extraction thread panicked",
+                    archive_path.display()
+                )))
+            }))
+        })
+    })?;
+    dereference_links(
+        archive_path,
+        directory,
+        &links.into_inner().unwrap_or_else(|err| err.into_inner()),
+        &mut extracted_size
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner()),
+        options.max_size,
+    )?;
+    if options.symlinks == SymlinkPolicy::Preserve {
+        check_links(archive_path, directory)?;
+    }
+    Ok(())
+}
+
+/// Unpack the entries of the zip <archive> at <archive_path> claimed from <next> into <directory>, see
+/// `unpack_zip`. Symbolic links to dereference are collected into <links>.
+fn unpack_zip_entries(
+    archive_path: &Path,
+    directory: &Path,
+    archive: &mut ZipArchive<BufReader<File>>,
+    next: &AtomicUsize,
+    links: &Mutex<Vec<(PathBuf, PathBuf)>>,
+    extracted_size: &Mutex<u64>,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        if index >= archive.len() {
+            return Ok(());
+        }
+        let result = unpack_zip_entry(
+            archive_path,
+            directory,
+            archive,
+            index,
+            links,
+            extracted_size,
+            options,
+        );
+        if result.is_err() {
+            // stop the other threads
+            next.store(archive.len(), Ordering::Relaxed);
+            return result;
+        }
+    }
+}
+
+/// Unpack the entry at <index> of the zip <archive> at <archive_path> into <directory>, see `unpack_zip_entries`.
+fn unpack_zip_entry(
+    archive_path: &Path,
+    directory: &Path,
+    archive: &mut ZipArchive<BufReader<File>>,
+    index: usize,
+    links: &Mutex<Vec<(PathBuf, PathBuf)>>,
+    extracted_size: &Mutex<u64>,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    let mut entry = archive
+        .by_index(index)
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    let path = zip_entry_path(archive_path, &entry)?;
+    let Some(path) = options.entry_path(&path) else {
+        return Ok(());
+    };
+    if entry.is_dir() {
+        fs::create_dir_all(directory.join(&path))?;
+        return Ok(());
+    }
+    add_size(
+        archive_path,
+        &mut extracted_size.lock().unwrap_or_else(|err| err.into_inner()),
+        entry.size(),
+        options.max_size,
+    )?;
+    let symlink = entry.is_symlink();
+    if symlink && options.symlinks != SymlinkPolicy::Preserve {
+        if options.symlinks == SymlinkPolicy::Dereference {
+            let link = read_link(archive_path, &path, &mut entry)?;
+            links
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(link);
+        }
+        return Ok(());
+    }
+    let mode = entry.unix_mode();
+    // DOS attributes in the low bits (also set by Info-ZIP on unix)
+    let attributes = entry.external_attributes() & 0xffff;
+    write_entry(
+        archive_path,
+        directory,
+        &path,
+        &mut entry,
+        symlink,
+        mode,
+        attributes,
+    )
+}
+
+/// Path of the zip <entry> of the archive at <archive_path>: its name checked with `check_path`, then as enclosed
+/// by the zip crate (with `\` separators of archives created on Windows, and without NUL bytes).
+fn zip_entry_path<R: Read>(archive_path: &Path, entry: &ZipFile<'_, R>) -> IgnitionResult<PathBuf> {
+    let name = entry
+        .name()
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    check_path(archive_path, Path::new(name.as_ref()))?;
+    entry.enclosed_name().ok_or_else(|| {
+        unsafe_entry(
+            archive_path,
+            Path::new(name.as_ref()),
+            "path outside of the archive".to_string(),
+        )
+    })
+}
+
+/// Unpack the 7z archive at <archive_path> into <directory>, see `extract`.
+fn unpack_7z(
+    archive_path: &Path,
+    directory: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    let file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+    let mut archive = ArchiveReader::new(file, Password::empty())
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    let mut links = Vec::new();
+    let mut extracted_size = 0u64;
+    // the first error of an entry stops the iteration (entries without data are still visited), and is returned
+    // after it
+    let mut result = Ok(());
+    archive
+        .for_each_entries(|entry, reader| {
+            if result.is_ok() && !entry.is_anti_item() {
+                result = unpack_7z_entry(
+                    archive_path,
+                    directory,
+                    entry,
+                    reader,
+                    &mut links,
+                    &mut extracted_size,
+                    options,
+                );
+            }
+            if result.is_ok() {
+                result = skip_7z_entry(archive_path, reader);
+            }
+            Ok(result.is_ok())
+        })
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    result?;
+    dereference_links(
+        archive_path,
+        directory,
+        &links,
+        &mut extracted_size,
+        options.max_size,
+    )?;
+    if options.symlinks == SymlinkPolicy::Preserve {
+        check_links(archive_path, directory)?;
+    }
+    Ok(())
+}
+
+/// Unpack the <entry> of the 7z archive at <archive_path> from <reader> into <directory>, see `extract`.
+/// Symbolic links to dereference are collected into <links>.
+fn unpack_7z_entry(
+    archive_path: &Path,
+    directory: &Path,
+    entry: &ArchiveEntry,
+    reader: &mut dyn Read,
+    links: &mut Vec<(PathBuf, PathBuf)>,
+    extracted_size: &mut u64,
+    options: &ExtractOptions,
+) -> IgnitionResult<()> {
+    // archives created on Windows may use `\` separators
+    let path = PathBuf::from(entry.name().replace('\\', "/"));
+    check_path(archive_path, &path)?;
+    let Some(path) = options.entry_path(&path) else {
+        return Ok(());
+    };
+    if entry.is_directory() {
+        fs::create_dir_all(directory.join(&path))?;
+        return Ok(());
+    }
+    add_size(archive_path, extracted_size, entry.size(), options.max_size)?;
+    let attributes = entry.windows_attributes();
+    let mode = seven_zip_mode(attributes);
+    let symlink = mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK);
+    if symlink && options.symlinks != SymlinkPolicy::Preserve {
+        if options.symlinks == SymlinkPolicy::Dereference {
+            links.push(read_link(archive_path, &path, reader)?);
+        }
+        return Ok(());
+    }
+    write_entry(
+        archive_path,
+        directory,
+        &path,
+        reader,
+        symlink,
+        mode,
+        attributes & 0xffff,
+    )
+}
+
+/// Unix file mode of a 7z entry of Windows file <attributes>: the mode in the high 16 bits, as stored by p7zip, or
+/// read-only permissions for read-only entries.
+fn seven_zip_mode(attributes: u32) -> Option<u32> {
+    match attributes & SEVEN_ZIP_UNIX_EXTENSION != 0 {
+        true => Some(attributes >> 16),
+        false if attributes & FILE_ATTRIBUTE_READONLY != 0 => Some(0o444),
+        false => None,
+    }
+}
+
+/// Consume the rest of the data of a 7z entry from <reader>: entries of solid blocks are decompressed in sequence,
+/// so the data of skipped entries must be read past.
+fn skip_7z_entry(archive_path: &Path, reader: &mut dyn Read) -> IgnitionResult<()> {
+    io::copy(reader, &mut io::sink()).map_err(|err| extraction_error(archive_path, &err))?;
+    Ok(())
+}
+
+/// List the entries of the tar stream <reader> of the archive at <archive_path>, see `list`.
+fn list_tar(
+    reader: impl Read,
+    archive_path: &Path,
+    options: &ExtractOptions,
+) -> IgnitionResult<Vec<ListedEntry>> {
+    let error = |err: io::Error| extraction_error(archive_path, &err);
+    let mut entries = Vec::new();
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries().map_err(error)? {
+        let entry = entry.map_err(error)?;
+        let original_path = entry.path().map_err(error)?.into_owned();
+        check_path(archive_path, &original_path)?;
+        let Some(path) = options.entry_path(&original_path) else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        let (kind, link_target) = match entry.link_name().map_err(error)? {
+            Some(target) if entry_type == EntryType::Symlink => {
+                let target = target.into_owned();
+                check_link(archive_path, &path, &target, true)?;
+                if options.symlinks == SymlinkPolicy::Skip {
+                    continue;
+                }
+                (EntryKind::SymbolicLink, Some(target))
+            }
+            Some(target) => {
+                let target = strip_path(&target, options.strip_components).ok_or_else(|| {
+                    unsafe_entry(
+                        archive_path,
+                        &original_path,
+                        format!("link target {} stripped", target.display()),
+                    )
+                })?;
+                check_link(archive_path, &path, &target, false)?;
+                (EntryKind::HardLink, Some(local_path(&target)))
+            }
+            None if entry_type == EntryType::Directory => (EntryKind::Directory, None),
+            None => (EntryKind::File, None),
+        };
+        entries.push(ListedEntry {
+            path,
+            kind,
+            size: entry.size(),
+            link_target,
+        });
+    }
+    Ok(entries)
+}
+
+/// List the entries of the zip archive at <archive_path>, see `list`.
+fn list_zip(archive_path: &Path, options: &ExtractOptions) -> IgnitionResult<Vec<ListedEntry>> {
+    let file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+    let mut archive = ZipArchive::new(file).map_err(|err| extraction_error(archive_path, &err))?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|err| extraction_error(archive_path, &err))?;
+        let path = zip_entry_path(archive_path, &entry)?;
+        let Some(path) = options.entry_path(&path) else {
+            continue;
+        };
+        let size = entry.size();
+        let (kind, link_target) = if entry.is_dir() {
+            (EntryKind::Directory, None)
+        } else if entry.is_symlink() {
+            let (_, target) = read_link(archive_path, &path, &mut entry)?;
+            if options.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+            (EntryKind::SymbolicLink, Some(target))
+        } else {
+            (EntryKind::File, None)
+        };
+        entries.push(ListedEntry {
+            path,
+            kind,
+            size,
+            link_target,
+        });
+    }
+    Ok(entries)
+}
+
+/// List the entries of the 7z archive at <archive_path>, see `list`.
+fn list_7z(archive_path: &Path, options: &ExtractOptions) -> IgnitionResult<Vec<ListedEntry>> {
+    let file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(archive_path)?);
+    let mut archive = ArchiveReader::new(file, Password::empty())
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    let mut entries = Vec::new();
+    // as `unpack_7z`, the first error stops the iteration
+    let mut result = Ok(());
+    archive
+        .for_each_entries(|entry, reader| {
+            if result.is_ok() && !entry.is_anti_item() {
+                result = list_7z_entry(archive_path, entry, reader, options)
+                    .map(|listed| entries.extend(listed));
+            }
+            if result.is_ok() {
+                result = skip_7z_entry(archive_path, reader);
+            }
+            Ok(result.is_ok())
+        })
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    result?;
+    Ok(entries)
+}
+
+/// List the <entry> of the 7z archive at <archive_path> (reading the target of symbolic links from <reader>),
+/// None if it isn't extracted, see `list`.
+fn list_7z_entry(
+    archive_path: &Path,
+    entry: &ArchiveEntry,
+    reader: &mut dyn Read,
+    options: &ExtractOptions,
+) -> IgnitionResult<Option<ListedEntry>> {
+    let path = PathBuf::from(entry.name().replace('\\', "/"));
+    check_path(archive_path, &path)?;
+    let Some(path) = options.entry_path(&path) else {
+        return Ok(None);
+    };
+    let symlink =
+        seven_zip_mode(entry.windows_attributes()).is_some_and(|mode| mode & S_IFMT == S_IFLNK);
+    let (kind, link_target) = if entry.is_directory() {
+        (EntryKind::Directory, None)
+    } else if symlink {
+        let (_, target) = read_link(archive_path, &path, reader)?;
+        if options.symlinks == SymlinkPolicy::Skip {
+            return Ok(None);
+        }
+        (EntryKind::SymbolicLink, Some(target))
+    } else {
+        (EntryKind::File, None)
+    };
+    Ok(Some(ListedEntry {
+        path,
+        kind,
+        size: entry.size(),
+        link_target,
+    }))
+}
+
+/// Write the file entry <path> of the archive at <archive_path> into <directory> from <reader>: the contents of
+/// the file, or the target of the symbolic link if <symlink>. Files are given the permissions of the unix <mode>,
+/// on Windows the Windows file <attributes> (see `create_file` and `set_permissions`).
+fn write_entry(
+    archive_path: &Path,
+    directory: &Path,
+    path: &Path,
+    reader: &mut dyn Read,
+    symlink: bool,
+    mode: Option<u32>,
+    attributes: u32,
+) -> IgnitionResult<()> {
+    let destination = directory.join(path);
+    create_parent(directory, &destination)?;
+    // replace existing files (and links) rather than writing through them
+    if fs::symlink_metadata(&destination).is_ok_and(|metadata| !metadata.is_dir()) {
+        remove_file(&destination)?;
+    }
+    if symlink {
+        let (_, target) = read_link(archive_path, path, reader)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &destination)?;
+        #[cfg(windows)]
+        {
+            // Windows distinguishes links to directories: the target (relative to the parent of the link) is one if
+            // it ends with a separator or is an already extracted directory
+            let target_path = destination.parent().unwrap_or(directory).join(&target);
+            if target.as_os_str().to_string_lossy().ends_with(['/', '\\']) || target_path.is_dir() {
+                std::os::windows::fs::symlink_dir(&target, &destination)?;
+            } else {
+                std::os::windows::fs::symlink_file(&target, &destination)?;
+            }
+        }
+        return Ok(());
+    }
+    io::copy(reader, &mut create_file(&destination, attributes)?)
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    set_permissions(&destination, mode, attributes)?;
+    Ok(())
+}
+
+/// Create the file at <destination>, on Windows with the hidden, system and archive Windows file <attributes> of
+/// its entry (read-only being applied once written, see `set_permissions`).
+fn create_file(destination: &Path, attributes: u32) -> io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .attributes(attributes & FILE_ATTRIBUTES_APPLIED)
+            .open(destination)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = attributes;
+        File::create(destination)
+    }
+}
+
+/// Give the file at <destination> the permissions of the unix <mode> of its entry, on Windows the read-only
+/// attribute if the Windows file <attributes> include it or <mode> lacks owner write permission.
+fn set_permissions(destination: &Path, mode: Option<u32>, attributes: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(destination, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    #[cfg(not(unix))]
+    if attributes & FILE_ATTRIBUTE_READONLY != 0 || mode.is_some_and(|mode| mode & 0o200 == 0) {
+        let mut permissions = fs::metadata(destination)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(destination, permissions)?;
+    }
+    #[cfg(unix)]
+    let _ = attributes;
+    Ok(())
+}
+
+/// Remove the file (or link) at <path>, even if read-only (which Windows refuses to remove).
+#[allow(clippy::permissions_set_readonly_false)]
+fn remove_file(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    if let Ok(metadata) = fs::symlink_metadata(path)
+        && metadata.permissions().readonly()
+    {
+        let mut permissions = metadata.permissions();
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+    fs::remove_file(path)
+}
+
+/// Read the target of the symbolic link entry <path> of the archive at <archive_path> (stored as its contents) from
+/// <reader>, verifying it stays inside the directory. Return is the path and the target.
+fn read_link(
+    archive_path: &Path,
+    path: &Path,
+    reader: &mut dyn Read,
+) -> IgnitionResult<(PathBuf, PathBuf)> {
+    let mut target = String::new();
+    reader
+        .read_to_string(&mut target)
+        .map_err(|err| extraction_error(archive_path, &err))?;
+    let target = PathBuf::from(target);
+    check_link(archive_path, path, &target, true)?;
+    Ok((path.to_path_buf(), target))
+}
+
+/// Replace the symbolic <links> (relative paths and targets) of the archive at <archive_path> by copies of their
+/// targets extracted into <directory> (see `SymlinkPolicy::Dereference`), adding the size of the copies to
+/// <extracted_size> (see `add_size`). Links to directories are copied after links to files, so the copies
+/// include them.
+fn dereference_links(
+    archive_path: &Path,
+    directory: &Path,
+    links: &[(PathBuf, PathBuf)],
+    extracted_size: &mut u64,
+    max_size: Option<u64>,
+) -> IgnitionResult<()> {
+    let targets: HashMap<&Path, &Path> = links
+        .iter()
+        .map(|(path, target)| (path.as_path(), target.as_path()))
+        .collect();
+    let mut copies = Vec::new();
+    for (path, target) in links {
+        let source = resolve_link(&targets, path).map(|source| directory.join(source));
+        match source.filter(|source| source.exists()) {
+            Some(source) => copies.push((source.is_dir(), source, directory.join(path))),
+            None => println!(
+                "cargo:warning=--symbolic link skipped: {} -> {} (target not extracted)",
+                path.display(),
+                target.display()
+            ),
+        }
+    }
+    copies.sort_by_key(|(is_dir, _, _)| *is_dir);
+    for (_, source, destination) in copies {
+        create_parent(directory, &destination)?;
+        let size = copy_tree(&source, &destination)?;
+        add_size(archive_path, extracted_size, size, max_size)?;
+    }
+    Ok(())
+}
+
+/// Resolve the relative <path> through the symbolic links <targets> (relative paths and targets) of an archive,
+/// None if it leaves the directory or too many links are followed (e.g. a cycle).
+fn resolve_link(targets: &HashMap<&Path, &Path>, path: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<Component> = path.components().rev().collect();
+    let mut hops = 0;
+    while let Some(component) = pending.pop() {
+        match component {
+            Component::Normal(name) => resolved.push(local_name(&name.to_string_lossy()).as_ref()),
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+                continue;
+            }
+            Component::CurDir => continue,
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+        if let Some(target) = targets.get(resolved.as_path()) {
+            hops += 1;
+            if hops > MAX_LINK_HOPS {
+                return None;
+            }
+            resolved.pop();
+            pending.extend(target.components().rev());
+        }
+    }
+    Some(resolved)
+}
+
+/// Verify the symbolic links within <directory>, extracted from the archive at <archive_path>, resolve inside of it
+/// through the other links: `check_link` only verifies the target of each link as a path, which a link through
+/// another link escapes (e.g. `y -> x/..` with `x -> .`).
+fn check_links(archive_path: &Path, directory: &Path) -> IgnitionResult<()> {
+    let mut links = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(directory.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            // not following links
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                links.push((path, fs::read_link(entry.path())?));
+            } else if file_type.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    let targets: HashMap<&Path, &Path> = links
+        .iter()
+        .map(|(path, target)| (path.as_path(), target.as_path()))
+        .collect();
+    for (path, target) in &links {
+        if resolve_link(&targets, path).is_none() {
+            return Err(unsafe_entry(
+                archive_path,
+                path,
+                format!(
+                    "link target {} outside of the directory (or through too many links)",
+                    target.display()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Verify the (extracted) <target> of the hard link entry <path> of the archive at <archive_path> resolves inside
+/// <directory>: `check_link` only verifies it as a path, which symbolic links extracted before may lead out of.
+fn check_hard_link(
+    archive_path: &Path,
+    directory: &Path,
+    path: &Path,
+    target: &Path,
+) -> IgnitionResult<()> {
+    let source = directory.join(target).canonicalize().map_err(|err| {
+        IgnitionError::ExtractionError(format!(
+            "{}: failed to link `{}` to `{}`: {}",
+            archive_path.display(),
+            path.display(),
+            target.display(),
+            err
+        ))
+    })?;
+    if !source.starts_with(directory.canonicalize()?) {
+        return Err(unsafe_entry(
+            archive_path,
+            path,
+            format!("link target {} outside of the directory", target.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Copy the file or directory tree at <source> to <destination> (replacing an existing file). Return is the size
+/// of the files copied.
+fn copy_tree(source: &Path, destination: &Path) -> io::Result<u64> {
+    if !source.is_dir() {
+        if fs::symlink_metadata(destination).is_ok_and(|metadata| !metadata.is_dir()) {
+            remove_file(destination)?;
+        }
+        return fs::copy(source, destination);
+    }
+    fs::create_dir_all(destination)?;
+    let mut size = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        size += copy_tree(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+    Ok(size)
+}
+
+/// Create the parent directories of <destination> within <directory>, verifying they resolve inside of it (i.e.
+/// not through symbolic links left by a previous extraction).
+fn create_parent(directory: &Path, destination: &Path) -> io::Result<()> {
+    let Some(parent) = destination.parent() else {
+        return Ok(());
+    };
+    fs::create_dir_all(parent)?;
+    if !parent
+        .canonicalize()?
+        .starts_with(directory.canonicalize()?)
+    {
+        return Err(io::Error::other(format!(
+            "`{}` outside of the directory",
+            parent.display()
+        )));
+    }
+    Ok(())
+}
+
+/// <directory> as an absolute path, on Windows in extended-length form (`\\?\C:\...` or `\\?\UNC\...`), so
+/// extracted paths aren't limited to `MAX_PATH` (260 characters). Entry paths joined to it must only have normal
+/// components (as built by `strip_path`), extended-length paths being used verbatim.
+fn extended_path(directory: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(directory)?;
+    #[cfg(windows)]
+    {
+        let absolute = absolute.to_string_lossy();
+        if !absolute.starts_with(r"\\?\") {
+            return Ok(PathBuf::from(match absolute.strip_prefix(r"\\") {
+                Some(unc) => format!(r"\\?\UNC\{}", unc),
+                None => format!(r"\\?\{}", absolute),
+            }));
+        }
+    }
+    Ok(absolute)
+}
+
+/// Relative <path> of an entry valid on the platform: on Windows, with each component renamed as by `local_name`.
+fn local_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) => PathBuf::from(local_name(&name.to_string_lossy()).as_ref()),
+            component => PathBuf::from(component.as_os_str()),
+        })
+        .collect()
+}
+
+/// File <name> valid on the platform: on Windows (as 7-Zip does), with invalid characters and trailing dots or
+/// spaces replaced by `_`, and reserved device names (e.g. `aux.h`) prefixed with `_`.
+fn local_name(name: &str) -> Cow<'_, str> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(name);
+    }
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    let mut local: String = trimmed
+        .chars()
+        .map(|c| match c.is_control() || INVALID_CHARACTERS.contains(c) {
+            true => '_',
+            false => c,
+        })
+        .collect();
+    local.extend(std::iter::repeat_n('_', name.len() - trimmed.len()));
+    let stem = local.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        local.insert(0, '_');
+    }
+    match local == name {
+        true => Cow::Borrowed(name),
+        false => Cow::Owned(local),
+    }
+}
+
+/// <path> without its first <count> components (ignoring `.` components), None if no component remains.
+pub(crate) fn strip_path(path: &Path, count: usize) -> Option<PathBuf> {
+    let stripped: PathBuf = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .skip(count)
+        .collect();
+    (!stripped.as_os_str().is_empty()).then_some(stripped)
+}
+
+/// Determine if the relative <path> matches the glob <pattern>, or is within a directory matching it (see
+/// `ExtractOptions::is_selected`).
+pub fn pattern_matches(pattern: &str, path: &Path) -> bool {
+    let components: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let components: Vec<&str> = components.iter().map(AsRef::as_ref).collect();
+    let pattern: Vec<&str> = pattern
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+    glob_match(&pattern, &components)
+}
+
+/// Determine if the path <components> match the glob <pattern> components (see `ExtractOptions::is_selected`),
+/// or are within a directory matching it.
+fn glob_match(pattern: &[&str], components: &[&str]) -> bool {
+    match (pattern.split_first(), components.split_first()) {
+        (None, _) => true,
+        (Some((&"**", rest)), _) => {
+            glob_match(rest, components)
+                || (!components.is_empty() && glob_match(pattern, &components[1..]))
+        }
+        (Some(_), None) => false,
+        (Some((first, rest)), Some((component, components))) => {
+            wildcard_match(first.as_bytes(), component.as_bytes()) && glob_match(rest, components)
+        }
+    }
+}
+
+/// Determine if <name> matches the wildcard <pattern> (`*` any characters, `?` a single character).
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(_), None) => false,
+        (Some((&first, rest)), Some((&character, name))) => {
+            (first == b'?' || first == character) && wildcard_match(rest, name)
+        }
+    }
+}
+
+/// Extraction error of the archive at <archive_path>, with the causes of <err> (e.g. a truncated stream).
+fn extraction_error(archive_path: &Path, err: &dyn Error) -> IgnitionError {
+    let mut message = format!("{}: {}", archive_path.display(), err);
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    IgnitionError::ExtractionError(message)
+}
+
+/// Unsafe entry <path> of the archive at <archive_path>.
+fn unsafe_entry(archive_path: &Path, path: &Path, reason: String) -> IgnitionError {
+    IgnitionError::UnsafeArchiveEntry {
+        archive: archive_path.display().to_string(),
+        entry: path.display().to_string(),
+        reason,
+    }
+}
+
+/// Verify the entry <path> of the archive at <archive_path> is relative, without `..` components.
+fn check_path(archive_path: &Path, path: &Path) -> IgnitionResult<()> {
+    if path.has_root() || path.components().any(|c| matches!(c, Component::Prefix(_))) {
+        return Err(unsafe_entry(
+            archive_path,
+            path,
+            "absolute path".to_string(),
+        ));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(unsafe_entry(
+            archive_path,
+            path,
+            "parent directory component".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Verify the <target> of the link entry <path> of the archive at <archive_path> stays inside the directory,
+/// relative to the link if <symbolic>, otherwise (hard links) relative to the archive root.
+fn check_link(
+    archive_path: &Path,
+    path: &Path,
+    target: &Path,
+    symbolic: bool,
+) -> IgnitionResult<()> {
+    let resolved = match symbolic {
+        true => path.parent().unwrap_or(Path::new("")).join(target),
+        false => target.to_path_buf(),
+    };
+    if !is_contained(&resolved) {
+        return Err(unsafe_entry(
+            archive_path,
+            path,
+            format!("link target {} outside of the directory", target.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Add the <size> of an entry of the archive at <archive_path> to <extracted_size>, verifying the total stays
+/// within <max_size>.
+fn add_size(
+    archive_path: &Path,
+    extracted_size: &mut u64,
+    size: u64,
+    max_size: Option<u64>,
+) -> IgnitionResult<()> {
+    *extracted_size = extracted_size.saturating_add(size);
+    if let Some(max_size) = max_size
+        && *extracted_size > max_size
+    {
+        return Err(IgnitionError::SizeLimitExceeded(format!(
+            "{}: extracted size over {} bytes, limit {} bytes",
+            archive_path.display(),
+            extracted_size,
+            max_size
+        )));
+    }
+    Ok(())
+}
+
+/// Determine if the relative <path> stays inside its base directory (lexically, `..` components not escaping it).
+pub(crate) fn is_contained(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent_depth) => depth = parent_depth,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, path::Path};
+
+    use flate2::{Compression, write::GzEncoder};
+    use tar::{EntryType, Header};
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::{ExtractOptions, check_link, check_path, extract, is_contained, pattern_matches};
+    use crate::{IgnitionError, retrieval::test_directory};
+
+    /// Gzipped tarball of <entries> (path, type, and contents or link target), paths being written as is (e.g.
+    /// with `..` components).
+    pub(super) fn tar_gz(entries: &[(&str, EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for &(path, entry_type, value) in entries {
+            let mut header = Header::new_gnu();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(if entry_type.is_dir() { 0o755 } else { 0o644 });
+            let data = match entry_type {
+                EntryType::Regular => value.as_bytes(),
+                EntryType::Directory => &[],
+                _ => {
+                    header.set_link_name(value).unwrap();
+                    &[]
+                }
+            };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Extract the gzipped tarball of <entries> (see `tar_gz`) into `<directory>/out`.
+    pub(super) fn extract_tar_gz(
+        directory: &Path,
+        entries: &[(&str, EntryType, &str)],
+    ) -> Result<(), IgnitionError> {
+        let archive_path = directory.join("archive.tar.gz");
+        fs::File::create(&archive_path)
+            .unwrap()
+            .write_all(&tar_gz(entries))
+            .unwrap();
+        extract(
+            &archive_path,
+            &directory.join("out"),
+            &ExtractOptions::default(),
+        )
+    }
+
+    #[test]
+    fn check_path_rejects_absolute_and_parent_paths() {
+        let archive = Path::new("archive.tar.gz");
+        assert!(check_path(archive, Path::new("opencv/lib/libopencv_core.so")).is_ok());
+        assert!(check_path(archive, Path::new("./opencv")).is_ok());
+        assert!(check_path(archive, Path::new("/etc/passwd")).is_err());
+        assert!(check_path(archive, Path::new("../outside")).is_err());
+        assert!(check_path(archive, Path::new("opencv/../../outside")).is_err());
+        assert!(check_path(archive, Path::new("opencv/../lib")).is_err());
+    }
+
+    #[test]
+    fn check_link_rejects_escaping_targets() {
+        let archive = Path::new("archive.tar.gz");
+        let link = Path::new("opencv/lib/libopencv_core.so");
+        assert!(check_link(archive, link, Path::new("libopencv_core.so.4.10"), true).is_ok());
+        assert!(check_link(archive, link, Path::new("../include"), true).is_ok());
+        assert!(check_link(archive, link, Path::new("../../../outside"), true).is_err());
+        assert!(check_link(archive, link, Path::new("/etc/passwd"), true).is_err());
+        // hard link targets are relative to the archive root
+        assert!(check_link(archive, link, Path::new("opencv/lib/a.so"), false).is_ok());
+        assert!(check_link(archive, link, Path::new("../a.so"), false).is_err());
+    }
+
+    #[test]
+    fn is_contained_paths() {
+        assert!(is_contained(Path::new("a/b/../c")));
+        assert!(is_contained(Path::new("./a/..")));
+        assert!(!is_contained(Path::new("a/../..")));
+        assert!(!is_contained(Path::new("/a")));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let path = |path: &str| Path::new(path).to_path_buf();
+        assert!(pattern_matches(
+            "opencv/include",
+            &path("opencv/include/core.hpp")
+        ));
+        assert!(pattern_matches("./opencv/", &path("opencv/lib/a.so")));
+        assert!(pattern_matches(
+            "opencv/lib/*.so*",
+            &path("opencv/lib/libopencv_core.so.4")
+        ));
+        assert!(!pattern_matches(
+            "opencv/lib/*.so*",
+            &path("opencv/lib/libopencv_core.a")
+        ));
+        assert!(pattern_matches(
+            "**/*.hpp",
+            &path("opencv/include/opencv2/core.hpp")
+        ));
+        assert!(pattern_matches("**/lib", &path("lib/a.so")));
+        assert!(pattern_matches("lib??/*.a", &path("lib64/libz.a")));
+        assert!(!pattern_matches("lib??/*.a", &path("lib/libz.a")));
+        assert!(!pattern_matches("opencv/lib", &path("opencv")));
+        assert!(!pattern_matches("opencv/lib", &path("opencv/libs/a.so")));
+    }
+
+    #[test]
+    fn extract_rejects_path_traversal() {
+        let directory = test_directory("extract-traversal");
+        let result = extract_tar_gz(
+            &directory,
+            &[
+                ("asset/lib.so", EntryType::Regular, "library"),
+                ("../outside", EntryType::Regular, "escaped"),
+            ],
+        );
+        assert!(matches!(
+            result,
+            Err(IgnitionError::UnsafeArchiveEntry { .. })
+        ));
+        assert!(!directory.join("outside").exists());
+    }
+
+    #[test]
+    fn extract_rejects_escaping_links() {
+        let directory = test_directory("extract-escaping-links");
+        for entry in [
+            ("asset/lib", EntryType::Symlink, "../../outside"),
+            ("asset/lib", EntryType::Symlink, "/etc"),
+            ("asset/lib", EntryType::Link, "../outside"),
+        ] {
+            let result = extract_tar_gz(&directory, &[entry]);
+            assert!(
+                matches!(result, Err(IgnitionError::UnsafeArchiveEntry { .. })),
+                "{:?}: {:?}",
+                entry,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn extract_rejects_links_escaping_through_links() {
+        // `y -> x/..` is inside the directory as a path, but resolves to its parent through `x -> .`
+        let directory = test_directory("extract-links-through-links");
+        fs::write(directory.join("secret"), "outside").unwrap();
+        let links = [
+            ("x", EntryType::Symlink, "."),
+            ("y", EntryType::Symlink, "x/.."),
+        ];
+        for entries in [
+            vec![links[0], links[1]],
+            vec![links[1], links[0]],
+            vec![links[0], links[1], ("h", EntryType::Link, "y/secret")],
+        ] {
+            let _ = fs::remove_dir_all(directory.join("out"));
+            let result = extract_tar_gz(&directory, &entries);
+            assert!(
+                matches!(result, Err(IgnitionError::UnsafeArchiveEntry { .. })),
+                "{:?}: {:?}",
+                entries,
+                result
+            );
+            assert!(!directory.join("out/h").exists());
+        }
+        assert_eq!(
+            fs::read_to_string(directory.join("secret")).unwrap(),
+            "outside"
+        );
+    }
+
+    #[test]
+    fn extract_zip_rejects_links_escaping_through_links() {
+        let directory = test_directory("extract-zip-links-through-links");
+        let archive_path = directory.join("archive.zip");
+        let mut writer = ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        writer
+            .add_symlink("x", ".", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .add_symlink("y", "x/..", SimpleFileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+        let result = extract(
+            &archive_path,
+            &directory.join("out"),
+            &ExtractOptions {
+                threads: 2,
+                ..ExtractOptions::default()
+            },
+        );
+        assert!(matches!(
+            result,
+            Err(IgnitionError::UnsafeArchiveEntry { .. })
+        ));
+    }
+
+    #[test]
+    fn extract_files_and_links() {
+        let directory = test_directory("extract-links");
+        extract_tar_gz(
+            &directory,
+            &[
+                ("asset/lib", EntryType::Directory, ""),
+                ("asset/lib/libz.so.1.3", EntryType::Regular, "library"),
+                ("asset/lib/libz.so", EntryType::Symlink, "libz.so.1.3"),
+                (
+                    "asset/lib/libz.so.1",
+                    EntryType::Link,
+                    "asset/lib/libz.so.1.3",
+                ),
+            ],
+        )
+        .unwrap();
+        let lib = directory.join("out/asset/lib");
+        assert_eq!(fs::read_to_string(lib.join("libz.so")).unwrap(), "library");
+        assert_eq!(
+            fs::read_to_string(lib.join("libz.so.1")).unwrap(),
+            "library"
+        );
+    }
+}
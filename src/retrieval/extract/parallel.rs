@@ -0,0 +1,468 @@
+// parallel.rs
+//
+// This module contains the multi-threaded parts of extraction: decompression on background threads (zstd frames
+// being decoded concurrently), and a pool of threads writing extracted files, so extraction of large archives
+// scales with the cores of CI machines.
+//
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread::{self, Scope},
+    time::{Duration, SystemTime},
+};
+
+use super::{create_file, create_parent, remove_file, set_permissions};
+use crate::{IgnitionResult, retrieval::env_value};
+
+const EXTRACT_THREADS_VARIABLE: &str = "IGNITION_EXTRACT_THREADS";
+/// Size of the decompressed chunks passed from decompression threads to the unpacking thread.
+const CHUNK_SIZE: usize = 1 << 20;
+/// Number of chunks (or frames) buffered between threads, per thread.
+const CHANNEL_CAPACITY: usize = 4;
+/// Maximum decompressed size of zstd frames decoded concurrently, larger frames (or frames of unknown size) being
+/// decoded as a stream.
+const MAX_FRAME_SIZE: u64 = 32 << 20;
+const ZSTD_MAGIC: u32 = 0xfd2f_b528;
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+const SKIPPABLE_MAGIC_MASK: u32 = 0xffff_fff0;
+
+/// Determine the number of extraction threads: `IGNITION_EXTRACT_THREADS` (1 disables multi-threading),
+/// otherwise the available parallelism.
+pub fn extract_threads() -> IgnitionResult<usize> {
+    Ok(env_value(EXTRACT_THREADS_VARIABLE)?
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get())))
+}
+
+/// Reader of decompressed chunks sent by decompression threads, in order. Dropping it stops the threads.
+pub struct ChunkReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl ChunkReader {
+    fn new(receiver: Receiver<io::Result<Vec<u8>>>) -> Self {
+        ChunkReader {
+            receiver,
+            chunk: Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.receiver.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                // all chunks received
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Decompress <decoder> on a background thread of <scope>, so decompression overlaps with unpacking. Return is
+/// the reader of the decompressed stream.
+pub fn decode_in_background<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    mut decoder: impl Read + Send + 'scope,
+) -> ChunkReader {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    scope.spawn(move || {
+        send_chunks(&mut decoder, &sender);
+    });
+    ChunkReader::new(receiver)
+}
+
+/// Read <reader> to the end in chunks sent to <sender>, up to the first error (sent too) or until the receiver is
+/// dropped.
+fn send_chunks(reader: &mut impl Read, sender: &SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let result = read_chunk(reader, &mut chunk).map(|read| {
+            chunk.truncate(read);
+            chunk
+        });
+        let end = result.as_ref().is_ok_and(Vec::is_empty);
+        let failed = result.is_err();
+        if end || sender.send(result).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Decompress the zstd stream <reader> on threads of <scope>: frames of a known size up to `MAX_FRAME_SIZE` (as
+/// written by `pzstd` or `zstd -B`) are decoded concurrently on <threads> threads, other frames as a stream on a
+/// background thread. Frames are decoded with a maximum window size of 2^<window_log_max>. Return is the reader
+/// of the decompressed stream.
+pub fn decode_zstd<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    mut reader: impl BufRead + Send + 'scope,
+    threads: usize,
+    window_log_max: u32,
+) -> ChunkReader {
+    // units (frames, or chunks of frames decoded as a stream) are numbered in order, and reordered once decoded
+    let (frame_sender, frame_receiver) = mpsc::sync_channel::<(u64, Vec<u8>, u64)>(threads);
+    let (unit_sender, unit_receiver) =
+        mpsc::sync_channel::<(u64, io::Result<Vec<u8>>)>(threads * CHANNEL_CAPACITY);
+    let (chunk_sender, chunk_receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let frame_receiver = Arc::new(Mutex::new(frame_receiver));
+    for _ in 0..threads {
+        let frame_receiver = frame_receiver.clone();
+        let unit_sender = unit_sender.clone();
+        scope.spawn(move || {
+            loop {
+                let frame = frame_receiver.lock().map(|receiver| receiver.recv());
+                let Ok(Ok((sequence, frame, content_size))) = frame else {
+                    return;
+                };
+                let decoded = decode_frame(&frame, content_size, window_log_max);
+                if unit_sender.send((sequence, decoded)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    scope.spawn(move || {
+        let mut sequence = 0;
+        let fail = |sequence, err| {
+            unit_sender.send((sequence, Err(err))).ok();
+        };
+        loop {
+            let header = match read_frame_header(&mut reader) {
+                Ok(Some(header)) => header,
+                Ok(None) => return,
+                Err(err) => return fail(sequence, err),
+            };
+            if let Some(content_size) = header.content_size.filter(|size| *size <= MAX_FRAME_SIZE) {
+                let mut frame = header.bytes;
+                if let Err(err) = read_frame_blocks(&mut reader, &mut frame, header.checksum) {
+                    return fail(sequence, err);
+                }
+                if frame_sender.send((sequence, frame, content_size)).is_err() {
+                    return;
+                }
+                sequence += 1;
+                continue;
+            }
+            // the decoder only consumes the bytes of the frame from <reader>
+            let decoder = zstd::Decoder::with_buffer(Cursor::new(header.bytes).chain(&mut reader))
+                .and_then(|mut decoder| {
+                    decoder.window_log_max(window_log_max)?;
+                    Ok(decoder.single_frame())
+                });
+            let mut decoder = match decoder {
+                Ok(decoder) => decoder,
+                Err(err) => return fail(sequence, err),
+            };
+            loop {
+                let mut chunk = vec![0; CHUNK_SIZE];
+                match read_chunk(&mut decoder, &mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => {
+                        chunk.truncate(read);
+                        if unit_sender.send((sequence, Ok(chunk))).is_err() {
+                            return;
+                        }
+                        sequence += 1;
+                    }
+                    Err(err) => return fail(sequence, err),
+                }
+            }
+        }
+    });
+    scope.spawn(move || {
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (sequence, unit) in unit_receiver {
+            pending.insert(sequence, unit);
+            while let Some(unit) = pending.remove(&next) {
+                let failed = unit.is_err();
+                if chunk_sender.send(unit).is_err() || failed {
+                    return;
+                }
+                next += 1;
+            }
+        }
+    });
+    ChunkReader::new(chunk_receiver)
+}
+
+/// Header of a zstd frame.
+struct FrameHeader {
+    /// Bytes of the header, from the magic number.
+    bytes: Vec<u8>,
+    /// Decompressed size of the frame, if recorded.
+    content_size: Option<u64>,
+    /// Determine if the frame ends with a checksum.
+    checksum: bool,
+}
+
+/// Read the header of the next zstd frame from <reader>, skipping skippable frames. Return is None at the end
+/// of the stream.
+fn read_frame_header(reader: &mut impl Read) -> io::Result<Option<FrameHeader>> {
+    loop {
+        let mut magic = [0; 4];
+        match reader.read(&mut magic[..1])? {
+            0 => return Ok(None),
+            _ => reader.read_exact(&mut magic[1..])?,
+        }
+        let magic_number = u32::from_le_bytes(magic);
+        if magic_number & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+            let mut size = [0; 4];
+            reader.read_exact(&mut size)?;
+            let size = u64::from(u32::from_le_bytes(size));
+            if io::copy(&mut reader.take(size), &mut io::sink())? != size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            continue;
+        }
+        if magic_number != ZSTD_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown zstd frame magic number",
+            ));
+        }
+        // frame header descriptor: content size flag, single segment flag, checksum flag and dictionary id flag
+        let mut descriptor = [0; 1];
+        reader.read_exact(&mut descriptor)?;
+        let descriptor = descriptor[0];
+        let single_segment = descriptor & 0x20 != 0;
+        let content_size_len = match descriptor >> 6 {
+            0 => usize::from(single_segment),
+            1 => 2,
+            2 => 4,
+            _ => 8,
+        };
+        let window_len = usize::from(!single_segment);
+        let dictionary_len = [0, 1, 2, 4][usize::from(descriptor & 0x3)];
+        let mut fields = vec![0; window_len + dictionary_len + content_size_len];
+        reader.read_exact(&mut fields)?;
+        let content_size = &fields[window_len + dictionary_len..];
+        let content_size = match content_size.len() {
+            0 => None,
+            1 => Some(u64::from(content_size[0])),
+            // 2 byte sizes are offset by 256
+            2 => Some(u64::from(u16::from_le_bytes([content_size[0], content_size[1]])) + 256),
+            _ => {
+                let mut bytes = [0; 8];
+                bytes[..content_size.len()].copy_from_slice(content_size);
+                Some(u64::from_le_bytes(bytes))
+            }
+        };
+        let mut bytes = magic.to_vec();
+        bytes.push(descriptor);
+        bytes.extend(fields);
+        return Ok(Some(FrameHeader {
+            bytes,
+            content_size,
+            checksum: descriptor & 0x4 != 0,
+        }));
+    }
+}
+
+/// Read the blocks (and <checksum>) of a zstd frame from <reader>, appending them to <frame>.
+fn read_frame_blocks(
+    reader: &mut impl Read,
+    frame: &mut Vec<u8>,
+    checksum: bool,
+) -> io::Result<()> {
+    loop {
+        // block header: last block flag, block type (raw, RLE, compressed) and block size
+        let mut header = [0; 3];
+        reader.read_exact(&mut header)?;
+        frame.extend_from_slice(&header);
+        let header = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+        let size = match (header >> 1) & 0x3 {
+            1 => 1,
+            3 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "reserved zstd block type",
+                ));
+            }
+            _ => u64::from(header >> 3),
+        };
+        if reader.take(size).read_to_end(frame)? as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if header & 0x1 != 0 {
+            break;
+        }
+    }
+    if checksum {
+        let mut checksum = [0; 4];
+        reader.read_exact(&mut checksum)?;
+        frame.extend_from_slice(&checksum);
+    }
+    Ok(())
+}
+
+/// Decode the complete zstd <frame> of <content_size> bytes.
+fn decode_frame(frame: &[u8], content_size: u64, window_log_max: u32) -> io::Result<Vec<u8>> {
+    let mut decoder = zstd::Decoder::with_buffer(frame)?;
+    decoder.window_log_max(window_log_max)?;
+    let mut decoded = Vec::with_capacity(content_size as usize);
+    decoder.read_to_end(&mut decoded)?;
+    if decoded.len() as u64 != content_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "zstd frame content size mismatch",
+        ));
+    }
+    Ok(decoded)
+}
+
+/// Read from <reader> until <buf> is full or the end of the stream. Return is the number of bytes read.
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(count) => read += count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// File written by a `WriterPool`.
+pub struct PooledFile {
+    /// Path of the file, within the directory.
+    pub destination: PathBuf,
+    pub contents: Vec<u8>,
+    /// Unix mode of the file.
+    pub mode: Option<u32>,
+    /// Modification time of the file, in seconds since the Unix epoch.
+    pub mtime: Option<u64>,
+}
+
+/// Message to a thread of a `WriterPool`.
+enum WriterMessage {
+    Write(PooledFile),
+    /// Acknowledge all previous writes on the sender.
+    Flush(SyncSender<()>),
+}
+
+/// Pool of threads writing extracted files into a directory, so file creation and writes (e.g. of many small
+/// headers) overlap with decompression. Files of the same path are written by the same thread, in order.
+pub struct WriterPool {
+    senders: Vec<SyncSender<WriterMessage>>,
+    /// First error of a write, later writes being skipped.
+    error: Arc<Mutex<Option<io::Error>>>,
+}
+
+impl WriterPool {
+    /// Start <threads> writer threads of <scope> writing files into <directory>.
+    pub fn new<'scope>(
+        scope: &'scope Scope<'scope, '_>,
+        directory: &'scope Path,
+        threads: usize,
+    ) -> Self {
+        let error = Arc::new(Mutex::new(None));
+        let senders = (0..threads)
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+                let error = error.clone();
+                scope.spawn(move || {
+                    for message in receiver {
+                        match message {
+                            WriterMessage::Write(file) => {
+                                // the lock is only held to check and record the first error, not while writing
+                                if error
+                                    .lock()
+                                    .unwrap_or_else(|err| err.into_inner())
+                                    .is_some()
+                                {
+                                    continue;
+                                }
+                                if let Err(err) = write_file(directory, &file) {
+                                    error
+                                        .lock()
+                                        .unwrap_or_else(|err| err.into_inner())
+                                        .get_or_insert(io::Error::new(
+                                            err.kind(),
+                                            format!(
+                                                "failed to write `{}`: {}",
+                                                file.destination.display(),
+                                                err
+                                            ),
+                                        ));
+                                }
+                            }
+                            WriterMessage::Flush(acknowledge) => {
+                                acknowledge.send(()).ok();
+                            }
+                        }
+                    }
+                });
+                sender
+            })
+            .collect();
+        WriterPool { senders, error }
+    }
+
+    /// Write <file> on a thread of the pool.
+    pub fn write(&self, file: PooledFile) -> io::Result<()> {
+        let index = file
+            .destination
+            .as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .fold(0usize, |hash, byte| {
+                hash.wrapping_mul(31).wrapping_add(usize::from(*byte))
+            });
+        self.senders[index % self.senders.len()]
+            .send(WriterMessage::Write(file))
+            .map_err(|_| io::Error::other("writer thread stopped"))
+    }
+
+    /// Wait for all files written so far, e.g. before creating links to them. Return is the first error of a write.
+    pub fn flush(&self) -> io::Result<()> {
+        let (acknowledge, acknowledged) = mpsc::sync_channel(self.senders.len());
+        for sender in self.senders.iter() {
+            sender
+                .send(WriterMessage::Flush(acknowledge.clone()))
+                .map_err(|_| io::Error::other("writer thread stopped"))?;
+        }
+        for _ in self.senders.iter() {
+            acknowledged
+                .recv()
+                .map_err(|_| io::Error::other("writer thread stopped"))?;
+        }
+        match self
+            .error
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+        {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write <file> into <directory>, replacing an existing file.
+fn write_file(directory: &Path, file: &PooledFile) -> io::Result<()> {
+    create_parent(directory, &file.destination)?;
+    if fs::symlink_metadata(&file.destination).is_ok_and(|metadata| !metadata.is_dir()) {
+        remove_file(&file.destination)?;
+    }
+    let mut output = create_file(&file.destination, 0)?;
+    output.write_all(&file.contents)?;
+    if let Some(mtime) = file.mtime {
+        output.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime))?;
+    }
+    set_permissions(&file.destination, file.mode, 0)
+}
@@ -0,0 +1,85 @@
+// format.rs
+//
+// This module contains the file formats of asset manifests: JSON (`environment.json`, the embedded manifest's), TOML
+// (`environment.toml`, with comments) and YAML (`environment.yaml`, e.g. generated by infrastructure tooling), parsed
+// into the same JSON tree, so every manifest is merged and deserialized into `AssetEnvironment` alike whatever its
+// format.
+//
+
+use std::{fmt, path::Path};
+
+use crate::{IgnitionError, IgnitionResult};
+
+/// File format of a manifest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ManifestFormat {
+    /// JSON (default).
+    Json,
+    /// TOML, assets being tables (e.g. `[opencv]`).
+    Toml,
+    /// YAML, assets being mappings.
+    Yaml,
+}
+
+impl fmt::Display for ManifestFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestFormat::Json => write!(f, "JSON"),
+            ManifestFormat::Toml => write!(f, "TOML"),
+            ManifestFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+impl ManifestFormat {
+    /// Format of the manifest at <path>, by extension: `.toml` for TOML, `.yaml` or `.yml` for YAML, otherwise JSON
+    /// (for compatibility).
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("toml") => ManifestFormat::Toml,
+            Some("yaml" | "yml") => ManifestFormat::Yaml,
+            _ => ManifestFormat::Json,
+        }
+    }
+
+    /// Parse the <contents> of a manifest of this format into JSON, the manifest's <origin> (e.g. its path) prefixing
+    /// parse errors. Manifests must be objects (tables, mappings) of assets.
+    pub fn parse(self, contents: &str, origin: &str) -> IgnitionResult<serde_json::Value> {
+        let invalid = |reason: String| {
+            IgnitionError::ConfigurationDeserializationError(format!(
+                "{} ({}): {}",
+                origin, self, reason
+            ))
+        };
+        let manifest: serde_json::Value = match self {
+            ManifestFormat::Json => {
+                serde_json::from_str(contents).map_err(|err| invalid(err.to_string()))?
+            }
+            ManifestFormat::Toml => toml::from_str(contents)
+                .map_err(|err| invalid(err.message().to_string() + &location(contents, &err)))?,
+            ManifestFormat::Yaml => {
+                serde_yaml_ng::from_str(contents).map_err(|err| invalid(err.to_string()))?
+            }
+        };
+        if !manifest.is_object() {
+            return Err(invalid("not an object of assets".to_string()));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Location of the TOML parse <err> within <contents> for error messages (` at line <line> column <column>`), empty
+/// if unknown.
+fn location(contents: &str, err: &toml::de::Error) -> String {
+    let Some(span) = err.span() else {
+        return String::new();
+    };
+    let before = &contents[..span.start.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    format!(" at line {} column {}", line, column)
+}
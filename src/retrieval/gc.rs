@@ -0,0 +1,309 @@
+// gc.rs
+//
+// This module contains the garbage collection of the asset cache (`IGNITION_CACHE_MAX_SIZE`): least recently used
+// cache entries are evicted until the cache fits its size limit, keeping the archives locked by current lockfiles,
+// so caches of long-lived CI runners stop growing with every asset version. Statistics of the cache (size, entries
+// and disk usage by asset) are reported the same way, e.g. for dashboards or to choose the size limit.
+//
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use super::{
+    cache::{self, CacheRecord},
+    checksum, env_size,
+    install::{self, TREE_EXTENSION},
+    lock::{LOCK_EXTENSION, PathLock},
+    lockfile::Lockfile,
+};
+use crate::IgnitionResult;
+
+const MAX_CACHE_SIZE_VARIABLE: &str = "IGNITION_CACHE_MAX_SIZE";
+const LOCKFILES_VARIABLE: &str = "IGNITION_CACHE_LOCKFILES";
+
+/// Entry of the cache: a cached file (e.g. an archive) and its side files (validators, signatures, extracted tree,
+/// partial downloads, ...), evicted together.
+struct CacheEntry {
+    /// Path of the cached file (or of a side file left without it, e.g. a partial download).
+    path: PathBuf,
+    /// Paths of the cached file and its side files, except lock files.
+    members: Vec<PathBuf>,
+    /// Paths whose locks cover the entry (<path>, or the file a side file belongs to, see `lock::PathLock`).
+    locks: Vec<PathBuf>,
+    /// Size of the entry in bytes.
+    size: u64,
+    /// Last use of the entry (latest modification time of its members, see `touch`).
+    used: SystemTime,
+}
+
+/// Result of a garbage collection of the cache.
+#[derive(Debug, Default)]
+pub struct Collection {
+    /// Paths of the evicted cache entries.
+    pub evicted: Vec<PathBuf>,
+    /// Bytes freed by evicting entries.
+    pub freed: u64,
+    /// Size of the cache in bytes after collection.
+    pub size: u64,
+}
+
+/// Statistics of the cache, see `stats`.
+#[derive(Debug, Default, Serialize)]
+pub struct CacheStats {
+    /// Size of the cache in bytes.
+    pub size: u64,
+    /// Number of cache entries.
+    pub entries: usize,
+    /// Disk usage of the cache by asset name.
+    pub assets: BTreeMap<String, AssetUsage>,
+    /// Disk usage of the entries of no known asset (e.g. archives cached before their records, partial downloads).
+    pub other: AssetUsage,
+}
+
+/// Disk usage of the cache entries of an asset.
+#[derive(Debug, Default, Serialize)]
+pub struct AssetUsage {
+    /// Size of the entries in bytes.
+    pub size: u64,
+    /// Last use of any of the entries, in seconds since the Unix epoch.
+    pub last_used: u64,
+    /// Entries, most recently used first.
+    pub entries: Vec<EntryUsage>,
+}
+
+/// Disk usage of a cache entry: a cached file with its side files (see `collect`).
+#[derive(Debug, Serialize)]
+pub struct EntryUsage {
+    /// Path of the cached file.
+    pub path: PathBuf,
+    /// Size of the entry in bytes.
+    pub size: u64,
+    /// Last use of the entry (see `touch`), in seconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+/// Record the use of the cached file at <path> for the eviction order of `collect` (its modification time, as
+/// access times are commonly not maintained).
+pub fn touch(path: &Path) -> IgnitionResult<()> {
+    install::set_modified(path, SystemTime::now())?;
+    Ok(())
+}
+
+/// Collect the cache at <directory> if `IGNITION_CACHE_MAX_SIZE` is set (e.g. `20G`, see `collect`), keeping the
+/// archives locked by the `ignition.lock` lockfile (see `Lockfile::path`) and the lockfiles listed in
+/// `IGNITION_CACHE_LOCKFILES` (separated as `PATH`). Return is None without a size limit.
+pub fn collect_configured(directory: &Path) -> IgnitionResult<Option<Collection>> {
+    let Some(max_size) = env_size(MAX_CACHE_SIZE_VARIABLE)? else {
+        return Ok(None);
+    };
+    let mut lockfiles = vec![Lockfile::path()?];
+    if let Some(paths) = env::var_os(LOCKFILES_VARIABLE) {
+        lockfiles.extend(env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty()));
+    }
+    collect(directory, max_size, &lockfiles).map(Some)
+}
+
+/// Evict the least recently used entries of the cache at <directory> until it is at most <max_size> bytes.
+///
+/// Entries are cached files with their side files (e.g. `<asset>.tar.gz` with `<asset>.tar.gz.validators.json` and
+/// the extracted tree `<asset>.tar.gz.extracted`), used in order of their modification times (see `touch`).
+/// Archives whose SHA-256 digest is locked by any of the <lockfiles> (see `lockfile::Lockfile`) are never evicted,
+/// nor are entries locked by a retrieval in progress (see `lock::PathLock`), which hold the lock meanwhile. Lock
+/// files are kept, as builds waiting for them would otherwise lock a deleted file.
+pub fn collect(
+    directory: &Path,
+    max_size: u64,
+    lockfiles: &[PathBuf],
+) -> IgnitionResult<Collection> {
+    let mut entries = Vec::new();
+    if directory.is_dir() {
+        scan(directory, &mut entries)?;
+    }
+    let mut collection = Collection {
+        size: entries.iter().map(|entry| entry.size).sum(),
+        ..Default::default()
+    };
+    if collection.size <= max_size {
+        return Ok(collection);
+    }
+    let mut locked = HashSet::new();
+    for lockfile in lockfiles {
+        let lockfile = Lockfile::load(lockfile)?;
+        locked.extend(
+            lockfile
+                .assets
+                .into_values()
+                .flat_map(|platforms| platforms.into_values())
+                .map(|asset| asset.sha256),
+        );
+    }
+    entries.sort_by_key(|entry| entry.used);
+    for entry in entries {
+        if collection.size <= max_size {
+            break;
+        }
+        if !locked.is_empty()
+            && entry.path.is_file()
+            && locked.contains(&checksum::sha256_file(&entry.path)?)
+        {
+            println!("--cache kept: {} (locked)", entry.path.display());
+            continue;
+        }
+        // entries in use are held until evicted
+        let mut guards = Vec::with_capacity(entry.locks.len());
+        for lock in entry.locks.iter() {
+            match PathLock::try_acquire(lock)? {
+                Some(guard) => guards.push(guard),
+                None => break,
+            }
+        }
+        if guards.len() < entry.locks.len() {
+            println!("--cache kept: {} (in use)", entry.path.display());
+            continue;
+        }
+        for member in entry.members.iter() {
+            match fs::symlink_metadata(member)?.is_dir() {
+                true => fs::remove_dir_all(member)?,
+                false => fs::remove_file(member)?,
+            }
+        }
+        println!("--evicted: {} ({} bytes)", entry.path.display(), entry.size);
+        collection.size -= entry.size;
+        collection.freed += entry.size;
+        collection.evicted.push(entry.path);
+    }
+    Ok(collection)
+}
+
+/// Report the size, entries and disk usage by asset of the cache at <directory>, without modifying it.
+///
+/// Entries are grouped as by `collect`, and attributed to assets by the records of their archives (see
+/// `CacheRecord`) or, for the files of assets, by their location (`<asset>/files`).
+pub fn stats(directory: &Path) -> IgnitionResult<CacheStats> {
+    let mut entries = Vec::new();
+    if directory.is_dir() {
+        scan(directory, &mut entries)?;
+    }
+    let mut stats = CacheStats::default();
+    entries.sort_by_key(|entry| Reverse(entry.used));
+    for entry in entries {
+        let asset = CacheRecord::load(&entry.path)
+            .and_then(|record| record.asset)
+            .or_else(|| files_asset(directory, &entry.path));
+        let usage = match asset {
+            Some(asset) => stats.assets.entry(asset).or_default(),
+            None => &mut stats.other,
+        };
+        let last_used = entry
+            .used
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |used| used.as_secs());
+        usage.size += entry.size;
+        usage.last_used = usage.last_used.max(last_used);
+        usage.entries.push(EntryUsage {
+            path: entry.path,
+            size: entry.size,
+            last_used,
+        });
+        stats.size += entry.size;
+        stats.entries += 1;
+    }
+    Ok(stats)
+}
+
+/// Asset of the cached file at <path> within the cache <directory> if it is a file of an asset
+/// (`<asset>/files/<hash>/<file>`, see `retrieval::retrieve`).
+fn files_asset(directory: &Path, path: &Path) -> Option<String> {
+    let components: Vec<_> = path.strip_prefix(directory).ok()?.iter().collect();
+    match components.as_slice() {
+        [.., asset, files, _, _] if *files == "files" => Some(asset.to_string_lossy().into_owned()),
+        _ => None,
+    }
+}
+
+/// Scan the cache <directory> (recursively) for its entries, appended to <entries>.
+///
+/// Files named after another file followed by an extension (`<file>.<extension>`) are side files of it, other
+/// files are cached files. Directories are part of the cache layout, except for extracted trees and side files.
+fn scan(directory: &Path, entries: &mut Vec<CacheEntry>) -> IgnitionResult<()> {
+    let mut names: Vec<OsString> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<_, _>>()?;
+    // cached files go before their side files
+    names.sort_by_key(|name| name.len());
+    let mut directory_entries: Vec<CacheEntry> = Vec::new();
+    let mut locks = Vec::new();
+    for name in names {
+        let path = directory.join(&name);
+        let name = name.to_string_lossy();
+        if let Some(stem) = name.strip_suffix(LOCK_EXTENSION) {
+            locks.push(stem.to_string());
+            continue;
+        }
+        // the layout of a cache goes with the cache, never evicted
+        if name == cache::LAYOUT_FILE_NAME {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(&path)?;
+        let owner = directory_entries.iter_mut().find(|entry| {
+            entry
+                .path
+                .file_name()
+                .is_some_and(|file_name| is_side_file(&name, &file_name.to_string_lossy()))
+        });
+        match owner {
+            Some(entry) => {
+                entry.size += size(&path)?;
+                entry.used = entry.used.max(metadata.modified()?);
+                entry.members.push(path);
+            }
+            None if metadata.is_dir() && !name.ends_with(TREE_EXTENSION) => scan(&path, entries)?,
+            None => directory_entries.push(CacheEntry {
+                size: size(&path)?,
+                used: metadata.modified()?,
+                members: vec![path.clone()],
+                locks: Vec::new(),
+                path,
+            }),
+        }
+    }
+    for entry in directory_entries.iter_mut() {
+        let file_name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        // the lock of an archive covers its side files, e.g. its partial download
+        entry.locks = locks
+            .iter()
+            .filter(|stem| **stem == file_name || is_side_file(&file_name, stem))
+            .map(|stem| directory.join(stem))
+            .collect();
+    }
+    entries.extend(directory_entries);
+    Ok(())
+}
+
+/// Whether <name> is the name of a side file of the file named <file_name> (`<file_name>.<extension>`).
+fn is_side_file(name: &str, file_name: &str) -> bool {
+    name.strip_prefix(file_name)
+        .is_some_and(|extension| extension.starts_with('.'))
+}
+
+/// Size of the file or directory (recursively) at <path> in bytes.
+pub(crate) fn size(path: &Path) -> IgnitionResult<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += self::size(&entry?.path())?;
+    }
+    Ok(size)
+}
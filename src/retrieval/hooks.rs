@@ -0,0 +1,142 @@
+// hooks.rs
+//
+// This module contains the post-extract hooks of assets (`post_extract` in the manifest): commands fixing up an
+// extracted asset (e.g. `install_name_tool` on macOS dylibs, or generating a `.pc` file), run within the asset
+// directory with a minimal environment, before its contents are verified.
+//
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use super::{download::sidecar_path, extract::is_contained, target::ResolvedTarget};
+use crate::{IgnitionError, IgnitionResult};
+
+const HOOK_ASSET_VARIABLE: &str = "IGNITION_ASSET";
+const HOOK_ASSET_DIR_VARIABLE: &str = "IGNITION_ASSET_DIR";
+const HOOK_TARGET_VARIABLE: &str = "IGNITION_TARGET";
+const HOOK_PLATFORM_VARIABLE: &str = "IGNITION_PLATFORM";
+/// Variables of the build environment passed on to hooks, everything else being cleared.
+const KEPT_VARIABLES: [&str; 3] = ["PATH", "SYSTEMROOT", "PATHEXT"];
+/// Variables pointing hooks to their scratch directory.
+const SCRATCH_VARIABLES: [&str; 4] = ["HOME", "TMPDIR", "TMP", "TEMP"];
+/// File extension of the scratch directory of the hooks of an asset, next to the asset directory.
+const SCRATCH_EXTENSION: &str = ".hook";
+
+/// Run the post-extract <hooks> of <asset> (in order) within its extracted asset directory <directory>, for the
+/// <resolved> target.
+///
+/// Each hook is split on whitespace (as `scan_command`), its program resolved against the current directory (the
+/// package of the build script) if a relative path, and run with the asset directory as working directory. Hooks
+/// are sandboxed to the directory: the environment is cleared except for `PATH` (and `SYSTEMROOT` / `PATHEXT` on
+/// Windows), `HOME` and the temporary directory point to a scratch directory (`<asset>.hook`, removed afterwards),
+/// and symbolic links left pointing outside of the directory are an error. The asset name, asset directory,
+/// target triplet and bucket platform path are passed in `IGNITION_ASSET`, `IGNITION_ASSET_DIR`, `IGNITION_TARGET`
+/// and `IGNITION_PLATFORM`. A non-zero exit status fails the retrieval.
+pub fn run(
+    asset: &str,
+    directory: &Path,
+    resolved: &ResolvedTarget,
+    hooks: &[String],
+) -> IgnitionResult<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let directory = fs::canonicalize(directory)?;
+    let scratch_path = sidecar_path(&directory, SCRATCH_EXTENSION);
+    if scratch_path.exists() {
+        fs::remove_dir_all(&scratch_path)?;
+    }
+    fs::create_dir_all(&scratch_path)?;
+    let result = hooks.iter().try_for_each(|hook| {
+        run_hook(asset, &directory, &scratch_path, resolved, hook)?;
+        check_links(&directory, Path::new(""), hook)
+    });
+    fs::remove_dir_all(&scratch_path)?;
+    result
+}
+
+/// Run the post-extract <hook> of <asset> within <directory> as `run`, with <scratch_path> as scratch directory.
+fn run_hook(
+    asset: &str,
+    directory: &Path,
+    scratch_path: &Path,
+    resolved: &ResolvedTarget,
+    hook: &str,
+) -> IgnitionResult<()> {
+    let mut words = hook.split_whitespace();
+    let Some(program) = words.next() else {
+        return Ok(());
+    };
+    let mut command = Command::new(program_path(program)?);
+    command
+        .args(words)
+        .current_dir(directory)
+        .env_clear()
+        .envs(
+            KEPT_VARIABLES
+                .into_iter()
+                .filter_map(|variable| env::var_os(variable).map(|value| (variable, value))),
+        )
+        .envs(SCRATCH_VARIABLES.map(|variable| (variable, scratch_path)))
+        .env(HOOK_ASSET_VARIABLE, asset)
+        .env(HOOK_ASSET_DIR_VARIABLE, directory)
+        .env(HOOK_TARGET_VARIABLE, &resolved.triplet)
+        .env(HOOK_PLATFORM_VARIABLE, resolved.path_components().join("/"));
+    let output = command.output().map_err(|err| {
+        IgnitionError::HookFailed(format!("{}: command '{}' failed: {}", asset, program, err))
+    })?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(IgnitionError::HookFailed(format!(
+            "{}: '{}' exited with {}: {}",
+            asset,
+            hook,
+            output.status,
+            [stdout.trim(), stderr.trim()]
+                .into_iter()
+                .filter(|output| !output.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        )));
+    }
+    println!("--hook: {}: {}", asset, hook);
+    Ok(())
+}
+
+/// Path of the hook <program>: relative paths (e.g. `scripts/fixup.sh`) are resolved against the current
+/// directory, bare program names are looked up in `PATH`.
+fn program_path(program: &str) -> IgnitionResult<PathBuf> {
+    let path = Path::new(program);
+    if path.is_relative() && path.components().count() > 1 {
+        return Ok(env::current_dir()?.join(path));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Verify the symbolic links within <directory> at the relative <path> stay inside it after the <hook> ran.
+fn check_links(directory: &Path, path: &Path, hook: &str) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory.join(path))? {
+        let entry = entry?;
+        let path = path.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            check_links(directory, &path, hook)?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            if !is_contained(&path.parent().unwrap_or(Path::new("")).join(&target)) {
+                return Err(IgnitionError::HookFailed(format!(
+                    "'{}' linked {} to {}, outside of {}",
+                    hook,
+                    path.display(),
+                    target.display(),
+                    directory.display()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
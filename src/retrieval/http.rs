@@ -0,0 +1,272 @@
+// http.rs
+//
+// This module contains the HTTP(S) client configuration (proxy, timeouts, TLS) shared by all HTTP-based asset
+// downloads.
+//
+
+use std::{collections::BTreeSet, sync::Mutex, time::Duration};
+
+use ureq::{
+    Agent, Proxy,
+    http::Uri,
+    unversioned::{
+        resolver::DefaultResolver,
+        transport::{
+            Buffers, ConnectProxyConnector, ConnectionDetails, Connector, DefaultConnector,
+            NextTimeout, TcpConnector, Transport,
+        },
+    },
+};
+
+use super::{allowlist, env_value, offline, tls};
+use crate::{IgnitionError, IgnitionResult};
+
+const PROXY_VARIABLE: &str = "IGNITION_PROXY";
+const PROXY_USERNAME_VARIABLE: &str = "IGNITION_PROXY_USERNAME";
+const PROXY_PASSWORD_VARIABLE: &str = "IGNITION_PROXY_PASSWORD";
+const TIMEOUT_VARIABLE: &str = "IGNITION_TIMEOUT_SECS";
+const CONNECT_TIMEOUT_VARIABLE: &str = "IGNITION_CONNECT_TIMEOUT_SECS";
+const READ_TIMEOUT_VARIABLE: &str = "IGNITION_READ_TIMEOUT_SECS";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+
+/// Proxies logged, so each is logged once per build rather than for every agent.
+static LOGGED_PROXIES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Create an HTTP agent configured for requests to <url>.
+///
+/// If TLS is required (see `tls::require_tls`), plain HTTP requests and redirects are refused. With a host
+/// allowlist (see `allowlist::allowed_hosts`), connections to other hosts (including redirects) are refused. In
+/// offline mode (see `offline::is_offline`), the agent is refused.
+pub fn agent(url: &str) -> IgnitionResult<Agent> {
+    offline::check_url(url)?;
+    let (connect_timeout, read_timeout) = timeouts()?;
+    let config = Agent::config_builder()
+        .https_only(tls::require_tls(false)?)
+        .proxy(proxy(url)?)
+        .timeout_resolve(connect_timeout)
+        .timeout_connect(connect_timeout)
+        .timeout_recv_response(read_timeout)
+        .build();
+    let read_timeout_connector = ReadTimeoutConnector(read_timeout);
+    let allowlist_connector = allowlist::HostAllowlistConnector(allowlist::allowed_hosts(&[])?);
+    let host = url.parse::<Uri>().ok();
+    let host = host.as_ref().and_then(Uri::host).unwrap_or_default();
+    let agent = match tls::connector(host)? {
+        // custom TLS takes the place of the default TLS connector in the default chain
+        Some(tls_connector) => Agent::with_parts(
+            config,
+            ().chain(allowlist_connector)
+                .chain(ConnectProxyConnector::default())
+                .chain(TcpConnector::default())
+                .chain(tls_connector)
+                .chain(read_timeout_connector),
+            DefaultResolver::default(),
+        ),
+        None => Agent::with_parts(
+            config,
+            ().chain(allowlist_connector)
+                .chain(DefaultConnector::new())
+                .chain(read_timeout_connector),
+            DefaultResolver::default(),
+        ),
+    };
+    Ok(agent)
+}
+
+/// Determine the (connect, read) timeouts, None if disabled.
+///
+/// The connect timeout (default 30s) covers name resolution and connection establishment. The read timeout
+/// (default 60s) bounds the wait for the response and for every read of the response body, so a stalled
+/// connection fails (and is retried) instead of hanging. `IGNITION_TIMEOUT_SECS` sets both, overridden by
+/// `IGNITION_CONNECT_TIMEOUT_SECS`/`IGNITION_READ_TIMEOUT_SECS`; 0 disables a timeout.
+pub fn timeouts() -> IgnitionResult<(Option<Duration>, Option<Duration>)> {
+    let timeout = env_value::<u64>(TIMEOUT_VARIABLE)?;
+    let connect_secs = env_value(CONNECT_TIMEOUT_VARIABLE)?
+        .or(timeout)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+    let read_secs = env_value(READ_TIMEOUT_VARIABLE)?
+        .or(timeout)
+        .unwrap_or(DEFAULT_READ_TIMEOUT_SECS);
+    let duration = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+    Ok((duration(connect_secs), duration(read_secs)))
+}
+
+/// Connector wrapping the default transport to apply the read timeout to each read.
+#[derive(Debug)]
+struct ReadTimeoutConnector(Option<Duration>);
+
+impl<In: Transport> Connector<In> for ReadTimeoutConnector {
+    type Out = ReadTimeoutTransport;
+
+    fn connect(
+        &self,
+        _details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, ureq::Error> {
+        Ok(chained.map(|inner| ReadTimeoutTransport {
+            inner: inner.boxed(),
+            read_timeout: self.0,
+        }))
+    }
+}
+
+/// Transport limiting the wait for input to the read timeout, regardless of the overall request timeouts.
+#[derive(Debug)]
+struct ReadTimeoutTransport {
+    inner: Box<dyn Transport>,
+    read_timeout: Option<Duration>,
+}
+
+impl Transport for ReadTimeoutTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.inner.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), ureq::Error> {
+        self.inner.transmit_output(amount, timeout)
+    }
+
+    fn await_input(&mut self, mut timeout: NextTimeout) -> Result<bool, ureq::Error> {
+        if let Some(read_timeout) = self.read_timeout {
+            timeout.after = timeout.after.min(read_timeout.into());
+        }
+        self.inner.await_input(timeout)
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+}
+
+/// Determine the proxy for requests to <url>.
+///
+/// `IGNITION_PROXY` overrides the standard variables (`HTTPS_PROXY`/`HTTP_PROXY` by url scheme, then
+/// `ALL_PROXY`), and `IGNITION_PROXY=none` disables proxying. Hosts matching `NO_PROXY` are never proxied.
+/// Credentials are taken from the proxy url, overridden by `IGNITION_PROXY_USERNAME`/`IGNITION_PROXY_PASSWORD`.
+pub fn proxy(url: &str) -> IgnitionResult<Option<Proxy>> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|_| IgnitionError::DownloadError(format!("invalid url: {}", url)))?;
+    let host = uri.host().unwrap_or_default();
+    let proxy_url = match env_value::<String>(PROXY_VARIABLE)? {
+        Some(proxy_url) => proxy_url,
+        None => {
+            let names: &[&str] = match uri.scheme_str() {
+                Some("https") => &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"],
+                _ => &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"],
+            };
+            match first_variable(names)? {
+                Some(proxy_url) => proxy_url,
+                None => return Ok(None),
+            }
+        }
+    };
+    if proxy_url.is_empty() || proxy_url == "none" || no_proxy(host)? {
+        return Ok(None);
+    }
+    let invalid =
+        |_| IgnitionError::ConfigurationError(format!("invalid proxy url: {}", proxy_url));
+    let mut proxy = Proxy::new(&proxy_url).map_err(invalid)?;
+    let username = env_value::<String>(PROXY_USERNAME_VARIABLE)?;
+    let password = env_value::<String>(PROXY_PASSWORD_VARIABLE)?;
+    if username.is_some() || password.is_some() {
+        let mut builder = Proxy::builder(proxy.protocol())
+            .host(proxy.host())
+            .port(proxy.port());
+        if let Some(username) = username.as_deref().or(proxy.username()) {
+            builder = builder.username(username);
+        }
+        if let Some(password) = password.as_deref().or(proxy.password()) {
+            builder = builder.password(password);
+        }
+        proxy = builder.build().map_err(invalid)?;
+    }
+    let proxy_address = format!("{}:{}", proxy.host(), proxy.port());
+    if LOGGED_PROXIES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(proxy_address.clone())
+    {
+        println!("--proxy: {}", proxy_address);
+    }
+    Ok(Some(proxy))
+}
+
+/// Value of the first of the environment variables <names> that is set, None if none is.
+fn first_variable(names: &[&str]) -> IgnitionResult<Option<String>> {
+    for name in names {
+        if let Some(value) = env_value::<String>(name)? {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+/// Determine if <host> is excluded from proxying by `NO_PROXY` (see `is_excluded`).
+fn no_proxy(host: &str) -> IgnitionResult<bool> {
+    Ok(first_variable(&["NO_PROXY", "no_proxy"])?
+        .is_some_and(|no_proxy| is_excluded(&no_proxy, host)))
+}
+
+/// Determine if <host> is excluded from proxying by <no_proxy> (comma-separated hosts or domain suffixes, or `*`).
+fn is_excluded(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_matches(['[', ']']).to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            // ignore any port, the proxy decision is per host (IPv6 addresses only have one in brackets)
+            let entry = match entry.rsplit_once(':') {
+                Some((name, port))
+                    if port.chars().all(|c| c.is_ascii_digit())
+                        && (!name.contains(':') || name.ends_with(']')) =>
+                {
+                    name.trim_matches(['[', ']']).to_string()
+                }
+                _ => entry.trim_matches(['[', ']']).to_string(),
+            };
+            let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+            entry == "*"
+                || host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_excluded;
+
+    #[test]
+    fn no_proxy_hosts_and_domains() {
+        let no_proxy =
+            "localhost, .internal.example.com,*.corp.example,mirror.example.org:8443,::1";
+        assert!(is_excluded(no_proxy, "localhost"));
+        assert!(is_excluded(no_proxy, "LOCALHOST"));
+        assert!(is_excluded(no_proxy, "internal.example.com"));
+        assert!(is_excluded(no_proxy, "bucket.internal.example.com"));
+        assert!(is_excluded(no_proxy, "a.b.corp.example"));
+        assert!(is_excluded(no_proxy, "mirror.example.org"));
+        assert!(is_excluded(no_proxy, "[::1]"));
+        assert!(is_excluded("[fd00::1]:8080", "[fd00::1]"));
+        assert!(!is_excluded(no_proxy, "[::2]"));
+        assert!(!is_excluded(no_proxy, "example.com"));
+        assert!(!is_excluded(no_proxy, "notinternal.example.com"));
+        assert!(!is_excluded(no_proxy, "mycorp.example"));
+        assert!(!is_excluded(no_proxy, "localhost.example.com"));
+    }
+
+    #[test]
+    fn no_proxy_wildcard_and_empty() {
+        assert!(is_excluded("*", "example.com"));
+        assert!(!is_excluded("", "example.com"));
+        assert!(!is_excluded(" , ", "example.com"));
+    }
+}
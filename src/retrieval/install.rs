@@ -0,0 +1,196 @@
+// install.rs
+//
+// This module contains the installation of assets from the cache (`install` in the manifest or `IGNITION_INSTALL`):
+// archives are extracted once into the cache, next to the archive, and installed into the asset directory of every
+// build as copies, symbolic links, hard links or copy-on-write clones of the cached tree, rather than extracted
+// again, so installations of large assets by many workspace members are near-instant and share disk space.
+//
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use serde::Deserialize;
+
+use super::{STAMP_EXTENSION, download::sidecar_path, env_value, extract::ExtractOptions};
+use crate::{IgnitionError, IgnitionResult};
+
+const INSTALL_VARIABLE: &str = "IGNITION_INSTALL";
+/// File extension of the extracted tree of an archive in the cache, next to it.
+pub const TREE_EXTENSION: &str = ".extracted";
+/// Windows access right to set file times, granted for read-only files.
+#[cfg(windows)]
+const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+
+/// How an asset is installed into its asset directory (`install` in the manifest or `IGNITION_INSTALL`).
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallMode {
+    /// The archive is extracted into the asset directory (default).
+    #[default]
+    Extract,
+    /// Files are copies of the cached tree, independent of the cache (as `reflink`, clones on copy-on-write
+    /// filesystems).
+    Copy,
+    /// Files are symbolic links to the cached tree (hard links where symbolic links are unsupported, e.g. on Windows
+    /// without the privilege to create them, otherwise copies). Installed files are the cached files, so they must
+    /// not be modified in place (see `read_only`).
+    Symlink,
+    /// Files are hard links to the cached tree (copied across filesystems). Installed files share their contents
+    /// (and permissions) with the cache, so they must not be modified in place (see `read_only`).
+    Hardlink,
+    /// Files are copy-on-write clones of the cached tree (reflinks on Btrfs, XFS or APFS), copied on filesystems
+    /// without copy-on-write support.
+    Reflink,
+}
+
+impl FromStr for InstallMode {
+    type Err = IgnitionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "extract" => Ok(InstallMode::Extract),
+            "copy" => Ok(InstallMode::Copy),
+            "symlink" | "symbolic" => Ok(InstallMode::Symlink),
+            "hardlink" | "link" => Ok(InstallMode::Hardlink),
+            "reflink" | "clone" => Ok(InstallMode::Reflink),
+            _ => Err(IgnitionError::ConfigurationError(format!(
+                "{}={}",
+                INSTALL_VARIABLE, value
+            ))),
+        }
+    }
+}
+
+impl InstallMode {
+    /// Resolve the mode: `IGNITION_INSTALL`, otherwise the manifest <install> mode of the asset, otherwise
+    /// `extract`.
+    pub fn resolve(install: Option<InstallMode>) -> IgnitionResult<Self> {
+        Ok(env_value(INSTALL_VARIABLE)?.or(install).unwrap_or_default())
+    }
+}
+
+/// Path of the extracted tree of the cached archive at <archive_path> (`<archive>.extracted`).
+pub fn tree_path(archive_path: &Path) -> PathBuf {
+    sidecar_path(archive_path, TREE_EXTENSION)
+}
+
+/// Key of the tree extracted from the archive of SHA-256 digest <sha256> with <options>, recorded in its stamp
+/// (`<archive>.extracted.stamp`): a tree extracted from another archive, or with other entries, is stale.
+pub fn tree_key(sha256: &str, options: &ExtractOptions) -> String {
+    format!(
+        "{} strip={} include={:?} exclude={:?} symlinks={:?} raw={:?}\n",
+        sha256,
+        options.strip_components,
+        options.include,
+        options.exclude,
+        options.symlinks,
+        options.raw_path
+    )
+}
+
+/// Determine if the extracted tree at <tree_path> is complete and stamped with <key> (see `tree_key`).
+pub fn is_current(tree_path: &Path, key: &str) -> bool {
+    tree_path.is_dir()
+        && fs::read_to_string(sidecar_path(tree_path, STAMP_EXTENSION))
+            .is_ok_and(|stamp| stamp == key)
+}
+
+/// Install the extracted tree at <tree_path> into <directory> according to <mode> (not `extract`): directories
+/// and symbolic links are recreated, files copied, linked or cloned. Links fall back (with a warning) if
+/// unsupported, symbolic links to hard links (e.g. on Windows without the privilege to create them), and hard links
+/// to copies (e.g. across filesystems). Copies keep the permissions and modification times of the cached files.
+pub fn install(tree_path: &Path, directory: &Path, mode: InstallMode) -> IgnitionResult<()> {
+    println!(
+        "--{}: {}",
+        match mode {
+            InstallMode::Copy => "copy",
+            InstallMode::Symlink => "symlink",
+            InstallMode::Hardlink => "hardlink",
+            _ => "reflink",
+        },
+        tree_path.display()
+    );
+    // links to the cached files hold absolute paths, wherever the asset directory is
+    let tree_path = std::path::absolute(tree_path)?;
+    let mut mode = mode;
+    install_tree(&tree_path, directory, &mut mode)
+        .map_err(|err| IgnitionError::ExtractionError(format!("{}: {}", directory.display(), err)))
+}
+
+/// Install the tree at <source> into <destination> as `install`, files according to <mode> (falling back once
+/// links are unsupported).
+fn install_tree(source: &Path, destination: &Path, mode: &mut InstallMode) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let source = entry.path();
+        let destination = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            install_tree(&source, &destination, mode)?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(&source)?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &destination)?;
+            #[cfg(windows)]
+            match fs::metadata(&source).is_ok_and(|metadata| metadata.is_dir()) {
+                true => std::os::windows::fs::symlink_dir(&target, &destination)?,
+                false => std::os::windows::fs::symlink_file(&target, &destination)?,
+            }
+        } else {
+            if *mode == InstallMode::Symlink {
+                #[cfg(unix)]
+                let linked = std::os::unix::fs::symlink(&source, &destination);
+                #[cfg(windows)]
+                let linked = std::os::windows::fs::symlink_file(&source, &destination);
+                match linked {
+                    Ok(()) => continue,
+                    Err(err) => {
+                        println!(
+                            "cargo:warning=--symbolic links unsupported: {} ({}, hard linking)",
+                            destination.display(),
+                            err
+                        );
+                        *mode = InstallMode::Hardlink;
+                    }
+                }
+            }
+            if *mode == InstallMode::Hardlink {
+                match fs::hard_link(&source, &destination) {
+                    Ok(()) => continue,
+                    Err(err) => {
+                        println!(
+                            "cargo:warning=--hard links unsupported: {} ({}, copying)",
+                            destination.display(),
+                            err
+                        );
+                        *mode = InstallMode::Copy;
+                    }
+                }
+            }
+            // copies clone files on copy-on-write filesystems (`copy_file_range` on Linux, `clonefile` on macOS)
+            fs::copy(&source, &destination)?;
+            set_modified(&destination, entry.metadata()?.modified()?)?;
+        }
+    }
+    // restrictive permissions of directories apply once their contents are installed
+    fs::set_permissions(destination, fs::metadata(source)?.permissions())
+}
+
+/// Set the modification time of the file at <path> to <modified>, even if the file is read-only.
+pub(crate) fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        options.access_mode(FILE_WRITE_ATTRIBUTES);
+    }
+    // file times are set by the owner of the file, without write permission
+    #[cfg(not(windows))]
+    options.read(true);
+    options.open(path)?.set_modified(modified)
+}
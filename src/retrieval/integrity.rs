@@ -0,0 +1,205 @@
+// integrity.rs
+//
+// This module contains the integrity check of extractions on reuse (`integrity` in the manifest or
+// `IGNITION_INTEGRITY`): the files of a complete extraction are recorded with their sizes and a digest of samples of
+// their contents, so extractions truncated or corrupted since (e.g. by a crashed build or a full disk) are detected
+// by every build without hashing gigabytes of assets again.
+//
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{download::sidecar_path, env_value, source};
+use crate::{IgnitionError, IgnitionResult};
+
+const INTEGRITY_VARIABLE: &str = "IGNITION_INTEGRITY";
+/// File extension of the record of the files of an extraction, next to it.
+const RECORD_EXTENSION: &str = ".integrity";
+/// Size of each sample of the contents of a file: files of up to three samples are hashed as a whole.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// How a complete extraction is checked before it is reused (`integrity` in the manifest or `IGNITION_INTEGRITY`).
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityCheck {
+    /// Only the stamp of the extraction is checked (see `retrieval::is_extracted`).
+    Stamp,
+    /// The files of the extraction are checked against their record: the same files, of the same sizes, with the
+    /// same samples of their contents (default). At most three samples of 64 KiB are read per file.
+    #[default]
+    Spot,
+    /// As `spot`, and files with digests (`content_sha256`, and every file of `read_only` assets) are hashed again.
+    Full,
+}
+
+impl FromStr for IntegrityCheck {
+    type Err = IgnitionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "stamp" | "none" => Ok(IntegrityCheck::Stamp),
+            "spot" | "sample" => Ok(IntegrityCheck::Spot),
+            "full" => Ok(IntegrityCheck::Full),
+            _ => Err(IgnitionError::ConfigurationError(format!(
+                "{}={}",
+                INTEGRITY_VARIABLE, value
+            ))),
+        }
+    }
+}
+
+impl IntegrityCheck {
+    /// Resolve the check: `IGNITION_INTEGRITY`, otherwise the manifest <integrity> check of the asset, otherwise
+    /// `spot`.
+    pub fn resolve(integrity: Option<IntegrityCheck>) -> IgnitionResult<Self> {
+        Ok(env_value(INTEGRITY_VARIABLE)?
+            .or(integrity)
+            .unwrap_or_default())
+    }
+}
+
+/// Recorded file of an extraction.
+#[derive(Debug, Deserialize, Serialize)]
+struct RecordedFile {
+    /// Size in bytes.
+    size: u64,
+    /// Lowercase hex SHA-256 digest of the samples of the contents, see `sample_digest`.
+    sample: String,
+}
+
+/// Record the regular files within the extraction at <directory> (`<directory>.integrity`), with their sizes and
+/// sample digests, see `check`.
+pub fn record(directory: &Path) -> IgnitionResult<()> {
+    let record = files(directory)?;
+    fs::write(
+        sidecar_path(directory, RECORD_EXTENSION),
+        serde_json::to_string_pretty(&record)?,
+    )?;
+    Ok(())
+}
+
+/// Check the extraction at <directory> against its record (see `record`) according to <check>. Extractions
+/// recorded before records (i.e. without one) are recorded as they are.
+///
+/// Return is the reason the extraction is corrupt (the first files added, removed, resized or modified), None if
+/// intact.
+pub fn check(directory: &Path, check: IntegrityCheck) -> IgnitionResult<Option<String>> {
+    if check == IntegrityCheck::Stamp {
+        return Ok(None);
+    }
+    let record_path = sidecar_path(directory, RECORD_EXTENSION);
+    let Some(recorded) = fs::read_to_string(&record_path).ok().and_then(|contents| {
+        serde_json::from_str::<BTreeMap<String, RecordedFile>>(&contents).ok()
+    }) else {
+        record(directory)?;
+        return Ok(None);
+    };
+    let mut files = Vec::new();
+    regular_files(directory, directory, &mut files)?;
+    let sizes: BTreeMap<String, (PathBuf, u64)> = files
+        .into_iter()
+        .map(|(relative_path, path, size)| (relative_path, (path, size)))
+        .collect();
+    // sizes are compared first, contents are only read if every file is there
+    let mut corrupt = Vec::new();
+    for (relative_path, (_, size)) in sizes.iter() {
+        match recorded.get(relative_path) {
+            None => corrupt.push(format!("{} added", relative_path)),
+            Some(file) if file.size != *size => corrupt.push(format!("{} resized", relative_path)),
+            Some(_) => {}
+        }
+    }
+    corrupt.extend(
+        recorded
+            .keys()
+            .filter(|relative_path| !sizes.contains_key(*relative_path))
+            .map(|relative_path| format!("{} removed", relative_path)),
+    );
+    if corrupt.is_empty() {
+        for (relative_path, (path, size)) in sizes.iter() {
+            if recorded[relative_path].sample != sample_digest(path, *size)? {
+                corrupt.push(format!("{} modified", relative_path));
+            }
+        }
+    }
+    Ok(summary(corrupt))
+}
+
+/// Summary of the <corrupt> files of an extraction (the first three), None if none.
+fn summary(mut corrupt: Vec<String>) -> Option<String> {
+    if corrupt.is_empty() {
+        return None;
+    }
+    let count = corrupt.len();
+    corrupt.truncate(3);
+    Some(match count > corrupt.len() {
+        true => format!("{} and {} more", corrupt.join(", "), count - corrupt.len()),
+        false => corrupt.join(", "),
+    })
+}
+
+/// Recorded regular files within <directory>, keyed by relative path (with `/` separators).
+fn files(directory: &Path) -> IgnitionResult<BTreeMap<String, RecordedFile>> {
+    let mut files = Vec::new();
+    regular_files(directory, directory, &mut files)?;
+    files
+        .into_iter()
+        .map(|(relative_path, path, size)| {
+            let sample = sample_digest(&path, size)?;
+            Ok((relative_path, RecordedFile { size, sample }))
+        })
+        .collect()
+}
+
+/// Collect the regular files within <directory> (recursively, not following symbolic links) into <files>, with
+/// their path relative to <root> (with `/` separators), path and size.
+fn regular_files(
+    root: &Path,
+    directory: &Path,
+    files: &mut Vec<(String, PathBuf, u64)>,
+) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            regular_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            let relative_path = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((relative_path, path, entry.metadata()?.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Lowercase hex SHA-256 digest of samples of the file at <path> of <size> bytes: its first, middle and last
+/// 64 KiB, or its whole contents if smaller than three samples.
+fn sample_digest(path: &Path, size: u64) -> IgnitionResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    if size <= 3 * SAMPLE_SIZE {
+        io::copy(&mut file, &mut hasher)?;
+    } else {
+        let mut sample = vec![0; SAMPLE_SIZE as usize];
+        for offset in [0, (size - SAMPLE_SIZE) / 2, size - SAMPLE_SIZE] {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut sample)?;
+            hasher.update(&sample);
+        }
+    }
+    Ok(source::hex(&hasher.finalize()))
+}
@@ -0,0 +1,133 @@
+// layers.rs
+//
+// This module contains the layers of the manifest merged over the embedded `config/environment.json`, from the
+// least to the most specific: the project manifest shared by the workspace (`ignition.toml`), the developer-local
+// manifest next to it (`ignition.local.toml`, not committed), then the override manifest of the environment
+// (`IGNITION_ENVIRONMENT_CONFIG`), so local experiments don't require editing shared config.
+//
+// Layers are merged in order (see `crate::manifest_value`), each over the result of the previous ones:
+// - assets are merged by name, so a layer only states the assets (and fields) it adds or modifies;
+// - maps (`environment`, `files`, `sha256`, `targets`, `variants`, ...) are merged entry by entry, recursively, e.g.
+//   `environment = { "opencv/lib" = "MY_LINK_PATHS" }` renames one variable and keeps the others;
+// - lists (`contents`, `mirrors`, `include`, ...) and other values replace those of the previous layers as a whole,
+//   so a layer adding a content lists the previous contents too;
+// - `null` values (JSON or YAML: TOML has no null) remove their entry, e.g. an asset, a field or a map entry such as
+//   `"environment": { "opencv/lib": null }`.
+//
+
+use std::path::{Path, PathBuf};
+
+use super::{env_value, rerun};
+use crate::{IgnitionError, IgnitionResult};
+
+const ENVIRONMENT_CONFIG_VARIABLE: &str = "IGNITION_ENVIRONMENT_CONFIG";
+const PROJECT_CONFIG_VARIABLE: &str = "IGNITION_PROJECT_CONFIG";
+/// File stem of the project manifest.
+const PROJECT_CONFIG_STEM: &str = "ignition";
+/// File stem of the developer-local manifest, next to the project manifest.
+const LOCAL_CONFIG_STEM: &str = "ignition.local";
+/// File extensions of manifests, in order of precedence (see `format::ManifestFormat::from_path`).
+const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// Layer of the manifest.
+#[derive(Clone, Debug)]
+pub struct ManifestLayer {
+    /// Path of the manifest of the layer.
+    pub path: PathBuf,
+    /// Description of the layer for errors, e.g. `project manifest <path>`.
+    pub description: String,
+}
+
+impl ManifestLayer {
+    /// Read the manifest of the layer.
+    pub fn read(&self) -> IgnitionResult<String> {
+        // the build script runs again when the manifest changes, see `rerun`
+        rerun::record_path(&self.path);
+        std::fs::read_to_string(&self.path).map_err(|err| {
+            IgnitionError::ConfigurationError(format!("{}: {}", self.description, err))
+        })
+    }
+}
+
+/// Layers of the manifest over the embedded manifest, in order of merge:
+/// - the project manifest: `IGNITION_PROJECT_CONFIG` if set (none if empty), otherwise the first `ignition.toml`
+///   (or `.yaml`, `.yml`, `.json`) found in the directory of the package of the build script or its ancestors (e.g.
+///   the root of its workspace), otherwise in those of `OUT_DIR` (the target directory, usually within the workspace
+///   of the build, also for dependencies of it);
+/// - the developer-local manifest: `ignition.local.toml` (or `.yaml`, `.yml`, `.json`) in the directory of the
+///   project manifest, or where it would be found, to be ignored by version control (e.g. in `.gitignore`);
+/// - the override manifest at `IGNITION_ENVIRONMENT_CONFIG`, if set.
+///
+/// Relative paths are relative to the current directory, i.e. that of the package for build scripts: set absolute
+/// paths, e.g. with `IGNITION_PROJECT_CONFIG = { value = "ignition.toml", relative = true }` in the `[env]` table of
+/// `.cargo/config.toml`. Manifests found are inputs of the build script (see `rerun`), but a manifest created
+/// where none was found is only merged once the build script runs again (e.g. after `cargo clean -p ignition`).
+pub fn layers() -> IgnitionResult<Vec<ManifestLayer>> {
+    let mut layers = Vec::new();
+    let directory = match env_value::<PathBuf>(PROJECT_CONFIG_VARIABLE)? {
+        Some(path) if path.as_os_str().is_empty() => None,
+        Some(path) => {
+            let directory = path.parent().map(Path::to_path_buf);
+            layers.push(ManifestLayer {
+                description: format!("{}={}", PROJECT_CONFIG_VARIABLE, path.display()),
+                path,
+            });
+            directory
+        }
+        None => project_directory().inspect(|directory| {
+            layers.extend(
+                find(directory, PROJECT_CONFIG_STEM).map(|path| ManifestLayer {
+                    description: format!("project manifest {}", path.display()),
+                    path,
+                }),
+            )
+        }),
+    };
+    if let Some(path) = directory.and_then(|directory| find(&directory, LOCAL_CONFIG_STEM)) {
+        layers.push(ManifestLayer {
+            description: format!("local manifest {}", path.display()),
+            path,
+        });
+    }
+    if let Some(path) = env_value::<PathBuf>(ENVIRONMENT_CONFIG_VARIABLE)?
+        .filter(|path| !path.as_os_str().is_empty())
+    {
+        layers.push(ManifestLayer {
+            description: format!("{}={}", ENVIRONMENT_CONFIG_VARIABLE, path.display()),
+            path,
+        });
+    }
+    Ok(layers)
+}
+
+/// Directory of the project and local manifests: the first directory holding either, among the directory of the
+/// package of the build script (`CARGO_MANIFEST_DIR`, otherwise the current directory) and its ancestors, then
+/// `OUT_DIR` and its ancestors. None if neither is found.
+fn project_directory() -> Option<PathBuf> {
+    let starts: Vec<PathBuf> = [
+        std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .or_else(|| std::env::current_dir().ok()),
+        std::env::var_os("OUT_DIR").map(PathBuf::from),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    starts
+        .iter()
+        .flat_map(|start| start.ancestors())
+        .find(|directory| {
+            find(directory, PROJECT_CONFIG_STEM).is_some()
+                || find(directory, LOCAL_CONFIG_STEM).is_some()
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Path of the manifest `<stem>.<extension>` in <directory>, the first of `CONFIG_EXTENSIONS` existing, None if
+/// none.
+fn find(directory: &Path, stem: &str) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|extension| directory.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,104 @@
+// licenses.rs
+//
+// This module contains the harvesting of license files shipped inside extracted assets (feature `licenses`):
+// LICENSE/NOTICE/COPYING files are collected into `OUT_DIR/ignition-licenses/` with a combined NOTICE, for
+// packaging.
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::IgnitionResult;
+
+/// Directory of the harvested license files within `OUT_DIR`.
+pub const LICENSES_DIRECTORY_NAME: &str = "ignition-licenses";
+const NOTICE_FILE_NAME: &str = "NOTICE";
+/// Uppercase file name prefixes of license files.
+const LICENSE_FILE_PREFIXES: [&str; 7] = [
+    "LICENSE",
+    "LICENCE",
+    "NOTICE",
+    "COPYING",
+    "COPYRIGHT",
+    "THIRDPARTYNOTICES",
+    "THIRD_PARTY_NOTICES",
+];
+
+/// Extraction directories of all asset retrievals of this build, by asset name.
+static EXTRACTED: Mutex<Vec<(String, PathBuf)>> = Mutex::new(Vec::new());
+
+/// Record the extraction <directory> of <asset> for license harvesting.
+pub fn record(asset: &str, directory: &Path) {
+    EXTRACTED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push((asset.to_string(), directory.to_path_buf()));
+}
+
+/// Collect the license files of all recorded assets into `<out_dir>/ignition-licenses/<asset>/<path>`, and
+/// concatenate them (headed by asset and path) into `<out_dir>/ignition-licenses/NOTICE`.
+///
+/// The directory is recreated on every call, so licenses of assets no longer retrieved don't linger.
+/// Return is the path of the licenses directory.
+pub fn write(out_dir: &Path) -> IgnitionResult<PathBuf> {
+    let mut extracted = EXTRACTED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    extracted.sort();
+
+    let licenses_dir = out_dir.join(LICENSES_DIRECTORY_NAME);
+    if licenses_dir.exists() {
+        fs::remove_dir_all(&licenses_dir)?;
+    }
+    fs::create_dir_all(&licenses_dir)?;
+    let mut notice = String::new();
+    for (asset, directory) in extracted.iter() {
+        let mut files = Vec::new();
+        license_files(directory, &mut files)?;
+        files.sort();
+        for file in files {
+            let relative_path = file.strip_prefix(directory).unwrap_or(&file);
+            let path = licenses_dir.join(asset).join(relative_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&file, &path)?;
+            println!("--license: {}", path.display());
+            notice.push_str(&format!(
+                "==== {}: {} ====\n\n{}\n\n",
+                asset,
+                relative_path.display(),
+                String::from_utf8_lossy(&fs::read(&file)?).trim_end()
+            ));
+        }
+    }
+    fs::write(licenses_dir.join(NOTICE_FILE_NAME), notice)?;
+    Ok(licenses_dir)
+}
+
+/// Collect the license files within <directory> (recursively, not following symbolic links) into <files>.
+fn license_files(directory: &Path, files: &mut Vec<PathBuf>) -> IgnitionResult<()> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            license_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            let file_name = entry.file_name().to_string_lossy().to_ascii_uppercase();
+            if LICENSE_FILE_PREFIXES
+                .iter()
+                .any(|prefix| file_name.starts_with(prefix))
+            {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(())
+}
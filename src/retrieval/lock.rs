@@ -0,0 +1,171 @@
+// lock.rs
+//
+// This module contains the advisory file locks serializing concurrent retrievals of an asset (e.g. by a workspace
+// build and rust-analyzer, or by builds sharing the global cache), so they never download or extract into the same
+// cache entry or asset directory at once. Lock files hold the process holding them (pid, program and time), so
+// builds waiting for a lock report who holds it.
+//
+
+use std::{
+    fs::{self, File, TryLockError},
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{download::sidecar_path, env_value, source};
+use crate::{IgnitionError, IgnitionResult};
+
+const LOCK_TIMEOUT_VARIABLE: &str = "IGNITION_LOCK_TIMEOUT_SECS";
+/// Default time waited for a lock, long enough for another build to download a multi-GB archive.
+const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 3600;
+/// Interval between attempts to acquire a lock held by another build.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Interval between reports of a build still waiting for a lock.
+const REPORT_INTERVAL: Duration = Duration::from_secs(30);
+/// File extension of the lock file of a path, next to it.
+pub const LOCK_EXTENSION: &str = ".lock";
+
+/// Exclusive advisory lock on a path (its `<path>.lock` file), released when dropped (or when the process exits,
+/// e.g. if a build is killed).
+pub struct PathLock {
+    _file: File,
+}
+
+impl PathLock {
+    /// Lock <path> (`<path>.lock`, created as required), waiting for other builds holding the lock for up to
+    /// `IGNITION_LOCK_TIMEOUT_SECS` (default one hour, 0 waiting indefinitely). Waits are reported with the holder
+    /// of the lock (see `LockHolder`), every 30 s. A lock still held once the timeout expires is a `LockTimeout`
+    /// error naming its holder.
+    pub fn acquire(path: &Path) -> IgnitionResult<Self> {
+        let (lock_path, file) = open(path)?;
+        let timeout = match env_value(LOCK_TIMEOUT_VARIABLE)?.unwrap_or(DEFAULT_LOCK_TIMEOUT_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let start = Instant::now();
+        let mut reported: Option<Instant> = None;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+            if let Some(timeout) = timeout
+                && start.elapsed() >= timeout
+            {
+                return Err(IgnitionError::LockTimeout(format!(
+                    "{}: held by {} (waited {} s, see {})",
+                    lock_path.display(),
+                    LockHolder::describe(&lock_path),
+                    timeout.as_secs(),
+                    LOCK_TIMEOUT_VARIABLE
+                )));
+            }
+            if reported.is_none_or(|reported| reported.elapsed() >= REPORT_INTERVAL) {
+                println!(
+                    "--waiting for lock: {} (held by {}, waiting for {} s)",
+                    lock_path.display(),
+                    LockHolder::describe(&lock_path),
+                    start.elapsed().as_secs()
+                );
+                reported = Some(Instant::now());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(PathLock::held(file))
+    }
+
+    /// Lock <path> as `acquire`, without waiting: return is None if another build holds the lock.
+    pub fn try_acquire(path: &Path) -> IgnitionResult<Option<Self>> {
+        let (_, file) = open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(PathLock::held(file))),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// Lock of the locked lock <file>, recording this process as its holder (see `LockHolder`).
+    fn held(mut file: File) -> Self {
+        // the holder is only informative, e.g. lock files on read-only media aren't written
+        if let Ok(holder) = serde_json::to_string(&LockHolder::current()) {
+            let _ = file
+                .set_len(0)
+                .and_then(|_| file.rewind())
+                .and_then(|_| file.write_all(holder.as_bytes()));
+        }
+        PathLock { _file: file }
+    }
+}
+
+/// Process holding a lock, recorded in its lock file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LockHolder {
+    /// Process id.
+    pub pid: u32,
+    /// Seconds since the unix epoch the lock was acquired.
+    pub since: u64,
+    /// File name of the program, e.g. `build-script-build` or `ignition-cache`.
+    pub program: Option<String>,
+    /// Package of the build script (`CARGO_PKG_NAME`), if any.
+    pub package: Option<String>,
+}
+
+impl LockHolder {
+    /// Holder of a lock acquired now by this process.
+    fn current() -> Self {
+        LockHolder {
+            pid: std::process::id(),
+            since: source::unix_time(),
+            program: std::env::current_exe().ok().and_then(|exe| {
+                exe.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            }),
+            package: std::env::var("CARGO_PKG_NAME").ok(),
+        }
+    }
+
+    /// Holder recorded in the lock file at <lock_path>, None if unrecorded or unreadable (e.g. locked files are
+    /// unreadable on Windows).
+    pub fn load(lock_path: &Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(lock_path).ok()?).ok()
+    }
+
+    /// Description of the holder of the lock file at <lock_path> for reports, e.g. `pid 1234 (build-script-build
+    /// of opencv-sys) since 2026-01-02 03:04:05 UTC`, otherwise `another build`.
+    fn describe(lock_path: &Path) -> String {
+        let Some(holder) = LockHolder::load(lock_path) else {
+            return "another build".to_string();
+        };
+        let (year, month, day, hour, minute, second) = source::utc_datetime(holder.since);
+        let program = match (holder.program, holder.package) {
+            (Some(program), Some(package)) => format!(" ({} of {})", program, package),
+            (Some(program), None) => format!(" ({})", program),
+            (None, Some(package)) => format!(" ({})", package),
+            (None, None) => String::new(),
+        };
+        format!(
+            "pid {}{} since {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            holder.pid, program, year, month, day, hour, minute, second
+        )
+    }
+}
+
+/// Open the lock file of <path>, created as required.
+fn open(path: &Path) -> IgnitionResult<(PathBuf, File)> {
+    let lock_path = sidecar_path(path, LOCK_EXTENSION);
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)?;
+    Ok((lock_path, file))
+}
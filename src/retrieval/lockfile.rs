@@ -0,0 +1,184 @@
+// lockfile.rs
+//
+// This module contains the `ignition.lock` lockfile: the resolved url, version, variant and archive digest of each
+// asset per target, for reproducible builds.
+//
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{checksum, env_flag, env_value, redact};
+use crate::{IgnitionError, IgnitionResult};
+
+const LOCKFILE_VARIABLE: &str = "IGNITION_LOCKFILE";
+const LOCKED_VARIABLE: &str = "IGNITION_LOCKED";
+const UPDATE_VARIABLE: &str = "IGNITION_UPDATE_LOCK";
+const LOCKFILE_NAME: &str = "ignition.lock";
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Serializes read-modify-write of the lockfile by concurrent asset retrievals.
+static LOCKFILE_MUTEX: Mutex<()> = Mutex::new(());
+
+/// Locked retrieval of an asset for a target.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LockedAsset {
+    /// Resolved url of the asset archive (primary bucket).
+    pub url: String,
+    /// Asset version, if versioned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Asset variant, if any (see `AssetEnvironment::variant`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// Lowercase hex SHA-256 digest of the asset archive.
+    pub sha256: String,
+}
+
+/// Lockfile contents: locked assets by name and target (bucket path `<os>/<arch>[/<environment>][/<variant>]`),
+/// sorted for stable diffs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Lockfile format version.
+    pub version: u32,
+    /// Locked assets.
+    #[serde(default)]
+    pub assets: BTreeMap<String, BTreeMap<String, LockedAsset>>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile {
+            version: LOCKFILE_VERSION,
+            assets: BTreeMap::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Path of the lockfile: `IGNITION_LOCKFILE`, otherwise `ignition.lock` next to `config/`.
+    pub fn path() -> IgnitionResult<PathBuf> {
+        Ok(env_value(LOCKFILE_VARIABLE)?
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(LOCKFILE_NAME)))
+    }
+
+    /// Load the lockfile at <path>, empty if absent.
+    pub fn load(path: &Path) -> IgnitionResult<Self> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let lockfile: Lockfile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(IgnitionError::ConfigurationError(format!(
+                "{}: unsupported lockfile version {}",
+                path.display(),
+                lockfile.version
+            )));
+        }
+        Ok(lockfile)
+    }
+
+    /// Save the lockfile to <path>.
+    pub fn save(&self, path: &Path) -> IgnitionResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+/// Check the retrieval of <asset> for <platform> (archive at <archive_path> from <url>, with <version> and
+/// <variant>) against the lockfile.
+///
+/// Mismatches (including assets missing from the lockfile) are an error with `IGNITION_LOCKED=true`, otherwise
+/// a warning for locked assets. The lockfile is only written with `IGNITION_UPDATE_LOCK=true`, recording the
+/// retrieval.
+pub fn check(
+    asset: &str,
+    platform: &str,
+    url: &str,
+    version: Option<&str>,
+    variant: Option<&str>,
+    archive_path: &Path,
+) -> IgnitionResult<()> {
+    let locked = env_flag(LOCKED_VARIABLE)?.unwrap_or(false);
+    let update = env_flag(UPDATE_VARIABLE)?.unwrap_or(false);
+    if locked && update {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{} and {} are mutually exclusive",
+            LOCKED_VARIABLE, UPDATE_VARIABLE
+        )));
+    }
+    let _guard = LOCKFILE_MUTEX.lock().unwrap_or_else(|err| err.into_inner());
+    let path = Lockfile::path()?;
+    let mut lockfile = Lockfile::load(&path)?;
+    let entry = lockfile
+        .assets
+        .get(asset)
+        .and_then(|platforms| platforms.get(platform));
+    if entry.is_none() && !locked && !update {
+        return Ok(());
+    }
+    let actual = LockedAsset {
+        url: url.to_string(),
+        version: version.map(str::to_string),
+        variant: variant.map(str::to_string),
+        sha256: checksum::sha256_file(archive_path)?,
+    };
+
+    if update {
+        if entry != Some(&actual) {
+            lockfile
+                .assets
+                .entry(asset.to_string())
+                .or_default()
+                .insert(platform.to_string(), actual);
+            lockfile.save(&path)?;
+            println!("--locked: {} {} ({})", asset, platform, path.display());
+        }
+        return Ok(());
+    }
+    let mismatch = match entry {
+        Some(entry) if *entry == actual => return Ok(()),
+        Some(entry) => {
+            let mut differences = Vec::new();
+            if entry.url != actual.url {
+                differences.push(format!("url {} (locked {})", actual.url, entry.url));
+            }
+            if entry.version != actual.version {
+                differences.push(format!(
+                    "version {} (locked {})",
+                    actual.version.as_deref().unwrap_or("none"),
+                    entry.version.as_deref().unwrap_or("none")
+                ));
+            }
+            if entry.variant != actual.variant {
+                differences.push(format!(
+                    "variant {} (locked {})",
+                    actual.variant.as_deref().unwrap_or("none"),
+                    entry.variant.as_deref().unwrap_or("none")
+                ));
+            }
+            if entry.sha256 != actual.sha256 {
+                differences.push(format!(
+                    "sha256 {} (locked {})",
+                    actual.sha256, entry.sha256
+                ));
+            }
+            format!("{} {}: {}", asset, platform, differences.join(", "))
+        }
+        None => format!("{} {}: not in {}", asset, platform, path.display()),
+    };
+    if locked {
+        return Err(IgnitionError::LockfileMismatch(mismatch));
+    }
+    println!(
+        "cargo:warning=--lockfile mismatch: {} (set {}=true to update)",
+        redact::redact(&mismatch),
+        UPDATE_VARIABLE
+    );
+    Ok(())
+}
@@ -0,0 +1,193 @@
+// metrics.rs
+//
+// This module contains the per-build download metrics report (`IGNITION_METRICS`): bytes downloaded, cache hits
+// and durations per asset, for build-performance dashboards, and their summary in the build output.
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use super::env_flag;
+use crate::IgnitionResult;
+
+const METRICS_VARIABLE: &str = "IGNITION_METRICS";
+const SUMMARY_VARIABLE: &str = "IGNITION_SUMMARY";
+const REPORT_FILE_NAME: &str = "ignition-metrics.json";
+
+/// Metrics of all asset retrievals of this build.
+static METRICS: Mutex<Vec<AssetMetrics>> = Mutex::new(Vec::new());
+
+/// Metrics of the retrieval of a particular asset.
+#[derive(Clone, Debug, Serialize)]
+pub struct AssetMetrics {
+    /// Asset name.
+    pub asset: String,
+    /// Target triplet.
+    pub target: String,
+    /// Url the archive was downloaded from, None on cache hits.
+    pub url: Option<String>,
+    /// Whether the archive was served from the cache.
+    pub cache_hit: bool,
+    /// Whether the cached archive was revalidated (downloaded again if modified, see `cache_hit`).
+    pub revalidated: bool,
+    /// Whether the archive was extracted, rather than kept extracted (or only cached).
+    pub extracted: bool,
+    /// Bytes downloaded (archive and additional files).
+    pub bytes_downloaded: u64,
+    /// Duration of downloads (including revalidation) in seconds.
+    pub download_secs: f64,
+    /// Duration of extraction in seconds.
+    pub extract_secs: f64,
+    /// Duration of the whole retrieval in seconds.
+    pub total_secs: f64,
+    /// Error of a failed retrieval.
+    pub error: Option<String>,
+    /// Local path of the archive, once resolved.
+    #[serde(skip)]
+    pub archive_path: Option<PathBuf>,
+    #[serde(skip)]
+    start: Option<Instant>,
+}
+
+impl AssetMetrics {
+    /// Start the metrics of retrieving <asset> for <target>.
+    pub fn start(asset: &str, target: &str) -> Self {
+        AssetMetrics {
+            asset: asset.to_string(),
+            target: target.to_string(),
+            url: None,
+            cache_hit: true,
+            revalidated: false,
+            extracted: false,
+            bytes_downloaded: 0,
+            download_secs: 0.0,
+            extract_secs: 0.0,
+            total_secs: 0.0,
+            error: None,
+            archive_path: None,
+            start: Some(Instant::now()),
+        }
+    }
+
+    /// Record the outcome of the retrieval, completing these metrics.
+    pub fn record<T>(mut self, result: &IgnitionResult<T>) {
+        self.total_secs = self
+            .start
+            .map_or(0.0, |start| start.elapsed().as_secs_f64());
+        self.error = result.as_ref().err().map(|err| err.to_string());
+        METRICS
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(self);
+    }
+}
+
+/// Report of all asset retrievals of a build.
+#[derive(Serialize)]
+struct Report<'a> {
+    bytes_downloaded: u64,
+    cache_hits: usize,
+    cache_misses: usize,
+    assets: &'a [AssetMetrics],
+}
+
+/// Write the metrics report to `<out_dir>/ignition-metrics.json` if enabled (`IGNITION_METRICS=true`).
+///
+/// Return is the path of the report, None if disabled.
+pub fn write_report(out_dir: &Path) -> IgnitionResult<Option<PathBuf>> {
+    if !env_flag(METRICS_VARIABLE)?.unwrap_or(false) {
+        return Ok(None);
+    }
+    let metrics = METRICS.lock().unwrap_or_else(|err| err.into_inner());
+    let cache_hits = metrics.iter().filter(|asset| asset.cache_hit).count();
+    let report = Report {
+        bytes_downloaded: metrics.iter().map(|asset| asset.bytes_downloaded).sum(),
+        cache_hits,
+        cache_misses: metrics.len() - cache_hits,
+        assets: &metrics,
+    };
+    let path = out_dir.join(REPORT_FILE_NAME);
+    fs::create_dir_all(out_dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&report)?)?;
+    Ok(Some(path))
+}
+
+/// Print the summary of all asset retrievals of this build, one line per asset (sorted) then the totals, e.g.:
+/// ```text
+/// --summary: opencv (x86_64-unknown-linux-gnu): downloaded 52.4 MB in 3.1s, extracted in 0.8s
+/// --summary: onnxruntime (x86_64-unknown-linux-gnu): cache hit, revalidated (unchanged) in 0.2s
+/// --summary: 2 assets, 1 cache hits, 52.4 MB downloaded in 3.3s
+/// ```
+/// Build script output is only shown by `cargo build -vv`: with `IGNITION_SUMMARY=true`, the summary is printed as
+/// cargo warnings instead, shown by every build. Nothing is printed without retrievals.
+pub fn print_summary() -> IgnitionResult<()> {
+    let prefix = match env_flag(SUMMARY_VARIABLE)?.unwrap_or(false) {
+        true => "cargo:warning=--summary",
+        false => "--summary",
+    };
+    let mut metrics = METRICS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    if metrics.is_empty() {
+        return Ok(());
+    }
+    metrics.sort_by(|a, b| (&a.asset, &a.target).cmp(&(&b.asset, &b.target)));
+    for asset in metrics.iter() {
+        println!(
+            "{}: {} ({}): {}",
+            prefix,
+            asset.asset,
+            asset.target,
+            outcome(asset)
+        );
+    }
+    println!(
+        "{}: {} assets, {} cache hits, {} downloaded in {:.1}s",
+        prefix,
+        metrics.len(),
+        metrics.iter().filter(|asset| asset.cache_hit).count(),
+        megabytes(metrics.iter().map(|asset| asset.bytes_downloaded).sum()),
+        metrics.iter().map(|asset| asset.download_secs).sum::<f64>()
+    );
+    Ok(())
+}
+
+/// Outcome of the retrieval of <asset> for the summary, e.g. "cache hit, extracted in 0.8s".
+fn outcome(asset: &AssetMetrics) -> String {
+    if let Some(error) = asset.error.as_deref() {
+        return format!("failed after {:.1}s: {}", asset.total_secs, error);
+    }
+    let mut outcome = match (asset.cache_hit, asset.revalidated) {
+        (true, true) => format!(
+            "cache hit, revalidated (unchanged) in {:.1}s",
+            asset.download_secs
+        ),
+        (true, false) => "cache hit".to_string(),
+        (false, true) => format!(
+            "revalidated, downloaded {} in {:.1}s",
+            megabytes(asset.bytes_downloaded),
+            asset.download_secs
+        ),
+        (false, false) => format!(
+            "downloaded {} in {:.1}s",
+            megabytes(asset.bytes_downloaded),
+            asset.download_secs
+        ),
+    };
+    if asset.extracted {
+        outcome.push_str(&format!(", extracted in {:.1}s", asset.extract_secs));
+    }
+    outcome
+}
+
+/// Format <bytes> in megabytes, e.g. "52.4 MB".
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
@@ -0,0 +1,43 @@
+// offline.rs
+//
+// This module contains the offline mode (`IGNITION_OFFLINE`, or Cargo's `CARGO_NET_OFFLINE`): network access is
+// refused, assets being retrieved from the cache or local (vendored) sources only.
+//
+
+use super::{env_flag, redact, source, vendor};
+use crate::{IgnitionError, IgnitionResult};
+
+const OFFLINE_VARIABLE: &str = "IGNITION_OFFLINE";
+/// Cargo's offline flag (`net.offline`), as set in the environment for `cargo --offline` builds.
+const CARGO_OFFLINE_VARIABLE: &str = "CARGO_NET_OFFLINE";
+
+/// Determine if network access is refused: always in vendored mode (see `vendor::dir`), otherwise
+/// `IGNITION_OFFLINE`, otherwise `CARGO_NET_OFFLINE` (default false).
+pub fn is_offline() -> IgnitionResult<bool> {
+    if vendor::dir().is_some() {
+        return Ok(true);
+    }
+    match env_flag(OFFLINE_VARIABLE)? {
+        Some(offline) => Ok(offline),
+        None => Ok(env_flag(CARGO_OFFLINE_VARIABLE)?.unwrap_or(false)),
+    }
+}
+
+/// Determine if <url> is retrieved without network access: local sources (`file://` urls and absolute paths, e.g.
+/// a vendored copy of the bucket).
+pub fn is_local(url: &str) -> bool {
+    source::local::path(url).is_some()
+}
+
+/// Error if <url> requires network access in offline mode (see `is_offline`).
+pub fn check_url(url: &str) -> IgnitionResult<()> {
+    if is_local(url) || !is_offline()? {
+        return Ok(());
+    }
+    Err(IgnitionError::Offline(format!(
+        "{}: network access refused ({} or {} set)",
+        redact::redact(url),
+        OFFLINE_VARIABLE,
+        CARGO_OFFLINE_VARIABLE
+    )))
+}
@@ -0,0 +1,319 @@
+// patch.rs
+//
+// This module contains the application of patches to extracted assets (`patches` in the manifest): unified diffs
+// stored in the consuming repository (e.g. fixes to the CMake config files of upstream prebuilt packages), applied
+// after extraction and recorded next to the asset directory, so applying them again is a no-op.
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{
+    checksum,
+    download::sidecar_path,
+    extract::{is_contained, strip_path},
+    source,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+/// File extension of the record of the patches applied to an asset directory, next to it.
+const RECORD_EXTENSION: &str = ".patches";
+/// Path of the missing side of a file created or deleted by a patch.
+const NULL_PATH: &str = "/dev/null";
+/// Maximum distance of a hunk from its line numbers, as patches of another upstream version shift lines.
+const MAX_OFFSET: usize = 1000;
+
+/// Patch of a file, from a unified diff.
+struct FilePatch {
+    /// Path of the original file (as in the diff), `None` if the patch creates the file.
+    old_path: Option<PathBuf>,
+    /// Path of the patched file (as in the diff), `None` if the patch deletes the file.
+    new_path: Option<PathBuf>,
+    /// Hunks of the patch, in order.
+    hunks: Vec<Hunk>,
+}
+
+/// Hunk of a file patch: the <old> lines starting at (1-based) line <old_start> replaced by the <new> lines.
+struct Hunk {
+    old_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Apply the <patches> (unified diff files, relative to the current directory, i.e. the package of the build script)
+/// of <asset> to its asset directory <directory>, in order.
+///
+/// File paths of the diffs are relative to the asset directory, with their first component stripped (as
+/// `patch -p1`, e.g. `a/lib/cmake/OpenCVConfig.cmake` of `git diff`), and must stay inside it. Hunks are matched by
+/// their context (exactly), anywhere around their line numbers. Patched files are replaced rather than modified
+/// in place, so hard links to the cache (see `install`) are kept intact. Applied patches are recorded with their
+/// SHA-256 digests in `<asset>.patches`, and patches already applied (recorded, or whose changes are already
+/// present) are skipped, so applying patches is idempotent. A patch which doesn't apply is an error.
+pub fn apply(asset: &str, directory: &Path, patches: &[String]) -> IgnitionResult<()> {
+    if patches.is_empty() {
+        return Ok(());
+    }
+    let record_path = sidecar_path(directory, RECORD_EXTENSION);
+    let mut recorded = match record_path.exists() {
+        true => fs::read_to_string(&record_path)?,
+        false => String::new(),
+    };
+    let applied = checksum::parse_sums(&recorded);
+    for patch in patches {
+        let contents = fs::read(patch)
+            .map_err(|err| IgnitionError::PatchFailed(format!("{}: {}: {}", asset, patch, err)))?;
+        let digest = source::hex(&Sha256::digest(&contents));
+        if applied.get(patch.strip_prefix("./").unwrap_or(patch)) == Some(&digest) {
+            continue;
+        }
+        let file_patches = parse(&String::from_utf8_lossy(&contents))
+            .map_err(|reason| patch_error(asset, patch, reason))?;
+        if file_patches.is_empty() {
+            return Err(patch_error(asset, patch, "no file patches".to_string()));
+        }
+        match apply_patch(directory, &file_patches)
+            .map_err(|reason| patch_error(asset, patch, reason))?
+        {
+            true => println!("--patch: {}: {}", asset, patch),
+            false => println!("--patch already applied: {}: {}", asset, patch),
+        }
+        recorded.push_str(&format!("{}  {}\n", digest, patch));
+        fs::write(&record_path, &recorded)?;
+    }
+    Ok(())
+}
+
+/// SHA-256 digests of the <patches> (as recorded by `apply`), e.g. to detect changed patches.
+pub fn digests(patches: &[String]) -> IgnitionResult<Vec<String>> {
+    patches
+        .iter()
+        .map(|patch| {
+            let contents = fs::read(patch)
+                .map_err(|err| IgnitionError::PatchFailed(format!("{}: {}", patch, err)))?;
+            Ok(source::hex(&Sha256::digest(&contents)))
+        })
+        .collect()
+}
+
+/// Remove the record of the patches applied to the asset directory <directory> (e.g. before extracting over it).
+pub fn clear(directory: &Path) -> IgnitionResult<()> {
+    let record_path = sidecar_path(directory, RECORD_EXTENSION);
+    if record_path.exists() {
+        fs::remove_file(record_path)?;
+    }
+    Ok(())
+}
+
+/// Error of the <patch> of <asset> failing for <reason>.
+fn patch_error(asset: &str, patch: &str, reason: String) -> IgnitionError {
+    IgnitionError::PatchFailed(format!("{}: {}: {}", asset, patch, reason))
+}
+
+/// Apply the <file_patches> of a patch to <directory>, all or none of them.
+///
+/// Return is whether the patch changed anything, `false` if its changes were all present already.
+fn apply_patch(directory: &Path, file_patches: &[FilePatch]) -> Result<bool, String> {
+    let mut changes = Vec::new();
+    for file_patch in file_patches {
+        let relative_path = file_patch
+            .new_path
+            .as_ref()
+            .or(file_patch.old_path.as_ref())
+            .and_then(|path| strip_path(path, 1))
+            .filter(|path| is_contained(path))
+            .ok_or_else(|| {
+                format!(
+                    "invalid file path {}",
+                    file_patch
+                        .new_path
+                        .as_ref()
+                        .or(file_patch.old_path.as_ref())
+                        .map_or(NULL_PATH.into(), |path| path.display().to_string())
+                )
+            })?;
+        let path = directory.join(&relative_path);
+        let exists = path.exists();
+        // created files must be missing, deleted files may be missing already
+        match (&file_patch.old_path, &file_patch.new_path) {
+            (None, _) if !exists => {
+                let lines = apply_hunks(&[], &file_patch.hunks, false)
+                    .ok_or_else(|| format!("{}: hunks don't apply", relative_path.display()))?;
+                changes.push((path, true, lines));
+                continue;
+            }
+            (_, None) if !exists => continue,
+            _ => {}
+        }
+        let original = fs::read_to_string(&path)
+            .map_err(|err| format!("{}: {}", relative_path.display(), err))?;
+        let lines: Vec<&str> = original.split_inclusive('\n').collect();
+        // hunks already applied reverse (checked first, as hunks adding lines may apply twice)
+        let applied = file_patch.new_path.is_some()
+            && apply_hunks(&lines, &file_patch.hunks, true)
+                .is_some_and(|unpatched| file_patch.old_path.is_some() || unpatched.is_empty());
+        if applied {
+            continue;
+        }
+        let patched = match file_patch.old_path.is_some() {
+            true => apply_hunks(&lines, &file_patch.hunks, false),
+            false => None,
+        }
+        .ok_or_else(|| format!("{}: hunks don't apply", relative_path.display()))?;
+        changes.push((path, file_patch.new_path.is_some(), patched));
+    }
+    let changed = !changes.is_empty();
+    for (path, keep, patched) in changes {
+        let permissions = fs::metadata(&path)
+            .ok()
+            .map(|metadata| metadata.permissions());
+        // replaced, so the file is never modified through a hard link
+        if path.exists() {
+            fs::remove_file(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        if !keep {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| format!("{}: {}", parent.display(), err))?;
+        }
+        fs::write(&path, patched).map_err(|err| format!("{}: {}", path.display(), err))?;
+        if let Some(permissions) = permissions {
+            fs::set_permissions(&path, permissions)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+    }
+    Ok(changed)
+}
+
+/// Apply <hunks> to the <lines> of a file (with their line terminators), in <reverse> to unapply them.
+///
+/// Return is the patched contents, `None` if a hunk doesn't match.
+fn apply_hunks(lines: &[&str], hunks: &[Hunk], reverse: bool) -> Option<String> {
+    let mut patched = String::new();
+    let mut cursor = 0;
+    // shift of the hunks applied so far, from the line numbers of the patch
+    let mut shift = 0isize;
+    for hunk in hunks {
+        let (old, new) = match reverse {
+            false => (&hunk.old, &hunk.new),
+            true => (&hunk.new, &hunk.old),
+        };
+        // hunks without old lines insert after their start line
+        let start = match old.is_empty() {
+            true => hunk.old_start,
+            false => hunk.old_start.saturating_sub(1),
+        };
+        let expected = start.saturating_add_signed(shift).max(cursor);
+        let position = (0..=MAX_OFFSET)
+            .flat_map(|offset| [expected.checked_add(offset), expected.checked_sub(offset)])
+            .flatten()
+            .filter(|position| *position >= cursor && *position + old.len() <= lines.len())
+            .find(|position| {
+                lines[*position..*position + old.len()]
+                    .iter()
+                    .zip(old)
+                    .all(|(line, old_line)| line == old_line)
+            })?;
+        shift += position as isize - start as isize;
+        patched.extend(lines[cursor..position].iter().copied());
+        patched.extend(new.iter().map(String::as_str));
+        cursor = position + old.len();
+    }
+    patched.extend(lines[cursor..].iter().copied());
+    Some(patched)
+}
+
+/// Parse the file patches of the unified diff <contents> (e.g. of `diff -u` or `git diff`), ignoring other lines.
+fn parse(contents: &str) -> Result<Vec<FilePatch>, String> {
+    let mut file_patches: Vec<FilePatch> = Vec::new();
+    let mut lines = contents.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        if let Some(old_path) = line.strip_prefix("--- ")
+            && let Some(new_path) = lines.peek().and_then(|line| line.strip_prefix("+++ "))
+        {
+            file_patches.push(FilePatch {
+                old_path: diff_path(old_path),
+                new_path: diff_path(new_path),
+                hunks: Vec::new(),
+            });
+            lines.next();
+            continue;
+        }
+        let Some(header) = line.strip_prefix("@@ -") else {
+            continue;
+        };
+        let file_patch = file_patches
+            .last_mut()
+            .ok_or_else(|| format!("hunk without file header: {}", line.trim_end()))?;
+        let (old_start, old_count, new_count) = hunk_range(header)
+            .ok_or_else(|| format!("invalid hunk header: {}", line.trim_end()))?;
+        let mut hunk = Hunk {
+            old_start,
+            old: Vec::new(),
+            new: Vec::new(),
+        };
+        while hunk.old.len() < old_count || hunk.new.len() < new_count {
+            let line = lines
+                .next()
+                .ok_or_else(|| format!("truncated hunk at line {}", old_start))?;
+            // the newline of a diff line stands for the line terminator (`\r` of CRLF lines is part of the text)
+            let text = line.strip_suffix('\n').unwrap_or(line);
+            let kind = text.chars().next();
+            match kind {
+                Some('-') => hunk.old.push(format!("{}\n", &text[1..])),
+                Some('+') => hunk.new.push(format!("{}\n", &text[1..])),
+                Some(' ') => {
+                    hunk.old.push(format!("{}\n", &text[1..]));
+                    hunk.new.push(format!("{}\n", &text[1..]));
+                }
+                // blank context lines (stripped of their trailing space by editors)
+                None => {
+                    hunk.old.push("\n".to_string());
+                    hunk.new.push("\n".to_string());
+                }
+                Some(_) => return Err(format!("invalid hunk line: {}", text)),
+            }
+            // `\ No newline at end of file` follows the last line of either side, or of both (context)
+            if lines.next_if(|line| line.starts_with('\\')).is_some() {
+                if kind != Some('+')
+                    && let Some(line) = hunk.old.last_mut()
+                {
+                    line.pop();
+                }
+                if kind != Some('-')
+                    && let Some(line) = hunk.new.last_mut()
+                {
+                    line.pop();
+                }
+            }
+        }
+        file_patch.hunks.push(hunk);
+    }
+    Ok(file_patches)
+}
+
+/// Path of the file header <text> of a diff (`--- <path>[\t<timestamp>]`), `None` for `/dev/null`.
+fn diff_path(text: &str) -> Option<PathBuf> {
+    let path = text.split('\t').next().unwrap_or(text).trim_end();
+    (path != NULL_PATH).then(|| PathBuf::from(path))
+}
+
+/// Start line and line count of the old side, and line count of the new side, of the hunk <header>
+/// (`-<start>[,<count>] +<start>[,<count>] @@`, without the leading `@@ -`).
+fn hunk_range(header: &str) -> Option<(usize, usize, usize)> {
+    let (old, rest) = header.split_once(" +")?;
+    let (new, _) = rest.split_once(" @@")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (_, new_count) = range(new)?;
+    Some((old_start, old_count, new_count))
+}
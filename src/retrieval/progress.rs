@@ -0,0 +1,207 @@
+// progress.rs
+//
+// This module contains download progress reporting: periodic `cargo:warning` lines (percentage, speed, ETA), or
+// a progress bar when stderr is a terminal.
+//
+
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use super::env_value;
+use crate::{IgnitionError, IgnitionResult};
+
+const PROGRESS_VARIABLE: &str = "IGNITION_PROGRESS";
+const INTERVAL_VARIABLE: &str = "IGNITION_PROGRESS_INTERVAL";
+const DEFAULT_INTERVAL_SECS: u64 = 10;
+const BAR_INTERVAL: Duration = Duration::from_millis(200);
+const BAR_WIDTH: usize = 30;
+
+/// How download progress is reported (`IGNITION_PROGRESS`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressMode {
+    /// Periodic `cargo:warning` lines, so long downloads show up in the cargo console (default).
+    Warning,
+    /// Progress bar on stderr if it is a terminal, otherwise as `Warning`.
+    Bar,
+    /// No progress reporting.
+    None,
+}
+
+impl FromStr for ProgressMode {
+    type Err = IgnitionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "warning" | "1" | "true" => Ok(ProgressMode::Warning),
+            "bar" => Ok(ProgressMode::Bar),
+            "none" | "off" | "0" | "false" => Ok(ProgressMode::None),
+            _ => Err(IgnitionError::ConfigurationError(format!(
+                "{}={}",
+                PROGRESS_VARIABLE, value
+            ))),
+        }
+    }
+}
+
+/// Progress of a single download, shared by all readers (e.g. concurrent segments) of the download.
+pub struct Progress {
+    name: String,
+    mode: ProgressMode,
+    interval: Duration,
+    start: Instant,
+    offset: u64,
+    total: Option<u64>,
+    received: AtomicU64,
+    last_report: Mutex<Instant>,
+    reported: AtomicBool,
+}
+
+impl Progress {
+    /// Track the download of <name> of <total> bytes (if known), continuing from <offset> bytes.
+    ///
+    /// Reports are emitted every `IGNITION_PROGRESS_INTERVAL` seconds (default 10), so short downloads are
+    /// not reported at all.
+    pub fn new(name: &str, offset: u64, total: Option<u64>) -> IgnitionResult<Self> {
+        let mut mode = env_value(PROGRESS_VARIABLE)?.unwrap_or(ProgressMode::Warning);
+        let mut interval =
+            Duration::from_secs(env_value(INTERVAL_VARIABLE)?.unwrap_or(DEFAULT_INTERVAL_SECS));
+        if mode == ProgressMode::Bar {
+            if io::stderr().is_terminal() {
+                interval = BAR_INTERVAL;
+            } else {
+                mode = ProgressMode::Warning;
+            }
+        }
+        let start = Instant::now();
+        Ok(Progress {
+            name: name.to_string(),
+            mode,
+            interval,
+            start,
+            offset,
+            total,
+            received: AtomicU64::new(0),
+            last_report: Mutex::new(start),
+            reported: AtomicBool::new(false),
+        })
+    }
+
+    /// Wrap <inner>, counting bytes read towards this download.
+    pub fn reader<R: Read>(&self, inner: R) -> ProgressReader<'_, R> {
+        ProgressReader {
+            inner,
+            progress: self,
+        }
+    }
+
+    /// Count <count> more bytes received, reporting if the interval elapsed.
+    fn add(&self, count: u64) {
+        self.received.fetch_add(count, Ordering::Relaxed);
+        if self.mode == ProgressMode::None {
+            return;
+        }
+        let now = Instant::now();
+        {
+            let mut last_report = self
+                .last_report
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            if now.duration_since(*last_report) < self.interval {
+                return;
+            }
+            *last_report = now;
+        }
+        self.reported.store(true, Ordering::Relaxed);
+        self.report(false);
+    }
+
+    /// Report completion of the download, if progress was reported before.
+    pub fn finish(&self) {
+        if self.reported.load(Ordering::Relaxed) {
+            self.report(true);
+        }
+    }
+
+    /// Emit a progress report.
+    fn report(&self, done: bool) {
+        let received = self.received.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let speed = received as f64 / elapsed.max(0.001);
+        let current = self.offset + received;
+        let mut status = match self.total {
+            Some(total) if total > 0 => format!(
+                "{:.0}% ({} / {})",
+                current as f64 * 100.0 / total as f64,
+                format_size(current),
+                format_size(total)
+            ),
+            _ => format_size(current),
+        };
+        if done {
+            status.push_str(&format!(" in {:.0}s", elapsed));
+        } else {
+            status.push_str(&format!(" {}/s", format_size(speed as u64)));
+            if let Some(total) = self.total
+                && speed > 0.0
+            {
+                let remaining = total.saturating_sub(current) as f64 / speed;
+                status.push_str(&format!(" ETA {:.0}s", remaining));
+            }
+        }
+        match self.mode {
+            ProgressMode::Bar => {
+                let filled = match self.total {
+                    Some(total) if total > 0 => {
+                        (current.min(total) as f64 / total as f64 * BAR_WIDTH as f64) as usize
+                    }
+                    _ => 0,
+                };
+                let mut stderr = io::stderr().lock();
+                let _ = write!(
+                    stderr,
+                    "\r{} [{}{}] {}\x1b[K",
+                    self.name,
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    status
+                );
+                if done {
+                    let _ = writeln!(stderr);
+                }
+                let _ = stderr.flush();
+            }
+            _ => println!("cargo:warning=--progress: {}: {}", self.name, status),
+        }
+    }
+}
+
+/// Reader counting the bytes read towards a download's progress.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.progress.add(count as u64);
+        Ok(count)
+    }
+}
+
+/// Human-readable byte size with binary multiples (e.g. "12.3M").
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{}B", bytes),
+        1024..1048576 => format!("{:.1}K", bytes as f64 / 1024.0),
+        1048576..1073741824 => format!("{:.1}M", bytes as f64 / 1048576.0),
+        _ => format!("{:.1}G", bytes as f64 / 1073741824.0),
+    }
+}
@@ -0,0 +1,101 @@
+// provenance.rs
+//
+// This module contains verification of SLSA provenance attestations (in-toto statements) of downloaded asset
+// archives before extraction, using the `slsa-verifier` client.
+//
+
+use std::{path::Path, process::Command};
+
+use serde::Deserialize;
+
+use super::{
+    download::{self, sidecar_path},
+    source,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const PROVENANCE_EXTENSION: &str = ".intoto.jsonl";
+
+/// Provenance policy of an asset: the attestation published with the archive must be signed for the archive by
+/// the expected builder from the expected source repository, for example:
+///
+/// ```json
+/// "provenance": {
+///     "source_uri": "github.com/org/repo",
+///     "builder_id": "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml",
+///     "file": "multiple.intoto.jsonl"
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProvenancePolicy {
+    /// Expected source repository (e.g. `github.com/org/repo`).
+    pub source_uri: String,
+    /// Expected builder identity, any trusted builder if None.
+    #[serde(default)]
+    pub builder_id: Option<String>,
+    /// Expected source tag (e.g. a release tag).
+    #[serde(default)]
+    pub source_tag: Option<String>,
+    /// Expected source branch.
+    #[serde(default)]
+    pub source_branch: Option<String>,
+    /// File name of the attestation next to the archive, `<archive file name>.intoto.jsonl` if None (e.g.
+    /// `multiple.intoto.jsonl` for all archives of a release).
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+/// Verify the archive at <archive_path> downloaded from <url> against its provenance attestation, as required by
+/// <policy>.
+///
+/// The attestation is kept next to the cached archive, and only fetched again with <fetch> (i.e. a fresh
+/// download) or if absent.
+pub fn verify(
+    url: &str,
+    archive_path: &Path,
+    policy: &ProvenancePolicy,
+    fetch: bool,
+) -> IgnitionResult<()> {
+    let provenance_path = sidecar_path(archive_path, PROVENANCE_EXTENSION);
+    if fetch || !provenance_path.exists() {
+        let provenance_url = match policy.file.as_deref() {
+            Some(file) => source::sibling_url(url, file).map_or(file.to_string(), |(_, url)| url),
+            None => source::suffixed_url(url, PROVENANCE_EXTENSION),
+        };
+        download::download(&provenance_url, &provenance_path).map_err(|err| {
+            IgnitionError::ProvenanceError(format!("no attestation: {}: {}", provenance_url, err))
+        })?;
+    }
+
+    let mut command = Command::new("slsa-verifier");
+    command
+        .arg("verify-artifact")
+        .arg("--provenance-path")
+        .arg(&provenance_path)
+        .args(["--source-uri", &policy.source_uri]);
+    if let Some(builder_id) = &policy.builder_id {
+        command.args(["--builder-id", builder_id]);
+    }
+    if let Some(source_tag) = &policy.source_tag {
+        command.args(["--source-tag", source_tag]);
+    }
+    if let Some(source_branch) = &policy.source_branch {
+        command.args(["--source-branch", source_branch]);
+    }
+    let output = command.arg(archive_path).output().map_err(|err| {
+        IgnitionError::ProvenanceError(format!("command 'slsa-verifier' failed: {}", err))
+    })?;
+    if !output.status.success() {
+        return Err(IgnitionError::ProvenanceError(format!(
+            "{}: {}",
+            archive_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    println!(
+        "--provenance verified: {} ({})",
+        archive_path.display(),
+        policy.source_uri
+    );
+    Ok(())
+}
@@ -0,0 +1,168 @@
+// prune.rs
+//
+// This module contains the pruning of stale extractions (`IGNITION_PRUNE`): extractions are namespaced by target
+// and asset version (see `AssetLocation`), so those of other targets, earlier versions or assets removed from the
+// manifest would otherwise linger in the asset directory of the build forever.
+//
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    AssetLocation, env_flag, gc,
+    lock::{LOCK_EXTENSION, PathLock},
+    target,
+};
+use crate::{IgnitionResult, manifest};
+
+const PRUNE_VARIABLE: &str = "IGNITION_PRUNE";
+
+/// Result of the pruning of an asset directory.
+#[derive(Debug, Default)]
+pub struct Pruning {
+    /// Paths of the removed extractions (and other stale entries).
+    pub removed: Vec<PathBuf>,
+    /// Bytes freed by removing entries.
+    pub freed: u64,
+}
+
+/// Prune the asset directory as `prune` if `IGNITION_PRUNE=true`. Return is None if disabled.
+pub fn prune_configured(
+    root: &Path,
+    directory_path: &str,
+    target: &str,
+) -> IgnitionResult<Option<Pruning>> {
+    if !env_flag(PRUNE_VARIABLE)?.unwrap_or(false) {
+        return Ok(None);
+    }
+    prune(root, directory_path, target).map(Some)
+}
+
+/// Remove the entries of the asset directory `<root>/<directory_path>` not referenced by the manifest for <target>:
+/// extractions of other targets, other versions or assets no longer in the manifest, and everything else but the
+/// current extractions of the manifest's assets (with their stamps, staging directories and additional `files`).
+///
+/// Entries locked by a retrieval in progress (see `lock::PathLock`) are kept, lock files being removed with the
+/// entries they lock. The asset directory must not be shared with builds for other targets, whose extractions
+/// would be removed.
+pub fn prune(root: &Path, directory_path: &str, target: &str) -> IgnitionResult<Pruning> {
+    let directory = root.join(directory_path);
+    let mut pruning = Pruning::default();
+    if !directory.is_dir() {
+        return Ok(pruning);
+    }
+    let resolved = target::resolve(target, None)?;
+    // names of the referenced entries, by parent directory
+    let mut referenced: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    for (asset, asset_cfg) in manifest()?.iter() {
+        let location =
+            AssetLocation::new(&[], asset, asset_cfg, root, "", directory_path, &resolved)?;
+        let names = referenced.entry(location.target_path.clone()).or_default();
+        names.insert(asset.clone());
+        // additional files are installed at their content paths, possibly outside of the extraction
+        names.extend(asset_cfg.files.keys().filter_map(|content| {
+            Path::new(content)
+                .iter()
+                .next()
+                .map(|name| name.to_string_lossy().into_owned())
+        }));
+    }
+    let ancestors: HashSet<PathBuf> = referenced
+        .keys()
+        .flat_map(|path| path.ancestors())
+        .filter(|path| path.starts_with(&directory))
+        .map(Path::to_path_buf)
+        .collect();
+    prune_directory(&directory, &referenced, &ancestors, &mut pruning)?;
+    Ok(pruning)
+}
+
+/// Prune the <directory> into <pruning>, keeping the <referenced> entries (and their side files) and recursing
+/// into the <ancestors> of referenced entries.
+fn prune_directory(
+    directory: &Path,
+    referenced: &HashMap<PathBuf, HashSet<String>>,
+    ancestors: &HashSet<PathBuf>,
+    pruning: &mut Pruning,
+) -> IgnitionResult<()> {
+    let names = referenced.get(directory);
+    let mut paths = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // side files (`<name>.<extension>`, e.g. stamps) go with their entry
+        let is_referenced = names.is_some_and(|names| {
+            names.iter().any(|referenced_name| {
+                name.strip_prefix(referenced_name.as_str())
+                    .is_some_and(|extension| extension.is_empty() || extension.starts_with('.'))
+            })
+        });
+        if is_referenced || name.ends_with(LOCK_EXTENSION) {
+            continue;
+        }
+        if ancestors.contains(&path) {
+            prune_directory(&path, referenced, ancestors, pruning)?;
+            continue;
+        }
+        // entries in use are held until removed
+        let Some(_locks) = acquire_locks(&path)? else {
+            println!("--prune kept: {} (in use)", path.display());
+            continue;
+        };
+        let size = gc::size(&path)?;
+        match fs::symlink_metadata(&path)?.is_dir() {
+            true => fs::remove_dir_all(&path)?,
+            false => fs::remove_file(&path)?,
+        }
+        // the lock of a removed entry goes with it, while held
+        fs::remove_file(super::download::sidecar_path(&path, LOCK_EXTENSION)).ok();
+        println!("--pruned: {} ({} bytes)", path.display(), size);
+        pruning.freed += size;
+        pruning.removed.push(path);
+    }
+    Ok(())
+}
+
+/// Acquire the locks of <path> and of the entries within it (if a directory), without waiting. Return is None if
+/// any of them is held by another build.
+fn acquire_locks(path: &Path) -> IgnitionResult<Option<Vec<PathLock>>> {
+    let mut locked = Vec::new();
+    if super::download::sidecar_path(path, LOCK_EXTENSION).is_file() {
+        locked.push(path.to_path_buf());
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        lock_files(path, &mut locked)?;
+    }
+    let mut locks = Vec::with_capacity(locked.len());
+    for path in locked {
+        match PathLock::try_acquire(&path)? {
+            Some(lock) => locks.push(lock),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(locks))
+}
+
+/// Collect the paths locked by lock files within <directory> (recursively) into <locked>.
+fn lock_files(directory: &Path, locked: &mut Vec<PathBuf>) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            lock_files(&path, locked)?;
+        } else if let Some(stem) = path.file_name().and_then(|name| {
+            name.to_string_lossy()
+                .strip_suffix(LOCK_EXTENSION)
+                .map(String::from)
+        }) {
+            locked.push(directory.join(stem));
+        }
+    }
+    Ok(())
+}
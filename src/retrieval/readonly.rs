@@ -0,0 +1,152 @@
+// readonly.rs
+//
+// This module contains the read-only mode of extracted assets (`read_only` in the manifest or `IGNITION_READ_ONLY`):
+// extracted asset trees are marked read-only after installation, with the digests of their files recorded next to
+// them, so modifications by downstream build scripts are prevented, and detected before the asset is extracted
+// again (or reused, with `full` integrity checks, see `integrity`).
+//
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{checksum, download::sidecar_path};
+use crate::IgnitionResult;
+
+/// File extension of the record of the files of a read-only asset tree, next to it.
+const RECORD_EXTENSION: &str = ".readonly";
+
+/// Mark the asset tree at <directory> read-only (files and directories, not following symbolic links), recording
+/// the SHA-256 digests of its files (`SHA256SUMS` format) in `<directory>.readonly`.
+pub fn protect(directory: &Path) -> IgnitionResult<()> {
+    let mut record = String::new();
+    for (relative_path, digest) in digests(directory)? {
+        record.push_str(&format!("{}  {}\n", digest, relative_path));
+    }
+    fs::write(sidecar_path(directory, RECORD_EXTENSION), record)?;
+    set_writable(directory, false)?;
+    println!("--read-only: {}", directory.display());
+    Ok(())
+}
+
+/// Make the read-only asset tree at <directory> writable again (e.g. before extracting over it), verifying it
+/// against its record first (see `verify`). Trees never marked read-only are left as is.
+///
+/// Return is the relative paths of the files modified, added or removed since the tree was marked read-only.
+pub fn unprotect(directory: &Path) -> IgnitionResult<Vec<String>> {
+    let record_path = sidecar_path(directory, RECORD_EXTENSION);
+    if !record_path.exists() {
+        return Ok(Vec::new());
+    }
+    let modified = verify(directory)?;
+    if directory.exists() {
+        set_writable(directory, true)?;
+    }
+    fs::remove_file(&record_path)?;
+    Ok(modified)
+}
+
+/// Verify the read-only asset tree at <directory> against its record, leaving it read-only. Trees never marked
+/// read-only (or missing) aren't verified.
+///
+/// Return is the relative paths of the files modified, added or removed since the tree was marked read-only.
+pub fn verify(directory: &Path) -> IgnitionResult<Vec<String>> {
+    let record_path = sidecar_path(directory, RECORD_EXTENSION);
+    if !record_path.exists() || !directory.exists() {
+        return Ok(Vec::new());
+    }
+    modified(directory, &fs::read_to_string(&record_path)?)
+}
+
+/// Compare the files within <directory> to the digests of their <sums> (`SHA256SUMS` format, by relative path).
+///
+/// Return is the relative paths of the files modified, added or removed, sorted.
+pub(crate) fn modified(directory: &Path, sums: &str) -> IgnitionResult<Vec<String>> {
+    let mut recorded = checksum::parse_sums(sums);
+    let mut modified = Vec::new();
+    for (relative_path, digest) in digests(directory)? {
+        if recorded.remove(&relative_path).as_deref() != Some(digest.as_str()) {
+            modified.push(relative_path);
+        }
+    }
+    modified.extend(recorded.into_keys());
+    modified.sort();
+    Ok(modified)
+}
+
+/// SHA-256 digests of the files within <directory> (recursively, not following symbolic links), keyed by relative
+/// path (with `/` separators).
+pub(crate) fn digests(directory: &Path) -> IgnitionResult<BTreeMap<String, String>> {
+    let mut files = Vec::new();
+    regular_files(directory, &mut files)?;
+    files
+        .into_iter()
+        .map(|file| {
+            let relative_path = file
+                .strip_prefix(directory)
+                .unwrap_or(&file)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Ok((relative_path, checksum::sha256_file(&file)?))
+        })
+        .collect()
+}
+
+/// Collect the regular files within <directory> (recursively, not following symbolic links) into <files>.
+fn regular_files(directory: &Path, files: &mut Vec<PathBuf>) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            regular_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Make <path> and everything within it (not following symbolic links) <writable> by its owner, or read-only for
+/// everyone. Directories are made writable before, and read-only after, their contents.
+fn set_writable(path: &Path, writable: bool) -> IgnitionResult<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let permissions = permissions(metadata.permissions(), writable);
+    if !metadata.is_dir() {
+        fs::set_permissions(path, permissions)?;
+        return Ok(());
+    }
+    if writable {
+        fs::set_permissions(path, permissions.clone())?;
+    }
+    for entry in fs::read_dir(path)? {
+        set_writable(&entry?.path(), writable)?;
+    }
+    if !writable {
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// <permissions> made <writable> by the owner, or read-only for everyone.
+#[allow(clippy::permissions_set_readonly_false)]
+fn permissions(mut permissions: fs::Permissions, writable: bool) -> fs::Permissions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = permissions.mode();
+        permissions.set_mode(match writable {
+            true => mode | 0o200,
+            false => mode & !0o222,
+        });
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(!writable);
+    permissions
+}
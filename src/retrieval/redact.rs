@@ -0,0 +1,121 @@
+// redact.rs
+//
+// This module contains the redaction of secrets in printed diagnostics and error messages: credentials and query
+// strings of urls (e.g. signed urls, SAS tokens) and values of credential headers (e.g. `Authorization`).
+//
+
+const REDACTED: &str = "<redacted>";
+/// Lowercase names of headers carrying credentials.
+const SECRET_HEADERS: [&str; 6] = [
+    "proxy-authorization",
+    "authorization",
+    "x-jfrog-art-api",
+    "x-amz-security-token",
+    "private-token",
+    "x-api-key",
+];
+/// Lowercase authorization scheme followed by a token.
+const BEARER: &str = "bearer ";
+/// Characters delimiting urls and header values in messages.
+const DELIMITERS: &str = "\"'<>()[]{}";
+
+/// Redact secrets in <text>: user info and query strings of urls (`https://<redacted>@host/path?<redacted>`),
+/// values of credential headers (`Authorization: <redacted>`) and bearer tokens (`Bearer <redacted>`).
+pub fn redact(text: &str) -> String {
+    redact_headers(&redact_urls(text))
+}
+
+/// Redact user info and query strings of all urls (`<scheme>://...`) in <text>.
+fn redact_urls(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find("://") {
+        let (before, after) = rest.split_at(index + 3);
+        redacted.push_str(before);
+        let end = after
+            .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c))
+            .unwrap_or(after.len());
+        // trailing punctuation belongs to the message, not the url
+        let end = after[..end].trim_end_matches([',', '.', ';', ':']).len();
+        let (url, remaining) = after.split_at(end);
+        let authority_end = url.find(['/', '?', '#']).unwrap_or(url.len());
+        let (authority, path) = url.split_at(authority_end);
+        match authority.rsplit_once('@') {
+            Some((_, host)) => {
+                redacted.push_str(REDACTED);
+                redacted.push('@');
+                redacted.push_str(host);
+            }
+            None => redacted.push_str(authority),
+        }
+        match path.split_once('?') {
+            Some((path, _)) => {
+                redacted.push_str(path);
+                redacted.push('?');
+                redacted.push_str(REDACTED);
+            }
+            None => redacted.push_str(path),
+        }
+        rest = remaining;
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Redact values of credential headers (`<name>: <value>` or `<name>=<value>`, up to the end of the line or a
+/// delimiter) and bearer tokens in <text>.
+fn redact_headers(text: &str) -> String {
+    // ASCII lowercasing preserves byte offsets, so matches in <lower> index into <text>
+    let lower = text.to_ascii_lowercase();
+    let mut redacted = String::with_capacity(text.len());
+    let mut position = 0;
+    loop {
+        let next = SECRET_HEADERS
+            .iter()
+            .chain([&BEARER])
+            .filter_map(|pattern| {
+                lower[position..]
+                    .find(pattern)
+                    .map(|index| (position + index, *pattern))
+            })
+            .min_by_key(|(index, pattern)| (*index, usize::MAX - pattern.len()));
+        let Some((index, pattern)) = next else {
+            break;
+        };
+        let mut value_start = index + pattern.len();
+        let value_end = if pattern == BEARER {
+            let value_end = text[value_start..]
+                .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c) || c == ',')
+                .map_or(text.len(), |end| value_start + end);
+            // authentication challenge parameters (`Bearer realm="..."`) aren't tokens
+            let value = &text[value_start..value_end];
+            if value.trim_end_matches('=').contains('=')
+                || (value.ends_with('=') && text[value_end..].starts_with('"'))
+            {
+                redacted.push_str(&text[position..value_start]);
+                position = value_start;
+                continue;
+            }
+            value_end
+        } else {
+            let separator = text[value_start..].trim_start_matches(['"', '\'', ' ']);
+            if !separator.starts_with([':', '=']) {
+                redacted.push_str(&text[position..value_start]);
+                position = value_start;
+                continue;
+            }
+            let value = separator[1..].trim_start_matches(['"', '\'', ' ', '\t']);
+            value_start = text.len() - value.len();
+            text[value_start..]
+                .find(['"', '\'', '\n', ',', ';'])
+                .map_or(text.len(), |end| value_start + end)
+        };
+        redacted.push_str(&text[position..value_start]);
+        if value_end > value_start {
+            redacted.push_str(REDACTED);
+        }
+        position = value_end;
+    }
+    redacted.push_str(&text[position..]);
+    redacted
+}
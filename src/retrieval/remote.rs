@@ -0,0 +1,141 @@
+// remote.rs
+//
+// This module contains the remote cache (`IGNITION_REMOTE_CACHE_URL` or `remote_cache` in the manifest), the
+// organization-wide level under the local cache, shared by machines as sccache shares compilation outputs: assets
+// are resolved from the local cache, then the remote cache, then upstream sources, verified archives and files
+// being pulled into the local cache from the remote cache, and pushed to it once retrieved from upstream sources
+// (with `IGNITION_REMOTE_CACHE_PUSH=true` or `remote_cache_push`, e.g. by CI builds of the main branch).
+//
+
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+
+use super::{
+    cache,
+    download::{self, sidecar_path},
+    env_flag, expiry, http, offline, redact, rerun, source,
+};
+use crate::IgnitionResult;
+
+const REMOTE_CACHE_URL_VARIABLE: &str = "IGNITION_REMOTE_CACHE_URL";
+const REMOTE_CACHE_PUSH_VARIABLE: &str = "IGNITION_REMOTE_CACHE_PUSH";
+/// File extension of a file pulled from the remote cache, next to the cached file until complete.
+const PULL_EXTENSION: &str = ".remote";
+
+/// Remote cache of an asset.
+#[derive(Clone, Debug)]
+pub struct RemoteCache {
+    /// Url of the remote cache (without trailing '/').
+    pub url: String,
+    /// Whether files retrieved from upstream sources are pushed to the remote cache.
+    pub push: bool,
+}
+
+impl RemoteCache {
+    /// Resolve the remote cache of an asset: `IGNITION_REMOTE_CACHE_URL`, otherwise the <manifest_url>
+    /// (`remote_cache` of the asset), an `http(s)://`, `s3://`, `gs://` or `az://` url, or a local directory (e.g.
+    /// `file:///mnt/cache`), pushing to it with `IGNITION_REMOTE_CACHE_PUSH`, otherwise <manifest_push>
+    /// (`remote_cache_push` of the asset). Return is None without a remote cache.
+    pub fn resolve(
+        manifest_url: Option<&str>,
+        manifest_push: bool,
+    ) -> IgnitionResult<Option<Self>> {
+        rerun::record_variable(REMOTE_CACHE_URL_VARIABLE);
+        let url = std::env::var(REMOTE_CACHE_URL_VARIABLE)
+            .ok()
+            .filter(|url| !url.is_empty())
+            .or(manifest_url.map(String::from));
+        let Some(url) = url else {
+            return Ok(None);
+        };
+        Ok(Some(RemoteCache {
+            url: url.trim_end_matches('/').to_string(),
+            push: env_flag(REMOTE_CACHE_PUSH_VARIABLE)?.unwrap_or(manifest_push),
+        }))
+    }
+
+    /// Url of the cached file at <path> in the remote cache, keyed by its path within the cache at <cache_root>
+    /// (see `cache::relative_path`). Return is None outside of the cache.
+    fn file_url(&self, path: &Path, cache_root: &Path) -> Option<String> {
+        let relative_path = cache::relative_path(path, cache_root)?;
+        let components: Vec<_> = relative_path
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect();
+        Some(format!("{}/{}", self.url, components.join("/")))
+    }
+}
+
+/// Pull the cached file at <path> of the cache at <cache_root> from the <remote> cache (if any), if there and not
+/// offline. Files larger than <max_size> bytes are an error. The pulled file is trusted as a file cached locally,
+/// i.e. archives are verified as usual before extraction.
+/// Return is true if the file was pulled, false if it isn't in the remote cache (or the remote cache fails).
+pub fn pull(
+    remote: Option<&RemoteCache>,
+    path: &Path,
+    cache_root: &Path,
+    max_size: Option<u64>,
+) -> IgnitionResult<bool> {
+    let Some(url) = remote.and_then(|remote| remote.file_url(path, cache_root)) else {
+        return Ok(false);
+    };
+    if offline::is_offline()? {
+        return Ok(false);
+    }
+    // pulled aside, so a failed pull never leaves a partial download for upstream sources to resume
+    let pull_path = sidecar_path(path, PULL_EXTENSION);
+    let pulled = download::download_limited(&url, &pull_path, max_size);
+    // revalidation goes to upstream sources rather than the remote cache
+    fs::remove_file(sidecar_path(&pull_path, download::VALIDATORS_EXTENSION)).ok();
+    if let Err(err) = pulled {
+        println!("--remote cache miss: {} ({})", redact::redact(&url), err);
+        fs::remove_file(download::partial_path(&pull_path)).ok();
+        fs::remove_file(&pull_path).ok();
+        return Ok(false);
+    }
+    fs::rename(&pull_path, path)?;
+    expiry::record(path)?;
+    println!("--remote cache hit: {}", redact::redact(&url));
+    Ok(true)
+}
+
+/// Push the (verified) cached file at <path> of the cache at <cache_root> to the <remote> cache (if any) if it is
+/// pushed to (see `RemoteCache::resolve`), uploading it with an HTTP `PUT` request (signed for `s3://` urls), or
+/// copying it into a local remote cache.
+/// Return is true if the file was pushed.
+pub fn push(remote: Option<&RemoteCache>, path: &Path, cache_root: &Path) -> IgnitionResult<bool> {
+    let Some(url) = remote
+        .filter(|remote| remote.push)
+        .and_then(|remote| remote.file_url(path, cache_root))
+    else {
+        return Ok(false);
+    };
+    if let Some(remote_path) = source::local::path(&url) {
+        // copied into a partial file first, so the remote cache never holds an incomplete file
+        if let Some(parent) = remote_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial_path = download::partial_path(&remote_path);
+        fs::copy(path, &partial_path)?;
+        fs::rename(&partial_path, &remote_path)?;
+        println!("--remote cache push: {}", remote_path.display());
+        return Ok(true);
+    }
+    let request = match source::scheme(&url) {
+        "s3" => source::s3::method_request("PUT", &url)?,
+        _ => source::http_request(&url)?,
+    };
+    let agent = http::agent(&request.url)?;
+    let mut put = agent.put(&request.url);
+    for (name, value) in request.headers.iter() {
+        put = put.header(name, value);
+    }
+    if source::scheme(&url) == "az" {
+        put = put.header("x-ms-blob-type", "BlockBlob");
+    }
+    put.send(File::open(path)?)?;
+    println!("--remote cache push: {}", redact::redact(&url));
+    Ok(true)
+}
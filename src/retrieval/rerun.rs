@@ -0,0 +1,53 @@
+// rerun.rs
+//
+// This module contains the inputs of the build script for Cargo's change detection: the settings read from the
+// environment and the files retrieved assets depend on (manifests, lockfile, extraction stamps), emitted as
+// `cargo:rerun-if-env-changed` and `cargo:rerun-if-changed` directives, so the build script runs again when (and
+// only when) one of them changes.
+//
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Environment variables read by the retrievals of this build.
+static VARIABLES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+/// Files the retrievals of this build depend on.
+static PATHS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Record the environment variable <name> as read (whether set or not), see `print_directives`.
+pub(crate) fn record_variable(name: &str) {
+    VARIABLES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(name.to_string());
+}
+
+/// Record the file at <path> as an input of the retrievals (whether it exists or not), see `print_directives`.
+pub(crate) fn record_path(path: &Path) {
+    PATHS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(path.to_path_buf());
+}
+
+/// Print the `cargo:rerun-if-changed` and `cargo:rerun-if-env-changed` directives of the inputs recorded by the
+/// retrievals of this build (sorted), e.g. at the end of a build script.
+///
+/// Extraction stamps are inputs too (see `retrieval::is_extracted`): Cargo runs the build script again if a file
+/// is missing, so an extraction removed (e.g. pruned, or deleted by hand) is retrieved again by the next build.
+/// Credentials read from the environment aren't inputs, so rotating them never runs the build script again.
+pub fn print_directives() {
+    for path in PATHS.lock().unwrap_or_else(|err| err.into_inner()).iter() {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    for name in VARIABLES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+    {
+        println!("cargo:rerun-if-env-changed={}", name);
+    }
+}
@@ -0,0 +1,122 @@
+// retry.rs
+//
+// This module contains the retry policy (attempts, exponential backoff, jitter) applied to asset retrieval.
+//
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::env_value;
+use crate::{IgnitionError, IgnitionResult};
+
+const ATTEMPTS_VARIABLE: &str = "IGNITION_RETRY_ATTEMPTS";
+const BACKOFF_VARIABLE: &str = "IGNITION_RETRY_BACKOFF_MS";
+const MAX_BACKOFF_VARIABLE: &str = "IGNITION_RETRY_MAX_BACKOFF_MS";
+const JITTER_VARIABLE: &str = "IGNITION_RETRY_JITTER";
+
+/// Retry policy for transient failures (5xx, 408, 429, connection errors and timeouts).
+///
+/// Manifest format (all fields optional):
+/// ```json
+/// "retry": {
+///     "attempts": 3,
+///     "backoff_ms": 1000,
+///     "max_backoff_ms": 30000,
+///     "jitter": 0.2
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first (1 disables retries).
+    pub attempts: u32,
+    /// Delay before the first retry, doubled on each subsequent retry.
+    pub backoff_ms: u64,
+    /// Upper bound of the delay between attempts.
+    pub max_backoff_ms: u64,
+    /// Fraction (0.0 to 1.0) of the delay randomly added or subtracted.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            backoff_ms: 1000,
+            max_backoff_ms: 30000,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Resolve the retry policy: defaults, overridden by the manifest, overridden by environment variables.
+    pub fn resolve(manifest: Option<&RetryPolicy>) -> IgnitionResult<Self> {
+        let mut policy = manifest.cloned().unwrap_or_default();
+        if let Some(attempts) = env_value(ATTEMPTS_VARIABLE)? {
+            policy.attempts = attempts;
+        }
+        if let Some(backoff_ms) = env_value(BACKOFF_VARIABLE)? {
+            policy.backoff_ms = backoff_ms;
+        }
+        if let Some(max_backoff_ms) = env_value(MAX_BACKOFF_VARIABLE)? {
+            policy.max_backoff_ms = max_backoff_ms;
+        }
+        if let Some(jitter) = env_value(JITTER_VARIABLE)? {
+            policy.jitter = jitter;
+        }
+        if policy.attempts == 0 || !(0.0..=1.0).contains(&policy.jitter) {
+            return Err(IgnitionError::ConfigurationError(format!(
+                "retry attempts must be at least 1 and jitter within [0, 1]: {:?}",
+                policy
+            )));
+        }
+        Ok(policy)
+    }
+
+    /// Delay before retry number <retry> (starting at 1), with jitter applied.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(32);
+        let backoff = self
+            .backoff_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff_ms) as f64;
+        // uniformly distributed in [-1, 1]
+        let random =
+            RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64 * 2.0 - 1.0;
+        Duration::from_millis((backoff * (1.0 + self.jitter * random)).max(0.0) as u64)
+    }
+
+    /// Run <operation> until it succeeds, fails with a non-transient error, or attempts are exhausted.
+    pub fn run<T>(
+        &self,
+        description: &str,
+        mut operation: impl FnMut() -> IgnitionResult<T>,
+    ) -> IgnitionResult<T> {
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(err) if err.is_transient() && attempt < self.attempts => {
+                    let delay = self.delay(attempt);
+                    println!(
+                        "--retry: {} attempt {}/{} in {:.1}s ({})",
+                        description,
+                        attempt + 1,
+                        self.attempts,
+                        delay.as_secs_f64(),
+                        err
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// sbom.rs
+//
+// This module contains the SBOM fragment of retrieved assets (`IGNITION_SBOM`): name, version, origin url, digest
+// and license of every asset, as CycloneDX or SPDX JSON for downstream compliance tooling to merge.
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use serde_json::{Value, json};
+
+use super::{
+    checksum, env_value,
+    source::{unix_time, utc_datetime},
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const SBOM_VARIABLE: &str = "IGNITION_SBOM";
+const CYCLONEDX_FILE_NAME: &str = "ignition-sbom.cdx.json";
+const SPDX_FILE_NAME: &str = "ignition-sbom.spdx.json";
+const TOOL_NAME: &str = "ignition";
+const NOASSERTION: &str = "NOASSERTION";
+
+/// Components of all asset retrievals of this build.
+static COMPONENTS: Mutex<Vec<Component>> = Mutex::new(Vec::new());
+
+/// SBOM format (`IGNITION_SBOM`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON.
+    CycloneDx,
+    /// SPDX 2.3 JSON.
+    Spdx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = IgnitionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "cyclonedx" | "cdx" | "1" | "true" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(IgnitionError::ConfigurationError(format!(
+                "{}={}",
+                SBOM_VARIABLE, value
+            ))),
+        }
+    }
+}
+
+/// A retrieved asset, as an SBOM component.
+#[derive(Clone, Debug)]
+pub struct Component {
+    /// Asset name.
+    pub name: String,
+    /// Asset version, if versioned.
+    pub version: Option<String>,
+    /// Bucket path of the retrieved build (`<os>/<arch>[/<environment>][/<variant>]`).
+    pub platform: String,
+    /// Origin url of the asset archive.
+    pub url: String,
+    /// Lowercase hex SHA-256 digest of the asset archive.
+    pub sha256: String,
+    /// SPDX license expression, if declared in the manifest.
+    pub license: Option<String>,
+}
+
+/// Determine the SBOM format, None if disabled (default).
+pub fn format() -> IgnitionResult<Option<SbomFormat>> {
+    match env_value::<String>(SBOM_VARIABLE)? {
+        Some(value) if matches!(value.as_str(), "" | "0" | "false" | "none" | "off") => Ok(None),
+        Some(value) => value.parse().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Record <asset> (archive at <archive_path> from <url>) as a component of the SBOM, if enabled.
+pub fn record(
+    asset: &str,
+    version: Option<&str>,
+    platform: &str,
+    url: &str,
+    license: Option<&str>,
+    archive_path: &Path,
+) -> IgnitionResult<()> {
+    if format()?.is_none() {
+        return Ok(());
+    }
+    let component = Component {
+        name: asset.to_string(),
+        version: version.map(str::to_string),
+        platform: platform.to_string(),
+        url: url.to_string(),
+        sha256: checksum::sha256_file(archive_path)?,
+        license: license.map(str::to_string),
+    };
+    COMPONENTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(component);
+    Ok(())
+}
+
+/// Write the SBOM fragment to `<out_dir>/ignition-sbom.cdx.json` (CycloneDX) or
+/// `<out_dir>/ignition-sbom.spdx.json` (SPDX) if enabled (`IGNITION_SBOM=cyclonedx|spdx`).
+///
+/// Return is the path of the SBOM, None if disabled.
+pub fn write(out_dir: &Path) -> IgnitionResult<Option<PathBuf>> {
+    let Some(format) = format()? else {
+        return Ok(None);
+    };
+    let mut components = COMPONENTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    let (year, month, day, hour, minute, second) = utc_datetime(unix_time());
+    let timestamp = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    );
+    let (file_name, document) = match format {
+        SbomFormat::CycloneDx => (CYCLONEDX_FILE_NAME, cyclonedx(&components, &timestamp)),
+        SbomFormat::Spdx => (SPDX_FILE_NAME, spdx(&components, &timestamp)),
+    };
+    let path = out_dir.join(file_name);
+    fs::create_dir_all(out_dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&document)?)?;
+    Ok(Some(path))
+}
+
+/// CycloneDX 1.5 document of <components>.
+fn cyclonedx(components: &[Component], timestamp: &str) -> Value {
+    let components: Vec<Value> = components
+        .iter()
+        .map(|component| {
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": format!("{}:{}@{}", TOOL_NAME, component.name, component.platform),
+                "name": component.name,
+                "hashes": [{ "alg": "SHA-256", "content": component.sha256 }],
+                "externalReferences": [{ "type": "distribution", "url": component.url }],
+                "properties": [{ "name": "ignition:platform", "value": component.platform }],
+            });
+            if let Some(version) = &component.version {
+                value["version"] = json!(version);
+            }
+            if let Some(license) = &component.license {
+                value["licenses"] = json!([{ "expression": license }]);
+            }
+            value
+        })
+        .collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": TOOL_NAME,
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+    })
+}
+
+/// SPDX 2.3 document of <components>.
+fn spdx(components: &[Component], timestamp: &str) -> Value {
+    let packages: Vec<Value> = components
+        .iter()
+        .map(|component| {
+            let mut value = json!({
+                "name": component.name,
+                "SPDXID": format!("SPDXRef-Package-{}", spdx_id(&component.name)),
+                "downloadLocation": component.url,
+                "filesAnalyzed": false,
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": component.sha256 }],
+                "licenseConcluded": NOASSERTION,
+                "licenseDeclared": component.license.as_deref().unwrap_or(NOASSERTION),
+                "copyrightText": NOASSERTION,
+                "comment": format!("platform: {}", component.platform),
+            });
+            if let Some(version) = &component.version {
+                value["versionInfo"] = json!(version);
+            }
+            value
+        })
+        .collect();
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-assets", TOOL_NAME),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-assets-{}-{}",
+            TOOL_NAME,
+            unix_time(),
+            std::process::id()
+        ),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: {}-{}", TOOL_NAME, env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+    })
+}
+
+/// SPDX identifier of <name> (letters, digits, `.` and `-` only).
+fn spdx_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+// scan.rs
+//
+// This module contains the scan hook run on asset archives before extraction, so malware scanners can gate the
+// asset flow: an external command (`IGNITION_SCAN_COMMAND` or `scan_command` in the manifest) and/or a Rust
+// callback registered with `set_hook`.
+//
+
+use std::{
+    path::Path,
+    process::Command,
+    sync::{Arc, RwLock},
+};
+
+use super::env_value;
+use crate::{IgnitionError, IgnitionResult};
+
+const SCAN_COMMAND_VARIABLE: &str = "IGNITION_SCAN_COMMAND";
+const SCAN_ASSET_VARIABLE: &str = "IGNITION_SCAN_ASSET";
+const SCAN_URL_VARIABLE: &str = "IGNITION_SCAN_URL";
+
+/// Scan callback: called with the asset name, its origin url and the path of the archive, returning the reason
+/// of a rejection as error.
+pub type ScanHook = dyn Fn(&str, &str, &Path) -> Result<(), String> + Send + Sync;
+
+/// Scan callback registered with `set_hook`.
+static HOOK: RwLock<Option<Arc<ScanHook>>> = RwLock::new(None);
+
+/// Register <hook> to scan every asset archive before extraction (replacing a previously registered hook).
+pub fn set_hook(hook: impl Fn(&str, &str, &Path) -> Result<(), String> + Send + Sync + 'static) {
+    *HOOK.write().unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(hook));
+}
+
+/// Remove the registered scan hook.
+pub fn clear_hook() {
+    *HOOK.write().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Determine if archives are scanned, i.e. a hook is registered or a scan command is set (`IGNITION_SCAN_COMMAND`,
+/// otherwise <manifest_command>).
+pub fn is_configured(manifest_command: Option<&str>) -> IgnitionResult<bool> {
+    if HOOK.read().unwrap_or_else(|err| err.into_inner()).is_some() {
+        return Ok(true);
+    }
+    Ok(env_value::<String>(SCAN_COMMAND_VARIABLE)?
+        .or_else(|| manifest_command.map(str::to_string))
+        .is_some_and(|command_line| !command_line.trim().is_empty()))
+}
+
+/// Scan the archive of <asset> at <archive_path> downloaded from <url> with the registered hook and the scan
+/// command (`IGNITION_SCAN_COMMAND`, otherwise <manifest_command>), if any.
+///
+/// The command is split on whitespace and run with the archive path as last argument, and the asset name and
+/// url in `IGNITION_SCAN_ASSET` and `IGNITION_SCAN_URL`: a non-zero exit status rejects the archive.
+pub fn scan(
+    asset: &str,
+    url: &str,
+    archive_path: &Path,
+    manifest_command: Option<&str>,
+) -> IgnitionResult<()> {
+    let hook = HOOK.read().unwrap_or_else(|err| err.into_inner()).clone();
+    if let Some(hook) = hook {
+        hook(asset, url, archive_path).map_err(|reason| {
+            IgnitionError::ScanRejected(format!("{}: {}", archive_path.display(), reason))
+        })?;
+        println!("--scan passed: {} (hook)", archive_path.display());
+    }
+
+    let Some(command_line) = env_value::<String>(SCAN_COMMAND_VARIABLE)?
+        .or_else(|| manifest_command.map(str::to_string))
+        .filter(|command_line| !command_line.trim().is_empty())
+    else {
+        return Ok(());
+    };
+    let mut words = command_line.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let output = Command::new(program)
+        .args(words)
+        .arg(archive_path)
+        .env(SCAN_ASSET_VARIABLE, asset)
+        .env(SCAN_URL_VARIABLE, url)
+        .output()
+        .map_err(|err| {
+            IgnitionError::ScanRejected(format!("command '{}' failed: {}", program, err))
+        })?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(IgnitionError::ScanRejected(format!(
+            "{}: '{}' exited with {}: {}",
+            archive_path.display(),
+            command_line,
+            output.status,
+            [stdout.trim(), stderr.trim()]
+                .into_iter()
+                .filter(|output| !output.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        )));
+    }
+    println!("--scan passed: {} ({})", archive_path.display(), program);
+    Ok(())
+}
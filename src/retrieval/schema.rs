@@ -0,0 +1,430 @@
+// schema.rs
+//
+// This module contains the validation of the merged manifest against the schema of assets (`AssetEnvironment`):
+// unknown fields, values of the wrong type, contents without environment variables, unknown variants and url
+// template placeholders are reported together, each with the asset, field and line of the manifest it stems from and
+// a suggestion, rather than as the first opaque deserialization error.
+//
+
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+use serde::de::DeserializeOwned;
+
+use super::template;
+use crate::{
+    AssetEnvironment, AssetTargetEnvironment, AssetVariant, IgnitionError, IgnitionResult,
+};
+
+/// Source of a manifest merged into the manifest (e.g. the override manifest), for locating issues.
+#[derive(Clone, Debug)]
+pub struct ManifestSource {
+    /// Origin of the manifest, e.g. its path.
+    pub origin: String,
+    /// Text of the manifest, None if not read from a file (e.g. assets declared by a build script).
+    pub contents: Option<String>,
+}
+
+/// Issue of the manifest.
+struct Issue {
+    /// Path of the field (asset, then field, e.g. `opencv`, `targets`, `windows`, `contents`).
+    path: Vec<String>,
+    /// Value of the field the issue is about (e.g. a content of `contents`), locating it more precisely.
+    value: Option<String>,
+    /// Description of the issue, with a suggestion if any.
+    message: String,
+}
+
+impl Issue {
+    fn new(path: &[&str], message: String) -> Self {
+        Issue {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            value: None,
+            message,
+        }
+    }
+
+    /// Render the issue with the location of the field within the last of <sources> defining it (`<origin>:<line>`),
+    /// otherwise that of the asset.
+    fn render(&self, sources: &[ManifestSource]) -> String {
+        let mut keys: Vec<&str> = self.path.iter().map(String::as_str).collect();
+        keys.extend(self.value.as_deref());
+        let location = [&keys[..], &keys[..1]].into_iter().find_map(|keys| {
+            sources.iter().rev().find_map(|source| {
+                let line = line(source.contents.as_deref()?, keys)?;
+                Some(format!("{}:{}: ", source.origin, line))
+            })
+        });
+        format!(
+            "{}{}: {}",
+            location.unwrap_or_default(),
+            self.path.join("."),
+            self.message
+        )
+    }
+}
+
+/// Validate the merged <manifest> (an object of assets), the manifests of <sources> (in order of merge) locating
+/// the issues, see `Issue::render`:
+/// - fields of assets, of their `targets` sections and of their `variants` must be known, e.g. `sha256` rather than
+///   `sha265` (the closest field being suggested), and their values of the expected type;
+/// - every content of `contents` must have an environment variable in `environment`, for every target section and
+///   variant (whose `contents` replace, and `environment` add to, those of the asset);
+/// - the default `variant` must be one of `variants`, and the placeholders of url templates known (see
+///   `AssetLocation`).
+///
+/// Lines are located by searching for the keys of the field in order, so they are a best effort for manifests
+/// listing other assets with the same fields.
+pub fn validate(manifest: &serde_json::Value, sources: &[ManifestSource]) -> IgnitionResult<()> {
+    let mut issues = Vec::new();
+    if let Some(assets) = manifest.as_object() {
+        for (asset, entry) in assets {
+            check_fields::<AssetEnvironment>(entry, &[asset], &mut issues);
+            for (key, section) in entries(entry.get("targets")) {
+                check_fields::<AssetTargetEnvironment>(
+                    section,
+                    &[asset, "targets", key],
+                    &mut issues,
+                );
+            }
+            for (name, variant) in entries(entry.get("variants")) {
+                check_fields::<AssetVariant>(variant, &[asset, "variants", name], &mut issues);
+                for (key, section) in entries(variant.get("targets")) {
+                    check_fields::<AssetTargetEnvironment>(
+                        section,
+                        &[asset, "variants", name, "targets", key],
+                        &mut issues,
+                    );
+                }
+            }
+        }
+    }
+    // assets are only deserialized, and checked as a whole, once their fields are valid
+    if issues.is_empty() {
+        let assets: HashMap<String, AssetEnvironment> = serde_json::from_value(manifest.clone())?;
+        let mut assets: Vec<(&String, &AssetEnvironment)> = assets.iter().collect();
+        assets.sort_by_key(|(asset, _)| *asset);
+        for (asset, asset_cfg) in assets {
+            check_asset(asset, asset_cfg, &mut issues);
+        }
+    }
+    if issues.is_empty() {
+        return Ok(());
+    }
+    // issues of contents shared by several target sections or variants are reported once
+    let mut rendered: Vec<String> = Vec::new();
+    for issue in issues.iter().map(|issue| issue.render(sources)) {
+        if !rendered.contains(&issue) {
+            rendered.push(issue);
+        }
+    }
+    Err(IgnitionError::InvalidManifest(rendered.join("\n  ")))
+}
+
+/// Entries of the object <value> (e.g. the `targets` sections of an asset), sorted, none if not an object.
+fn entries(value: Option<&serde_json::Value>) -> Vec<(&str, &serde_json::Value)> {
+    let mut entries: Vec<(&str, &serde_json::Value)> = value
+        .and_then(serde_json::Value::as_object)
+        .map(|object| {
+            object
+                .iter()
+                .map(|(key, value)| (key.as_str(), value))
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
+
+/// Check the fields of the <section> at <path> (e.g. an asset) against those of <T>: unknown fields, and values
+/// that don't deserialize, each field being deserialized on its own (every field of <T> has a default).
+fn check_fields<T: DeserializeOwned>(
+    section: &serde_json::Value,
+    path: &[&str],
+    issues: &mut Vec<Issue>,
+) {
+    let Some(fields) = section.as_object() else {
+        issues.push(Issue::new(
+            path,
+            format!("expected an object of fields, found {}", kind(section)),
+        ));
+        return;
+    };
+    let known = field_names::<T>();
+    for (field, value) in fields {
+        let field_path: Vec<&str> = path.iter().copied().chain([field.as_str()]).collect();
+        if !known.contains(&field.as_str()) {
+            issues.push(Issue::new(
+                &field_path,
+                format!("unknown field{}", suggestion(field, known.iter().copied())),
+            ));
+            continue;
+        }
+        let single =
+            serde_json::Value::Object([(field.clone(), value.clone())].into_iter().collect());
+        if let Err(err) = serde_json::from_value::<T>(single) {
+            issues.push(Issue::new(&field_path, err.to_string()));
+        }
+    }
+}
+
+/// Check the <asset> as a whole: its contents and their environment variables (for each target section and
+/// variant), its default variant and its url templates.
+fn check_asset(asset: &str, asset_cfg: &AssetEnvironment, issues: &mut Vec<Issue>) {
+    let mut variants: Vec<(&String, &AssetVariant)> = asset_cfg.variants.iter().collect();
+    variants.sort_by_key(|(name, _)| *name);
+    check_environment(
+        &[asset],
+        (&[asset, "contents"], &asset_cfg.contents),
+        &asset_cfg.environment,
+        &asset_cfg.targets,
+        issues,
+    );
+    for (name, variant) in variants.iter() {
+        let mut environment = asset_cfg.environment.clone();
+        environment.extend(variant.environment.clone());
+        let mut targets: HashMap<String, &AssetTargetEnvironment> = asset_cfg
+            .targets
+            .iter()
+            .map(|(key, section)| (key.clone(), section))
+            .collect();
+        targets.extend(
+            variant
+                .targets
+                .iter()
+                .map(|(key, section)| (key.clone(), section)),
+        );
+        let contents = match variant.contents.as_ref() {
+            Some(contents) => (&[asset, "variants", name, "contents"][..], contents),
+            None => (&[asset, "contents"][..], &asset_cfg.contents),
+        };
+        check_environment(
+            &[asset, "variants", name],
+            contents,
+            &environment,
+            &targets,
+            issues,
+        );
+    }
+
+    if let Some(variant) = asset_cfg.variant.as_deref()
+        && !asset_cfg.variants.contains_key(variant)
+    {
+        let mut names: Vec<&str> = variants.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        issues.push(Issue::new(
+            &[asset, "variant"],
+            format!(
+                "variant '{}' is not one of variants ({}){}",
+                variant,
+                match names.is_empty() {
+                    true => "none".to_string(),
+                    false => names.join(", "),
+                },
+                suggestion(variant, names.iter().copied())
+            ),
+        ));
+    }
+
+    let url_templates = [(
+        vec![asset, "url_template"],
+        asset_cfg.url_template.as_deref(),
+    )]
+    .into_iter()
+    .chain(variants.iter().map(|(name, variant)| {
+        (
+            vec![asset, "variants", name.as_str(), "url_template"],
+            variant.url_template.as_deref(),
+        )
+    }));
+    for (path, url_template) in url_templates {
+        let Some(url_template) = url_template else {
+            continue;
+        };
+        let unknown = RefCell::new(None);
+        let expanded = template::expand(url_template, |name| {
+            if template::PLACEHOLDERS.contains(&name) {
+                return Some(String::new());
+            }
+            unknown.replace(Some(name.to_string()));
+            None
+        });
+        if let Err(err) = expanded {
+            issues.push(Issue::new(
+                &path,
+                match unknown.into_inner() {
+                    Some(name) => format!(
+                        "unknown placeholder {{{}}}{}",
+                        name,
+                        suggestion(&name, template::PLACEHOLDERS.iter().copied())
+                    ),
+                    None => err.to_string(),
+                },
+            ));
+        }
+    }
+}
+
+/// Check that every content of <contents> (with the path of its list) has an environment variable in
+/// <environment>, as resolved for the section at <path> (an asset or variant) and each of its <targets> sections.
+fn check_environment<T: std::borrow::Borrow<AssetTargetEnvironment>>(
+    path: &[&str],
+    contents: (&[&str], &Vec<String>),
+    environment: &HashMap<String, String>,
+    targets: &HashMap<String, T>,
+    issues: &mut Vec<Issue>,
+) {
+    let mut check = |contents_path: &[&str],
+                     contents: &Vec<String>,
+                     environment: &HashMap<String, String>| {
+        for content in contents {
+            if !environment.contains_key(content) {
+                let mut issue = Issue::new(
+                    contents_path,
+                    format!(
+                        "content '{}' listed in contents but missing from environment map (add \"{}\": \
+                         \"<VARIABLE>\" to environment)",
+                        content, content
+                    ),
+                );
+                issue.value = Some(content.clone());
+                issues.push(issue);
+            }
+        }
+    };
+    check(contents.0, contents.1, environment);
+    let mut keys: Vec<&String> = targets.keys().collect();
+    keys.sort();
+    for key in keys {
+        let section = targets[key].borrow();
+        let mut target_environment = environment.clone();
+        target_environment.extend(section.environment.clone());
+        match section.contents.as_ref() {
+            Some(target_contents) => {
+                let contents_path: Vec<&str> = path
+                    .iter()
+                    .copied()
+                    .chain(["targets", key.as_str(), "contents"])
+                    .collect();
+                check(&contents_path, target_contents, &target_environment);
+            }
+            None => check(contents.0, contents.1, &target_environment),
+        }
+    }
+}
+
+/// Suggestion of the closest of <candidates> to the unknown <name> (` (did you mean '<candidate>'?)`), empty if
+/// none is close.
+fn suggestion<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> String {
+    candidates
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= (name.chars().count() / 3).max(1))
+        .min()
+        .map(|(_, candidate)| format!(" (did you mean '{}'?)", candidate))
+        .unwrap_or_default()
+}
+
+/// Edit (Levenshtein) distance between <a> and <b>.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            current.push(
+                (previous[j] + usize::from(a != *b))
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Kind of the JSON <value>, for messages.
+fn kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Line (from 1) of the field at the path of <keys> within the <contents> of a manifest, each key being searched
+/// after the previous one (e.g. `"opencv": {` then `"contents": [`, `[opencv]` then `contents = [`, or `opencv:`
+/// then `contents:`), None if not found.
+fn line(contents: &str, keys: &[&str]) -> Option<usize> {
+    let mut offset = 0;
+    for key in keys {
+        offset = contents[offset..]
+            .match_indices(key)
+            .map(|(index, _)| offset + index)
+            .find(|&index| {
+                contents[..index].chars().next_back().is_none_or(|c| {
+                    c.is_whitespace() || matches!(c, '"' | '\'' | '[' | '{' | '.' | ',')
+                }) && contents[index + key.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| {
+                        c.is_whitespace() || matches!(c, '"' | '\'' | ']' | '.' | ':' | '=')
+                    })
+            })?
+            + key.len();
+    }
+    Some(contents[..offset].matches('\n').count() + 1)
+}
+
+/// Names of the fields of the struct <T> (e.g. `contents` for `AssetEnvironment`), as passed by its derived
+/// `Deserialize` implementation to the deserializer.
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    match T::deserialize(FieldNames) {
+        Err(FieldNamesError(fields)) => fields,
+        Ok(_) => &[],
+    }
+}
+
+/// Deserializer failing with the names of the fields of the struct deserialized, see `field_names`.
+struct FieldNames;
+
+/// Error of `FieldNames`, holding the names of the fields (none if not a struct).
+#[derive(Debug)]
+struct FieldNamesError(&'static [&'static str]);
+
+impl fmt::Display for FieldNamesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fields: {}", self.0.join(", "))
+    }
+}
+
+impl std::error::Error for FieldNamesError {}
+
+impl serde::de::Error for FieldNamesError {
+    fn custom<M: fmt::Display>(_: M) -> Self {
+        FieldNamesError(&[])
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for FieldNames {
+    type Error = FieldNamesError;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(FieldNamesError(&[]))
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(FieldNamesError(fields))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
@@ -0,0 +1,677 @@
+// signature.rs
+//
+// This module contains signature verification of downloaded asset archives before extraction: detached OpenPGP
+// signatures (using the `gpg` client), minisign (ed25519) signatures, and sigstore signatures (using the `cosign`
+// client). Trusted keys may be limited to a validity window, so publishers can rotate keys.
+//
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use blake2::{Blake2b512, Digest};
+use ring::signature::{ED25519, UnparsedPublicKey};
+use serde::Deserialize;
+
+use super::{
+    download::{self, sidecar_path},
+    source::{self, oci::ArtifactReference, parse_utc_datetime, scheme, unix_time, utc_datetime},
+};
+use crate::{AssetEnvironment, IgnitionError, IgnitionResult};
+
+const TRUSTED_KEYS_VARIABLE: &str = "IGNITION_TRUSTED_KEYS";
+const GPG_SIGNATURE_EXTENSION: &str = ".asc";
+const ARMORED_KEY_PREFIX: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+const MINISIGN_SIGNATURE_EXTENSION: &str = ".minisig";
+const TRUSTED_COMMENT_PREFIX: &str = "trusted comment: ";
+/// Prefix of the signature time (seconds since the unix epoch) in minisign trusted comments.
+const TIMESTAMP_PREFIX: &str = "timestamp:";
+/// Tolerated clock difference (in seconds) with the signer for signature times later than the current time.
+const CLOCK_SKEW: u64 = 5 * 60;
+const SIGSTORE_BUNDLE_EXTENSION: &str = ".sigstore.json";
+
+/// Sigstore (keyless cosign) verification policy of an asset: the identity of the signing certificate issued by
+/// Fulcio, for example:
+///
+/// ```json
+/// "sigstore": {
+///     "identity_regexp": "^https://github.com/org/repo/\\.github/workflows/",
+///     "issuer": "https://token.actions.githubusercontent.com"
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct SigstorePolicy {
+    /// Expected certificate identity (e.g. an email address or workflow url).
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// Regular expression matching the certificate identity, instead of `identity`.
+    #[serde(default)]
+    pub identity_regexp: Option<String>,
+    /// Expected OIDC issuer of the certificate.
+    pub issuer: String,
+}
+
+/// Trusted public key, optionally only for signatures made within a validity window (UTC `YYYY-MM-DD` or
+/// `YYYY-MM-DDTHH:MM:SSZ`, inclusive), so a publisher can rotate keys with the current and previous keys both
+/// configured, for example:
+///
+/// ```json
+/// "trusted_keys": [
+///     { "key": "path/to/publisher-2025.asc", "not_after": "2025-12-31T23:59:59Z" },
+///     { "key": "path/to/publisher-2026.asc", "not_before": "2026-01-01" }
+/// ]
+/// ```
+///
+/// The signing time is the one recorded in the signature, which the signer controls: a window bounds the signatures
+/// a key is trusted for, not what a compromised key can sign (it can backdate signatures into its window). Only
+/// signatures claiming a time later than the verification time are rejected regardless, so a retired key must be
+/// removed, not merely bounded, once compromised.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TrustedKey {
+    /// Key trusted for signatures made at any time.
+    Key(String),
+    /// Key trusted for signatures made within a validity window.
+    Window {
+        key: String,
+        #[serde(default)]
+        not_before: Option<String>,
+        #[serde(default)]
+        not_after: Option<String>,
+    },
+}
+
+impl TrustedKey {
+    /// Public key (ASCII-armored OpenPGP key or key file path, or minisign public key).
+    pub fn key(&self) -> &str {
+        match self {
+            TrustedKey::Key(key) | TrustedKey::Window { key, .. } => key,
+        }
+    }
+
+    /// Determine if the key has a validity window.
+    fn has_window(&self) -> bool {
+        matches!(self, TrustedKey::Window { not_before, not_after, .. } if not_before.is_some() || not_after.is_some())
+    }
+
+    /// Determine if the key is trusted for signatures made at <signed_at> (seconds since the unix epoch).
+    ///
+    /// <signed_at> is the signer-controlled time recorded in the signature, so it is only checked against the window
+    /// and against the current time (give or take `CLOCK_SKEW`): a compromised key can still backdate signatures
+    /// within its window.
+    fn is_valid_at(&self, signed_at: u64) -> IgnitionResult<bool> {
+        let TrustedKey::Window {
+            not_before,
+            not_after,
+            ..
+        } = self
+        else {
+            return Ok(true);
+        };
+        if signed_at > unix_time().saturating_add(CLOCK_SKEW) {
+            return Ok(false);
+        }
+        let bound = |value: &String| {
+            parse_utc_datetime(value).ok_or_else(|| {
+                IgnitionError::ConfigurationError(format!(
+                    "invalid trusted key validity date: {}",
+                    value
+                ))
+            })
+        };
+        Ok(not_before
+            .as_ref()
+            .map(bound)
+            .transpose()?
+            .is_none_or(|not_before| signed_at >= not_before)
+            && not_after
+                .as_ref()
+                .map(bound)
+                .transpose()?
+                .is_none_or(|not_after| signed_at <= not_after))
+    }
+}
+
+/// Trusted OpenPGP public keys for <asset_cfg>: the manifest's `trusted_keys` (ASCII-armored keys or key file
+/// paths, see `TrustedKey`), plus the key files listed in `IGNITION_TRUSTED_KEYS` (separated like `PATH`).
+pub fn trusted_keys(asset_cfg: &AssetEnvironment) -> Vec<TrustedKey> {
+    let mut keys = asset_cfg.trusted_keys.clone();
+    if let Some(paths) = std::env::var_os(TRUSTED_KEYS_VARIABLE) {
+        keys.extend(
+            std::env::split_paths(&paths)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| TrustedKey::Key(path.display().to_string())),
+        );
+    }
+    keys
+}
+
+/// Trusted minisign public keys for <asset_cfg>: the manifest's `minisign_key`, plus its `minisign_keys` (see
+/// `TrustedKey`).
+pub fn minisign_keys(asset_cfg: &AssetEnvironment) -> Vec<TrustedKey> {
+    asset_cfg
+        .minisign_key
+        .iter()
+        .cloned()
+        .map(TrustedKey::Key)
+        .chain(asset_cfg.minisign_keys.iter().cloned())
+        .collect()
+}
+
+/// Verify the archive at <archive_path> downloaded from <url> against its detached signature (`<url>.asc`),
+/// made by one of the trusted <keys> (within its validity window).
+///
+/// The signature is kept next to the cached archive, and only fetched again with <fetch> (i.e. a fresh download)
+/// or if absent.
+pub fn verify_gpg(
+    url: &str,
+    archive_path: &Path,
+    keys: &[TrustedKey],
+    fetch: bool,
+) -> IgnitionResult<()> {
+    let signature_path = sidecar_path(archive_path, GPG_SIGNATURE_EXTENSION);
+    if fetch || !signature_path.exists() {
+        download::download(
+            &source::suffixed_url(url, GPG_SIGNATURE_EXTENSION),
+            &signature_path,
+        )
+        .map_err(|err| IgnitionError::SignatureError(format!("no signature: {}", err)))?;
+    }
+    let home = temporary_home()?;
+    let result = gpg_verify(&home, &signature_path, archive_path, keys);
+    fs::remove_dir_all(&home).ok();
+    result
+}
+
+/// Verify the archive at <archive_path> downloaded from <url> against its minisign signature (`<url>.minisig`),
+/// made by the secret key of one of the <public_keys> (the base64 key line, optionally preceded by its untrusted
+/// comment as in `minisign.pub`), selected by key id, within its validity window.
+///
+/// Both legacy (`Ed`) and prehashed (`ED`, BLAKE2b-512) signatures are supported. The signature time is the
+/// `timestamp` of the trusted comment, or the current time without one. The signature is kept next to the cached
+/// archive, and only fetched again with <fetch> (i.e. a fresh download) or if absent.
+pub fn verify_minisign(
+    url: &str,
+    archive_path: &Path,
+    public_keys: &[TrustedKey],
+    fetch: bool,
+) -> IgnitionResult<()> {
+    let signature_path = fetch_signature(url, archive_path, MINISIGN_SIGNATURE_EXTENSION, fetch)?;
+    let invalid = |reason: &str| {
+        IgnitionError::SignatureError(format!("{}: {}", signature_path.display(), reason))
+    };
+
+    // public key: <"Ed"><key id (8)><ed25519 public key (32)>
+    let public_keys = public_keys
+        .iter()
+        .map(|trusted_key| {
+            let public_key = trusted_key.key();
+            decode_line(public_key.lines().last().unwrap_or_default())
+                .filter(|key| key.len() == 42 && key.starts_with(b"Ed"))
+                .map(|key| (trusted_key, key))
+                .ok_or_else(|| {
+                    IgnitionError::ConfigurationError(format!(
+                        "invalid minisign public key: {}",
+                        public_key
+                    ))
+                })
+        })
+        .collect::<IgnitionResult<Vec<_>>>()?;
+
+    // signature file: untrusted comment, <algorithm (2)><key id (8)><signature (64)>, trusted comment, global
+    // signature (64) of signature and trusted comment
+    let contents = fs::read_to_string(&signature_path)?;
+    let mut lines = contents.lines().skip(1);
+    let signature = lines
+        .next()
+        .and_then(decode_line)
+        .filter(|signature| signature.len() == 74)
+        .ok_or_else(|| invalid("malformed signature"))?;
+    let trusted_comment = lines
+        .next()
+        .and_then(|line| line.strip_prefix(TRUSTED_COMMENT_PREFIX))
+        .ok_or_else(|| invalid("malformed trusted comment"))?;
+    let global_signature = lines
+        .next()
+        .and_then(decode_line)
+        .ok_or_else(|| invalid("malformed global signature"))?;
+    let (algorithm, signature) = signature.split_at(2);
+    let (signature_key_id, signature) = signature.split_at(8);
+    let Some((trusted_key, public_key)) = public_keys
+        .iter()
+        .find(|(_, public_key)| &public_key[2..10] == signature_key_id)
+    else {
+        return Err(invalid(&format!(
+            "signed by key {}, expected key {}",
+            key_id_hex(signature_key_id),
+            public_keys
+                .iter()
+                .map(|(_, public_key)| key_id_hex(&public_key[2..10]))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        )));
+    };
+    let (key_id, public_key) = public_key[2..].split_at(8);
+
+    let public_key = UnparsedPublicKey::new(&ED25519, public_key);
+    let verified = match algorithm {
+        b"Ed" => public_key.verify(&fs::read(archive_path)?, signature),
+        b"ED" => {
+            let mut hasher = Blake2b512::new();
+            io::copy(&mut File::open(archive_path)?, &mut hasher)?;
+            public_key.verify(&hasher.finalize(), signature)
+        }
+        _ => return Err(invalid("unsupported signature algorithm")),
+    };
+    verified.map_err(|_| invalid("invalid signature"))?;
+    public_key
+        .verify(
+            &[signature, trusted_comment.as_bytes()].concat(),
+            &global_signature,
+        )
+        .map_err(|_| invalid("invalid trusted comment signature"))?;
+    let signed_at = trusted_comment
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix(TIMESTAMP_PREFIX)?.parse().ok())
+        .unwrap_or_else(unix_time);
+    if !trusted_key.is_valid_at(signed_at)? {
+        return Err(invalid(&format!(
+            "signed by key {} at {}, outside of its validity window",
+            key_id_hex(key_id),
+            format_time(signed_at)
+        )));
+    }
+    println!(
+        "--signature verified: {} (minisign key {}, {})",
+        archive_path.display(),
+        key_id_hex(key_id),
+        trusted_comment
+    );
+    Ok(())
+}
+
+/// Verify the archive at <archive_path> downloaded from <url> against its sigstore signature, made by a signing
+/// certificate matching <policy>, using `cosign` (which checks the certificate chain to Fulcio and the
+/// transparency log entry in Rekor).
+///
+/// For `oci://` urls, the signature of the artifact manifest in the registry is verified (pin the artifact by
+/// digest to tie it to the downloaded archive), otherwise the sigstore bundle published next to the archive
+/// (`<url>.sigstore.json`). The bundle is kept next to the cached archive, and only fetched again with <fetch>
+/// (i.e. a fresh download) or if absent.
+pub fn verify_sigstore(
+    url: &str,
+    archive_path: &Path,
+    policy: &SigstorePolicy,
+    fetch: bool,
+) -> IgnitionResult<()> {
+    let mut args: Vec<std::ffi::OsString> = Vec::new();
+    let subject = if scheme(url) == "oci" {
+        let artifact = ArtifactReference::parse(url)?;
+        args.push("verify".into());
+        if artifact.plain_http()? {
+            args.push("--allow-http-registry".into());
+        }
+        artifact.image()
+    } else {
+        let bundle_path = fetch_signature(url, archive_path, SIGSTORE_BUNDLE_EXTENSION, fetch)?;
+        args.extend(["verify-blob".into(), "--bundle".into(), bundle_path.into()]);
+        archive_path.display().to_string()
+    };
+    match (&policy.identity, &policy.identity_regexp) {
+        (Some(identity), _) => args.extend(["--certificate-identity".into(), identity.into()]),
+        (None, Some(regexp)) => {
+            args.extend(["--certificate-identity-regexp".into(), regexp.into()])
+        }
+        (None, None) => {
+            return Err(IgnitionError::ConfigurationError(
+                "sigstore policy without identity or identity_regexp".to_string(),
+            ));
+        }
+    }
+    args.extend([
+        "--certificate-oidc-issuer".into(),
+        policy.issuer.as_str().into(),
+        subject.as_str().into(),
+    ]);
+
+    let output = Command::new("cosign").args(&args).output().map_err(|err| {
+        IgnitionError::SignatureError(format!("command 'cosign' failed: {}", err))
+    })?;
+    if !output.status.success() {
+        return Err(IgnitionError::SignatureError(format!(
+            "{}: {}",
+            subject,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    println!(
+        "--signature verified: {} (sigstore {})",
+        subject,
+        policy
+            .identity
+            .as_deref()
+            .or(policy.identity_regexp.as_deref())
+            .unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Fetch the signature of the archive at <archive_path> from <url> with the signature <extension> into a
+/// sidecar file, if absent or <fetch>.
+///
+/// Return is the path of the signature.
+fn fetch_signature(
+    url: &str,
+    archive_path: &Path,
+    extension: &str,
+    fetch: bool,
+) -> IgnitionResult<PathBuf> {
+    let signature_path = sidecar_path(archive_path, extension);
+    if fetch || !signature_path.exists() {
+        download::download(&source::suffixed_url(url, extension), &signature_path)
+            .map_err(|err| IgnitionError::SignatureError(format!("no signature: {}", err)))?;
+    }
+    Ok(signature_path)
+}
+
+/// Decode a base64 <line> of a minisign key or signature file.
+fn decode_line(line: &str) -> Option<Vec<u8>> {
+    BASE64_STANDARD.decode(line.trim()).ok()
+}
+
+/// Minisign key id as displayed by `minisign` (little-endian, uppercase hex).
+fn key_id_hex(key_id: &[u8]) -> String {
+    key_id
+        .iter()
+        .rev()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+/// Import <keys> into the gpg <home> directory and verify <signature_path> of <data_path>, made by a key within
+/// its validity window.
+fn gpg_verify(
+    home: &Path,
+    signature_path: &Path,
+    data_path: &Path,
+    keys: &[TrustedKey],
+) -> IgnitionResult<()> {
+    let mut key_paths = Vec::new();
+    for (index, key) in keys.iter().map(TrustedKey::key).enumerate() {
+        let key_path = if key.trim_start().starts_with(ARMORED_KEY_PREFIX) {
+            let key_path = home.join(format!("key-{}.asc", index));
+            fs::write(&key_path, key)?;
+            key_path
+        } else {
+            PathBuf::from(key)
+        };
+        gpg(home, &["--import".as_ref(), key_path.as_os_str()]).map_err(|err| {
+            IgnitionError::SignatureError(format!("{}: {}", key_path.display(), err))
+        })?;
+        key_paths.push(key_path);
+    }
+    let status = gpg(
+        home,
+        &[
+            "--status-fd".as_ref(),
+            "1".as_ref(),
+            "--verify".as_ref(),
+            signature_path.as_os_str(),
+            data_path.as_os_str(),
+        ],
+    )
+    .map_err(|err| IgnitionError::SignatureError(format!("{}: {}", data_path.display(), err)))?;
+    // a good signature of an imported (i.e. trusted) key, regardless of the web of trust
+    match status
+        .lines()
+        .find_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+    {
+        Some(validsig) => {
+            // <fingerprint> <creation date> <creation timestamp> ... <primary key fingerprint>
+            let fields: Vec<&str> = validsig.split_whitespace().collect();
+            let fingerprint = fields.first().copied().unwrap_or_default();
+            if keys.iter().any(TrustedKey::has_window) {
+                let signed_at = fields
+                    .get(2)
+                    .and_then(|timestamp| timestamp.parse().ok())
+                    .or_else(|| fields.get(1).and_then(|date| parse_utc_datetime(date)))
+                    .unwrap_or_else(unix_time);
+                let mut valid = false;
+                for (key, key_path) in keys.iter().zip(&key_paths) {
+                    let fingerprints = gpg(
+                        home,
+                        &[
+                            "--with-colons".as_ref(),
+                            "--import-options".as_ref(),
+                            "show-only".as_ref(),
+                            "--import".as_ref(),
+                            key_path.as_os_str(),
+                        ],
+                    )
+                    .map_err(|err| {
+                        IgnitionError::SignatureError(format!("{}: {}", key_path.display(), err))
+                    })?;
+                    // fingerprint records: fpr:::::::::<fingerprint>:
+                    if fingerprints
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("fpr:"))
+                        .any(|line| line.split(':').nth(8) == Some(fingerprint))
+                        && key.is_valid_at(signed_at)?
+                    {
+                        valid = true;
+                        break;
+                    }
+                }
+                if !valid {
+                    return Err(IgnitionError::SignatureError(format!(
+                        "{}: signed by key {} at {}, outside of its validity window",
+                        data_path.display(),
+                        fingerprint,
+                        format_time(signed_at)
+                    )));
+                }
+            }
+            println!(
+                "--signature verified: {} ({})",
+                data_path.display(),
+                fingerprint
+            );
+            Ok(())
+        }
+        None => Err(IgnitionError::SignatureError(format!(
+            "{}: no valid signature",
+            data_path.display()
+        ))),
+    }
+}
+
+/// UTC time of <unix_time> (`YYYY-MM-DDTHH:MM:SSZ`).
+fn format_time(unix_time: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc_datetime(unix_time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Run gpg with <args> in the <home> directory, returning its stdout.
+fn gpg(home: &Path, args: &[&std::ffi::OsStr]) -> Result<String, String> {
+    let output = Command::new("gpg")
+        .args(["--batch", "--no-tty", "--quiet", "--homedir"])
+        .arg(home)
+        .args(args)
+        .output()
+        .map_err(|err| format!("command 'gpg' failed: {}", err))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Create an empty gpg home directory, so verification only trusts the configured keys.
+fn temporary_home() -> IgnitionResult<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    let home = std::env::temp_dir().join(format!("ignition-gpg-{}-{}", std::process::id(), nanos));
+    fs::create_dir_all(&home)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&home, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(home)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use blake2::{Blake2b512, Digest};
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    use super::{MINISIGN_SIGNATURE_EXTENSION, TrustedKey, verify_minisign};
+    use crate::retrieval::{download::sidecar_path, source::unix_time, test_directory};
+
+    const URL: &str = "https://example.com/asset.tar.gz";
+
+    /// Minisign secret key for tests: key pair and key id.
+    struct SecretKey(Ed25519KeyPair, [u8; 8]);
+
+    impl SecretKey {
+        fn generate(key_id: [u8; 8]) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            SecretKey(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap(), key_id)
+        }
+
+        /// Public key, as in `minisign.pub`.
+        fn public_key(&self) -> String {
+            let key = [b"Ed", &self.1[..], self.0.public_key().as_ref()].concat();
+            format!(
+                "untrusted comment: minisign public key\n{}",
+                BASE64_STANDARD.encode(key)
+            )
+        }
+
+        /// Sign the archive at <archive_path> (prehashed if <prehashed>) with <trusted_comment>, writing the
+        /// signature next to it.
+        fn sign(&self, archive_path: &Path, prehashed: bool, trusted_comment: &str) {
+            let contents = fs::read(archive_path).unwrap();
+            let (algorithm, signature) = match prehashed {
+                true => (b"ED", self.0.sign(&Blake2b512::digest(&contents))),
+                false => (b"Ed", self.0.sign(&contents)),
+            };
+            let global_signature = self
+                .0
+                .sign(&[signature.as_ref(), trusted_comment.as_bytes()].concat());
+            fs::write(
+                sidecar_path(archive_path, MINISIGN_SIGNATURE_EXTENSION),
+                format!(
+                    "untrusted comment: signature\n{}\ntrusted comment: {}\n{}\n",
+                    BASE64_STANDARD.encode([&algorithm[..], &self.1, signature.as_ref()].concat()),
+                    trusted_comment,
+                    BASE64_STANDARD.encode(global_signature)
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    /// Archive of the test <name>, in its scratch directory.
+    fn archive(name: &str) -> PathBuf {
+        let archive_path = test_directory(name).join("asset.tar.gz");
+        fs::write(&archive_path, "archive").unwrap();
+        archive_path
+    }
+
+    #[test]
+    fn minisign_signatures_verify() {
+        let archive_path = archive("minisign-verify");
+        let secret_key = SecretKey::generate(*b"12345678");
+        let keys = [TrustedKey::Key(secret_key.public_key())];
+        for prehashed in [false, true] {
+            secret_key.sign(
+                &archive_path,
+                prehashed,
+                "timestamp:1700000000\tfile:asset.tar.gz",
+            );
+            verify_minisign(URL, &archive_path, &keys, false).unwrap();
+        }
+        // the key is selected by id
+        let other_key = SecretKey::generate(*b"87654321");
+        let keys = [
+            TrustedKey::Key(other_key.public_key()),
+            TrustedKey::Key(secret_key.public_key()),
+        ];
+        verify_minisign(URL, &archive_path, &keys, false).unwrap();
+    }
+
+    #[test]
+    fn minisign_rejects_invalid_signatures() {
+        let archive_path = archive("minisign-invalid");
+        let secret_key = SecretKey::generate(*b"12345678");
+        let keys = [TrustedKey::Key(secret_key.public_key())];
+        secret_key.sign(&archive_path, true, "timestamp:1700000000");
+
+        // tampered archive
+        fs::write(&archive_path, "tampered").unwrap();
+        assert!(verify_minisign(URL, &archive_path, &keys, false).is_err());
+        fs::write(&archive_path, "archive").unwrap();
+        verify_minisign(URL, &archive_path, &keys, false).unwrap();
+
+        // tampered trusted comment
+        let signature_path = sidecar_path(&archive_path, MINISIGN_SIGNATURE_EXTENSION);
+        let signature = fs::read_to_string(&signature_path).unwrap();
+        fs::write(
+            &signature_path,
+            signature.replace("1700000000", "1600000000"),
+        )
+        .unwrap();
+        assert!(verify_minisign(URL, &archive_path, &keys, false).is_err());
+
+        // signed by another key (with the same key id)
+        SecretKey::generate(*b"12345678").sign(&archive_path, true, "timestamp:1700000000");
+        assert!(verify_minisign(URL, &archive_path, &keys, false).is_err());
+        // signed by an unknown key
+        SecretKey::generate(*b"87654321").sign(&archive_path, true, "timestamp:1700000000");
+        assert!(verify_minisign(URL, &archive_path, &keys, false).is_err());
+    }
+
+    #[test]
+    fn minisign_key_validity_windows() {
+        let archive_path = archive("minisign-window");
+        let secret_key = SecretKey::generate(*b"12345678");
+        let window = |not_before: Option<&str>, not_after: Option<&str>| {
+            [TrustedKey::Window {
+                key: secret_key.public_key(),
+                not_before: not_before.map(str::to_string),
+                not_after: not_after.map(str::to_string),
+            }]
+        };
+        // 2023-11-14T22:13:20Z
+        secret_key.sign(&archive_path, true, "timestamp:1700000000");
+        let retired = window(None, Some("2023-12-31"));
+        verify_minisign(URL, &archive_path, &retired, false).unwrap();
+        let rotated = window(Some("2024-01-01"), None);
+        assert!(verify_minisign(URL, &archive_path, &rotated, false).is_err());
+        let bounded = window(Some("2023-11-14T00:00:00Z"), Some("2023-11-14T22:13:20Z"));
+        verify_minisign(URL, &archive_path, &bounded, false).unwrap();
+
+        // signing times later than the current time are rejected
+        let future = unix_time() + 24 * 60 * 60;
+        secret_key.sign(&archive_path, true, &format!("timestamp:{}", future));
+        assert!(verify_minisign(URL, &archive_path, &rotated, false).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// source.rs
+//
+// This module resolves asset urls of any supported scheme (https, s3, gs, az, oci, hf) into plain HTTP(S) requests,
+// except local (file://, absolute paths), sftp:// and delta-transfer (rsync://, zsync+http(s)://) sources which
+// are copied directly.
+//
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::IgnitionResult;
+
+pub mod authenticated;
+pub mod azure;
+pub mod delta;
+pub mod gcs;
+pub mod helper;
+pub mod huggingface;
+pub mod local;
+pub mod oci;
+pub mod s3;
+pub mod sftp;
+pub mod torrent;
+
+/// An HTTP(S) request for an asset archive file, with any authentication headers.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    /// HTTP(S) url of the asset archive file.
+    pub url: String,
+    /// Additional request headers (e.g. authorization).
+    pub headers: Vec<(String, String)>,
+}
+
+/// Resolve an asset url to an HTTP(S) request, dispatching on the url scheme.
+///
+/// Supported schemes:
+/// ```text
+/// http://, https://   request with API-key, bearer or basic authentication of the host (if configured)
+/// s3://<bucket>/<key> SigV4-signed request to the S3 (compatible) endpoint
+/// gs://<bucket>/<key> OAuth2-authorized request to Google Cloud Storage
+/// az://<account>/<container>/<blob>
+///                     SAS or managed-identity authorized request to Azure Blob Storage
+/// oci://<registry>/<repository>[:<tag>|@<digest>][/<file>]
+///                     token-authorized request for the layer blob of an OCI artifact
+/// hf://<org>/<repo>[@<revision>]/<path>
+///                     token-authorized request for a file of a Hugging Face Hub repository
+/// ```
+pub fn http_request(url: &str) -> IgnitionResult<HttpRequest> {
+    match scheme(url) {
+        "s3" => s3::request(url),
+        "gs" => gcs::request(url),
+        "az" => azure::request(url),
+        "oci" => oci::request(url),
+        "hf" => huggingface::request(url),
+        _ => authenticated::request(url),
+    }
+}
+
+/// Scheme of <url>, empty if not present.
+pub fn scheme(url: &str) -> &str {
+    url.split_once("://").map_or("", |(scheme, _)| scheme)
+}
+
+/// Split a `<scheme>://<container>/<path>` url into its container (bucket) and path (key).
+pub fn split_url(url: &str) -> (&str, &str) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split_once('/').unwrap_or((rest, ""))
+}
+
+/// <url> with <suffix> appended to its path (e.g. a `.asc` signature of an archive), before its query and fragment
+/// (e.g. the parameters of a presigned url).
+pub fn suffixed_url(url: &str, suffix: &str) -> String {
+    let (base, rest) = url
+        .find(['?', '#'])
+        .map_or((url, ""), |index| url.split_at(index));
+    format!("{}{}{}", base, suffix, rest)
+}
+
+/// Last path segment (file name) of <url>, and <url> with that segment replaced by <file_name>, keeping its query
+/// and fragment (e.g. the parameters of a presigned url). None if <url> has no path.
+pub fn sibling_url<'a>(url: &'a str, file_name: &str) -> Option<(&'a str, String)> {
+    let (base, suffix) = url
+        .find(['?', '#'])
+        .map_or((url, ""), |index| url.split_at(index));
+    let path_start = base.find("://").map_or(0, |index| index + 3);
+    let slash = path_start + base[path_start..].rfind('/')?;
+    Some((
+        &base[slash + 1..],
+        format!("{}{}{}", &base[..=slash], file_name, suffix),
+    ))
+}
+
+/// Percent-encode <value> per RFC 3986, leaving unreserved characters (and optionally '/') as is.
+pub fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Home directory of the current user.
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Lowercase hexadecimal encoding of <bytes>.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// UTC calendar date and time of <unix_time>: (year, month, day, hour, minute, second).
+pub fn utc_datetime(unix_time: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = (unix_time / 86400) as i64;
+    let seconds = (unix_time % 86400) as u32;
+    // civil-from-days (H. Hinnant)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+/// Seconds since the unix epoch of the UTC date <value> (`YYYY-MM-DD`, optionally followed by `THH:MM:SSZ`), None
+/// if invalid.
+pub fn parse_utc_datetime(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let seconds = match time {
+        Some(time) => {
+            let time = time.strip_suffix('Z').unwrap_or(time);
+            let mut time = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+            let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+            if hour > 23 || minute > 59 || second > 60 {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+    // days-from-civil (H. Hinnant)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+    Some(days * 86400 + seconds)
+}
@@ -0,0 +1,206 @@
+// authenticated.rs
+//
+// This module contains the generic authenticated HTTP(S) source (Artifactory, Nexus, ...): API-key, bearer or
+// basic authentication and custom headers, keyed by host, or credentials of a per-host credential helper.
+//
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+
+use super::{HttpRequest, helper, home_dir, split_url};
+use crate::{IgnitionError, IgnitionResult};
+
+const CREDENTIALS_FILE_VARIABLE: &str = "IGNITION_CREDENTIALS_FILE";
+const API_KEY_VARIABLE: &str = "IGNITION_HTTP_API_KEY";
+const TOKEN_VARIABLE: &str = "IGNITION_HTTP_TOKEN";
+const USERNAME_VARIABLE: &str = "IGNITION_HTTP_USERNAME";
+const PASSWORD_VARIABLE: &str = "IGNITION_HTTP_PASSWORD";
+const HEADERS_VARIABLE: &str = "IGNITION_HTTP_HEADERS";
+const ANY_HOST: &str = "*";
+const DEFAULT_API_KEY_HEADER: &str = "X-JFrog-Art-Api";
+
+/// Credentials for a particular host.
+///
+/// Credentials file format (`IGNITION_CREDENTIALS_FILE`, default `~/.ignition/credentials.json`):
+/// ```json
+/// {
+///     "artifactory.example.com": {
+///         "api_key": "...",
+///         "api_key_header": "X-JFrog-Art-Api"
+///     },
+///     "nexus.example.com": {
+///         "username": "...",
+///         "password": "..."
+///     },
+///     "cdn.example.com": {
+///         "headers": {
+///             "X-CDN-Token": "..."
+///         }
+///     },
+///     "registry.example.com": {
+///         "helper": "docker-credential-pass"
+///     },
+///     "my-bucket": {
+///         "aws_profile": "assets"
+///     }
+/// }
+/// ```
+///
+/// The "*" entry applies to all hosts, with fields of the host's own entry taking precedence.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HostCredentials {
+    /// API key, sent in the <api_key_header> header.
+    pub api_key: Option<String>,
+    /// Header name of the API key (default `X-JFrog-Art-Api`).
+    pub api_key_header: Option<String>,
+    /// Bearer token, sent in the `Authorization` header.
+    pub token: Option<String>,
+    /// Basic authentication username.
+    pub username: Option<String>,
+    /// Basic authentication password.
+    pub password: Option<String>,
+    /// Additional request headers.
+    pub headers: HashMap<String, String>,
+    /// Credential helper command (see `helper::get`), used if no api key, token or username is set.
+    pub helper: Option<String>,
+    /// AWS profile of an S3 bucket (keyed by bucket name).
+    pub aws_profile: Option<String>,
+}
+
+impl HostCredentials {
+    /// Fill the fields missing from these credentials with <defaults>.
+    pub fn or(self, defaults: HostCredentials) -> Self {
+        let mut headers = defaults.headers;
+        headers.extend(self.headers);
+        HostCredentials {
+            api_key: self.api_key.or(defaults.api_key),
+            api_key_header: self.api_key_header.or(defaults.api_key_header),
+            token: self.token.or(defaults.token),
+            username: self.username.or(defaults.username),
+            password: self.password.or(defaults.password),
+            headers,
+            helper: self.helper.or(defaults.helper),
+            aws_profile: self.aws_profile.or(defaults.aws_profile),
+        }
+    }
+
+    /// Authentication and custom headers for these credentials.
+    pub fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(api_key) = self.api_key.as_ref() {
+            headers.push((
+                self.api_key_header
+                    .clone()
+                    .unwrap_or(DEFAULT_API_KEY_HEADER.to_string()),
+                api_key.to_string(),
+            ));
+        }
+        if let Some(token) = self.token.as_ref() {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        } else if let Some(username) = self.username.as_ref() {
+            let password = self.password.as_deref().unwrap_or_default();
+            headers.push((
+                "Authorization".to_string(),
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", username, password))
+                ),
+            ));
+        }
+        for (name, value) in self.headers.iter() {
+            headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+            headers.push((name.to_string(), value.to_string()));
+        }
+        headers
+    }
+}
+
+/// Resolve an HTTP(S) url to a request authenticated with the credentials of its host, if any.
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    let (host, _) = split_url(url);
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    Ok(HttpRequest {
+        url: url.to_string(),
+        headers: credentials(host)?.headers(),
+    })
+}
+
+/// Resolve the credentials of <host>: the credentials file entry for the host (with or without port),
+/// overridden by `IGNITION_HTTP_*` environment variables. Without an api key, token or username, the host's
+/// credential helper (otherwise `IGNITION_CREDENTIAL_HELPER`) is asked for credentials.
+///
+/// `IGNITION_HTTP_HEADERS` adds headers per host, as `;`-separated `<host> <name>: <value>` entries
+/// (e.g. `cdn.example.com X-CDN-Token: abc; * Authorization: Bearer xyz`).
+pub fn credentials(host: &str) -> IgnitionResult<HostCredentials> {
+    let host_name = host.split(':').next().unwrap_or(host);
+    let mut credentials = file_credentials(host)?;
+    if let Ok(headers) = std::env::var(HEADERS_VARIABLE) {
+        for entry in headers.split([';', '\n']).map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let header = entry
+                .split_once(char::is_whitespace)
+                .and_then(|(entry_host, header)| Some((entry_host, header.split_once(':')?)));
+            match header {
+                Some((entry_host, (name, value)))
+                    if [ANY_HOST, host, host_name].contains(&entry_host) =>
+                {
+                    credentials
+                        .headers
+                        .insert(name.trim().to_string(), value.trim().to_string());
+                }
+                Some(_) => {}
+                None => {
+                    return Err(IgnitionError::ConfigurationError(format!(
+                        "{}: invalid entry: {}",
+                        HEADERS_VARIABLE, entry
+                    )));
+                }
+            }
+        }
+    }
+    let env = |name: &str| std::env::var(name).ok();
+    credentials.api_key = env(API_KEY_VARIABLE).or(credentials.api_key);
+    credentials.token = env(TOKEN_VARIABLE).or(credentials.token);
+    credentials.username = env(USERNAME_VARIABLE).or(credentials.username);
+    credentials.password = env(PASSWORD_VARIABLE).or(credentials.password);
+    if credentials.api_key.is_none()
+        && credentials.token.is_none()
+        && credentials.username.is_none()
+        && let Some(command_line) = credentials.helper.clone().or_else(helper::default_helper)
+        && let Some(helper_credentials) = helper::get(&command_line, host)?
+    {
+        credentials = helper_credentials.host_credentials().or(credentials);
+    }
+    Ok(credentials)
+}
+
+/// Credentials file entry of <host> (with or without port), with defaults of the "*" entry.
+pub fn file_credentials(host: &str) -> IgnitionResult<HostCredentials> {
+    let host_name = host.split(':').next().unwrap_or(host);
+    match credentials_path().filter(|path| path.exists()) {
+        Some(path) => {
+            let mut hosts: HashMap<String, HostCredentials> =
+                serde_json::from_str(&fs::read_to_string(path)?)?;
+            let defaults = hosts.remove(ANY_HOST).unwrap_or_default();
+            Ok(hosts
+                .remove(host)
+                .or_else(|| hosts.remove(host_name))
+                .unwrap_or_default()
+                .or(defaults))
+        }
+        None => Ok(HostCredentials::default()),
+    }
+}
+
+/// Path of the credentials file.
+fn credentials_path() -> Option<PathBuf> {
+    std::env::var(CREDENTIALS_FILE_VARIABLE)
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| Some(home_dir()?.join(".ignition").join("credentials.json")))
+}
@@ -0,0 +1,100 @@
+// azure.rs
+//
+// This module contains the Azure Blob Storage source: SAS token or managed-identity authentication.
+//
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use ureq::Agent;
+
+use super::{HttpRequest, split_url, uri_encode};
+use crate::{IgnitionError, IgnitionResult};
+
+const SAS_TOKEN_VARIABLE: &str = "AZURE_STORAGE_SAS_TOKEN";
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const IMDS_TIMEOUT: Duration = Duration::from_secs(2);
+const RESOURCE: &str = "https://storage.azure.com/";
+const STORAGE_VERSION: &str = "2020-04-08";
+
+/// Managed-identity token endpoint response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Resolve an `az://<account>/<container>/<blob>` url to an authorized GET request.
+///
+/// A SAS token in `AZURE_STORAGE_SAS_TOKEN` is appended to the url, otherwise a managed-identity access token
+/// is requested (App Service `IDENTITY_ENDPOINT`, or the VM instance metadata service).
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    let (account, path) = split_url(url);
+    if account.is_empty() || !path.contains('/') {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "invalid azure blob url: {}",
+            url
+        )));
+    }
+    let blob_url = format!(
+        "https://{}.blob.core.windows.net/{}",
+        account,
+        uri_encode(path, false)
+    );
+    if let Ok(sas_token) = std::env::var(SAS_TOKEN_VARIABLE) {
+        return Ok(HttpRequest {
+            url: format!("{}?{}", blob_url, sas_token.trim_start_matches('?')),
+            headers: Vec::new(),
+        });
+    }
+    Ok(HttpRequest {
+        url: blob_url,
+        headers: vec![
+            (
+                "Authorization".to_string(),
+                format!("Bearer {}", managed_identity_token()?),
+            ),
+            ("x-ms-version".to_string(), STORAGE_VERSION.to_string()),
+        ],
+    })
+}
+
+/// Retrieve a storage access token of the managed identity (user-assigned if `AZURE_CLIENT_ID` is set).
+pub fn managed_identity_token() -> IgnitionResult<String> {
+    let agent: Agent = Agent::config_builder()
+        .proxy(None)
+        .timeout_global(Some(IMDS_TIMEOUT))
+        .build()
+        .into();
+    let client_id = std::env::var("AZURE_CLIENT_ID").ok();
+    let request = match (
+        std::env::var("IDENTITY_ENDPOINT"),
+        std::env::var("IDENTITY_HEADER"),
+    ) {
+        // App Service / Functions
+        (Ok(endpoint), Ok(header)) => agent
+            .get(endpoint)
+            .query("api-version", "2019-08-01")
+            .header("X-IDENTITY-HEADER", header),
+        _ => agent
+            .get(IMDS_TOKEN_URL)
+            .query("api-version", "2018-02-01")
+            .header("Metadata", "true"),
+    };
+    let request = match client_id {
+        Some(client_id) => request.query("client_id", client_id),
+        None => request,
+    };
+    let token = request
+        .query("resource", RESOURCE)
+        .call()
+        .map_err(IgnitionError::from)
+        .and_then(|mut response| Ok(response.body_mut().read_to_string()?))
+        .and_then(|body| Ok(serde_json::from_str::<TokenResponse>(&body)?))
+        .map_err(|err| {
+            IgnitionError::CredentialError(format!(
+                "failed to retrieve azure managed-identity token: {}",
+                err
+            ))
+        })?;
+    Ok(token.access_token)
+}
@@ -0,0 +1,116 @@
+// delta.rs
+//
+// This module contains the delta-transfer sources (`rsync://` and `zsync+http(s)://`), which only transfer the
+// blocks changed relative to the cached copy, using the `rsync` and `zsync` clients.
+//
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+    process::{Command, Output},
+};
+
+use super::scheme;
+use crate::{
+    IgnitionError, IgnitionResult,
+    retrieval::{
+        download::{partial_path, sidecar_path},
+        throttle,
+    },
+};
+
+const ZSYNC_PREFIX: &str = "zsync+";
+const ZSYNC_EXTENSION: &str = ".zsync";
+const ZSYNC_BACKUP_EXTENSION: &str = ".zs-old";
+
+/// Determine if <url> is a delta-transfer source.
+pub fn is_delta(url: &str) -> bool {
+    scheme(url) == "rsync" || url.starts_with(ZSYNC_PREFIX)
+}
+
+/// Synchronize the file at <path> with <url>, transferring only the changed blocks when a previous copy exists.
+///
+/// `rsync://<host>/<module>/<path>` urls use the rsync daemon protocol. `zsync+http(s)://<host>/<path>` urls
+/// use the zsync control file published next to the file (`<path>.zsync`). The transfer is written to
+/// `<path>.part` (seeded with the current copy), which is resumed if interrupted.
+/// Return is true if the file changed.
+pub fn sync(url: &str, path: &Path) -> IgnitionResult<bool> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial_path = partial_path(path);
+    if partial_path.exists() {
+        println!("--resume: {}", partial_path.display());
+    } else if path.exists() {
+        fs::copy(path, &partial_path)?;
+    }
+
+    let (program, output) = match url.strip_prefix(ZSYNC_PREFIX) {
+        Some(http_url) => {
+            let mut zsync = Command::new("zsync");
+            zsync.arg("-q").arg("-o").arg(&partial_path);
+            (
+                "zsync",
+                zsync
+                    .arg(super::suffixed_url(http_url, ZSYNC_EXTENSION))
+                    .output(),
+            )
+        }
+        None => {
+            let mut rsync = Command::new("rsync");
+            rsync.args(["--times", "--compress", "--partial"]);
+            if let Some(rate) = throttle::max_rate()? {
+                // rsync limit is in KiB/s
+                rsync.arg(format!("--bwlimit={}", (rate >> 10).max(1)));
+            }
+            ("rsync", rsync.arg(url).arg(&partial_path).output())
+        }
+    };
+    check(program, url, output)?;
+    fs::remove_file(sidecar_path(&partial_path, ZSYNC_BACKUP_EXTENSION)).ok();
+
+    if path.exists() && files_equal(path, &partial_path)? {
+        fs::remove_file(&partial_path)?;
+        return Ok(false);
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(true)
+}
+
+/// Check the <output> of running <program> for <url>.
+fn check(program: &str, url: &str, output: std::io::Result<Output>) -> IgnitionResult<()> {
+    let output = output.map_err(|err| {
+        IgnitionError::DownloadError(format!("command '{}' failed: {}", program, err))
+    })?;
+    if !output.status.success() {
+        return Err(IgnitionError::DownloadError(format!(
+            "{} {}: {}",
+            program,
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Determine if the files at <a> and <b> have identical contents.
+fn files_equal(a: &Path, b: &Path) -> IgnitionResult<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buffer_a = vec![0; 64 << 10];
+    let mut buffer_b = vec![0; 64 << 10];
+    loop {
+        let count = a.read(&mut buffer_a)?;
+        if count == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buffer_b[..count])?;
+        if buffer_a[..count] != buffer_b[..count] {
+            return Ok(false);
+        }
+    }
+}
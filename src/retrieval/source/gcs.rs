@@ -0,0 +1,202 @@
+// gcs.rs
+//
+// This module contains the Google Cloud Storage source: OAuth2 access tokens from service-account or
+// application-default credentials.
+//
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rsa::{
+    RsaPrivateKey,
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+};
+use serde::Deserialize;
+use sha2::Sha256;
+use ureq::Agent;
+
+use super::{HttpRequest, home_dir, split_url, unix_time, uri_encode};
+use crate::{IgnitionError, IgnitionResult, retrieval::http};
+
+const STORAGE_URL: &str = "https://storage.googleapis.com";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+const JWT_BEARER_GRANT: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+const SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+const TOKEN_LIFETIME: u64 = 3600;
+
+/// Credentials file contents: a service-account key or (gcloud) authorized-user credentials.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Credentials {
+    /// Service-account key file.
+    ServiceAccount {
+        /// Service-account email address.
+        client_email: String,
+        /// PEM-encoded PKCS#8 RSA private key.
+        private_key: String,
+        /// OAuth2 token endpoint.
+        #[serde(default = "default_token_uri")]
+        token_uri: String,
+    },
+    /// Application-default credentials of a user (`gcloud auth application-default login`).
+    AuthorizedUser {
+        /// OAuth2 client id.
+        client_id: String,
+        /// OAuth2 client secret.
+        client_secret: String,
+        /// OAuth2 refresh token.
+        refresh_token: String,
+    },
+}
+
+/// OAuth2 token endpoint response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Resolve a `gs://<bucket>/<object>` url to an authorized GET request.
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    let (bucket, object) = split_url(url);
+    if bucket.is_empty() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "invalid gcs url: {}",
+            url
+        )));
+    }
+    Ok(HttpRequest {
+        url: format!("{}/{}/{}", STORAGE_URL, bucket, uri_encode(object, false)),
+        headers: vec![(
+            "Authorization".to_string(),
+            format!("Bearer {}", access_token()?),
+        )],
+    })
+}
+
+/// Retrieve an OAuth2 access token: `GOOGLE_APPLICATION_CREDENTIALS`, then the gcloud application-default
+/// credentials file, then the compute metadata server.
+pub fn access_token() -> IgnitionResult<String> {
+    let credentials_path = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(application_default_path)
+        .filter(|path| path.exists());
+    let response = match credentials_path {
+        Some(path) => {
+            let credentials: Credentials = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            token_request(&credentials)
+        }
+        None => metadata_token_request(),
+    };
+    response.map(|token| token.access_token).map_err(|err| {
+        IgnitionError::CredentialError(format!("failed to retrieve gcs access token: {}", err))
+    })
+}
+
+/// Exchange <credentials> for an access token at the OAuth2 token endpoint.
+fn token_request(credentials: &Credentials) -> IgnitionResult<TokenResponse> {
+    let (token_uri, form): (&str, Vec<(&str, String)>) = match credentials {
+        Credentials::ServiceAccount {
+            client_email,
+            private_key,
+            token_uri,
+        } => (
+            token_uri,
+            vec![
+                ("grant_type", JWT_BEARER_GRANT.to_string()),
+                (
+                    "assertion",
+                    service_account_jwt(client_email, private_key, token_uri)?,
+                ),
+            ],
+        ),
+        Credentials::AuthorizedUser {
+            client_id,
+            client_secret,
+            refresh_token,
+        } => (
+            TOKEN_URL,
+            vec![
+                ("grant_type", "refresh_token".to_string()),
+                ("client_id", client_id.to_string()),
+                ("client_secret", client_secret.to_string()),
+                ("refresh_token", refresh_token.to_string()),
+            ],
+        ),
+    };
+    let body = http::agent(token_uri)?
+        .post(token_uri)
+        .send_form(form)?
+        .body_mut()
+        .read_to_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Retrieve an access token of the instance service account from the compute metadata server.
+fn metadata_token_request() -> IgnitionResult<TokenResponse> {
+    let agent: Agent = Agent::config_builder()
+        .proxy(None)
+        .timeout_global(Some(METADATA_TIMEOUT))
+        .build()
+        .into();
+    let body = agent
+        .get(METADATA_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .call()?
+        .body_mut()
+        .read_to_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// RS256-signed JWT assertion of a service account, for the read-only storage scope.
+fn service_account_jwt(
+    client_email: &str,
+    private_key: &str,
+    token_uri: &str,
+) -> IgnitionResult<String> {
+    let key = RsaPrivateKey::from_pkcs8_pem(private_key).map_err(|err| {
+        IgnitionError::CredentialError(format!("invalid service-account private key: {}", err))
+    })?;
+    let issued_at = unix_time();
+    let header = serde_json::json!({ "alg": "RS256", "typ": "JWT" });
+    let claims = serde_json::json!({
+        "iss": client_email,
+        "scope": SCOPE,
+        "aud": token_uri,
+        "iat": issued_at,
+        "exp": issued_at + TOKEN_LIFETIME,
+    });
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = SigningKey::<Sha256>::new(key).sign(message.as_bytes());
+    Ok(format!(
+        "{}.{}",
+        message,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+/// Path of the gcloud application-default credentials file.
+fn application_default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var("CLOUDSDK_CONFIG") {
+        Ok(config_dir) => PathBuf::from(config_dir),
+        Err(_) => match std::env::var("APPDATA") {
+            Ok(app_data) if cfg!(windows) => PathBuf::from(app_data).join("gcloud"),
+            _ => home_dir()?.join(".config").join("gcloud"),
+        },
+    };
+    Some(config_dir.join("application_default_credentials.json"))
+}
+
+/// Default OAuth2 token endpoint of service-account key files.
+fn default_token_uri() -> String {
+    TOKEN_URL.to_string()
+}
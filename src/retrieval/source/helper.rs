@@ -0,0 +1,92 @@
+// helper.rs
+//
+// This module contains credential helpers: external commands resolving the credentials of a host on demand (e.g.
+// from a keychain or a cloud login), so secrets don't have to be stored in environment variables. Helpers follow
+// the docker credential helper protocol, so any `docker-credential-*` helper can be used.
+//
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use serde::Deserialize;
+
+use super::authenticated::HostCredentials;
+use crate::{IgnitionError, IgnitionResult};
+
+const CREDENTIAL_HELPER_VARIABLE: &str = "IGNITION_CREDENTIAL_HELPER";
+/// Username of helper credentials holding an identity (bearer) token instead of a password.
+pub const TOKEN_USERNAME: &str = "<token>";
+
+/// Output of `<helper> get`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HelperCredentials {
+    /// Username, `<token>` (or empty) if the secret is a token.
+    #[serde(default)]
+    pub username: String,
+    /// Password or token.
+    pub secret: String,
+}
+
+impl HelperCredentials {
+    /// Determine if the secret is a token rather than a password.
+    pub fn is_token(&self) -> bool {
+        self.username.is_empty() || self.username == TOKEN_USERNAME
+    }
+
+    /// HTTP credentials: bearer token or basic authentication.
+    pub fn host_credentials(self) -> HostCredentials {
+        if self.is_token() {
+            HostCredentials {
+                token: Some(self.secret),
+                ..Default::default()
+            }
+        } else {
+            HostCredentials {
+                username: Some(self.username),
+                password: Some(self.secret),
+                ..Default::default()
+            }
+        }
+    }
+}
+
+/// Generic credential helper command (`IGNITION_CREDENTIAL_HELPER`), used for hosts without credentials.
+pub fn default_helper() -> Option<String> {
+    std::env::var(CREDENTIAL_HELPER_VARIABLE)
+        .ok()
+        .filter(|command_line| !command_line.trim().is_empty())
+}
+
+/// Retrieve the credentials of <host> from the helper <command_line> (split on whitespace), None if it has none.
+///
+/// The helper is run as `<command_line> get` with <host> on stdin, and prints `{"Username": ..., "Secret": ...}`
+/// on stdout, or exits with an error for hosts without credentials (docker credential helper protocol).
+pub fn get(command_line: &str, host: &str) -> IgnitionResult<Option<HelperCredentials>> {
+    let mut words = command_line.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let mut child = Command::new(program)
+        .args(words)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| {
+            IgnitionError::CredentialError(format!("command '{}' failed: {}", program, err))
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(host.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    serde_json::from_slice(&output.stdout)
+        .map(Some)
+        .map_err(|err| {
+            IgnitionError::CredentialError(format!("invalid output of '{}': {}", program, err))
+        })
+}
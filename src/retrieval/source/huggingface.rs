@@ -0,0 +1,92 @@
+// huggingface.rs
+//
+// This module contains the Hugging Face Hub source (`hf://<org>/<repo>[@<revision>]/<path>`), e.g. for ONNX
+// models, with token authentication.
+//
+
+use std::{fs, path::PathBuf};
+
+use super::{HttpRequest, helper, home_dir, split_url, uri_encode};
+use crate::{IgnitionError, IgnitionResult};
+
+const ENDPOINT_VARIABLE: &str = "HF_ENDPOINT";
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+const DEFAULT_REVISION: &str = "main";
+const REPO_TYPE_PREFIXES: [&str; 2] = ["datasets", "spaces"];
+
+/// Resolve an `hf://<org>/<repo>[@<revision>]/<path>` url to a GET request of the file at the revision.
+///
+/// The revision is a branch, tag or commit hash (default `main`); pin a commit hash for reproducible builds.
+/// Dataset and space repositories are prefixed with their type (`hf://datasets/<org>/<repo>/<path>`).
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    let (first, rest) = split_url(url);
+    let (prefix, org, rest) = if REPO_TYPE_PREFIXES.contains(&first) {
+        let (org, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        (format!("{}/", first), org, rest)
+    } else {
+        (String::new(), first, rest)
+    };
+    let (repo, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let (repo, revision) = repo.split_once('@').unwrap_or((repo, DEFAULT_REVISION));
+    if org.is_empty() || repo.is_empty() || revision.is_empty() || path.is_empty() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "invalid hugging face url: {}",
+            url
+        )));
+    }
+    let endpoint = std::env::var(ENDPOINT_VARIABLE).unwrap_or(DEFAULT_ENDPOINT.to_string());
+    let headers = match token(&endpoint)? {
+        Some(token) => vec![("Authorization".to_string(), format!("Bearer {}", token))],
+        None => Vec::new(),
+    };
+    Ok(HttpRequest {
+        url: format!(
+            "{}/{}{}/{}/resolve/{}/{}",
+            endpoint.trim_end_matches('/'),
+            prefix,
+            org,
+            repo,
+            uri_encode(revision, true),
+            uri_encode(path, false)
+        ),
+        headers,
+    })
+}
+
+/// Resolve the access token: `HF_TOKEN` (or legacy `HUGGING_FACE_HUB_TOKEN`), then the token file of
+/// `huggingface-cli login` (`HF_TOKEN_PATH`, default `$HF_HOME/token`), then the generic credential helper
+/// (`IGNITION_CREDENTIAL_HELPER`) for the host of <endpoint>, None for anonymous access.
+pub fn token(endpoint: &str) -> IgnitionResult<Option<String>> {
+    if let Ok(token) =
+        std::env::var("HF_TOKEN").or_else(|_| std::env::var("HUGGING_FACE_HUB_TOKEN"))
+    {
+        return Ok(Some(token));
+    }
+    if let Some(path) = token_path().filter(|path| path.exists()) {
+        return Ok(
+            Some(fs::read_to_string(path)?.trim().to_string()).filter(|token| !token.is_empty())
+        );
+    }
+    match helper::default_helper() {
+        Some(command_line) => {
+            let (host, _) = split_url(endpoint);
+            Ok(helper::get(&command_line, host)?.map(|credentials| credentials.secret))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Path of the token file.
+fn token_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("HF_TOKEN_PATH") {
+        return Some(PathBuf::from(path));
+    }
+    let hf_home = match std::env::var("HF_HOME") {
+        Ok(hf_home) => PathBuf::from(hf_home),
+        Err(_) => match std::env::var("XDG_CACHE_HOME") {
+            Ok(cache_home) => PathBuf::from(cache_home).join("huggingface"),
+            Err(_) => home_dir()?.join(".cache").join("huggingface"),
+        },
+    };
+    Some(hf_home.join("token"))
+}
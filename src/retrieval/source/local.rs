@@ -0,0 +1,69 @@
+// local.rs
+//
+// This module contains the local filesystem source (`file://` urls and absolute paths, e.g. an NFS mount).
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{IgnitionError, IgnitionResult, retrieval::download::partial_path};
+
+/// Local path of a `file://` url or absolute path, None for any other url.
+pub fn path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        // file://localhost/<path> is equivalent to file:///<path>
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+    let path = Path::new(url);
+    path.is_absolute().then(|| path.to_path_buf())
+}
+
+/// Install the file at <source> as <path>: hard-linked if on the same filesystem, copied otherwise.
+pub fn copy(source: &Path, path: &Path) -> IgnitionResult<()> {
+    if !source.is_file() {
+        return Err(IgnitionError::DownloadError(format!(
+            "no such file: {}",
+            source.display()
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::hard_link(source, path).is_ok() {
+        println!("--link: {}", source.display());
+        return Ok(());
+    }
+    // copy into a partial file first, so an existing file at <path> is always complete
+    println!("--copy: {}", source.display());
+    let partial_path = partial_path(path);
+    fs::copy(source, &partial_path)?;
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
+
+/// Decode %XX escapes of a url path.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
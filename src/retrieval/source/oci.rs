@@ -0,0 +1,432 @@
+// oci.rs
+//
+// This module contains the OCI registry source: asset archives published as OCI artifacts (e.g. `oras push`),
+// authenticated with docker credentials (config file or credential helpers).
+//
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+use ureq::{Agent, Body, http::Response};
+
+use super::{HttpRequest, helper, home_dir, split_url, uri_encode};
+use crate::{
+    IgnitionError, IgnitionResult,
+    retrieval::{env_flag, http},
+};
+
+const PLAIN_HTTP_VARIABLE: &str = "IGNITION_OCI_PLAIN_HTTP";
+const USERNAME_VARIABLE: &str = "IGNITION_OCI_USERNAME";
+const PASSWORD_VARIABLE: &str = "IGNITION_OCI_PASSWORD";
+const DEFAULT_TAG: &str = "latest";
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+const DOCKER_HUB_CONFIG_KEY: &str = "https://index.docker.io/v1/";
+
+/// A file of an OCI artifact: `oci://<registry>/<repository>[:<tag>|@<digest>][/<file>]`.
+#[derive(Clone, Debug)]
+pub struct ArtifactReference {
+    /// Registry host (with optional port).
+    pub registry: String,
+    /// Repository within the registry.
+    pub repository: String,
+    /// Tag or digest of the artifact manifest.
+    pub reference: String,
+    /// Title of the artifact layer, None for single-layer artifacts.
+    pub file: Option<String>,
+}
+
+impl ArtifactReference {
+    /// Parse an `oci://` <url>.
+    ///
+    /// Without an explicit tag or digest, the last path component is the file and the tag is `latest`, so a
+    /// bucket url of `oci://<registry>/<namespace>` maps every asset archive to its own repository
+    /// (`<namespace>/<directory>/<asset>/<os>/<arch>...`). With an explicit tag
+    /// (`oci://<registry>/<repository>:<tag>/<file>`), the remaining path is the file, matching the file names
+    /// of `oras push`.
+    pub fn parse(url: &str) -> IgnitionResult<Self> {
+        let invalid = || IgnitionError::ConfigurationError(format!("invalid oci url: {}", url));
+        let (registry, path) = split_url(url);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (repository, reference, file) =
+            match segments.iter().position(|s| s.contains([':', '@'])) {
+                Some(index) => {
+                    let (name, reference) = segments[index]
+                        .split_once('@')
+                        .or_else(|| segments[index].split_once(':'))
+                        .ok_or_else(invalid)?;
+                    let repository = segments[..index]
+                        .iter()
+                        .copied()
+                        .chain([name])
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    let file = segments[index + 1..].join("/");
+                    (repository, reference.to_string(), Some(file))
+                }
+                None => {
+                    let (file, repository) = segments.split_last().ok_or_else(invalid)?;
+                    (
+                        repository.join("/"),
+                        DEFAULT_TAG.to_string(),
+                        Some(file.to_string()),
+                    )
+                }
+            };
+        if registry.is_empty() || repository.is_empty() || reference.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ArtifactReference {
+            registry: registry.to_string(),
+            repository,
+            reference,
+            file: file.filter(|file| !file.is_empty()),
+        })
+    }
+
+    /// Host of the registry's HTTP API.
+    pub fn host(&self) -> &str {
+        match self.registry.as_str() {
+            DOCKER_HUB => DOCKER_HUB_REGISTRY,
+            registry => registry,
+        }
+    }
+
+    /// Whether the registry is accessed over plain HTTP (localhost, or `IGNITION_OCI_PLAIN_HTTP=true`).
+    pub fn plain_http(&self) -> IgnitionResult<bool> {
+        let host = self.host();
+        let host_name = host.split(':').next().unwrap_or(host);
+        Ok(env_flag(PLAIN_HTTP_VARIABLE)?
+            .unwrap_or(host_name == "localhost" || host_name == "127.0.0.1"))
+    }
+
+    /// Image reference of the artifact (`<registry>/<repository>:<tag>` or `<registry>/<repository>@<digest>`),
+    /// as used by docker and cosign.
+    pub fn image(&self) -> String {
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        format!(
+            "{}/{}{}{}",
+            self.registry, self.repository, separator, self.reference
+        )
+    }
+}
+
+/// Image (artifact) manifest.
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+/// Content descriptor of a manifest layer.
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+/// Docker client configuration file (`~/.docker/config.json`).
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct DockerConfig {
+    auths: HashMap<String, DockerAuth>,
+    creds_store: Option<String>,
+    cred_helpers: HashMap<String, String>,
+}
+
+/// Credentials of a registry in the docker configuration file.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct DockerAuth {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+}
+
+/// Registry token endpoint response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Credentials for a registry.
+#[derive(Clone, Debug)]
+pub enum RegistryCredentials {
+    /// Username and password (or access token).
+    Basic(String, String),
+    /// OAuth2 refresh token (`docker login` identity token).
+    IdentityToken(String),
+}
+
+/// Resolve an `oci://` url to a GET request for the blob of the artifact layer, authorized with a registry
+/// token if required.
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    let artifact = ArtifactReference::parse(url)?;
+    let repository_url = format!(
+        "{}://{}/v2/{}",
+        if artifact.plain_http()? {
+            "http"
+        } else {
+            "https"
+        },
+        artifact.host(),
+        artifact.repository
+    );
+    let manifest_url = format!("{}/manifests/{}", repository_url, artifact.reference);
+    let agent = http::agent(&manifest_url)?;
+
+    let mut headers = Vec::new();
+    let mut response = get_manifest(&agent, &manifest_url, &headers)?;
+    if response.status().as_u16() == 401 {
+        let challenge = response
+            .headers()
+            .get("WWW-Authenticate")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let scope = format!("repository:{}:pull", artifact.repository);
+        let credentials = credentials(&artifact.registry)?;
+        headers.push((
+            "Authorization".to_string(),
+            authorization(&challenge, &scope, credentials.as_ref())?,
+        ));
+        response = get_manifest(&agent, &manifest_url, &headers)?;
+    }
+    let status = response.status().as_u16();
+    if status >= 400 {
+        return Err(IgnitionError::HttpStatusError(status));
+    }
+    let manifest: Manifest = serde_json::from_str(&response.body_mut().read_to_string()?)?;
+    let layer = match artifact.file.as_deref() {
+        Some(file) => manifest.layers.iter().find(|layer| {
+            layer.annotations.get(TITLE_ANNOTATION).map(String::as_str) == Some(file)
+        }),
+        None if manifest.layers.len() == 1 => manifest.layers.first(),
+        None => None,
+    }
+    .ok_or_else(|| {
+        IgnitionError::DownloadError(format!(
+            "no layer {} in oci artifact {}/{}:{}",
+            artifact.file.as_deref().unwrap_or_default(),
+            artifact.registry,
+            artifact.repository,
+            artifact.reference
+        ))
+    })?;
+    Ok(HttpRequest {
+        url: format!("{}/blobs/{}", repository_url, layer.digest),
+        headers,
+    })
+}
+
+/// Send a GET request for the manifest at <url>, without erroring on status codes.
+fn get_manifest(
+    agent: &Agent,
+    url: &str,
+    headers: &[(String, String)],
+) -> IgnitionResult<Response<Body>> {
+    let mut request = agent
+        .get(url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .header("Accept", MANIFEST_MEDIA_TYPES);
+    for (name, value) in headers.iter() {
+        request = request.header(name, value);
+    }
+    Ok(request.call()?)
+}
+
+/// `Authorization` header value answering the `WWW-Authenticate` <challenge> of a registry: basic
+/// authentication, or a bearer token for <scope> from the token endpoint (anonymous without <credentials>).
+fn authorization(
+    challenge: &str,
+    scope: &str,
+    credentials: Option<&RegistryCredentials>,
+) -> IgnitionResult<String> {
+    let (auth_scheme, params) = challenge.split_once(' ').unwrap_or((challenge, ""));
+    if auth_scheme.eq_ignore_ascii_case("basic") {
+        return match credentials {
+            Some(RegistryCredentials::Basic(username, password)) => Ok(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            )),
+            _ => Err(IgnitionError::CredentialError(
+                "registry requires basic authentication, but no credentials found".to_string(),
+            )),
+        };
+    }
+    if !auth_scheme.eq_ignore_ascii_case("bearer") {
+        return Err(IgnitionError::CredentialError(format!(
+            "unsupported registry authentication challenge: {}",
+            challenge
+        )));
+    }
+    let params = challenge_params(params);
+    let realm = params.get("realm").ok_or_else(|| {
+        IgnitionError::CredentialError(format!("registry challenge without realm: {}", challenge))
+    })?;
+    let service = params
+        .get("service")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let scope = params.get("scope").map_or(scope, String::as_str);
+    let token_agent = http::agent(realm)?;
+    let body = match credentials {
+        Some(RegistryCredentials::IdentityToken(refresh_token)) => token_agent
+            .post(realm)
+            .send_form([
+                ("grant_type", "refresh_token"),
+                ("client_id", "ignition"),
+                ("service", service),
+                ("scope", scope),
+                ("refresh_token", refresh_token),
+            ])?
+            .body_mut()
+            .read_to_string()?,
+        credentials => {
+            let token_url = format!(
+                "{}?service={}&scope={}",
+                realm,
+                uri_encode(service, true),
+                uri_encode(scope, true)
+            );
+            let mut request = token_agent.get(&token_url);
+            if let Some(RegistryCredentials::Basic(username, password)) = credentials {
+                request = request.header(
+                    "Authorization",
+                    format!(
+                        "Basic {}",
+                        STANDARD.encode(format!("{}:{}", username, password))
+                    ),
+                );
+            }
+            request.call()?.body_mut().read_to_string()?
+        }
+    };
+    let response: TokenResponse = serde_json::from_str(&body)?;
+    response
+        .token
+        .or(response.access_token)
+        .map(|token| format!("Bearer {}", token))
+        .ok_or_else(|| {
+            IgnitionError::CredentialError("registry token response without token".to_string())
+        })
+}
+
+/// Parameters of a `WWW-Authenticate` challenge (`key="value",...`).
+fn challenge_params(params: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        parsed.insert(key, value.to_string());
+        rest = remaining.trim();
+    }
+    parsed
+}
+
+/// Resolve the credentials of <registry>: `IGNITION_OCI_USERNAME`/`IGNITION_OCI_PASSWORD`, then the docker
+/// configuration file (`DOCKER_CONFIG`, default `~/.docker`): credential helper of the registry, static
+/// credentials, then the default credential store, and finally the generic credential helper
+/// (`IGNITION_CREDENTIAL_HELPER`).
+pub fn credentials(registry: &str) -> IgnitionResult<Option<RegistryCredentials>> {
+    if let Ok(username) = std::env::var(USERNAME_VARIABLE) {
+        let password = std::env::var(PASSWORD_VARIABLE).unwrap_or_default();
+        return Ok(Some(RegistryCredentials::Basic(username, password)));
+    }
+    let Some(path) = docker_config_path().filter(|path| path.exists()) else {
+        return default_credential_helper(registry);
+    };
+    let mut config: DockerConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let config_key = match registry {
+        DOCKER_HUB | DOCKER_HUB_REGISTRY => DOCKER_HUB_CONFIG_KEY,
+        registry => registry,
+    };
+    if let Some(helper) = config.cred_helpers.get(config_key) {
+        return credential_helper(helper, config_key);
+    }
+    let auth = config
+        .auths
+        .remove(config_key)
+        .or_else(|| config.auths.remove(&format!("https://{}", config_key)));
+    if let Some(auth) = auth {
+        if let Some(identity_token) = auth.identitytoken.filter(|token| !token.is_empty()) {
+            return Ok(Some(RegistryCredentials::IdentityToken(identity_token)));
+        }
+        if let Some(encoded) = auth.auth.filter(|auth| !auth.is_empty()) {
+            let decoded = STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .ok_or_else(|| {
+                    IgnitionError::CredentialError(format!("invalid docker auth for {}", registry))
+                })?;
+            let (username, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+            return Ok(Some(RegistryCredentials::Basic(
+                username.to_string(),
+                password.to_string(),
+            )));
+        }
+        if let Some(username) = auth.username {
+            return Ok(Some(RegistryCredentials::Basic(
+                username,
+                auth.password.unwrap_or_default(),
+            )));
+        }
+    }
+    match config.creds_store {
+        Some(helper) => credential_helper(&helper, config_key),
+        None => default_credential_helper(registry),
+    }
+}
+
+/// Retrieve the credentials of <registry> from `docker-credential-<helper>`, None if it has none.
+fn credential_helper(helper: &str, registry: &str) -> IgnitionResult<Option<RegistryCredentials>> {
+    let credentials = helper::get(&format!("docker-credential-{}", helper), registry)?;
+    Ok(credentials.map(registry_credentials))
+}
+
+/// Retrieve the credentials of <registry> from the generic credential helper, if any.
+fn default_credential_helper(registry: &str) -> IgnitionResult<Option<RegistryCredentials>> {
+    match helper::default_helper() {
+        Some(command_line) => Ok(helper::get(&command_line, registry)?.map(registry_credentials)),
+        None => Ok(None),
+    }
+}
+
+/// Registry credentials of credential helper <credentials>.
+fn registry_credentials(credentials: helper::HelperCredentials) -> RegistryCredentials {
+    if credentials.username == helper::TOKEN_USERNAME {
+        RegistryCredentials::IdentityToken(credentials.secret)
+    } else {
+        RegistryCredentials::Basic(credentials.username, credentials.secret)
+    }
+}
+
+/// Path of the docker client configuration file.
+fn docker_config_path() -> Option<PathBuf> {
+    std::env::var("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| Some(home_dir()?.join(".docker")))
+        .map(|dir| dir.join("config.json"))
+}
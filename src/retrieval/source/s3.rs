@@ -0,0 +1,317 @@
+// s3.rs
+//
+// This module contains the S3 source: SigV4 request signing with standard AWS credential resolution (including
+// `credential_process` helpers and per-bucket profiles).
+//
+
+use std::{collections::HashMap, fs, path::PathBuf, process::Command, time::Duration};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use ureq::Agent;
+
+use super::{
+    HttpRequest, authenticated, hex, home_dir, split_url, unix_time, uri_encode, utc_datetime,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const DEFAULT_PROFILE: &str = "default";
+const IMDS_URL: &str = "http://169.254.169.254/latest";
+const IMDS_TIMEOUT: Duration = Duration::from_secs(2);
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// AWS credentials used to sign requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Credentials {
+    /// Access key id.
+    pub access_key_id: String,
+    /// Secret access key.
+    pub secret_access_key: String,
+    /// Session token of temporary credentials.
+    #[serde(default, rename = "Token", alias = "SessionToken")]
+    pub session_token: Option<String>,
+}
+
+/// Resolve an `s3://<bucket>/<key>` url to a SigV4-signed GET request.
+///
+/// The endpoint is `https://<bucket>.s3.<region>.amazonaws.com`, unless `AWS_ENDPOINT_URL_S3` (or
+/// `AWS_ENDPOINT_URL`) is set for S3-compatible storage, which is then addressed path-style. The AWS profile is
+/// the `aws_profile` of the bucket in the credentials file (see `authenticated::HostCredentials`), otherwise
+/// `AWS_PROFILE`.
+pub fn request(url: &str) -> IgnitionResult<HttpRequest> {
+    method_request("GET", url)
+}
+
+/// Resolve an `s3://<bucket>/<key>` url to a SigV4-signed request of <method> (e.g. `PUT` to upload the key), see
+/// `request`.
+pub fn method_request(method: &str, url: &str) -> IgnitionResult<HttpRequest> {
+    let (bucket, key) = split_url(url);
+    if bucket.is_empty() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "invalid s3 url: {}",
+            url
+        )));
+    }
+    let profile = match authenticated::file_credentials(bucket)?.aws_profile {
+        Some(profile) => profile,
+        None => std::env::var("AWS_PROFILE").unwrap_or(DEFAULT_PROFILE.to_string()),
+    };
+    let region = region(&profile);
+    let credentials = credentials(&profile)?;
+    let endpoint = ["AWS_ENDPOINT_URL_S3", "AWS_ENDPOINT_URL"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok());
+    let (base_url, canonical_uri) = match endpoint {
+        Some(endpoint) => (
+            endpoint.trim_end_matches('/').to_string(),
+            format!("/{}/{}", bucket, uri_encode(key, false)),
+        ),
+        // dotted bucket names break virtual-hosted TLS certificates, so use path-style
+        None if bucket.contains('.') => (
+            format!("https://s3.{}.amazonaws.com", region),
+            format!("/{}/{}", bucket, uri_encode(key, false)),
+        ),
+        None => (
+            format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+            format!("/{}", uri_encode(key, false)),
+        ),
+    };
+    let host = base_url
+        .split_once("://")
+        .map_or(base_url.as_str(), |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let headers = sign(
+        method,
+        &credentials,
+        &region,
+        &host,
+        &canonical_uri,
+        unix_time(),
+    );
+    Ok(HttpRequest {
+        url: base_url + &canonical_uri,
+        headers,
+    })
+}
+
+/// SigV4 headers (`Authorization`, `x-amz-*`) for a <method> (e.g. `GET`) request of <canonical_uri> on <host>.
+pub fn sign(
+    method: &str,
+    credentials: &Credentials,
+    region: &str,
+    host: &str,
+    canonical_uri: &str,
+    unix_time: u64,
+) -> Vec<(String, String)> {
+    let (year, month, day, hour, minute, second) = utc_datetime(unix_time);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let amz_date = format!("{}T{:02}{:02}{:02}Z", date, hour, minute, second);
+    let mut headers = vec![
+        ("host".to_string(), host.to_string()),
+        (
+            "x-amz-content-sha256".to_string(),
+            UNSIGNED_PAYLOAD.to_string(),
+        ),
+        ("x-amz-date".to_string(), amz_date.clone()),
+    ];
+    if let Some(token) = credentials.session_token.as_ref() {
+        headers.push(("x-amz-security-token".to_string(), token.to_string()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method, canonical_uri, canonical_headers, signed_headers, UNSIGNED_PAYLOAD
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signing_key = ["s3", "aws4_request"].iter().fold(
+        hmac_sha256(
+            &hmac_sha256(
+                format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+                &date,
+            ),
+            region,
+        ),
+        |key, part| hmac_sha256(&key, part),
+    );
+    let signature = hex(&hmac_sha256(&signing_key, &string_to_sign));
+    // host header is set by the http client
+    headers.remove(0);
+    headers.push((
+        "Authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    headers
+}
+
+/// Resolve AWS credentials: environment variables, then shared credentials file profile, then the profile's
+/// `credential_process` in the shared config file, then instance metadata.
+pub fn credentials(profile: &str) -> IgnitionResult<Credentials> {
+    if let (Ok(access_key_id), Ok(secret_access_key)) = (
+        std::env::var("AWS_ACCESS_KEY_ID"),
+        std::env::var("AWS_SECRET_ACCESS_KEY"),
+    ) {
+        return Ok(Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        });
+    }
+    let credentials_path = std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| aws_path("credentials"));
+    if let Some(mut section) = credentials_path.and_then(|path| ini_section(&path, profile))
+        && let (Some(access_key_id), Some(secret_access_key)) = (
+            section.remove("aws_access_key_id"),
+            section.remove("aws_secret_access_key"),
+        )
+    {
+        return Ok(Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: section.remove("aws_session_token"),
+        });
+    }
+    if let Some(command_line) =
+        config_section(profile).and_then(|mut section| section.remove("credential_process"))
+    {
+        return credential_process(&command_line);
+    }
+    if std::env::var("AWS_EC2_METADATA_DISABLED").is_ok_and(|value| value == "true") {
+        return Err(IgnitionError::CredentialError(
+            "no aws credentials found".to_string(),
+        ));
+    }
+    imds_credentials().map_err(|err| {
+        IgnitionError::CredentialError(format!("no aws credentials found (imds: {})", err))
+    })
+}
+
+/// Retrieve credentials from the `credential_process` <command_line> (split on whitespace), printing
+/// `{"Version": 1, "AccessKeyId": ..., "SecretAccessKey": ..., "SessionToken": ...}`.
+fn credential_process(command_line: &str) -> IgnitionResult<Credentials> {
+    let mut words = command_line.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let output = Command::new(program).args(words).output().map_err(|err| {
+        IgnitionError::CredentialError(format!("command '{}' failed: {}", program, err))
+    })?;
+    if !output.status.success() {
+        return Err(IgnitionError::CredentialError(format!(
+            "credential_process '{}' exited with {}: {}",
+            command_line,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|err| {
+        IgnitionError::CredentialError(format!("invalid output of '{}': {}", program, err))
+    })
+}
+
+/// Retrieve temporary credentials of the instance role from the EC2 instance metadata service (IMDSv2).
+fn imds_credentials() -> IgnitionResult<Credentials> {
+    let agent: Agent = Agent::config_builder()
+        .proxy(None)
+        .timeout_global(Some(IMDS_TIMEOUT))
+        .build()
+        .into();
+    let token = agent
+        .put(format!("{}/api/token", IMDS_URL))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .send_empty()?
+        .body_mut()
+        .read_to_string()?;
+    let get = |path: &str| -> IgnitionResult<String> {
+        Ok(agent
+            .get(format!(
+                "{}/meta-data/iam/security-credentials/{}",
+                IMDS_URL, path
+            ))
+            .header("X-aws-ec2-metadata-token", &token)
+            .call()?
+            .body_mut()
+            .read_to_string()?)
+    };
+    let role = get("")?;
+    let role = role.lines().next().unwrap_or_default().trim();
+    Ok(serde_json::from_str(&get(role)?)?)
+}
+
+/// Resolve the AWS region: `AWS_REGION`, `AWS_DEFAULT_REGION`, shared config file profile, then us-east-1.
+pub fn region(profile: &str) -> String {
+    ["AWS_REGION", "AWS_DEFAULT_REGION"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .or_else(|| config_section(profile)?.remove("region"))
+        .unwrap_or(DEFAULT_REGION.to_string())
+}
+
+/// Key-value pairs of <profile> in the shared config file (`AWS_CONFIG_FILE`, default `~/.aws/config`).
+fn config_section(profile: &str) -> Option<HashMap<String, String>> {
+    let config_path = std::env::var("AWS_CONFIG_FILE")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| aws_path("config"))?;
+    let section = match profile {
+        DEFAULT_PROFILE => profile.to_string(),
+        profile => format!("profile {}", profile),
+    };
+    ini_section(&config_path, &section)
+}
+
+/// Path of <file_name> in the `~/.aws` directory.
+fn aws_path(file_name: &str) -> Option<PathBuf> {
+    Some(home_dir()?.join(".aws").join(file_name))
+}
+
+/// Key-value pairs of [<section>] in the INI file at <path>.
+fn ini_section(path: &PathBuf, section: &str) -> Option<HashMap<String, String>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut values = None;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if values.is_some() {
+                break;
+            }
+            if name.trim() == section {
+                values = Some(HashMap::new());
+            }
+        } else if let (Some(values), Some((key, value))) = (values.as_mut(), line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    values
+}
+
+/// HMAC-SHA256 of <data> with <key>.
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,94 @@
+// sftp.rs
+//
+// This module contains the SFTP source (`sftp://[<user>@]<host>[:<port>]/<path>`), using the OpenSSH `sftp`
+// client in batch mode with key-based authentication.
+//
+
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use super::split_url;
+use crate::{
+    IgnitionError, IgnitionResult,
+    retrieval::{download::partial_path, throttle},
+};
+
+const KEY_VARIABLE: &str = "IGNITION_SSH_KEY";
+
+/// Download the file at an `sftp://` <url> into <path>, resuming a partial download if present.
+///
+/// The remote path is absolute (`sftp://host/srv/assets`), or relative to the home directory when prefixed
+/// with `~` (`sftp://host/~/assets`). The private key is `IGNITION_SSH_KEY`, otherwise the ssh defaults
+/// (agent, `~/.ssh/id_*`, `~/.ssh/config`). Password prompts are disabled.
+pub fn download(url: &str, path: &Path) -> IgnitionResult<()> {
+    let (authority, remote_path) = split_url(url);
+    let (destination, port) = match authority.rsplit_once(':') {
+        Some((destination, port)) if port.chars().all(|c| c.is_ascii_digit()) => {
+            (destination, Some(port))
+        }
+        _ => (authority, None),
+    };
+    if destination.is_empty() || remote_path.is_empty() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "invalid sftp url: {}",
+            url
+        )));
+    }
+    let remote_path = match remote_path.strip_prefix("~/") {
+        Some(relative_path) => relative_path.to_string(),
+        None => format!("/{}", remote_path),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial_path = partial_path(path);
+    let command = if partial_path.exists() {
+        println!("--resume: {}", partial_path.display());
+        "reget"
+    } else {
+        "get"
+    };
+
+    let mut sftp = Command::new("sftp");
+    sftp.args(["-q", "-b", "-", "-o", "BatchMode=yes"]);
+    if let Ok(key) = std::env::var(KEY_VARIABLE) {
+        sftp.args(["-i", &key]);
+    }
+    if let Some(port) = port {
+        sftp.args(["-P", port]);
+    }
+    if let Some(rate) = throttle::max_rate()? {
+        // sftp limit is in Kbit/s
+        sftp.args(["-l", &(rate * 8 / 1000).max(1).to_string()]);
+    }
+    let mut child = sftp
+        .arg(destination)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| IgnitionError::DownloadError(format!("command 'sftp' failed: {}", err)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(
+            stdin,
+            "{} \"{}\" \"{}\"",
+            command,
+            remote_path.replace('"', "\\\""),
+            partial_path.display().to_string().replace('"', "\\\"")
+        )?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(IgnitionError::DownloadError(format!(
+            "sftp {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
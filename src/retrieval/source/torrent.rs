@@ -0,0 +1,92 @@
+// torrent.rs
+//
+// This module contains the BitTorrent source for very large asset archives, using the `aria2c` client with the
+// bucket urls as web seeds.
+//
+
+use std::{fs, path::Path, process::Command};
+
+use super::scheme;
+use crate::{
+    IgnitionError, IgnitionResult,
+    retrieval::{
+        download::{self, partial_path, sidecar_path},
+        env_value, throttle,
+    },
+};
+
+const STOP_TIMEOUT_VARIABLE: &str = "IGNITION_TORRENT_TIMEOUT";
+const DEFAULT_STOP_TIMEOUT: u64 = 300;
+const TORRENT_EXTENSION: &str = ".torrent";
+
+/// Download an asset archive over BitTorrent into <path>, from the torrent published next to it
+/// (`<url>.torrent`) at the first of <urls> serving one.
+///
+/// Plain HTTP(S) <urls> are passed to `aria2c` as web seeds, so the download completes from the bucket when
+/// there are no (or slow) peers. The download is abandoned if stalled for `IGNITION_TORRENT_TIMEOUT` seconds
+/// (default 300). Seeding stops on completion.
+pub fn download(urls: &[String], path: &Path) -> IgnitionResult<()> {
+    let torrent_path = sidecar_path(path, TORRENT_EXTENSION);
+    fs::remove_file(&torrent_path).ok();
+    let mut errors = Vec::new();
+    for url in urls.iter() {
+        let torrent_url = super::suffixed_url(url, TORRENT_EXTENSION);
+        match download::download(&torrent_url, &torrent_path) {
+            Ok(()) => break,
+            Err(err) => errors.push(format!("{}: {}", torrent_url, err)),
+        }
+    }
+    if !torrent_path.exists() {
+        return Err(IgnitionError::DownloadError(format!(
+            "no torrent: {}",
+            errors.join("; ")
+        )));
+    }
+    let partial_path = partial_path(path);
+    let (Some(directory), Some(file_name)) = (partial_path.parent(), partial_path.file_name())
+    else {
+        return Err(IgnitionError::DownloadError(format!(
+            "invalid path: {}",
+            path.display()
+        )));
+    };
+
+    let mut aria2c = Command::new("aria2c");
+    aria2c
+        .args([
+            "--seed-time=0",
+            "--allow-overwrite=true",
+            "--auto-file-renaming=false",
+            "--summary-interval=0",
+            "--console-log-level=warn",
+        ])
+        .arg(format!(
+            "--bt-stop-timeout={}",
+            env_value(STOP_TIMEOUT_VARIABLE)?.unwrap_or(DEFAULT_STOP_TIMEOUT)
+        ))
+        .arg("--dir")
+        .arg(directory)
+        .arg(format!("--index-out=1={}", file_name.to_string_lossy()));
+    if let Some(rate) = throttle::max_rate()? {
+        aria2c.arg(format!("--max-overall-download-limit={}", rate));
+    }
+    aria2c.arg("--torrent-file").arg(&torrent_path);
+    // web seeds must be reachable without request signing
+    for url in urls.iter() {
+        if matches!(scheme(url), "http" | "https") {
+            aria2c.arg(url);
+        }
+    }
+    let output = aria2c
+        .output()
+        .map_err(|err| IgnitionError::DownloadError(format!("command 'aria2c' failed: {}", err)))?;
+    if !output.status.success() {
+        return Err(IgnitionError::DownloadError(format!(
+            "aria2c {}: {}",
+            torrent_path.display(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        )));
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,89 @@
+// split.rs
+//
+// This module contains the retrieval of split archives (`parts` in the manifest): assets published as ordered parts
+// (`<asset>.tar.gz.part01`, `<asset>.tar.gz.part02`, ...), e.g. by hosts capping object sizes, downloaded and
+// verified one by one, then concatenated into the cached archive, which is verified and extracted as any other.
+//
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    checksum,
+    download::{self, sidecar_path},
+    redact, source,
+};
+use crate::IgnitionResult;
+
+/// Minimum number of digits of part numbers (`.part01`, as `split -d`).
+const PART_DIGITS: usize = 2;
+
+/// Extension of the part <number> (1-based) of an archive split into <parts> parts (`.part01` to `.partNN`,
+/// numbers padded to the digits of <parts>, at least 2).
+pub fn part_extension(number: usize, parts: usize) -> String {
+    let digits = parts.to_string().len().max(PART_DIGITS);
+    format!(".part{:0digits$}", number)
+}
+
+/// Download the archive split into <parts> parts at <url> (`<url>.part01` to `<url>.partNN`) into the file at
+/// <archive_path>, erroring (and deleting the download) if it is larger than <max_size> bytes in total.
+///
+/// Parts are downloaded next to the archive (`<archive>.partNN`, see `download::download_limited`), parts already
+/// downloaded (e.g. before a failed attempt) being kept. Each part listed in the `SHA256SUMS` file published next
+/// to the parts is verified once downloaded, a corrupted part being deleted, so only it is downloaded again. The
+/// parts are then concatenated into the archive (through `<archive>.part`, renamed on completion) and deleted.
+pub fn download(
+    url: &str,
+    archive_path: &Path,
+    parts: usize,
+    max_size: Option<u64>,
+) -> IgnitionResult<()> {
+    let mut part_paths = Vec::with_capacity(parts);
+    let mut size = 0u64;
+    for number in 1..=parts {
+        let extension = part_extension(number, parts);
+        let part_url = source::suffixed_url(url, &extension);
+        let part_path = sidecar_path(archive_path, &extension);
+        if !part_path.exists() {
+            println!("--part url: {}", redact::redact(&part_url));
+            download::download_limited(
+                &part_url,
+                &part_path,
+                max_size.map(|max_size| max_size.saturating_sub(size)),
+            )?;
+        }
+        // published checksums are fetched with the first part only
+        if let Some(expected) = checksum::published(&part_url, archive_path, number == 1)?
+            && let Err(err) = checksum::verify(&part_path, &expected)
+        {
+            fs::remove_file(&part_path)?;
+            fs::remove_file(sidecar_path(&part_path, download::VALIDATORS_EXTENSION)).ok();
+            return Err(err);
+        }
+        size = size.saturating_add(fs::metadata(&part_path)?.len());
+        part_paths.push(part_path);
+    }
+    concatenate(&part_paths, archive_path)?;
+    println!("--joined: {} ({} parts)", archive_path.display(), parts);
+    for part_path in part_paths.iter() {
+        fs::remove_file(part_path)?;
+        fs::remove_file(sidecar_path(part_path, download::VALIDATORS_EXTENSION)).ok();
+    }
+    // parts downloaded before count towards the limit too
+    download::enforce_size_limit(url, archive_path, max_size)
+}
+
+/// Concatenate the files at <part_paths> (in order) into the file at <archive_path>.
+fn concatenate(part_paths: &[PathBuf], archive_path: &Path) -> IgnitionResult<()> {
+    let partial_path = download::partial_path(archive_path);
+    let mut archive = File::create(&partial_path)?;
+    for part_path in part_paths {
+        io::copy(&mut File::open(part_path)?, &mut archive)?;
+    }
+    archive.sync_all()?;
+    fs::rename(&partial_path, archive_path)?;
+    Ok(())
+}
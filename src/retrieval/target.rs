@@ -0,0 +1,193 @@
+// target.rs
+//
+// This module resolves a target triplet to one of the builds listed in `config/target.json`.
+//
+
+use std::{collections::HashMap, process::Command};
+
+use serde::Deserialize;
+use serde_json::from_str;
+
+use crate::{IgnitionError, IgnitionResult};
+
+// `config/target.json` not available at runtime, so need to include contents as a string
+const TARGET_CONFIG: &str = include_str!("../../config/target.json");
+
+/// Jetpack releases (nvidia-l4t-core major versions) with available builds.
+const JETPACK_VARIANTS: [&str; 2] = ["35", "36"];
+
+/// Build configuration for a particular operating system.
+#[derive(Deserialize)]
+pub struct OperatingSystemBuilds {
+    /// List of available builds.
+    #[serde(default)]
+    pub build: Vec<TargetBuild>,
+}
+
+/// A single available build, matched against the parts of a target triplet.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TargetBuild {
+    /// Triplet architecture (e.g. "aarch64").
+    pub architecture: String,
+    /// Bucket path component for the architecture (e.g. "arm64").
+    pub architecture_alias: String,
+    /// Triplet environment (e.g. "gnu", "sim").
+    pub environment: String,
+    /// Bucket path component for the environment, empty if not part of the path.
+    pub environment_alias: String,
+    /// Variant (e.g. Jetpack release "35").
+    pub variant: String,
+    /// Bucket path component for the variant, empty if not part of the path.
+    pub variant_alias: String,
+}
+
+/// Parts of a target triplet: <architecture>-<vendor>-<os>[-<environment>].
+#[derive(Clone, Debug)]
+pub struct TargetTriplet {
+    /// Architecture (e.g. "x86_64").
+    pub architecture: String,
+    /// Vendor (e.g. "unknown", "apple").
+    pub vendor: String,
+    /// Operating system (e.g. "linux", "darwin").
+    pub os: String,
+    /// Environment, empty if not present (e.g. "gnu").
+    pub environment: String,
+}
+
+impl TargetTriplet {
+    /// Split a target triplet into its parts.
+    pub fn parse(triplet: &str) -> IgnitionResult<Self> {
+        let parts: Vec<&str> = triplet.split('-').collect();
+        if parts.len() < 3 || parts.iter().any(|part| part.is_empty()) {
+            return Err(IgnitionError::UnsupportedTargetError(format!(
+                "invalid target triplet: {}",
+                triplet
+            )));
+        }
+        Ok(TargetTriplet {
+            architecture: parts[0].to_string(),
+            vendor: parts[1].to_string(),
+            os: parts[2].to_string(),
+            environment: parts.get(3).map(|env| env.to_string()).unwrap_or_default(),
+        })
+    }
+
+    /// Operating system key in `config/target.json`.
+    pub fn operating_system(&self) -> &str {
+        match self.os.as_str() {
+            "androideabi" => "android",
+            os => os,
+        }
+    }
+}
+
+/// A target triplet resolved to exactly one build.
+#[derive(Clone, Debug)]
+pub struct ResolvedTarget {
+    /// Target triplet (e.g. "x86_64-unknown-linux-gnu").
+    pub triplet: String,
+    /// Operating system key in `config/target.json`.
+    pub os: String,
+    /// Matching build.
+    pub build: TargetBuild,
+}
+
+impl ResolvedTarget {
+    /// Bucket path components: <os>/<arch>[/<environment>][/<variant>].
+    pub fn path_components(&self) -> Vec<String> {
+        [
+            self.os.as_str(),
+            self.build.architecture_alias.as_str(),
+            self.build.environment_alias.as_str(),
+            self.build.variant_alias.as_str(),
+        ]
+        .into_iter()
+        .filter(|component| !component.is_empty())
+        .map(String::from)
+        .collect()
+    }
+}
+
+/// Resolve a target triplet to a build listed in `config/target.json`.
+///
+/// The optional <variant> parameter is only relevant to aarch64 linux (Jetson) targets, where it otherwise
+/// defaults to the local Jetpack release.
+pub fn resolve(triplet: &str, variant: Option<&str>) -> IgnitionResult<ResolvedTarget> {
+    let target_cfg: HashMap<String, OperatingSystemBuilds> = from_str(TARGET_CONFIG)?;
+    let target_triplet = TargetTriplet::parse(triplet)?;
+    let os = target_triplet.operating_system();
+    let os_cfg = target_cfg
+        .get(os)
+        .ok_or(IgnitionError::UnsupportedTargetError(format!(
+            "invalid operating system: {}",
+            os
+        )))?;
+    let target_variant = target_variant(&target_triplet, variant)?;
+    let builds: Vec<&TargetBuild> = os_cfg
+        .build
+        .iter()
+        .filter(|build| build.architecture == target_triplet.architecture)
+        .filter(|build| build.environment == target_triplet.environment)
+        .filter(|build| build.variant == target_variant)
+        .collect();
+    match builds.as_slice() {
+        [build] => Ok(ResolvedTarget {
+            triplet: triplet.to_string(),
+            os: os.to_string(),
+            build: (*build).clone(),
+        }),
+        [] => Err(IgnitionError::UnsupportedTargetError(format!(
+            "no matching build: {}",
+            triplet
+        ))),
+        _ => Err(IgnitionError::UnsupportedTargetError(format!(
+            "more than one matching build: {}",
+            triplet
+        ))),
+    }
+}
+
+/// Determine the build variant, only relevant to aarch64 linux (Jetson) targets.
+fn target_variant(target_triplet: &TargetTriplet, variant: Option<&str>) -> IgnitionResult<String> {
+    if target_triplet.architecture != "aarch64" || target_triplet.operating_system() != "linux" {
+        return Ok(String::new());
+    }
+    let target_variant = match variant {
+        Some(variant) if !variant.is_empty() => variant.to_string(),
+        _ => jetpack_release()?,
+    };
+    // variant must be a string of digits with periods
+    if !target_variant
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return Err(IgnitionError::UnsupportedTargetError(format!(
+            "failed to determine variant: {}",
+            target_variant
+        )));
+    }
+    if !JETPACK_VARIANTS.contains(&target_variant.as_str()) {
+        return Err(IgnitionError::UnsupportedTargetError(format!(
+            "unknown jetpack version: {}",
+            target_variant
+        )));
+    }
+    Ok(target_variant)
+}
+
+/// Retrieve the local Jetpack release from the nvidia-l4t-core package version.
+fn jetpack_release() -> IgnitionResult<String> {
+    let output = Command::new("dpkg-query")
+        .args(["--showformat=${Version}", "--show", "nvidia-l4t-core"])
+        .output()
+        .map_err(|err| {
+            IgnitionError::UnsupportedTargetError(format!("command 'dpkg-query' failed: {}", err))
+        })?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    Ok(version
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
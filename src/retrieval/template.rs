@@ -0,0 +1,58 @@
+// template.rs
+//
+// This module expands asset url templates (`url_template` in `config/environment.json`), so publishers can use
+// their own bucket layout.
+//
+
+use crate::{IgnitionError, IgnitionResult};
+
+/// Url template matching the default bucket layout.
+pub const DEFAULT_URL_TEMPLATE: &str = "{bucket}/{directory}/{asset}/{platform}/{file}";
+/// Url template matching the default bucket layout of versioned assets.
+pub const VERSIONED_URL_TEMPLATE: &str = "{bucket}/{directory}/{asset}/{version}/{platform}/{file}";
+/// Url template matching the default bucket layout of assets with a variant.
+pub const VARIANT_URL_TEMPLATE: &str =
+    "{bucket}/{directory}/{asset}/{asset_variant}/{platform}/{file}";
+/// Url template matching the default bucket layout of versioned assets with a variant.
+pub const VERSIONED_VARIANT_URL_TEMPLATE: &str =
+    "{bucket}/{directory}/{asset}/{version}/{asset_variant}/{platform}/{file}";
+/// Placeholders of url templates, see `AssetLocation`.
+pub const PLACEHOLDERS: [&str; 12] = [
+    "bucket",
+    "directory",
+    "asset",
+    "version",
+    "asset_variant",
+    "target",
+    "platform",
+    "os",
+    "arch",
+    "environment",
+    "variant",
+    "file",
+];
+
+/// Expand the `{<name>}` placeholders of <template> with <value> of each name.
+///
+/// Placeholders without a value (unknown, or e.g. `{version}` for an asset without version, `{asset_variant}`
+/// without variant) are an error.
+pub fn expand(template: &str, value: impl Fn(&str) -> Option<String>) -> IgnitionResult<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            IgnitionError::ConfigurationError(format!("unterminated placeholder: {}", template))
+        })? + start;
+        let name = &rest[start + 1..end];
+        expanded.push_str(&value(name).ok_or_else(|| {
+            IgnitionError::ConfigurationError(format!(
+                "unknown or unset placeholder {{{}}}: {}",
+                name, template
+            ))
+        })?);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
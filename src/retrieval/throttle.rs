@@ -0,0 +1,70 @@
+// throttle.rs
+//
+// This module contains the download bandwidth limit (`IGNITION_MAX_DOWNLOAD_RATE`), shared by all concurrent
+// asset downloads.
+//
+
+use std::{
+    io::{self, Read},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::env_size;
+use crate::{IgnitionError, IgnitionResult};
+
+const MAX_RATE_VARIABLE: &str = "IGNITION_MAX_DOWNLOAD_RATE";
+
+/// Time at which the bandwidth reserved by all downloads so far is used up.
+static RESERVED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Reader limiting the (combined) rate of all throttled readers to <rate> bytes per second.
+pub struct Throttled<R> {
+    inner: R,
+    rate: Option<u64>,
+}
+
+impl<R: Read> Throttled<R> {
+    /// Wrap <inner>, limited to the `IGNITION_MAX_DOWNLOAD_RATE` (unlimited if unset).
+    pub fn new(inner: R) -> IgnitionResult<Self> {
+        Ok(Throttled {
+            inner,
+            rate: max_rate()?,
+        })
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if let Some(rate) = self.rate {
+            reserve(count as u64, rate);
+        }
+        Ok(count)
+    }
+}
+
+/// Reserve bandwidth for <count> bytes at <rate> bytes per second, sleeping until it is available.
+fn reserve(count: u64, rate: u64) {
+    let duration = Duration::from_secs_f64(count as f64 / rate as f64);
+    let until = {
+        let mut reserved_until = RESERVED_UNTIL.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        let until = reserved_until.map_or(now, |until| until.max(now)) + duration;
+        *reserved_until = Some(until);
+        until
+    };
+    thread::sleep(until.saturating_duration_since(Instant::now()));
+}
+
+/// Maximum download rate in bytes per second, from `IGNITION_MAX_DOWNLOAD_RATE` (e.g. "512K" or "10M").
+pub fn max_rate() -> IgnitionResult<Option<u64>> {
+    match env_size(MAX_RATE_VARIABLE)? {
+        Some(0) => Err(IgnitionError::ConfigurationError(format!(
+            "{} must be greater than 0",
+            MAX_RATE_VARIABLE
+        ))),
+        rate => Ok(rate),
+    }
+}
@@ -0,0 +1,290 @@
+// tls.rs
+//
+// This module contains the TLS configuration of asset downloads: custom CA bundle, SPKI pinning per host and the
+// require-TLS policy.
+//
+
+use std::{
+    cell::Cell,
+    fmt,
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    StreamOwned,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+};
+use sha2::{Digest, Sha256};
+use ureq::unversioned::transport::{
+    Buffers, ConnectionDetails, Connector, Either, LazyBuffers, NextTimeout, Transport,
+    TransportAdapter,
+};
+
+use super::{
+    env_flag, env_value,
+    source::{self, oci::ArtifactReference},
+};
+use crate::{IgnitionError, IgnitionResult, manifest};
+
+const CA_BUNDLE_VARIABLE: &str = "IGNITION_CA_BUNDLE";
+const REQUIRE_TLS_VARIABLE: &str = "IGNITION_REQUIRE_TLS";
+const PIN_PREFIX: &str = "sha256/";
+
+thread_local! {
+    /// Whether TLS is required for requests on this thread, see `RequireTlsGuard`.
+    static REQUIRE_TLS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Determine if TLS is required: `IGNITION_REQUIRE_TLS`, otherwise the manifest flag <require_tls> of the asset
+/// (or a retrieval in progress on this thread requiring it).
+pub fn require_tls(require_tls: bool) -> IgnitionResult<bool> {
+    Ok(env_flag(REQUIRE_TLS_VARIABLE)?.unwrap_or(require_tls || REQUIRE_TLS.get()))
+}
+
+/// Guard requiring TLS for all requests on this thread (i.e. of an asset retrieval) while alive.
+pub struct RequireTlsGuard(bool);
+
+impl RequireTlsGuard {
+    /// Require TLS on this thread if <require_tls>, until the guard is dropped.
+    pub fn new(require_tls: bool) -> Self {
+        RequireTlsGuard(REQUIRE_TLS.replace(REQUIRE_TLS.get() || require_tls))
+    }
+}
+
+impl Drop for RequireTlsGuard {
+    fn drop(&mut self) {
+        REQUIRE_TLS.set(self.0);
+    }
+}
+
+/// Determine if <url> is retrieved over an encrypted transport: `https`, cloud storage, Hugging Face and OCI
+/// (unless plain HTTP) urls, sftp (SSH) and local files; not plain `http`, `rsync` or `zsync+http` urls.
+pub fn is_secure_url(url: &str) -> IgnitionResult<bool> {
+    Ok(match source::scheme(url) {
+        "https" | "zsync+https" | "s3" | "gs" | "az" | "hf" | "sftp" | "file" | "" => true,
+        "oci" => !ArtifactReference::parse(url)?.plain_http()?,
+        _ => false,
+    })
+}
+
+/// Create a TLS connector for connections to <host>, None if the default TLS configuration applies.
+///
+/// The trusted roots are the bundled webpki roots plus the PEM certificates in `IGNITION_CA_BUNDLE`. Hosts
+/// with `pins` in the manifest additionally require a certificate of the presented chain to match one of
+/// the pinned SPKI hashes (`sha256/<base64>`, as output by
+/// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`).
+pub fn connector(host: &str) -> IgnitionResult<Option<TlsConnector>> {
+    let ca_bundle = env_value::<String>(CA_BUNDLE_VARIABLE)?;
+    let pins = pins(host)?;
+    if ca_bundle.is_none() && pins.is_empty() {
+        return Ok(None);
+    }
+    let tls_error = |err: String| IgnitionError::ConfigurationError(format!("tls: {}", err));
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Some(ca_bundle) = ca_bundle {
+        let certs = CertificateDer::pem_file_iter(&ca_bundle)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| tls_error(format!("{}: {}", ca_bundle, err)))?;
+        if certs.is_empty() {
+            return Err(tls_error(format!("{}: no certificates", ca_bundle)));
+        }
+        for cert in certs {
+            roots
+                .add(cert)
+                .map_err(|err| tls_error(format!("{}: {}", ca_bundle, err)))?;
+        }
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|err| tls_error(err.to_string()))?;
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| tls_error(err.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+            inner: verifier,
+            host: host.to_string(),
+            pins,
+        }))
+        .with_no_client_auth();
+    Ok(Some(TlsConnector {
+        config: Arc::new(config),
+    }))
+}
+
+/// SPKI hashes (base64, without prefix) pinned for <host> by any asset in the manifest.
+pub fn pins(host: &str) -> IgnitionResult<Vec<String>> {
+    let mut pins = Vec::new();
+    for asset_cfg in manifest()?.values() {
+        for pin in asset_cfg.pins.get(host).into_iter().flatten() {
+            let hash = pin.strip_prefix(PIN_PREFIX).ok_or_else(|| {
+                IgnitionError::ConfigurationError(format!(
+                    "invalid pin for {} (expected {}<base64>): {}",
+                    host, PIN_PREFIX, pin
+                ))
+            })?;
+            pins.push(hash.to_string());
+        }
+    }
+    Ok(pins)
+}
+
+/// Base64 SHA-256 hash of the SPKI of <cert>, None if the certificate can't be parsed.
+fn spki_hash(cert: &CertificateDer<'_>) -> Option<String> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    Some(STANDARD.encode(Sha256::digest(cert.subject_public_key_info())))
+}
+
+/// Certificate verifier requiring a pinned SPKI in the chain, on top of regular webpki verification.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    host: String,
+    pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        if self.pins.is_empty()
+            || [end_entity]
+                .into_iter()
+                .chain(intermediates)
+                .filter_map(spki_hash)
+                .any(|hash| self.pins.contains(&hash))
+        {
+            return Ok(verified);
+        }
+        Err(rustls::Error::General(format!(
+            "no certificate matches the pinned public keys of {}",
+            self.host
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Connector wrapping the chained transport in TLS for https urls.
+#[derive(Debug)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl<In: Transport> Connector<In> for TlsConnector {
+    type Out = Either<In, TlsTransport>;
+
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, ureq::Error> {
+        let Some(transport) = chained else {
+            return Ok(None);
+        };
+        if !details.needs_tls() || transport.is_tls() {
+            return Ok(Some(Either::A(transport)));
+        }
+        let host = details
+            .uri
+            .host()
+            .unwrap_or_default()
+            .trim_matches(['[', ']'])
+            .to_string();
+        let name = ServerName::try_from(host).map_err(|_| ureq::Error::Tls("invalid dns name"))?;
+        let mut conn = ClientConnection::new(self.config.clone(), name)?;
+        let mut sock = TransportAdapter::new(transport.boxed());
+        sock.set_timeout(details.timeout);
+        conn.complete_io(&mut sock)?;
+        Ok(Some(Either::B(TlsTransport {
+            buffers: LazyBuffers::new(
+                details.config.input_buffer_size(),
+                details.config.output_buffer_size(),
+            ),
+            stream: StreamOwned { conn, sock },
+        })))
+    }
+}
+
+/// TLS transport using the custom client configuration.
+pub struct TlsTransport {
+    buffers: LazyBuffers,
+    stream: StreamOwned<ClientConnection, TransportAdapter>,
+}
+
+impl Transport for TlsTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), ureq::Error> {
+        self.stream.get_mut().set_timeout(timeout);
+        let output = &self.buffers.output()[..amount];
+        self.stream.write_all(output)?;
+        Ok(())
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, ureq::Error> {
+        self.stream.get_mut().set_timeout(timeout);
+        let input = self.buffers.input_append_buf();
+        let amount = self.stream.read(input)?;
+        self.buffers.input_appended(amount);
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.stream.get_mut().get_mut().is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for TlsTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsTransport").finish()
+    }
+}
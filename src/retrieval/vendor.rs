@@ -0,0 +1,103 @@
+// vendor.rs
+//
+// This module contains vendoring, for fully hermetic builds without network access nor shared cache: verified
+// archives and files are copied into a directory of the repository (`vendor/ignition/` by default, see `vendor`),
+// committed with it, and builds in vendored mode (`IGNITION_VENDOR_DIR`) retrieve assets from that directory only.
+//
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    BUCKET_URL_VARIABLE, DEFAULT_DIRECTORY_PATH, DIRECTORY_PATH_VARIABLE, env_value, expiry,
+    lock::LOCK_EXTENSION, rerun,
+};
+use crate::{IgnitionError, IgnitionResult};
+
+const VENDOR_DIR_VARIABLE: &str = "IGNITION_VENDOR_DIR";
+/// Path of the vendored directory within the repository, by default.
+pub const DEFAULT_VENDOR_PATH: &str = "vendor/ignition";
+
+/// Directory of the vendored assets in vendored mode (`IGNITION_VENDOR_DIR`), None if unset. Relative paths are
+/// relative to the current directory, i.e. that of the package for build scripts: set an absolute path, e.g. with
+/// `IGNITION_VENDOR_DIR = { value = "vendor/ignition", relative = true }` in the `[env]` table of
+/// `.cargo/config.toml`.
+///
+/// In vendored mode, the vendored directory is the read-only lower cache (see `cache::lower_dir`) of a cache within
+/// the build directory, never shared, and network access is refused (see `offline::is_offline`), so assets
+/// missing in the vendored directory are an error.
+pub fn dir() -> Option<PathBuf> {
+    rerun::record_variable(VENDOR_DIR_VARIABLE);
+    std::env::var_os(VENDOR_DIR_VARIABLE)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Vendor <assets> for <targets> into <vendor_dir>, to be committed with the repository.
+///
+/// Assets are retrieved as by `retrieval::prefetch` from `IGNITION_BUCKET_URL`, with <vendor_dir> as the cache:
+/// archives and additional `files` are downloaded (if absent, or copied from the lower and remote caches) and
+/// verified, but neither extracted nor installed, archives being recorded as in any cache (see
+/// `cache::CacheRecord`). Lock files and fetch times left by the retrieval are removed, so the vendored directory
+/// only holds the files to commit. From a standalone tool (see `ignition-cache vendor`):
+/// ```text
+/// ignition::retrieval::vendor::vendor(&["opencv"], &["x86_64-unknown-linux-gnu"], Path::new("vendor/ignition"))?;
+/// ```
+/// Return is the paths of the vendored archives, by target then in the order of <assets>.
+pub fn vendor(
+    assets: &[&str],
+    targets: &[&str],
+    vendor_dir: &Path,
+) -> IgnitionResult<Vec<PathBuf>> {
+    if dir().is_some() {
+        return Err(IgnitionError::ConfigurationError(format!(
+            "{} is set (vendored mode): unset it to vendor assets",
+            VENDOR_DIR_VARIABLE
+        )));
+    }
+    let bucket_url = std::env::var(BUCKET_URL_VARIABLE).map_err(|err| {
+        IgnitionError::ConfigurationError(format!("{}: {}", BUCKET_URL_VARIABLE, err))
+    })?;
+    let directory_path = env_value::<String>(DIRECTORY_PATH_VARIABLE)?
+        .unwrap_or_else(|| DEFAULT_DIRECTORY_PATH.to_string());
+    fs::create_dir_all(vendor_dir)?;
+    let mut archives = Vec::new();
+    for target in targets {
+        for asset in assets {
+            println!("--vendor: {} ({})", asset, target);
+            archives.push(super::retrieve_recorded(
+                &bucket_url,
+                asset,
+                vendor_dir,
+                "",
+                &directory_path,
+                target,
+                None,
+                true,
+            )?);
+        }
+    }
+    clean(vendor_dir)?;
+    Ok(archives)
+}
+
+/// Remove the lock files and fetch times (see `expiry::record`) within <directory> (recursively), and the
+/// directories left empty.
+fn clean(directory: &Path) -> IgnitionResult<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            clean(&path)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        } else if name.ends_with(LOCK_EXTENSION) || name.ends_with(expiry::FETCHED_EXTENSION) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}